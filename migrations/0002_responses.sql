@@ -0,0 +1,9 @@
+CREATE TABLE responses (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    answers TEXT NOT NULL,
+    is_test BOOLEAN NOT NULL DEFAULT false,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX responses_form_id ON responses(form_id);
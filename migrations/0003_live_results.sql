@@ -0,0 +1 @@
+ALTER TABLE forms ADD COLUMN live_results BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1 @@
+ALTER TABLE responses ADD COLUMN device TEXT;
@@ -0,0 +1,4 @@
+ALTER TABLE responses ADD COLUMN status TEXT NOT NULL DEFAULT 'new'
+    CHECK (status IN ('new', 'in-progress', 'resolved', 'rejected'));
+
+CREATE INDEX responses_form_id_status ON responses(form_id, status);
@@ -0,0 +1,3 @@
+ALTER TABLE responses ADD COLUMN assigned_to INTEGER REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX responses_assigned_to ON responses(assigned_to);
@@ -0,0 +1,10 @@
+CREATE TABLE response_comments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    response_id INTEGER NOT NULL REFERENCES responses(id) ON DELETE CASCADE,
+    parent_id INTEGER REFERENCES response_comments(id) ON DELETE CASCADE,
+    author_id INTEGER NOT NULL REFERENCES users(id),
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX response_comments_response_id ON response_comments(response_id);
@@ -0,0 +1,16 @@
+ALTER TABLE users ADD COLUMN is_approver BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE publish_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    requested_by INTEGER NOT NULL REFERENCES users(id),
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'approved', 'changes_requested')),
+    reviewer_id INTEGER REFERENCES users(id),
+    review_comment TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    reviewed_at TEXT
+);
+
+CREATE INDEX publish_requests_form_id ON publish_requests(form_id);
+CREATE INDEX publish_requests_status ON publish_requests(status);
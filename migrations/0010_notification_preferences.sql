@@ -0,0 +1,26 @@
+ALTER TABLE users ADD COLUMN email TEXT;
+
+CREATE TABLE notification_preferences (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    in_app BOOLEAN NOT NULL DEFAULT true,
+    email BOOLEAN NOT NULL DEFAULT false,
+    PRIMARY KEY (user_id, kind)
+);
+
+CREATE TABLE muted_forms (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, form_id)
+);
+
+CREATE TABLE email_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sent_at TEXT
+);
+
+CREATE INDEX email_queue_unsent ON email_queue(sent_at) WHERE sent_at IS NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN digest TEXT CHECK (digest IN ('daily', 'weekly'));
+ALTER TABLE users ADD COLUMN digest_sent_at TEXT;
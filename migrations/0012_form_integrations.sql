@@ -0,0 +1,14 @@
+CREATE TABLE form_integrations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('slack', 'discord')),
+    webhook_url TEXT NOT NULL,
+    fields TEXT NOT NULL DEFAULT '[]',
+    cadence TEXT NOT NULL DEFAULT 'submission' CHECK (cadence IN ('submission', 'digest')),
+    last_delivered_at TEXT,
+    last_error TEXT,
+    last_error_at TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX form_integrations_form_id ON form_integrations(form_id);
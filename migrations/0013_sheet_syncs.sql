@@ -0,0 +1,13 @@
+CREATE TABLE sheet_syncs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    spreadsheet_id TEXT NOT NULL,
+    sheet_name TEXT NOT NULL,
+    columns TEXT NOT NULL,
+    last_synced_response_id INTEGER NOT NULL DEFAULT 0,
+    last_synced_at TEXT,
+    last_error TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX sheet_syncs_form_id ON sheet_syncs(form_id);
@@ -0,0 +1,37 @@
+CREATE TABLE form_integrations_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('slack', 'discord', 'webhook', 'email')),
+    config TEXT NOT NULL DEFAULT '{}',
+    cadence TEXT NOT NULL DEFAULT 'submission' CHECK (cadence IN ('submission', 'digest')),
+    last_delivered_at TEXT,
+    last_error TEXT,
+    last_error_at TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO form_integrations_new
+    (id, form_id, kind, config, cadence, last_delivered_at, last_error, last_error_at, created_at)
+SELECT id, form_id, kind, json_object('url', webhook_url, 'fields', json(fields)), cadence,
+       last_delivered_at, last_error, last_error_at, created_at
+FROM form_integrations;
+
+DROP TABLE form_integrations;
+ALTER TABLE form_integrations_new RENAME TO form_integrations;
+
+CREATE INDEX form_integrations_form_id ON form_integrations(form_id);
+
+CREATE TABLE integration_deliveries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    integration_id INTEGER NOT NULL REFERENCES form_integrations(id) ON DELETE CASCADE,
+    response_id INTEGER NOT NULL REFERENCES responses(id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TEXT
+);
+
+CREATE INDEX integration_deliveries_pending ON integration_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX integration_deliveries_integration_id ON integration_deliveries(integration_id);
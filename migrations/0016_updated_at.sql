@@ -0,0 +1,28 @@
+ALTER TABLE forms ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+ALTER TABLE responses ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+
+UPDATE forms SET updated_at = CURRENT_TIMESTAMP;
+UPDATE responses SET updated_at = created_at;
+
+CREATE INDEX forms_author_id_updated_at ON forms(author_id, updated_at);
+CREATE INDEX responses_form_id_updated_at ON responses(form_id, updated_at);
+
+CREATE TRIGGER forms_insert_updated_at AFTER INSERT ON forms FOR EACH ROW WHEN NEW.updated_at = ''
+BEGIN
+    UPDATE forms SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER forms_touch_updated_at AFTER UPDATE ON forms FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE forms SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER responses_insert_updated_at AFTER INSERT ON responses FOR EACH ROW WHEN NEW.updated_at = ''
+BEGIN
+    UPDATE responses SET updated_at = NEW.created_at WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER responses_touch_updated_at AFTER UPDATE ON responses FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE responses SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
@@ -0,0 +1,15 @@
+ALTER TABLE forms ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE responses ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+DROP TRIGGER forms_touch_updated_at;
+DROP TRIGGER responses_touch_updated_at;
+
+CREATE TRIGGER forms_touch_updated_at AFTER UPDATE ON forms FOR EACH ROW WHEN NEW.version IS OLD.version
+BEGIN
+    UPDATE forms SET updated_at = CURRENT_TIMESTAMP, version = OLD.version + 1 WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER responses_touch_updated_at AFTER UPDATE ON responses FOR EACH ROW WHEN NEW.version IS OLD.version
+BEGIN
+    UPDATE responses SET updated_at = CURRENT_TIMESTAMP, version = OLD.version + 1 WHERE id = NEW.id;
+END;
@@ -0,0 +1,5 @@
+CREATE TABLE form_restrictions (
+    form_id INTEGER PRIMARY KEY NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    blocked_ranges TEXT NOT NULL DEFAULT '[]',
+    allowed_countries TEXT NOT NULL DEFAULT '[]'
+);
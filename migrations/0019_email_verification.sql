@@ -0,0 +1,14 @@
+ALTER TABLE forms ADD COLUMN verify_email BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE responses ADD COLUMN respondent_email TEXT;
+
+CREATE TABLE email_verifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    code_hash TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX email_verifications_form_id_email ON email_verifications(form_id, email);
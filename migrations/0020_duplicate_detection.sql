@@ -0,0 +1,8 @@
+ALTER TABLE forms ADD COLUMN duplicate_policy TEXT NOT NULL DEFAULT 'off'
+    CHECK (duplicate_policy IN ('off', 'flag', 'reject'));
+ALTER TABLE forms ADD COLUMN duplicate_window_hours INTEGER NOT NULL DEFAULT 24;
+
+ALTER TABLE responses ADD COLUMN answers_hash TEXT;
+ALTER TABLE responses ADD COLUMN duplicate_of INTEGER REFERENCES responses(id) ON DELETE SET NULL;
+
+CREATE INDEX responses_form_id_answers_hash ON responses(form_id, answers_hash);
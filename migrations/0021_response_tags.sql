@@ -0,0 +1,20 @@
+CREATE TABLE response_tags (
+    response_id INTEGER NOT NULL REFERENCES responses(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (response_id, tag)
+);
+
+CREATE INDEX response_tags_tag ON response_tags(tag);
+
+CREATE TABLE saved_filters (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    status TEXT,
+    tag TEXT,
+    since TEXT,
+    until TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (user_id, form_id, name)
+);
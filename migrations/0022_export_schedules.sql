@@ -0,0 +1,14 @@
+ALTER TABLE email_queue ADD COLUMN attachment_name TEXT;
+ALTER TABLE email_queue ADD COLUMN attachment TEXT;
+
+CREATE TABLE export_schedules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    frequency TEXT NOT NULL CHECK (frequency IN ('daily', 'weekly')),
+    recipient TEXT NOT NULL,
+    last_run_at TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX export_schedules_form_id ON export_schedules(form_id);
@@ -0,0 +1,2 @@
+ALTER TABLE api_tokens ADD COLUMN scopes TEXT NOT NULL DEFAULT '["read:forms","write:forms","read:responses"]';
+ALTER TABLE api_tokens ADD COLUMN form_ids TEXT;
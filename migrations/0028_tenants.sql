@@ -0,0 +1,52 @@
+-- no-transaction
+-- Usernames only need to be unique within a tenant. SQLite cannot drop a
+-- column's UNIQUE constraint, so users is rebuilt with tenant_id, with
+-- foreign keys off so dropping the old table does not cascade into
+-- everything users own.
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+CREATE TABLE tenants (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    logo_url TEXT,
+    accent_color TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO tenants (id, slug, name) VALUES (1, 'default', 'Forms');
+
+CREATE TABLE users_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    username TEXT NOT NULL,
+    password_hash TEXT NOT NULL,
+    is_approver BOOLEAN NOT NULL DEFAULT false,
+    email TEXT,
+    digest TEXT CHECK (digest IN ('daily', 'weekly')),
+    digest_sent_at TEXT,
+    oidc_subject TEXT,
+    active BOOLEAN NOT NULL DEFAULT true,
+    external_id TEXT,
+    plan TEXT NOT NULL DEFAULT 'default',
+    tenant_id INTEGER NOT NULL DEFAULT 1 REFERENCES tenants(id),
+    UNIQUE (tenant_id, username)
+);
+
+INSERT INTO users_new
+    (id, username, password_hash, is_approver, email, digest, digest_sent_at, oidc_subject, active, external_id, plan)
+SELECT id, username, password_hash, is_approver, email, digest, digest_sent_at, oidc_subject, active, external_id, plan
+FROM users;
+
+DROP TABLE users;
+ALTER TABLE users_new RENAME TO users;
+
+CREATE UNIQUE INDEX users_oidc_subject ON users(oidc_subject);
+CREATE INDEX users_tenant_id ON users(tenant_id);
+
+PRAGMA foreign_key_check;
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
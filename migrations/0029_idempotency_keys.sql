@@ -0,0 +1,3 @@
+ALTER TABLE responses ADD COLUMN idempotency_key TEXT;
+
+CREATE UNIQUE INDEX responses_idempotency_key ON responses(form_id, idempotency_key);
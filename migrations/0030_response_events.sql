@@ -0,0 +1,12 @@
+CREATE TABLE response_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    response_id INTEGER NOT NULL REFERENCES responses(id) ON DELETE CASCADE,
+    actor_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    kind TEXT NOT NULL,
+    detail TEXT NOT NULL DEFAULT '',
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX response_events_response_id ON response_events(response_id);
+
+INSERT INTO response_events (response_id, kind, created_at) SELECT id, 'submitted', created_at FROM responses;
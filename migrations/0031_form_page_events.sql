@@ -0,0 +1,9 @@
+CREATE TABLE form_page_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    visitor TEXT NOT NULL,
+    page INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (form_id, visitor, page, event)
+);
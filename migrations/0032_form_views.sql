@@ -0,0 +1,6 @@
+CREATE TABLE form_views (
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    visitor TEXT NOT NULL,
+    day TEXT NOT NULL,
+    PRIMARY KEY (form_id, visitor, day)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE responses ADD COLUMN utm_source TEXT;
+ALTER TABLE responses ADD COLUMN utm_medium TEXT;
+ALTER TABLE responses ADD COLUMN utm_campaign TEXT;
+ALTER TABLE responses ADD COLUMN referrer TEXT;
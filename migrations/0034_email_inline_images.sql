@@ -0,0 +1 @@
+ALTER TABLE email_queue ADD COLUMN inline_image BLOB;
@@ -0,0 +1 @@
+ALTER TABLE forms ADD COLUMN anonymous BOOLEAN NOT NULL DEFAULT false;
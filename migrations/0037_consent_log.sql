@@ -0,0 +1,32 @@
+CREATE TABLE consent_texts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    field_key TEXT NOT NULL,
+    text TEXT NOT NULL,
+    text_hash TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (form_id, field_key, text_hash)
+);
+
+CREATE TABLE consent_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    response_id INTEGER REFERENCES responses(id) ON DELETE SET NULL,
+    field_key TEXT NOT NULL,
+    consent_text_id INTEGER NOT NULL REFERENCES consent_texts(id),
+    consented_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX consent_log_form_id ON consent_log(form_id);
+
+-- Consent records are append-only. Only deleting the form removes them, and
+-- deleting a response just detaches it.
+CREATE TRIGGER consent_texts_immutable BEFORE UPDATE ON consent_texts
+BEGIN
+    SELECT RAISE(ABORT, 'consent texts cannot be changed');
+END;
+
+CREATE TRIGGER consent_log_immutable BEFORE UPDATE OF form_id, field_key, consent_text_id, consented_at ON consent_log
+BEGIN
+    SELECT RAISE(ABORT, 'consent records cannot be changed');
+END;
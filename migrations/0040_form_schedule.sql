@@ -0,0 +1,4 @@
+ALTER TABLE forms ADD COLUMN opens_at TEXT;
+ALTER TABLE forms ADD COLUMN closes_at TEXT;
+ALTER TABLE forms ADD COLUMN closed_message TEXT;
+ALTER TABLE forms ADD COLUMN show_countdown BOOLEAN NOT NULL DEFAULT false;
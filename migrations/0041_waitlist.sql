@@ -0,0 +1,3 @@
+ALTER TABLE forms ADD COLUMN response_cap INTEGER;
+ALTER TABLE forms ADD COLUMN waitlist BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE responses ADD COLUMN waitlisted BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,10 @@
+CREATE TABLE form_rsvp (
+    form_id INTEGER PRIMARY KEY NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    starts_at TEXT NOT NULL,
+    ends_at TEXT,
+    location TEXT,
+    choice_field TEXT
+);
+
+ALTER TABLE email_queue ADD COLUMN attachment_type TEXT NOT NULL DEFAULT 'text/csv';
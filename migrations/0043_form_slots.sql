@@ -0,0 +1,20 @@
+CREATE TABLE form_slots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    field_key TEXT NOT NULL,
+    starts_at TEXT NOT NULL,
+    ends_at TEXT,
+    capacity INTEGER NOT NULL CHECK (capacity >= 0),
+    booked INTEGER NOT NULL DEFAULT 0 CHECK (booked >= 0),
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX form_slots_form_id ON form_slots(form_id, field_key);
+
+-- Deleting or withdrawing a response frees the slot it booked.
+CREATE TRIGGER form_slots_release AFTER DELETE ON responses
+BEGIN
+    UPDATE form_slots SET booked = booked - 1
+    WHERE form_id = OLD.form_id AND booked > 0
+    AND id = CAST(json_extract(OLD.answers, '$."' || field_key || '"') AS INTEGER);
+END;
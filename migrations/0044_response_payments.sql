@@ -0,0 +1,5 @@
+ALTER TABLE responses ADD COLUMN payment_status TEXT;
+ALTER TABLE responses ADD COLUMN payment_amount INTEGER;
+ALTER TABLE responses ADD COLUMN payment_session TEXT;
+
+CREATE INDEX responses_payment_session ON responses(payment_session);
@@ -0,0 +1,4 @@
+ALTER TABLE responses ADD COLUMN payment_intent TEXT;
+ALTER TABLE responses ADD COLUMN payment_refunded INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX responses_payment_intent ON responses(payment_intent);
@@ -0,0 +1,5 @@
+ALTER TABLE forms ADD COLUMN captcha BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE forms ADD COLUMN captcha_accept_score REAL NOT NULL DEFAULT 0.5;
+ALTER TABLE forms ADD COLUMN captcha_reject_score REAL NOT NULL DEFAULT 0.1;
+
+ALTER TABLE responses ADD COLUMN captcha_score REAL;
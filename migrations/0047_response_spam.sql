@@ -0,0 +1,2 @@
+ALTER TABLE responses ADD COLUMN spam BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE responses ADD COLUMN spam_reason TEXT;
@@ -0,0 +1,6 @@
+CREATE TABLE form_stages (
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    stage TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    PRIMARY KEY (form_id, stage)
+);
@@ -0,0 +1,5 @@
+CREATE TABLE form_scripts (
+    form_id INTEGER PRIMARY KEY NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    source TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,9 @@
+CREATE TABLE user_profiles (
+    user_id INTEGER PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    display_name TEXT,
+    avatar TEXT,
+    bio TEXT,
+    timezone TEXT,
+    show_on_forms BOOLEAN NOT NULL DEFAULT false,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
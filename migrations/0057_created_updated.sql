@@ -0,0 +1,37 @@
+ALTER TABLE forms ADD COLUMN created_at TEXT NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN created_at TEXT NOT NULL DEFAULT '';
+ALTER TABLE users ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+
+-- Nothing recorded when these were made; a form's last edit is the best guess.
+UPDATE forms SET created_at = updated_at;
+UPDATE users SET created_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP;
+
+CREATE INDEX users_tenant_id_updated_at ON users(tenant_id, updated_at);
+
+-- Filling in the timestamps of a new row is not an edit, so it neither
+-- bumps the version nor touches updated_at again.
+DROP TRIGGER forms_insert_updated_at;
+DROP TRIGGER forms_touch_updated_at;
+
+CREATE TRIGGER forms_insert_timestamps AFTER INSERT ON forms FOR EACH ROW WHEN NEW.created_at = '' OR NEW.updated_at = ''
+BEGIN
+    UPDATE forms SET
+        created_at = CASE NEW.created_at WHEN '' THEN CURRENT_TIMESTAMP ELSE NEW.created_at END,
+        updated_at = CASE NEW.updated_at WHEN '' THEN CURRENT_TIMESTAMP ELSE NEW.updated_at END
+    WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER forms_touch_updated_at AFTER UPDATE ON forms FOR EACH ROW WHEN NEW.version IS OLD.version AND OLD.created_at != ''
+BEGIN
+    UPDATE forms SET updated_at = CURRENT_TIMESTAMP, version = OLD.version + 1 WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER users_insert_timestamps AFTER INSERT ON users FOR EACH ROW WHEN NEW.created_at = ''
+BEGIN
+    UPDATE users SET created_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER users_touch_updated_at AFTER UPDATE ON users FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at AND OLD.created_at != ''
+BEGIN
+    UPDATE users SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
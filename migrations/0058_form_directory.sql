@@ -0,0 +1,9 @@
+ALTER TABLE forms ADD COLUMN listed BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE form_tags (
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (form_id, tag)
+);
+
+CREATE INDEX form_tags_tag ON form_tags(tag);
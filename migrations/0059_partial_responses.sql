@@ -0,0 +1,14 @@
+CREATE TABLE partial_responses (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    -- The token itself is never stored; it is also the key the answers are
+    -- encrypted with.
+    token_hash TEXT NOT NULL UNIQUE,
+    answers TEXT NOT NULL,
+    page INTEGER NOT NULL DEFAULT 1,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TEXT NOT NULL
+);
+
+CREATE INDEX partial_responses_expires_at ON partial_responses(expires_at);
@@ -0,0 +1,3 @@
+-- Set on responses an author keyed in by hand, such as ones taken by phone
+-- or on paper; unset on everything respondents submitted themselves.
+ALTER TABLE responses ADD COLUMN entered_by INTEGER REFERENCES users(id) ON DELETE SET NULL;
@@ -0,0 +1,5 @@
+CREATE TABLE form_share_codes (
+    form_id INTEGER PRIMARY KEY NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    code TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
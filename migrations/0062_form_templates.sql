@@ -0,0 +1,13 @@
+CREATE TABLE form_templates (
+    form_id INTEGER PRIMARY KEY NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- On a template, the fields its copies must keep; on a copy, the fields it
+-- must keep. Copies carry their own rows so they stay locked even if the
+-- template goes away.
+CREATE TABLE form_locked_fields (
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    field TEXT NOT NULL,
+    PRIMARY KEY (form_id, field)
+);
@@ -0,0 +1,18 @@
+-- Fields saved without a key were unreachable by exports, webhooks and the
+-- API. Give each one a generated key like the app now does on save, with
+-- eight random bytes so two generated within one form won't collide.
+--
+-- Only forms.fields is rewritten. form_revisions isn't backfilled, which is
+-- safe only because 0064 creates it after this runs: there are no earlier
+-- revisions holding keyless fields to fix up.
+UPDATE forms SET fields = (
+    SELECT json_group_array(
+        CASE WHEN coalesce(json_extract(f.value, '$.key'), '') = ''
+            THEN json_set(f.value, '$.key', 'f_' || lower(hex(randomblob(8))))
+            ELSE json(f.value)
+        END
+    )
+    FROM json_each(forms.fields) f
+)
+WHERE json_valid(fields) AND json_type(fields) = 'array'
+AND EXISTS (SELECT 1 FROM json_each(forms.fields) f WHERE coalesce(json_extract(f.value, '$.key'), '') = '');
@@ -0,0 +1,24 @@
+-- A form's fields as they were before each edit that changed them.
+CREATE TABLE form_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    form_id INTEGER NOT NULL REFERENCES forms(id) ON DELETE CASCADE,
+    edited_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    fields TEXT NOT NULL,
+    -- For edits that removed or retyped answered fields: where each one's
+    -- answers went, as a JSON object of old key to new key, with an empty
+    -- key for answers that were archived.
+    mapping TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX form_revisions_form_id ON form_revisions(form_id);
+
+CREATE TABLE archived_answers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    response_id INTEGER NOT NULL REFERENCES responses(id) ON DELETE CASCADE,
+    revision_id INTEGER NOT NULL REFERENCES form_revisions(id) ON DELETE CASCADE,
+    field_key TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+
+CREATE INDEX archived_answers_response_id ON archived_answers(response_id);
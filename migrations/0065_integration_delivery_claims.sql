@@ -0,0 +1,25 @@
+-- Deliveries are claimed by moving them to 'sending', so two dispatchers
+-- never deliver the same row. A delivery without an integration emails the
+-- form's author, as their notification preferences allow.
+CREATE TABLE integration_deliveries_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    integration_id INTEGER REFERENCES form_integrations(id) ON DELETE CASCADE,
+    response_id INTEGER NOT NULL REFERENCES responses(id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sending', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TEXT
+);
+
+INSERT INTO integration_deliveries_new
+    (id, integration_id, response_id, status, attempts, last_error, next_attempt_at, created_at, delivered_at)
+SELECT id, integration_id, response_id, status, attempts, last_error, next_attempt_at, created_at, delivered_at
+FROM integration_deliveries;
+
+DROP TABLE integration_deliveries;
+ALTER TABLE integration_deliveries_new RENAME TO integration_deliveries;
+
+CREATE INDEX integration_deliveries_due ON integration_deliveries(next_attempt_at) WHERE status IN ('pending', 'sending');
+CREATE INDEX integration_deliveries_integration_id ON integration_deliveries(integration_id);
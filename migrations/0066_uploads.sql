@@ -0,0 +1,20 @@
+-- Every stored upload and where its virus scan stands. Files waiting for a
+-- scan, or that failed one, are kept in quarantine and never served.
+CREATE TABLE uploads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    key TEXT NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    size INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'clean', 'infected')),
+    signature TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    scanned_at TEXT
+);
+
+CREATE INDEX uploads_pending ON uploads(id) WHERE status = 'pending';
+CREATE INDEX uploads_user_id ON uploads(user_id);
+
+-- Avatars from before were scanned, if at all, when they were uploaded.
+-- Their sizes were never recorded.
+INSERT INTO uploads (key, user_id, size, status, scanned_at)
+SELECT avatar, user_id, 0, 'clean', updated_at FROM user_profiles WHERE avatar IS NOT NULL;
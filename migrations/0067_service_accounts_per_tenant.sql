@@ -0,0 +1,24 @@
+-- Subjects only need to be unique within a tenant, and are looked up within
+-- the request's tenant. SQLite cannot drop a column's UNIQUE constraint, so
+-- the table is rebuilt.
+CREATE TABLE service_accounts_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    tenant_id INTEGER NOT NULL REFERENCES tenants(id),
+    name TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    form_ids TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TEXT,
+    UNIQUE (tenant_id, subject)
+);
+
+INSERT INTO service_accounts_new (id, user_id, tenant_id, name, subject, scopes, form_ids, created_at, last_used_at)
+SELECT s.id, s.user_id, u.tenant_id, s.name, s.subject, s.scopes, s.form_ids, s.created_at, s.last_used_at
+FROM service_accounts s JOIN users u ON u.id = s.user_id;
+
+DROP TABLE service_accounts;
+ALTER TABLE service_accounts_new RENAME TO service_accounts;
+
+CREATE INDEX service_accounts_user_id ON service_accounts(user_id);
@@ -0,0 +1,5 @@
+-- The DN an LDAP login bound as, linking the directory entry to the account
+-- its first login created. Lower-cased, as DNs compare without case.
+ALTER TABLE users ADD COLUMN ldap_dn TEXT;
+
+CREATE UNIQUE INDEX users_ldap_dn ON users(tenant_id, ldap_dn);
@@ -0,0 +1,12 @@
+-- Forms and responses carry their tenant, so tenant-scoped queries filter
+-- on it directly instead of joining through the author. SQLite cannot add a
+-- REFERENCES column with a non-null default; the tenant is always the
+-- author's, which users.tenant_id already constrains.
+ALTER TABLE forms ADD COLUMN tenant_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE responses ADD COLUMN tenant_id INTEGER NOT NULL DEFAULT 1;
+
+UPDATE forms SET tenant_id = (SELECT tenant_id FROM users WHERE users.id = forms.author_id);
+UPDATE responses SET tenant_id = (SELECT tenant_id FROM forms WHERE forms.id = responses.form_id);
+
+CREATE INDEX forms_tenant_id ON forms(tenant_id);
+CREATE INDEX responses_tenant_id ON responses(tenant_id);
@@ -0,0 +1,8 @@
+-- Sessions live in the server's memory, so `admin purge-sessions` asks the
+-- server to end them by leaving a row here. Without a user, every session
+-- ends.
+CREATE TABLE session_purges (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
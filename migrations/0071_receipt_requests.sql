@@ -0,0 +1,12 @@
+-- Receipt links are deleted once opened, so requests are counted here to
+-- limit how often an address is mailed and how often an IP may ask.
+CREATE TABLE receipt_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    tenant_id INTEGER NOT NULL REFERENCES tenants(id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    ip TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX receipt_requests_email ON receipt_requests(tenant_id, email, created_at);
+CREATE INDEX receipt_requests_ip ON receipt_requests(ip, created_at);
@@ -0,0 +1,23 @@
+-- The places each response booked. Releasing by reading the slot id back
+-- out of the answers freed whatever slot an answer named, booked or not,
+-- and nothing for encrypted answers.
+CREATE TABLE response_slots (
+    response_id INTEGER NOT NULL REFERENCES responses(id) ON DELETE CASCADE,
+    slot_id INTEGER NOT NULL REFERENCES form_slots(id) ON DELETE CASCADE,
+    PRIMARY KEY (response_id, slot_id)
+);
+
+CREATE INDEX response_slots_slot_id ON response_slots(slot_id);
+
+INSERT OR IGNORE INTO response_slots (response_id, slot_id)
+SELECT r.id, s.id
+FROM form_slots s JOIN responses r ON r.form_id = s.form_id
+AND CAST(json_extract(r.answers, '$."' || s.field_key || '"') AS INTEGER) = s.id;
+
+DROP TRIGGER form_slots_release;
+
+-- Deleting or withdrawing a response frees the slots it booked.
+CREATE TRIGGER response_slots_release AFTER DELETE ON response_slots
+BEGIN
+    UPDATE form_slots SET booked = booked - 1 WHERE id = OLD.slot_id AND booked > 0;
+END;
@@ -0,0 +1,140 @@
+use std::net::IpAddr;
+
+use ipnet::IpNet;
+use maxminddb::{geoip2, Reader};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+
+/// The address a request came from. That is the peer's address, unless the
+/// peer is one of the `trusted_proxies`, in which case it is the address
+/// the proxy put in Rocket's `ip_header` (X-Real-IP by default).
+pub struct ClientIp(pub Option<IpAddr>);
+
+/// Proxies whose forwarded client address is believed. Anyone else could
+/// set the header to whatever they like.
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn parse(ranges: &[String]) -> Result<Self, String> {
+        parse_ranges(&ranges.join(","))
+            .map(|ranges| TrustedProxies(ranges.iter().filter_map(|range| range.parse().ok()).collect()))
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|range| range.contains(&ip))
+    }
+}
+
+pub struct GeoIp(Option<Reader<Vec<u8>>>);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FormRestriction {
+    pub form_id: i64,
+    pub blocked_ranges: String,
+    pub allowed_countries: String,
+}
+
+impl GeoIp {
+    pub fn load(path: Option<&str>) -> Result<Self, String> {
+        match path {
+            Some(path) => Reader::open_readfile(path).map(|reader| GeoIp(Some(reader))).map_err(|e| e.to_string()),
+            None => Ok(GeoIp(None)),
+        }
+    }
+
+    fn country(&self, ip: IpAddr) -> Option<String> {
+        let reader = self.0.as_ref()?;
+        let country: geoip2::Country = reader.lookup(ip).ok()?;
+        country.country?.iso_code.map(str::to_string)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let remote = request.remote().map(|remote| remote.ip());
+        let proxied = match (remote, request.rocket().state::<TrustedProxies>()) {
+            (Some(remote), Some(proxies)) if proxies.contains(remote) => request.real_ip(),
+            _ => None,
+        };
+        Outcome::Success(ClientIp(proxied.or(remote)))
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for ClientIp {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+pub fn parse_ranges(input: &str) -> Result<Vec<String>, String> {
+    input.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|range| !range.is_empty())
+        .map(|range| match range.parse::<IpNet>() {
+            Ok(net) => Ok(net.to_string()),
+            Err(_) => range.parse::<IpAddr>()
+                .map(|ip| IpNet::from(ip).to_string())
+                .map_err(|_| format!("\"{}\" is not an IP address or CIDR range", range)),
+        })
+        .collect()
+}
+
+pub fn parse_countries(input: &str) -> Result<Vec<String>, String> {
+    input.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|code| !code.is_empty())
+        .map(|code| {
+            if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+                Ok(code.to_ascii_uppercase())
+            } else {
+                Err(format!("\"{}\" is not a two-letter country code", code))
+            }
+        })
+        .collect()
+}
+
+pub async fn allowed(db: &SqlitePool, geoip: &GeoIp, form_id: i64, ip: &ClientIp) -> Result<bool, Status> {
+    let restriction = sqlx::query_as!(FormRestriction, "SELECT * FROM form_restrictions WHERE form_id = ?", form_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let Some(restriction) = restriction else {
+        return Ok(true);
+    };
+
+    let ranges: Vec<IpNet> = serde_json::from_str::<Vec<String>>(&restriction.blocked_ranges)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|range| range.parse().ok())
+        .collect();
+    let countries: Vec<String> = serde_json::from_str(&restriction.allowed_countries).unwrap_or_default();
+
+    let Some(ip) = ip.0 else {
+        return Ok(ranges.is_empty() && countries.is_empty());
+    };
+
+    if ranges.iter().any(|range| range.contains(&ip)) {
+        return Ok(false);
+    }
+
+    if countries.is_empty() {
+        return Ok(true);
+    }
+
+    if geoip.0.is_none() {
+        warn!("Form {} restricts countries but no geoip_database is configured", form_id);
+        return Ok(true);
+    }
+
+    Ok(geoip.country(ip).is_some_and(|country| countries.contains(&country)))
+}
@@ -0,0 +1,118 @@
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+
+/// The `[accounts]` configuration table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AccountConfig {
+    /// How long a deleted account's forms and responses are kept before
+    /// they are removed for good, so a mistaken deletion can be undone by
+    /// hand.
+    pub deletion_grace_days: i64,
+}
+
+impl Default for AccountConfig {
+    fn default() -> Self {
+        AccountConfig { deletion_grace_days: 30 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Profile {
+    pub display_name: Option<String>,
+    /// A storage key; pages link to the `avatar` route instead.
+    #[serde(skip)]
+    pub avatar: Option<String>,
+    pub has_avatar: bool,
+    pub bio: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+    pub show_on_forms: bool,
+}
+
+/// The user's profile; users who never saved one get an empty one.
+pub async fn profile(db: &SqlitePool, user_id: i64) -> Result<Profile, sqlx::Error> {
+    let profile = sqlx::query_as!(Profile,
+        r#"SELECT display_name, avatar, avatar IS NOT NULL AS "has_avatar!: bool", bio, timezone, locale, show_on_forms
+         FROM user_profiles WHERE user_id = ?"#,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(profile.unwrap_or_default())
+}
+
+/// The author's profile, for their public forms to show, if they opted in.
+pub async fn public_profile(db: &SqlitePool, user_id: i64) -> Result<Option<Profile>, sqlx::Error> {
+    Ok(Some(profile(db, user_id).await?).filter(|profile| profile.show_on_forms))
+}
+
+/// The parts of a deleted account that are not in the database.
+pub struct Deletion {
+    /// Forms that were published until now.
+    pub unpublished: Vec<i64>,
+    pub avatar: Option<String>,
+}
+
+/// Deactivates the account and frees its username, unpublishes its forms,
+/// drops its profile and schedules its data for removal.
+pub async fn schedule_deletion(db: &SqlitePool, user_id: i64) -> Result<Deletion, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query!(
+        "UPDATE users SET active = false, username = 'deleted-' || id, email = NULL, oidc_subject = NULL, ldap_dn = NULL, external_id = NULL,
+         digest = NULL, deleted_at = CURRENT_TIMESTAMP
+         WHERE id = ?",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let unpublished = sqlx::query_scalar!("UPDATE forms SET published = false WHERE author_id = ? AND published = true RETURNING id", user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    let avatar = sqlx::query_scalar!("DELETE FROM user_profiles WHERE user_id = ? RETURNING avatar", user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .flatten();
+
+    tx.commit().await?;
+    Ok(Deletion { unpublished, avatar })
+}
+
+/// Removes accounts whose grace period is over, with their forms and
+/// everything hanging off them.
+pub async fn purge_deleted(db: &SqlitePool, config: &AccountConfig) -> Result<(), sqlx::Error> {
+    let cutoff = format!("-{} days", config.deletion_grace_days);
+    let due = sqlx::query_scalar!(
+        "SELECT id FROM users WHERE deleted_at IS NOT NULL AND deleted_at < datetime('now', ?)",
+        cutoff
+    )
+    .fetch_all(db)
+    .await?;
+
+    for user_id in due {
+        let mut tx = db.begin().await?;
+        sqlx::query!("DELETE FROM response_comments WHERE author_id = ?", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("UPDATE publish_requests SET reviewer_id = NULL WHERE reviewer_id = ?", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM publish_requests WHERE requested_by = ?", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM forms WHERE author_id = ?", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM users WHERE id = ?", user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        info!("Purged deleted account {}", user_id);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,415 @@
+use std::collections::{BTreeSet, HashMap};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::models::{EntryFilter, PageEvent};
+use crate::schema::FieldDef;
+
+const NO_ANSWER: &str = "(no answer)";
+
+const DIRECT: &str = "(direct)";
+
+#[derive(Debug, Serialize)]
+pub struct CrossTabRow {
+    pub value: String,
+    pub counts: Vec<i64>,
+    pub total: i64,
+    /// Each count as a percentage of `total`.
+    pub percentages: Vec<f64>,
+}
+
+/// A contingency table of two choice fields over a form's non-test
+/// responses. Categories follow the fields' option order, then any other
+/// values answered, then blanks.
+#[derive(Debug, Serialize)]
+pub struct CrossTab {
+    pub row_field: String,
+    pub column_field: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<CrossTabRow>,
+    pub column_totals: Vec<i64>,
+    pub total: i64,
+}
+
+/// How many visitors reached a page of a form and how many completed it.
+/// The rest dropped out there.
+#[derive(Debug, Serialize)]
+pub struct FunnelStep {
+    pub page: i64,
+    pub reached: i64,
+    pub completed: i64,
+    pub dropped: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Conversion {
+    pub form_id: i64,
+    pub views: i64,
+    pub submissions: i64,
+    /// Submissions per view as a percentage, unset until the form has views.
+    pub rate: Option<f64>,
+}
+
+/// The dashboard's totals over an author's forms. Weeks are the last seven
+/// days, today included.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub forms: i64,
+    pub published: i64,
+    pub responses_this_week: i64,
+    /// The date the week starts on, for the responses list's `since` filter.
+    pub week_start: String,
+    /// The form with the most non-test submissions, unset until one has any.
+    pub top_form: Option<TopForm>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopForm {
+    pub id: i64,
+    pub title: String,
+    pub submissions: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SourceCount {
+    /// `utm_source`, else the referring host, else "(direct)".
+    pub source: String,
+    pub medium: Option<String>,
+    pub campaign: Option<String>,
+    pub submissions: i64,
+}
+
+/// Accepted responses to an RSVP form giving one answer to its choice field.
+#[derive(Debug, Serialize)]
+pub struct Headcount {
+    pub choice: String,
+    pub responses: i64,
+}
+
+/// Money taken by a form, in the currency's minor unit.
+#[derive(Debug, Serialize)]
+pub struct PaymentTotals {
+    pub paid: i64,
+    pub collected: i64,
+    pub refunded: i64,
+    pub net: i64,
+}
+
+/// Encrypted answers are opaque to SQL, so those fields cannot be tabulated.
+pub fn tabulable(field: &FieldDef) -> bool {
+    field.field_type().aggregate(field) && !field.encrypted
+}
+
+fn json_path(key: &str) -> String {
+    format!("$.\"{}\"", key.replace('"', "\\\""))
+}
+
+fn categories<'a>(field: &FieldDef, answered: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut categories = field.options.clone();
+    let others: BTreeSet<&String> = answered
+        .filter(|value| value.as_str() != NO_ANSWER && !categories.contains(value))
+        .collect();
+    categories.extend(others.into_iter().cloned());
+    categories.push(NO_ANSWER.to_string());
+    categories
+}
+
+pub async fn cross_tab(db: &SqlitePool, form_id: i64, row: &FieldDef, column: &FieldDef, entries: EntryFilter) -> Result<CrossTab, sqlx::Error> {
+    let row_path = json_path(&row.key);
+    let column_path = json_path(&column.key);
+    let manual = entries.manual();
+    let cells = sqlx::query!(
+        r#"SELECT COALESCE(NULLIF(json_extract(answers, ?1), ''), ?4) AS "row_value!: String",
+                  COALESCE(NULLIF(json_extract(answers, ?2), ''), ?4) AS "column_value!: String",
+                  COUNT(*) AS "count!: i64"
+           FROM responses WHERE form_id = ?3 AND is_test = false AND spam = false AND (?5 IS NULL OR (entered_by IS NOT NULL) = ?5)
+           GROUP BY 1, 2"#,
+        row_path,
+        column_path,
+        form_id,
+        NO_ANSWER,
+        manual
+    )
+    .fetch_all(db)
+    .await?;
+
+    let rows = categories(row, cells.iter().map(|cell| &cell.row_value));
+    let columns = categories(column, cells.iter().map(|cell| &cell.column_value));
+    let counts: HashMap<(&str, &str), i64> = cells.iter()
+        .map(|cell| ((cell.row_value.as_str(), cell.column_value.as_str()), cell.count))
+        .collect();
+
+    let rows: Vec<CrossTabRow> = rows.into_iter()
+        .map(|value| {
+            let counts: Vec<i64> = columns.iter()
+                .map(|column| counts.get(&(value.as_str(), column.as_str())).copied().unwrap_or_default())
+                .collect();
+            let total = counts.iter().sum();
+            let percentages = counts.iter()
+                .map(|&count| if total == 0 { 0.0 } else { count as f64 * 100.0 / total as f64 })
+                .collect();
+            CrossTabRow { value, counts, total, percentages }
+        })
+        .collect();
+
+    let column_totals = (0..columns.len()).map(|i| rows.iter().map(|row| row.counts[i]).sum()).collect();
+    let total = rows.iter().map(|row| row.total).sum();
+
+    Ok(CrossTab {
+        row_field: row.key.clone(),
+        column_field: column.key.clone(),
+        columns,
+        rows,
+        column_totals,
+        total,
+    })
+}
+
+/// Each visitor counts once per page and event, however often they reload.
+pub async fn record_page_event(db: &SqlitePool, form_id: i64, visitor: &str, page: i64, event: PageEvent) -> Result<(), sqlx::Error> {
+    let event = event.as_str();
+    sqlx::query!(
+        "INSERT OR IGNORE INTO form_page_events (form_id, visitor, page, event) VALUES (?, ?, ?, ?)",
+        form_id,
+        visitor,
+        page,
+        event
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn funnel(db: &SqlitePool, form_id: i64) -> Result<Vec<FunnelStep>, sqlx::Error> {
+    let pages = sqlx::query!(
+        r#"SELECT page AS "page!: i64",
+                  COUNT(DISTINCT CASE WHEN event = 'view' THEN visitor END) AS "reached!: i64",
+                  COUNT(DISTINCT CASE WHEN event = 'complete' THEN visitor END) AS "completed!: i64"
+           FROM form_page_events WHERE form_id = ? GROUP BY page ORDER BY page"#,
+        form_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(pages.into_iter()
+        .map(|page| FunnelStep {
+            page: page.page,
+            reached: page.reached,
+            completed: page.completed,
+            dropped: (page.reached - page.completed).max(0),
+        })
+        .collect())
+}
+
+/// Views count each visitor at most once a day.
+pub async fn record_view(db: &SqlitePool, form_id: i64, visitor: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT OR IGNORE INTO form_views (form_id, visitor, day) VALUES (?, ?, date('now'))",
+        form_id,
+        visitor
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Views and non-test submissions for an author's forms, or for just
+/// `form_id` when it is given. Responses keyed in by hand had no view, so
+/// they are left out.
+pub async fn conversions(db: &SqlitePool, author_id: i64, form_id: Option<i64>) -> Result<Vec<Conversion>, sqlx::Error> {
+    let forms = sqlx::query!(
+        r#"SELECT f.id AS "form_id!: i64",
+                  (SELECT COUNT(*) FROM form_views v WHERE v.form_id = f.id) AS "views!: i64",
+                  (SELECT COUNT(*) FROM responses r WHERE r.form_id = f.id AND r.is_test = false AND r.spam = false AND r.entered_by IS NULL) AS "submissions!: i64"
+           FROM forms f WHERE f.author_id = ?1 AND (?2 IS NULL OR f.id = ?2) ORDER BY f.id"#,
+        author_id,
+        form_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(forms.into_iter()
+        .map(|form| Conversion {
+            form_id: form.form_id,
+            views: form.views,
+            submissions: form.submissions,
+            rate: (form.views > 0).then(|| form.submissions as f64 * 100.0 / form.views as f64),
+        })
+        .collect())
+}
+
+/// Dashboard totals for an author's forms. Test responses, spam and
+/// responses whose payment has not gone through are left out.
+pub async fn summary(db: &SqlitePool, author_id: i64) -> Result<Summary, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"WITH counts AS (
+               SELECT f.id, f.title, f.published,
+                      (SELECT COUNT(*) FROM responses r WHERE r.form_id = f.id AND r.is_test = false AND r.spam = false
+                       AND (r.payment_status IS NULL OR r.payment_status = 'paid')) AS submissions,
+                      (SELECT COUNT(*) FROM responses r WHERE r.form_id = f.id AND r.is_test = false AND r.spam = false
+                       AND (r.payment_status IS NULL OR r.payment_status = 'paid')
+                       AND r.created_at >= datetime('now', 'start of day', '-6 days')) AS this_week
+               FROM forms f WHERE f.author_id = ?
+           )
+           SELECT COUNT(c.id) AS "forms!: i64",
+                  COALESCE(SUM(c.published), 0) AS "published!: i64",
+                  COALESCE(SUM(c.this_week), 0) AS "responses_this_week!: i64",
+                  date('now', '-6 days') AS "week_start!: String",
+                  t.id AS "top_form_id?: i64",
+                  t.title AS "top_form_title?: String",
+                  t.submissions AS "top_form_submissions?: i64"
+           FROM counts c
+           LEFT JOIN (SELECT id, title, submissions FROM counts WHERE submissions > 0 ORDER BY submissions DESC, id LIMIT 1) t ON true"#,
+        author_id
+    )
+    .fetch_one(db)
+    .await?;
+
+    let top_form = match (row.top_form_id, row.top_form_title, row.top_form_submissions) {
+        (Some(id), Some(title), Some(submissions)) => Some(TopForm { id, title, submissions }),
+        _ => None,
+    };
+    Ok(Summary {
+        forms: row.forms,
+        published: row.published,
+        responses_this_week: row.responses_this_week,
+        week_start: row.week_start,
+        top_form,
+    })
+}
+
+fn referrer_host(referrer: &str) -> Option<String> {
+    let uri = rocket::http::uri::Absolute::parse(referrer).ok()?;
+    uri.authority().map(|authority| authority.host().to_string())
+}
+
+/// Non-test submissions grouped by where respondents came from, busiest
+/// source first.
+pub async fn sources(db: &SqlitePool, form_id: i64, entries: EntryFilter) -> Result<Vec<SourceCount>, sqlx::Error> {
+    let manual = entries.manual();
+    let groups = sqlx::query!(
+        r#"SELECT utm_source, utm_medium, utm_campaign, referrer, COUNT(*) AS "count!: i64"
+           FROM responses WHERE form_id = ?1 AND is_test = false AND spam = false AND (?2 IS NULL OR (entered_by IS NOT NULL) = ?2)
+           GROUP BY utm_source, utm_medium, utm_campaign, referrer"#,
+        form_id,
+        manual
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut counts: HashMap<(String, Option<String>, Option<String>), i64> = HashMap::new();
+    for group in groups {
+        let source = group.utm_source
+            .or_else(|| group.referrer.as_deref().and_then(referrer_host))
+            .unwrap_or_else(|| DIRECT.to_string());
+        *counts.entry((source, group.utm_medium, group.utm_campaign)).or_default() += group.count;
+    }
+
+    let mut sources: Vec<SourceCount> = counts.into_iter()
+        .map(|((source, medium, campaign), submissions)| SourceCount { source, medium, campaign, submissions })
+        .collect();
+    sources.sort_by(|a, b| b.submissions.cmp(&a.submissions).then_with(|| a.source.cmp(&b.source)));
+    Ok(sources)
+}
+
+/// Non-test submissions per day over the last `days` days, oldest first and
+/// with empty days included, across an author's forms or just `form_id`.
+pub async fn daily_submissions(
+    db: &SqlitePool,
+    author_id: i64,
+    form_id: Option<i64>,
+    days: i64,
+    entries: EntryFilter
+) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let start = format!("-{} days", days.max(1) - 1);
+    let manual = entries.manual();
+    let days = sqlx::query!(
+        r#"WITH RECURSIVE days(day) AS (
+               SELECT date('now', ?1) UNION ALL SELECT date(day, '+1 day') FROM days WHERE day < date('now')
+           )
+           SELECT days.day AS "day!: String", COUNT(r.id) AS "count!: i64"
+           FROM days
+           LEFT JOIN forms f ON f.author_id = ?2 AND (?3 IS NULL OR f.id = ?3)
+           LEFT JOIN responses r ON r.form_id = f.id AND r.is_test = false AND r.spam = false AND date(r.created_at) = days.day
+               AND (?4 IS NULL OR (r.entered_by IS NOT NULL) = ?4)
+           GROUP BY days.day ORDER BY days.day"#,
+        start,
+        author_id,
+        form_id,
+        manual
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(days.into_iter().map(|day| (day.day, day.count)).collect())
+}
+
+/// The most common non-blank answers to `field` since `since`, an SQLite
+/// datetime modifier such as `-7 days`.
+pub async fn top_answers(db: &SqlitePool, form_id: i64, field: &FieldDef, since: &str, limit: i64) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let path = json_path(&field.key);
+    let answers = sqlx::query!(
+        r#"SELECT json_extract(answers, ?1) AS "answer!: String", COUNT(*) AS "count!: i64"
+           FROM responses
+           WHERE form_id = ?2 AND is_test = false AND spam = false AND created_at > datetime('now', ?3)
+           AND COALESCE(json_extract(answers, ?1), '') != ''
+           GROUP BY 1 ORDER BY 2 DESC, 1 LIMIT ?4"#,
+        path,
+        form_id,
+        since,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(answers.into_iter().map(|answer| (answer.answer, answer.count)).collect())
+}
+
+/// Non-test, non-waitlisted responses per answer to `field`, in the field's
+/// option order with other answers and blanks after.
+pub async fn headcount(db: &SqlitePool, form_id: i64, field: &FieldDef) -> Result<Vec<Headcount>, sqlx::Error> {
+    let path = json_path(&field.key);
+    let counts = sqlx::query!(
+        r#"SELECT COALESCE(NULLIF(json_extract(answers, ?1), ''), ?3) AS "choice!: String", COUNT(*) AS "count!: i64"
+           FROM responses WHERE form_id = ?2 AND is_test = false AND spam = false AND waitlisted = false
+           GROUP BY 1"#,
+        path,
+        form_id,
+        NO_ANSWER
+    )
+    .fetch_all(db)
+    .await?;
+
+    let by_choice: HashMap<&str, i64> = counts.iter().map(|count| (count.choice.as_str(), count.count)).collect();
+    Ok(categories(field, counts.iter().map(|count| &count.choice))
+        .into_iter()
+        .map(|choice| Headcount {
+            responses: by_choice.get(choice.as_str()).copied().unwrap_or_default(),
+            choice,
+        })
+        .collect())
+}
+
+/// Fully refunded responses still count towards `collected`, so that
+/// `collected - refunded` is what the form kept.
+pub async fn payment_totals(db: &SqlitePool, form_id: i64) -> Result<PaymentTotals, sqlx::Error> {
+    let totals = sqlx::query!(
+        r#"SELECT COUNT(*) AS "paid!: i64",
+                  COALESCE(SUM(payment_amount), 0) AS "collected!: i64",
+                  COALESCE(SUM(payment_refunded), 0) AS "refunded!: i64"
+           FROM responses WHERE form_id = ? AND is_test = false AND spam = false AND payment_status IN ('paid', 'refunded')"#,
+        form_id
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(PaymentTotals {
+        paid: totals.paid,
+        collected: totals.collected,
+        refunded: totals.refunded,
+        net: totals.collected - totals.refunded,
+    })
+}
@@ -0,0 +1,995 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, status, Responder, Response};
+use rocket::serde::json::{Json, Value, json};
+use rocket::State;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::{Object, SecurityRequirement, SecurityScheme, SecuritySchemeData};
+use rocket_okapi::okapi::openapi3::Responses;
+use rocket_okapi::openapi;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use rocket_okapi::response::OpenApiResponderInner;
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use crate::{AppConfig, crypto, form_templates, revisions};
+use crate::access::{self, ClientIp, GeoIp};
+use crate::cache::FormCache;
+use crate::db::published_form;
+use crate::events::EventBus;
+use crate::flags::FlagCache;
+use crate::guards::IdempotencyKey;
+use crate::models::{ClosedReason, FormResponse};
+use crate::pipeline::{Pipeline, Rejection, Services, Source, Submission};
+use crate::schema::{self, FieldError};
+use crate::service_auth::{self, Jwks};
+use crate::tenant::Tenant;
+use crate::write_buffer::WriteBuffer;
+
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Forms offered by the command palette at once; typing narrows them down.
+const QUICK_ACTION_FORMS: i64 = 8;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+pub struct ApiUser(pub i64, Grant);
+
+struct Grant {
+    scopes: Vec<Scope>,
+    form_ids: Option<Vec<i64>>,
+}
+
+pub struct Preconditions {
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+}
+
+pub struct Tagged<T>(String, Option<T>);
+
+pub enum SubmitError {
+    Invalid(Vec<FieldError>),
+    Refused(String),
+    Status(Status),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub token_hash: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub scopes: String,
+    pub form_ids: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServiceAccount {
+    pub id: i64,
+    pub user_id: i64,
+    pub tenant_id: i64,
+    pub name: String,
+    pub subject: String,
+    pub scopes: String,
+    pub form_ids: Option<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, FromFormField)]
+pub enum Scope {
+    #[serde(rename = "read:forms")]
+    #[field(value = "read:forms")]
+    ReadForms,
+    #[serde(rename = "write:forms")]
+    #[field(value = "write:forms")]
+    WriteForms,
+    #[serde(rename = "read:responses")]
+    #[field(value = "read:responses")]
+    ReadResponses,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RestHook {
+    pub id: i64,
+    pub user_id: i64,
+    pub form_id: Option<i64>,
+    pub event: String,
+    pub target_url: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, JsonSchema, FromFormField)]
+pub enum HookEvent {
+    #[serde(rename = "response.submitted")]
+    #[field(value = "response.submitted")]
+    ResponseSubmitted,
+    #[serde(rename = "form.published")]
+    #[field(value = "form.published")]
+    FormPublished,
+}
+
+impl HookEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookEvent::ResponseSubmitted => "response.submitted",
+            HookEvent::FormPublished => "form.published",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ApiForm {
+    pub id: i64,
+    pub title: String,
+    pub fields: String,
+    pub published: bool,
+    pub live_results: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub version: i64,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FormUpdate {
+    pub title: String,
+    pub fields: String,
+    pub live_results: bool,
+    /// Where the answers of fields the update removes or retypes go: old
+    /// field key to new, or to an empty key to archive them. Needed only
+    /// when such fields have answers.
+    #[serde(default)]
+    pub mapping: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuickActionKind {
+    Open,
+    Edit,
+    ViewResponses,
+    NewForm,
+}
+
+/// One entry of the command palette, pointing at a page of the web UI.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct QuickAction {
+    pub kind: QuickActionKind,
+    pub label: String,
+    pub form_id: Option<i64>,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ApiResponse {
+    pub id: i64,
+    pub form_id: i64,
+    pub answers: BTreeMap<String, String>,
+    pub status: String,
+    pub assigned_to: Option<i64>,
+    pub respondent_email: Option<String>,
+    pub duplicate_of: Option<i64>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub version: i64,
+}
+
+impl From<FormResponse> for ApiResponse {
+    fn from(response: FormResponse) -> Self {
+        ApiResponse {
+            id: response.id,
+            form_id: response.form_id,
+            answers: crypto::decrypt_answers(&response.answers).into_iter().collect(),
+            status: response.status,
+            assigned_to: response.assigned_to,
+            respondent_email: response.respondent_email,
+            duplicate_of: response.duplicate_of,
+            created_at: response.created_at,
+            updated_at: response.updated_at,
+            version: response.version,
+        }
+    }
+}
+
+#[derive(Debug, FromForm, JsonSchema)]
+pub struct PageParams {
+    pub after: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl PageParams {
+    fn after(&self) -> i64 {
+        self.after.unwrap_or(0)
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+
+    fn fetch(&self) -> i64 {
+        self.limit() + 1
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<i64>,
+}
+
+impl<T> Page<T> {
+    fn new(mut data: Vec<T>, params: &PageParams, cursor: impl Fn(&T) -> i64) -> Self {
+        let limit = params.limit() as usize;
+        let next_cursor = if data.len() > limit {
+            data.truncate(limit);
+            data.last().map(cursor)
+        } else {
+            None
+        };
+
+        Page { data, next_cursor }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SubmissionReceipt {
+    pub id: i64,
+    pub form_id: i64,
+    pub created_at: String,
+    /// The form was at its cap, so the response joined the waitlist.
+    pub waitlisted: bool,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+    /// Why the submission as a whole was refused, when the form's script
+    /// says so.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NewHook {
+    pub event: HookEvent,
+    pub target_url: String,
+    pub form_id: Option<i64>,
+}
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn etag(kind: &str, id: i64, version: i64) -> String {
+    format!("W/\"{}-{}-{}\"", kind, id, version)
+}
+
+fn etag_matches(header: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    header.split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+impl Preconditions {
+    fn not_modified(&self, etag: &str) -> bool {
+        self.if_none_match.as_deref().is_some_and(|header| etag_matches(header, etag))
+    }
+
+    fn failed(&self, etag: &str) -> bool {
+        self.if_match.as_deref().is_some_and(|header| !etag_matches(header, etag))
+    }
+
+    fn respond<T>(&self, etag: String, body: T) -> Tagged<T> {
+        if self.not_modified(&etag) {
+            Tagged(etag, None)
+        } else {
+            Tagged(etag, Some(body))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Preconditions {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Preconditions {
+            if_match: request.headers().get_one("If-Match").map(str::to_string),
+            if_none_match: request.headers().get_one("If-None-Match").map(str::to_string),
+        })
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for Preconditions {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Tagged<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let Tagged(etag, body) = self;
+        let mut response = match body {
+            Some(body) => Json(body).respond_to(request)?,
+            None => Response::build().status(Status::NotModified).finalize(),
+        };
+        response.set_raw_header("ETag", etag);
+        Ok(response)
+    }
+}
+
+impl<T: Serialize + JsonSchema> OpenApiResponderInner for Tagged<T> {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Json::<T>::responses(gen)?;
+        rocket_okapi::util::ensure_status_code_exists(&mut responses, 304);
+        Ok(responses)
+    }
+}
+
+impl ApiUser {
+    pub fn require(&self, scope: Scope) -> Result<(), Status> {
+        if self.1.scopes.contains(&scope) { Ok(()) } else { Err(Status::Forbidden) }
+    }
+
+    pub fn require_form(&self, scope: Scope, form_id: i64) -> Result<(), Status> {
+        self.require(scope)?;
+        match &self.1.form_ids {
+            Some(form_ids) if !form_ids.contains(&form_id) => Err(Status::Forbidden),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn require_all_forms(&self, scope: Scope) -> Result<(), Status> {
+        self.require(scope)?;
+        if self.1.form_ids.is_some() { Err(Status::Forbidden) } else { Ok(()) }
+    }
+
+    fn form_ids(&self) -> Option<String> {
+        self.1.form_ids.as_ref().and_then(|form_ids| serde_json::to_string(form_ids).ok())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiUser {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(token) = request.headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+        else {
+            return Outcome::Error((Status::Unauthorized, ()));
+        };
+
+        let tenant = match request.guard::<Tenant>().await {
+            Outcome::Success(tenant) => tenant,
+            Outcome::Error((status, _)) => return Outcome::Error((status, ())),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+
+        let db = request.rocket().state::<SqlitePool>().unwrap();
+        let config = &request.rocket().state::<AppConfig>().unwrap().service_jwt;
+        if config.jwks_url.is_some() && service_auth::looks_like_jwt(token.trim()) {
+            let jwks = request.rocket().state::<Jwks>().unwrap();
+            let client = request.rocket().state::<reqwest::Client>().unwrap();
+            let subject = match service_auth::subject(config, jwks, client, token.trim()).await {
+                Ok(subject) => subject,
+                Err(e) => {
+                    info!("Rejected service JWT: {}", e);
+                    return Outcome::Error((Status::Unauthorized, ()));
+                }
+            };
+
+            let account = sqlx::query!(
+                "UPDATE service_accounts SET last_used_at = CURRENT_TIMESTAMP
+                 WHERE subject = ?1 AND tenant_id = ?2 AND user_id IN (SELECT id FROM users WHERE active = true AND tenant_id = ?2)
+                 RETURNING user_id, scopes, form_ids",
+                subject,
+                tenant.id
+            )
+            .fetch_optional(db)
+            .await;
+
+            return match account {
+                Ok(Some(account)) => Outcome::Success(ApiUser(account.user_id, Grant {
+                    scopes: serde_json::from_str(&account.scopes).unwrap_or_default(),
+                    form_ids: account.form_ids.and_then(|form_ids| serde_json::from_str(&form_ids).ok()),
+                })),
+                Ok(None) => Outcome::Error((Status::Unauthorized, ())),
+                Err(_) => Outcome::Error((Status::InternalServerError, ())),
+            };
+        }
+
+        let token_hash = hash_token(token.trim());
+        let token = sqlx::query!(
+            "UPDATE api_tokens SET last_used_at = CURRENT_TIMESTAMP
+             WHERE token_hash = ? AND user_id IN (SELECT id FROM users WHERE active = true AND tenant_id = ?)
+             RETURNING user_id, scopes, form_ids",
+            token_hash,
+            tenant.id
+        )
+        .fetch_optional(db)
+        .await;
+
+        match token {
+            Ok(Some(token)) => Outcome::Success(ApiUser(token.user_id, Grant {
+                scopes: serde_json::from_str(&token.scopes).unwrap_or_default(),
+                form_ids: token.form_ids.and_then(|form_ids| serde_json::from_str(&form_ids).ok()),
+            })),
+            Ok(None) => Outcome::Error((Status::Unauthorized, ())),
+            Err(_) => Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for ApiUser {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        let scheme = SecurityScheme {
+            description: Some("Personal API token created at /settings/tokens, or a JWT from the configured issuer".to_string()),
+            data: SecuritySchemeData::Http {
+                scheme: "bearer".to_string(),
+                bearer_format: None,
+            },
+            extensions: Object::default(),
+        };
+        let mut requirement = SecurityRequirement::new();
+        requirement.insert("ApiToken".to_string(), Vec::new());
+
+        Ok(RequestHeaderInput::Security("ApiToken".to_string(), scheme, requirement))
+    }
+}
+
+impl From<Status> for SubmitError {
+    fn from(status: Status) -> Self {
+        SubmitError::Status(status)
+    }
+}
+
+impl From<Rejection> for SubmitError {
+    fn from(rejection: Rejection) -> Self {
+        match rejection {
+            Rejection::Invalid(errors) => SubmitError::Invalid(errors),
+            Rejection::Closed(ClosedReason::Full) => SubmitError::Status(Status::TooManyRequests),
+            Rejection::Duplicate => SubmitError::Status(Status::Conflict),
+            Rejection::SlotTaken => SubmitError::Status(Status::Gone),
+            // Including payments, since there is no one to send to Checkout.
+            Rejection::Closed(_) | Rejection::Challenge | Rejection::Bot | Rejection::Unavailable => SubmitError::Status(Status::Forbidden),
+            Rejection::Refused(message) => SubmitError::Refused(message),
+            Rejection::Error(status) => SubmitError::Status(status),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for SubmitError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        match self {
+            SubmitError::Invalid(errors) => {
+                status::Custom(Status::UnprocessableEntity, Json(ValidationErrors { errors, message: None })).respond_to(request)
+            }
+            SubmitError::Refused(message) => {
+                let errors = ValidationErrors { errors: Vec::new(), message: Some(message) };
+                status::Custom(Status::UnprocessableEntity, Json(errors)).respond_to(request)
+            }
+            SubmitError::Status(status) => Err(status),
+        }
+    }
+}
+
+impl OpenApiResponderInner for SubmitError {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Responses::default();
+        let schema = gen.json_schema::<ValidationErrors>();
+        rocket_okapi::util::add_schema_response(&mut responses, 422, "application/json", schema)?;
+        rocket_okapi::util::ensure_status_code_exists(&mut responses, 403);
+        rocket_okapi::util::ensure_status_code_exists(&mut responses, 404);
+        rocket_okapi::util::ensure_status_code_exists(&mut responses, 409);
+        rocket_okapi::util::ensure_status_code_exists(&mut responses, 429);
+        Ok(responses)
+    }
+}
+
+#[catch(default)]
+pub fn api_error(status: Status, _request: &Request<'_>) -> Json<Value> {
+    Json(json!({ "error": status.reason_lossy(), "status": status.code }))
+}
+
+#[openapi(tag = "Forms")]
+#[get("/forms?<published>&<updated_since>&<page..>")]
+pub async fn list_forms(
+    db: &State<SqlitePool>,
+    user: ApiUser,
+    published: Option<bool>,
+    updated_since: Option<String>,
+    page: PageParams
+) -> Result<Json<Page<ApiForm>>, Status> {
+    user.require(Scope::ReadForms)?;
+
+    let form_ids = user.form_ids();
+    let after = page.after();
+    let fetch = page.fetch();
+    let forms = sqlx::query_as!(ApiForm,
+        "SELECT id, title, fields, published, live_results, created_at, updated_at, version FROM forms
+         WHERE author_id = ?1 AND (?2 IS NULL OR published = ?2) AND (?3 IS NULL OR updated_at >= datetime(?3))
+         AND (?6 IS NULL OR id IN (SELECT value FROM json_each(?6)))
+         AND id > ?4 ORDER BY id LIMIT ?5",
+        user.0,
+        published,
+        updated_since,
+        after,
+        fetch,
+        form_ids
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Json(Page::new(forms, &page, |form| form.id)))
+}
+
+#[openapi(tag = "Forms")]
+#[get("/forms/<id>")]
+pub async fn get_form(
+    db: &State<SqlitePool>,
+    user: ApiUser,
+    preconditions: Preconditions,
+    id: i64
+) -> Result<Tagged<ApiForm>, Status> {
+    user.require_form(Scope::ReadForms, id)?;
+
+    let form = sqlx::query_as!(ApiForm,
+        "SELECT id, title, fields, published, live_results, created_at, updated_at, version FROM forms WHERE id = ? AND author_id = ?",
+        id,
+        user.0
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .ok_or(Status::NotFound)?;
+
+    Ok(preconditions.respond(etag("form", form.id, form.version), form))
+}
+
+/// Refused with 409 Conflict when the new fields remove or change the type
+/// of fields that already have answers and no `mapping` says where those
+/// answers should go. Removing or retyping a field the form's template
+/// locks is refused with 422, listing those fields.
+#[openapi(tag = "Forms")]
+#[put("/forms/<id>", data = "<update>")]
+pub async fn update_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    user: ApiUser,
+    preconditions: Preconditions,
+    id: i64,
+    update: Json<FormUpdate>
+) -> Result<Tagged<ApiForm>, SubmitError> {
+    user.require_form(Scope::WriteForms, id)?;
+
+    let current = sqlx::query!("SELECT version, fields FROM forms WHERE id = ? AND author_id = ?", id, user.0)
+        .fetch_optional(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+    let version = current.version;
+
+    if preconditions.failed(&etag("form", id, version)) {
+        return Err(Status::PreconditionFailed.into());
+    }
+
+    let broken = form_templates::broken_locks(db, id, &update.fields).await.map_err(|_| Status::InternalServerError)?;
+    if !broken.is_empty() {
+        return Err(SubmitError::Invalid(broken.into_iter()
+            .map(|field| FieldError { field, message: "is locked by the form's template".to_string() })
+            .collect()));
+    }
+
+    let fields = schema::assign_keys(&update.fields);
+    let changes = revisions::changes(db, id, &current.fields, &fields).await.map_err(|_| Status::InternalServerError)?;
+    if !changes.is_empty() && update.mapping.is_none() {
+        return Err(Status::Conflict.into());
+    }
+
+    let mut tx = db.begin().await.map_err(|_| Status::InternalServerError)?;
+    if let Some(mapping) = update.mapping.as_ref().filter(|_| !changes.is_empty()) {
+        revisions::migrate(&mut *tx, id, user.0, &current.fields, &fields, &changes, mapping)
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+    }
+    let updated = sqlx::query!(
+        "UPDATE forms SET title = ?, fields = ?, live_results = ? WHERE id = ? AND author_id = ? AND version = ?",
+        update.title,
+        fields,
+        update.live_results,
+        id,
+        user.0,
+        version
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(Status::PreconditionFailed.into());
+    }
+    tx.commit().await.map_err(|_| Status::InternalServerError)?;
+    cache.invalidate(id);
+    if changes.is_empty() && fields != current.fields {
+        revisions::record_edit(db, id, user.0, &current.fields).await.map_err(|_| Status::InternalServerError)?;
+    }
+
+    let form = sqlx::query_as!(ApiForm,
+        "SELECT id, title, fields, published, live_results, created_at, updated_at, version FROM forms WHERE id = ?",
+        id
+    )
+    .fetch_one(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Tagged(etag("form", form.id, form.version), Some(form)))
+}
+
+/// Forms whose title contains `q`, most recently edited first, each with
+/// what the token may do to it, plus "New form" when it matches too. Only
+/// actions the token's scopes and forms allow are offered.
+#[openapi(tag = "Forms")]
+#[get("/quick-actions?<q>")]
+pub async fn quick_actions(db: &State<SqlitePool>, user: ApiUser, q: Option<String>) -> Result<Json<Vec<QuickAction>>, Status> {
+    user.require(Scope::ReadForms)?;
+
+    let q = q.unwrap_or_default().trim().to_lowercase();
+    let form_ids = user.form_ids();
+    let forms = sqlx::query!(
+        "SELECT id, title, published FROM forms
+         WHERE author_id = ?1 AND instr(lower(title), ?2) > 0
+         AND (?3 IS NULL OR id IN (SELECT value FROM json_each(?3)))
+         ORDER BY updated_at DESC, id DESC LIMIT ?4",
+        user.0,
+        q,
+        form_ids,
+        QUICK_ACTION_FORMS
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let mut actions = Vec::new();
+    if user.require_all_forms(Scope::WriteForms).is_ok() && "new form".contains(q.as_str()) {
+        actions.push(QuickAction { kind: QuickActionKind::NewForm, label: "New form".to_string(), form_id: None, url: "/form/new".to_string() });
+    }
+    for form in forms {
+        let mut action = |kind, label: &str, url: String| {
+            actions.push(QuickAction { kind, label: format!("{}: {}", label, form.title), form_id: Some(form.id), url });
+        };
+        if form.published {
+            action(QuickActionKind::Open, "Open", format!("/f/{}", form.id));
+        }
+        if user.require_form(Scope::WriteForms, form.id).is_ok() {
+            action(QuickActionKind::Edit, "Edit", format!("/form/{}", form.id));
+        }
+        if user.require_form(Scope::ReadResponses, form.id).is_ok() {
+            action(QuickActionKind::ViewResponses, "View responses", format!("/form/{}/responses", form.id));
+        }
+    }
+
+    Ok(Json(actions))
+}
+
+#[openapi(tag = "Responses")]
+#[get("/forms/<id>/responses?<since>&<updated_since>&<page..>")]
+pub async fn list_responses(
+    db: &State<SqlitePool>,
+    user: ApiUser,
+    id: i64,
+    since: Option<String>,
+    updated_since: Option<String>,
+    page: PageParams
+) -> Result<Json<Page<ApiResponse>>, Status> {
+    user.require_form(Scope::ReadResponses, id)?;
+
+    sqlx::query_scalar!("SELECT id FROM forms WHERE id = ? AND author_id = ?", id, user.0)
+        .fetch_optional(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+
+    let after = page.after();
+    let fetch = page.fetch();
+    let responses = sqlx::query_as!(FormResponse,
+        "SELECT * FROM responses
+         WHERE form_id = ?1 AND is_test = false AND (?2 IS NULL OR updated_at >= datetime(?2))
+         AND (?5 IS NULL OR created_at >= datetime(?5))
+         AND id > ?3 ORDER BY id LIMIT ?4",
+        id,
+        updated_since,
+        after,
+        fetch,
+        since
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let responses = responses.into_iter().map(ApiResponse::from).collect();
+    Ok(Json(Page::new(responses, &page, |response| response.id)))
+}
+
+#[openapi(tag = "Responses")]
+#[get("/forms/<id>/responses/<response_id>")]
+pub async fn get_response(
+    db: &State<SqlitePool>,
+    user: ApiUser,
+    preconditions: Preconditions,
+    id: i64,
+    response_id: i64
+) -> Result<Tagged<ApiResponse>, Status> {
+    user.require_form(Scope::ReadResponses, id)?;
+
+    let response = sqlx::query_as!(FormResponse,
+        "SELECT r.* FROM responses r JOIN forms f ON f.id = r.form_id
+         WHERE r.id = ? AND f.id = ? AND f.author_id = ?",
+        response_id,
+        id,
+        user.0
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .ok_or(Status::NotFound)?;
+
+    let etag = etag("response", response.id, response.version);
+    Ok(preconditions.respond(etag, ApiResponse::from(response)))
+}
+
+#[openapi(tag = "Submissions")]
+#[get("/forms/<id>/schema.json")]
+pub async fn form_schema(db: &State<SqlitePool>, cache: &State<FormCache>, tenant: Tenant, id: i64) -> Result<Json<Value>, Status> {
+    let form = published_form(db, cache, &tenant, id).await?.ok_or(Status::NotFound)?;
+
+    Ok(Json(schema::json_schema(&form.title, &schema::parse(&form.fields))))
+}
+
+#[openapi(tag = "Submissions")]
+#[post("/f/<id>/submit", data = "<payload>", format = "json")]
+pub async fn submit_response(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    config: &State<AppConfig>,
+    events: &State<EventBus>,
+    writes: &State<WriteBuffer>,
+    flags: &State<FlagCache>,
+    geoip: &State<GeoIp>,
+    client: &State<reqwest::Client>,
+    pipeline: &State<Pipeline>,
+    ip: ClientIp,
+    idempotency_key: IdempotencyKey,
+    tenant: Tenant,
+    id: i64,
+    payload: Json<HashMap<String, Value>>
+) -> Result<(Status, Json<SubmissionReceipt>), SubmitError> {
+    let form = published_form(db, cache, &tenant, id).await?.ok_or(Status::NotFound)?;
+    // An email code cannot be answered over the API.
+    if form.verify_email || !access::allowed(db, geoip, form.id, &ip).await? {
+        return Err(SubmitError::Status(Status::Forbidden));
+    }
+
+    let answers = schema::answers_from_json(payload.into_inner()).map_err(SubmitError::Invalid)?;
+    let services = Services { db, config, client, events, writes, flags };
+    let mut submission = Submission::new(&form, Source::Api, answers)
+        .idempotency_key(idempotency_key.0.as_deref())
+        .ip(ip.0);
+    let response = pipeline.submit(&services, &mut submission).await?;
+
+    Ok((Status::Created, Json(SubmissionReceipt {
+        id: response.id,
+        form_id: response.form_id,
+        created_at: response.created_at,
+        waitlisted: response.waitlisted,
+    })))
+}
+
+#[openapi(tag = "Hooks")]
+#[get("/hooks?<event>&<page..>")]
+pub async fn list_hooks(
+    db: &State<SqlitePool>,
+    user: ApiUser,
+    event: Option<HookEvent>,
+    page: PageParams
+) -> Result<Json<Page<RestHook>>, Status> {
+    user.require(Scope::ReadResponses)?;
+
+    let event = event.map(HookEvent::as_str);
+    let after = page.after();
+    let fetch = page.fetch();
+    let hooks = sqlx::query_as!(RestHook,
+        "SELECT * FROM rest_hooks WHERE user_id = ?1 AND (?2 IS NULL OR event = ?2) AND id > ?3 ORDER BY id LIMIT ?4",
+        user.0,
+        event,
+        after,
+        fetch
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Json(Page::new(hooks, &page, |hook| hook.id)))
+}
+
+#[openapi(tag = "Hooks")]
+#[post("/hooks", data = "<hook>")]
+pub async fn subscribe_hook(db: &State<SqlitePool>, user: ApiUser, hook: Json<NewHook>) -> Result<(Status, Json<RestHook>), Status> {
+    match hook.form_id {
+        Some(form_id) => user.require_form(Scope::ReadResponses, form_id)?,
+        None => user.require_all_forms(Scope::ReadResponses)?,
+    }
+
+    if !hook.target_url.starts_with("https://") {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    if let Some(form_id) = hook.form_id {
+        sqlx::query_scalar!("SELECT id FROM forms WHERE id = ? AND author_id = ?", form_id, user.0)
+            .fetch_optional(db.inner())
+            .await
+            .map_err(|_| Status::InternalServerError)?
+            .ok_or(Status::NotFound)?;
+    }
+
+    let event = hook.event.as_str();
+    let hook = sqlx::query_as!(RestHook,
+        "INSERT INTO rest_hooks (user_id, form_id, event, target_url) VALUES (?, ?, ?, ?) RETURNING *",
+        user.0,
+        hook.form_id,
+        event,
+        hook.target_url
+    )
+    .fetch_one(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok((Status::Created, Json(hook)))
+}
+
+#[openapi(tag = "Hooks")]
+#[delete("/hooks/<id>")]
+pub async fn unsubscribe_hook(db: &State<SqlitePool>, user: ApiUser, id: i64) -> Result<Status, Status> {
+    user.require(Scope::ReadResponses)?;
+
+    let deleted = sqlx::query!("DELETE FROM rest_hooks WHERE id = ? AND user_id = ?", id, user.0)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .rows_affected();
+
+    if deleted == 0 {
+        return Err(Status::NotFound);
+    }
+
+    Ok(Status::NoContent)
+}
+
+#[openapi(tag = "Hooks")]
+#[get("/hooks/sample?<event>&<form_id>")]
+pub async fn sample_hook_payload(
+    db: &State<SqlitePool>,
+    user: ApiUser,
+    event: HookEvent,
+    form_id: Option<i64>
+) -> Result<Json<Vec<Value>>, Status> {
+    match form_id {
+        Some(form_id) => user.require_form(Scope::ReadResponses, form_id)?,
+        None => user.require_all_forms(Scope::ReadResponses)?,
+    }
+
+    let sample = match event {
+        HookEvent::ResponseSubmitted => sqlx::query!(
+            "SELECT r.id, r.form_id, r.answers, r.created_at, f.title
+             FROM responses r JOIN forms f ON f.id = r.form_id
+             WHERE f.author_id = ?1 AND (?2 IS NULL OR f.id = ?2) AND r.is_test = false
+             ORDER BY r.id DESC LIMIT 1",
+            user.0,
+            form_id
+        )
+        .fetch_optional(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map(|row| response_payload(row.form_id, &row.title, row.id, &row.created_at, &row.answers)),
+        HookEvent::FormPublished => sqlx::query!(
+            "SELECT id, title FROM forms WHERE author_id = ?1 AND (?2 IS NULL OR id = ?2) ORDER BY id DESC LIMIT 1",
+            user.0,
+            form_id
+        )
+        .fetch_optional(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map(|row| form_published_payload(row.id, &row.title)),
+    };
+
+    Ok(Json(sample.into_iter().collect()))
+}
+
+fn response_payload(form_id: i64, form_title: &str, response_id: i64, submitted_at: &str, answers: &str) -> Value {
+    let answers = crypto::decrypt_answers(answers);
+    json!({
+        "event": HookEvent::ResponseSubmitted.as_str(),
+        "form_id": form_id,
+        "form_title": form_title,
+        "response_id": response_id,
+        "submitted_at": submitted_at,
+        "answers": answers,
+    })
+}
+
+fn form_published_payload(form_id: i64, title: &str) -> Value {
+    json!({
+        "event": HookEvent::FormPublished.as_str(),
+        "form_id": form_id,
+        "title": title,
+    })
+}
+
+async fn deliver_hooks(db: &SqlitePool, client: &reqwest::Client, form_id: i64, event: HookEvent, payload: Value) -> Result<(), sqlx::Error> {
+    let event = event.as_str();
+    let hooks = sqlx::query_as!(RestHook,
+        "SELECT h.* FROM rest_hooks h JOIN forms f ON f.author_id = h.user_id
+         WHERE f.id = ?1 AND h.event = ?2 AND (h.form_id IS NULL OR h.form_id = ?1)",
+        form_id,
+        event
+    )
+    .fetch_all(db)
+    .await?;
+
+    for hook in hooks {
+        let result = client.post(&hook.target_url)
+            .timeout(HOOK_TIMEOUT)
+            .json(&payload)
+            .send()
+            .await;
+
+        match result {
+            Ok(response) if response.status() == reqwest::StatusCode::GONE => {
+                sqlx::query!("DELETE FROM rest_hooks WHERE id = ?", hook.id)
+                    .execute(db)
+                    .await?;
+            }
+            Ok(response) if !response.status().is_success() => {
+                warn!("REST hook {} responded with {}", hook.id, response.status());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to deliver REST hook {}: {}", hook.id, e),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn form_published(db: &SqlitePool, client: &reqwest::Client, form_id: i64) -> Result<(), sqlx::Error> {
+    let title = sqlx::query_scalar!("SELECT title FROM forms WHERE id = ?", form_id).fetch_one(db).await?;
+    let payload = form_published_payload(form_id, &title);
+    deliver_hooks(db, client, form_id, HookEvent::FormPublished, payload).await
+}
+
+/// `response.answers` must already be decrypted.
+pub async fn response_submitted(db: &SqlitePool, client: &reqwest::Client, response: &FormResponse) -> Result<(), sqlx::Error> {
+    let title = sqlx::query_scalar!("SELECT title FROM forms WHERE id = ?", response.form_id).fetch_one(db).await?;
+    let payload = response_payload(response.form_id, &title, response.id, &response.created_at, &response.answers);
+    deliver_hooks(db, client, response.form_id, HookEvent::ResponseSubmitted, payload).await
+}
@@ -0,0 +1,33 @@
+use rocket::tokio::sync::broadcast::Receiver;
+use sqlx::SqlitePool;
+
+use crate::events::{self, DomainEvent};
+
+/// Records every domain event in `audit_log`. Rows outlive the forms and
+/// users they mention.
+pub async fn run(db: SqlitePool, mut events: Receiver<DomainEvent>) {
+    while let Some(event) = events::next(&mut events, "Audit log").await {
+        let name = event.name();
+        let form_id = event.form_id();
+        let (user_id, response_id, detail) = match &event {
+            DomainEvent::FormDeleted { author_id, .. } => (Some(*author_id), None, None),
+            DomainEvent::ResponseSubmitted(response) => (None, Some(response.id), response.device.clone()),
+            DomainEvent::UserRegistered { user_id, via } => (Some(*user_id), None, Some(via.to_string())),
+            DomainEvent::FormPublished { .. } | DomainEvent::FormUnpublished { .. } => (None, None, None),
+        };
+
+        let logged = sqlx::query!(
+            "INSERT INTO audit_log (event, form_id, user_id, response_id, detail) VALUES (?, ?, ?, ?, ?)",
+            name,
+            form_id,
+            user_id,
+            response_id,
+            detail
+        )
+        .execute(&db)
+        .await;
+        if let Err(e) = logged {
+            error!("Failed to record {} in the audit log: {}", name, e);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use ldap3::{dn_escape, ldap_escape, LdapConnAsync, Scope};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LdapConfig {
+    pub url: Option<String>,
+    pub bind_dn: String,
+    pub group_filter: Option<String>,
+    pub admin_group: Option<String>,
+}
+
+pub struct LdapUser {
+    /// The DN the login bound as, lower-cased. Accounts are linked by it.
+    pub dn: String,
+    pub is_admin: bool,
+}
+
+impl LdapConfig {
+    pub fn enabled(&self) -> bool {
+        self.url.is_some()
+    }
+}
+
+pub async fn authenticate(config: &LdapConfig, username: &str, password: &str) -> Result<Option<LdapUser>, String> {
+    let url = config.url.as_deref().ok_or("no ldap url is configured")?;
+    if password.is_empty() {
+        return Ok(None);
+    }
+
+    let (conn, mut ldap) = LdapConnAsync::new(url).await.map_err(|e| e.to_string())?;
+    ldap3::drive!(conn);
+
+    let dn = config.bind_dn.replace("{username}", &dn_escape(username));
+    if ldap.simple_bind(&dn, password).await.map_err(|e| e.to_string())?.success().is_err() {
+        return Ok(None);
+    }
+
+    if let Some(filter) = &config.group_filter {
+        if !matches_filter(&mut ldap, &dn, filter).await? {
+            let _ = ldap.unbind().await;
+            return Ok(None);
+        }
+    }
+
+    let is_admin = match &config.admin_group {
+        Some(group) => matches_filter(&mut ldap, &dn, &format!("(memberOf={})", ldap_escape(group))).await?,
+        None => false,
+    };
+
+    let _ = ldap.unbind().await;
+    Ok(Some(LdapUser { dn: dn.to_lowercase(), is_admin }))
+}
+
+async fn matches_filter(ldap: &mut ldap3::Ldap, dn: &str, filter: &str) -> Result<bool, String> {
+    let (entries, _) = ldap.search(dn, Scope::Base, filter, vec!["dn"])
+        .await
+        .and_then(|result| result.success())
+        .map_err(|e| e.to_string())?;
+
+    Ok(!entries.is_empty())
+}
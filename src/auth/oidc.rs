@@ -0,0 +1,170 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use rocket::http::RawStr;
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OidcConfig {
+    pub issuer: Option<String>,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub username_claim: String,
+    pub sso_only: bool,
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        OidcConfig {
+            issuer: None,
+            client_id: String::new(),
+            client_secret: None,
+            redirect_uri: String::new(),
+            scopes: ["openid", "email", "profile"].map(String::from).to_vec(),
+            username_claim: "preferred_username".to_string(),
+            sso_only: false,
+        }
+    }
+}
+
+impl OidcConfig {
+    pub fn enabled(&self) -> bool {
+        self.issuer.is_some()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Flow {
+    pub state: String,
+    nonce: String,
+    verifier: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+pub struct Identity {
+    pub subject: String,
+    pub username: String,
+}
+
+fn random() -> String {
+    format!("{}{}", Uuid::new_v4().to_simple(), Uuid::new_v4().to_simple())
+}
+
+async fn discover(client: &reqwest::Client, issuer: &str) -> Result<Discovery, String> {
+    let url = format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/'));
+    client.get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+pub async fn authorization_url(config: &OidcConfig, client: &reqwest::Client) -> Result<(String, Flow), String> {
+    let issuer = config.issuer.as_deref().ok_or("no oidc issuer is configured")?;
+    let discovery = discover(client, issuer).await?;
+    let flow = Flow { state: random(), nonce: random(), verifier: random() };
+    let scope = config.scopes.join(" ");
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(flow.verifier.as_bytes()));
+
+    let query = [
+        ("response_type", "code"),
+        ("client_id", config.client_id.as_str()),
+        ("redirect_uri", config.redirect_uri.as_str()),
+        ("scope", scope.as_str()),
+        ("state", flow.state.as_str()),
+        ("nonce", flow.nonce.as_str()),
+        ("code_challenge", challenge.as_str()),
+        ("code_challenge_method", "S256"),
+    ]
+    .iter()
+    .map(|(key, value)| format!("{}={}", key, RawStr::new(value).percent_encode()))
+    .collect::<Vec<_>>()
+    .join("&");
+
+    let separator = if discovery.authorization_endpoint.contains('?') { '&' } else { '?' };
+    Ok((format!("{}{}{}", discovery.authorization_endpoint, separator, query), flow))
+}
+
+pub async fn complete(config: &OidcConfig, client: &reqwest::Client, flow: &Flow, code: &str) -> Result<Identity, String> {
+    let issuer = config.issuer.as_deref().ok_or("no oidc issuer is configured")?;
+    let discovery = discover(client, issuer).await?;
+
+    let mut params = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", config.redirect_uri.as_str()),
+        ("client_id", config.client_id.as_str()),
+        ("code_verifier", flow.verifier.as_str()),
+    ];
+    if let Some(secret) = &config.client_secret {
+        params.push(("client_secret", secret.as_str()));
+    }
+
+    let tokens: TokenResponse = client.post(&discovery.token_endpoint)
+        .form(&params)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let header = decode_header(&tokens.id_token).map_err(|e| e.to_string())?;
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err("symmetric algorithms are not accepted".to_string());
+    }
+    let keys: JwkSet = client.get(&discovery.jwks_uri)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    let jwk = match &header.kid {
+        Some(kid) => keys.find(kid),
+        None => keys.keys.first(),
+    }
+    .ok_or("no matching key in the provider's JWKS")?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|e| e.to_string())?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&discovery.issuer]);
+    validation.set_audience(&[&config.client_id]);
+    let claims = decode::<Map<String, Value>>(&tokens.id_token, &key, &validation).map_err(|e| e.to_string())?.claims;
+
+    if claims.get("nonce").and_then(Value::as_str) != Some(flow.nonce.as_str()) {
+        return Err("nonce does not match".to_string());
+    }
+
+    let subject = claims.get("sub").and_then(Value::as_str).ok_or("id token has no sub claim")?;
+    let username = claims.get(&config.username_claim)
+        .or_else(|| claims.get("email"))
+        .and_then(Value::as_str)
+        .unwrap_or(subject);
+
+    Ok(Identity { subject: subject.to_string(), username: username.to_string() })
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use rocket_dyn_templates::tera::{self, Value};
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FooterLink {
+    pub label: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Branding {
+    pub product_name: String,
+    pub logo_url: Option<String>,
+    pub hide_powered_by: bool,
+    pub footer_links: Vec<FooterLink>,
+}
+
+impl Default for Branding {
+    fn default() -> Self {
+        Branding {
+            product_name: "Forms".to_string(),
+            logo_url: None,
+            hide_powered_by: false,
+            footer_links: Vec::new(),
+        }
+    }
+}
+
+impl Branding {
+    pub fn load() -> Self {
+        rocket::Config::figment().extract_inner("branding").unwrap_or_default()
+    }
+
+    pub fn email_footer(&self) -> String {
+        let mut footer = format!("\n\n-- \n{}\n", self.product_name);
+        for link in &self.footer_links {
+            footer.push_str(&format!("{}: {}\n", link.label, link.url));
+        }
+        footer
+    }
+
+    pub fn register(self, tera: &mut tera::Tera) {
+        let value = tera::to_value(&self).unwrap_or(Value::Null);
+        tera.register_function("branding", move |_: &HashMap<String, Value>| Ok(value.clone()));
+    }
+}
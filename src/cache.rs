@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::models::WebForm;
+
+/// Entries also expire on their own, which bounds staleness for any write
+/// path that forgets to invalidate.
+const FORM_CACHE_TTL: Duration = Duration::from_secs(300);
+
+const FORM_CACHE_CAPACITY: usize = 10_000;
+
+/// Published forms keyed by `(tenant_id, form_id)`, so public form views and
+/// submissions skip the database while a form is popular.
+#[derive(Default)]
+pub struct FormCache {
+    forms: RwLock<HashMap<(i64, i64), (Instant, WebForm)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl FormCache {
+    pub fn get(&self, tenant_id: i64, id: i64) -> Option<WebForm> {
+        let forms = self.forms.read().unwrap();
+        let form = forms.get(&(tenant_id, id))
+            .filter(|(cached_at, _)| cached_at.elapsed() < FORM_CACHE_TTL)
+            .map(|(_, form)| form.clone());
+
+        let counter = if form.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        form
+    }
+
+    pub fn insert(&self, tenant_id: i64, form: WebForm) {
+        let mut forms = self.forms.write().unwrap();
+        if forms.len() >= FORM_CACHE_CAPACITY {
+            forms.retain(|_, (cached_at, _)| cached_at.elapsed() < FORM_CACHE_TTL);
+            if forms.len() >= FORM_CACHE_CAPACITY {
+                forms.clear();
+            }
+        }
+        forms.insert((tenant_id, form.id), (Instant::now(), form));
+    }
+
+    pub fn invalidate(&self, id: i64) {
+        self.forms.write().unwrap().retain(|&(_, form_id), _| form_id != id);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.forms.read().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::net::IpAddr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::models::WebForm;
+
+const DEFAULT_VERIFY_URL: &str = "https://www.google.com/recaptcha/api/siteverify";
+
+/// The hidden field the score-based widget writes its token into.
+pub const CAPTCHA_TOKEN_FIELD: &str = "_captcha_token";
+
+/// The field the interactive widget posts its token in. The name is fixed by
+/// the widget.
+pub const CHALLENGE_TOKEN_FIELD: &str = "g-recaptcha-response";
+
+/// The `[captcha]` configuration table: a score-based site key pair
+/// (reCAPTCHA v3) and, optionally, an interactive one (v2) for the
+/// challenge. Without the interactive pair, middling scores are accepted.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CaptchaConfig {
+    pub site_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub challenge_site_key: Option<String>,
+    pub challenge_secret_key: Option<String>,
+    /// Any provider speaking the siteverify protocol.
+    pub verify_url: String,
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        CaptchaConfig {
+            site_key: None,
+            secret_key: None,
+            challenge_site_key: None,
+            challenge_secret_key: None,
+            verify_url: DEFAULT_VERIFY_URL.to_string(),
+        }
+    }
+}
+
+/// What the public form template needs to render the widgets.
+#[derive(Debug, Default, Serialize)]
+pub struct CaptchaPrompt<'a> {
+    pub site_key: Option<&'a str>,
+    /// Set when the respondent has to pass the interactive challenge.
+    pub challenge_site_key: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Accept,
+    Challenge,
+    Reject,
+}
+
+#[derive(Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+    score: Option<f64>,
+}
+
+impl CaptchaConfig {
+    pub fn enabled(&self) -> bool {
+        self.site_key.is_some() && self.secret_key.is_some()
+    }
+
+    fn challenge_enabled(&self) -> bool {
+        self.challenge_site_key.is_some() && self.challenge_secret_key.is_some()
+    }
+
+    /// Kiosk pages are asked too: anyone can open one, so being a kiosk
+    /// proves nothing.
+    pub fn prompt(&self, form: &WebForm, challenge: bool) -> CaptchaPrompt<'_> {
+        if !form.captcha || !self.enabled() {
+            return CaptchaPrompt::default();
+        }
+        CaptchaPrompt {
+            site_key: self.site_key.as_deref(),
+            challenge_site_key: self.challenge_site_key.as_deref().filter(|_| challenge),
+        }
+    }
+
+    /// Scores run from 0.0, almost certainly a bot, to 1.0. A token that
+    /// fails verification, such as a missing or replayed one, scores 0.0.
+    pub async fn score(&self, client: &reqwest::Client, token: &str, ip: Option<IpAddr>) -> Result<f64, String> {
+        let secret = self.secret_key.as_deref().ok_or("no captcha secret key is configured")?;
+        let verified = self.verify(client, secret, token, ip).await?;
+        Ok(if verified.success { verified.score.unwrap_or_default() } else { 0.0 })
+    }
+
+    pub async fn passed_challenge(&self, client: &reqwest::Client, token: &str, ip: Option<IpAddr>) -> Result<bool, String> {
+        let secret = self.challenge_secret_key.as_deref().ok_or("no captcha challenge key is configured")?;
+        Ok(self.verify(client, secret, token, ip).await?.success)
+    }
+
+    async fn verify(&self, client: &reqwest::Client, secret: &str, token: &str, ip: Option<IpAddr>) -> Result<SiteVerifyResponse, String> {
+        let ip = ip.map(|ip| ip.to_string()).unwrap_or_default();
+        client.post(&self.verify_url)
+            .form(&[("secret", secret), ("response", token), ("remoteip", ip.as_str())])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn verdict(&self, form: &WebForm, score: f64) -> Verdict {
+        if score >= form.captcha_accept_score {
+            Verdict::Accept
+        } else if score < form.captcha_reject_score {
+            Verdict::Reject
+        } else if self.challenge_enabled() {
+            Verdict::Challenge
+        } else {
+            Verdict::Accept
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::io::Cursor;
+
+use image::{ImageFormat, RgbImage};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use rocket::http::ContentType;
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+
+const CHART_SIZE: (u32, u32) = (640, 360);
+
+const SPARKLINE_SIZE: (u32, u32) = (240, 48);
+
+const CHART_COLOR: RGBColor = RGBColor(37, 99, 235);
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum ChartFormat {
+    Png,
+    Svg,
+}
+
+/// A rendered chart. PNG labels are drawn with the system's sans-serif
+/// font, so a host without fonts should use SVG.
+pub struct Chart {
+    pub format: ChartFormat,
+    pub body: Vec<u8>,
+}
+
+impl<'r> Responder<'r, 'static> for Chart {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let content_type = match self.format {
+            ChartFormat::Png => ContentType::PNG,
+            ChartFormat::Svg => ContentType::SVG,
+        };
+        Response::build()
+            .header(content_type)
+            .sized_body(self.body.len(), Cursor::new(self.body))
+            .ok()
+    }
+}
+
+fn encode_png(pixels: Vec<u8>, (width, height): (u32, u32)) -> Result<Vec<u8>, String> {
+    let image = RgbImage::from_raw(width, height, pixels).ok_or("pixel buffer does not match the chart size")?;
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png).map_err(|e| e.to_string())?;
+    Ok(png.into_inner())
+}
+
+/// Runs the same drawing against whichever backend `format` needs. The
+/// callback is passed twice because each backend is a different type.
+fn render(
+    format: ChartFormat,
+    size: (u32, u32),
+    draw_svg: impl FnOnce(DrawingArea<SVGBackend, Shift>) -> Result<(), String>,
+    draw_png: impl FnOnce(DrawingArea<BitMapBackend, Shift>) -> Result<(), String>
+) -> Result<Chart, String> {
+    let body = match format {
+        ChartFormat::Svg => {
+            let mut svg = String::new();
+            draw_svg(SVGBackend::with_string(&mut svg, size).into_drawing_area())?;
+            svg.into_bytes()
+        }
+        ChartFormat::Png => {
+            let mut pixels = vec![0; (size.0 * size.1 * 3) as usize];
+            draw_png(BitMapBackend::with_buffer(&mut pixels, size).into_drawing_area())?;
+            encode_png(pixels, size)?
+        }
+    };
+    Ok(Chart { format, body })
+}
+
+fn draw_bars<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, title: &str, labels: &[String], values: &[i64]) -> Result<(), String> {
+    root.fill(&WHITE).map_err(|e| e.to_string())?;
+    let max = values.iter().copied().max().unwrap_or_default().max(1);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 20))
+        .margin(12)
+        .x_label_area_size(40)
+        .y_label_area_size(48)
+        .build_cartesian_2d((0..labels.len().max(1)).into_segmented(), 0..max + max / 10 + 1)
+        .map_err(|e| e.to_string())?;
+
+    chart.configure_mesh()
+        .disable_x_mesh()
+        .x_label_formatter(&|x| match x {
+            SegmentValue::CenterOf(i) => labels.get(*i).cloned().unwrap_or_default(),
+            _ => String::new(),
+        })
+        .draw()
+        .map_err(|e| e.to_string())?;
+
+    chart.draw_series(
+        Histogram::vertical(&chart)
+            .style(CHART_COLOR.filled())
+            .margin(8)
+            .data(values.iter().enumerate().map(|(i, &value)| (i, value)))
+    )
+    .map_err(|e| e.to_string())?;
+
+    root.present().map_err(|e| e.to_string())
+}
+
+fn draw_line<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, title: &str, labels: &[String], values: &[i64]) -> Result<(), String> {
+    root.fill(&WHITE).map_err(|e| e.to_string())?;
+    let max = values.iter().copied().max().unwrap_or_default().max(1);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 20))
+        .margin(12)
+        .x_label_area_size(40)
+        .y_label_area_size(48)
+        .build_cartesian_2d(0..values.len().max(2) - 1, 0..max + max / 10 + 1)
+        .map_err(|e| e.to_string())?;
+
+    chart.configure_mesh()
+        .x_labels(6)
+        .x_label_formatter(&|i| labels.get(*i).cloned().unwrap_or_default())
+        .draw()
+        .map_err(|e| e.to_string())?;
+
+    chart.draw_series(LineSeries::new(values.iter().enumerate().map(|(i, &value)| (i, value)), CHART_COLOR.stroke_width(2)))
+        .map_err(|e| e.to_string())?;
+
+    root.present().map_err(|e| e.to_string())
+}
+
+/// No text at all, so it renders the same with or without fonts.
+fn draw_sparkline<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, values: &[i64]) -> Result<(), String> {
+    root.fill(&WHITE).map_err(|e| e.to_string())?;
+    let max = values.iter().copied().max().unwrap_or_default().max(1);
+
+    let mut chart = ChartBuilder::on(&root)
+        .margin(4)
+        .build_cartesian_2d(0..values.len().max(2) - 1, 0..max)
+        .map_err(|e| e.to_string())?;
+
+    chart.draw_series(LineSeries::new(values.iter().enumerate().map(|(i, &value)| (i, value)), CHART_COLOR.stroke_width(2)))
+        .map_err(|e| e.to_string())?;
+
+    root.present().map_err(|e| e.to_string())
+}
+
+pub fn bar_chart(format: ChartFormat, title: &str, labels: &[String], values: &[i64]) -> Result<Chart, String> {
+    render(
+        format,
+        CHART_SIZE,
+        |root| draw_bars(root, title, labels, values),
+        |root| draw_bars(root, title, labels, values)
+    )
+}
+
+pub fn line_chart(format: ChartFormat, title: &str, labels: &[String], values: &[i64]) -> Result<Chart, String> {
+    render(
+        format,
+        CHART_SIZE,
+        |root| draw_line(root, title, labels, values),
+        |root| draw_line(root, title, labels, values)
+    )
+}
+
+/// A small PNG trend line for embedding in emails.
+pub fn sparkline(values: &[i64]) -> Result<Vec<u8>, String> {
+    render(
+        ChartFormat::Png,
+        SPARKLINE_SIZE,
+        |root| draw_sparkline(root, values),
+        |root| draw_sparkline(root, values)
+    )
+    .map(|chart| chart.body)
+}
@@ -0,0 +1,148 @@
+use bcrypt::{hash, DEFAULT_COST};
+use sqlx::SqlitePool;
+
+use crate::{AppConfig, crypto, db, export, schema, seed};
+use crate::db::response_tags;
+use crate::models::{FormResponse, WebForm};
+use crate::repository::UserRepository;
+use crate::tenant::DEFAULT_TENANT;
+
+const USAGE: &str = "usage: forms_system admin <command>
+
+commands:
+  create-user <username> <password> [--approver]
+  reset-password <username> <password>
+  promote <username>
+  link-ldap <username> <dn>
+  purge-sessions [<username>]
+  export-form <id>
+  seed";
+
+async fn connect() -> Result<SqlitePool, String> {
+    let config: db::DatabaseConfig = rocket::Config::figment().focus("database").extract().map_err(|e| e.to_string())?;
+    let db = db::connect(&config).await.map_err(|e| e.to_string())?;
+    sqlx::migrate!().run(&db).await.map_err(|e| e.to_string())?;
+    Ok(db)
+}
+
+pub async fn run(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["create-user", username, password, flags @ ..] => create_user(username, password, flags.contains(&"--approver")).await,
+        ["reset-password", username, password] => reset_password(username, password).await,
+        ["promote", username] => promote(username).await,
+        ["link-ldap", username, dn] => link_ldap(username, dn).await,
+        ["purge-sessions"] => purge_sessions(None).await,
+        ["purge-sessions", username] => purge_sessions(Some(username)).await,
+        ["export-form", id] => match id.parse() {
+            Ok(id) => export_form(id).await,
+            Err(_) => Err(format!("\"{}\" is not a form id", id)),
+        },
+        ["seed"] => seed().await,
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+async fn create_user(username: &str, password: &str, approver: bool) -> Result<(), String> {
+    let db = connect().await?;
+    let password_hash = hash(password, DEFAULT_COST).map_err(|e| e.to_string())?;
+    let id = db.create_user(DEFAULT_TENANT, username, &password_hash, approver)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    println!("Created user {} ({})", username, id);
+    Ok(())
+}
+
+async fn reset_password(username: &str, password: &str) -> Result<(), String> {
+    let db = connect().await?;
+    let password_hash = hash(password, DEFAULT_COST).map_err(|e| e.to_string())?;
+    if !db.set_password(DEFAULT_TENANT, username, &password_hash).await.map_err(|e| e.to_string())? {
+        return Err(format!("no user named {}", username));
+    }
+    println!("Reset the password for {}", username);
+    Ok(())
+}
+
+async fn promote(username: &str) -> Result<(), String> {
+    let db = connect().await?;
+    if !db.promote(DEFAULT_TENANT, username).await.map_err(|e| e.to_string())? {
+        return Err(format!("no user named {}", username));
+    }
+    println!("{} is now an approver", username);
+    Ok(())
+}
+
+async fn link_ldap(username: &str, dn: &str) -> Result<(), String> {
+    let db = connect().await?;
+    let dn = dn.to_lowercase();
+    if !db.link_ldap(DEFAULT_TENANT, username, &dn).await.map_err(|e| e.to_string())? {
+        return Err(format!("no user named {}", username));
+    }
+    println!("{} now signs in through LDAP as {}", username, dn);
+    Ok(())
+}
+
+/// Sessions are held in the running server's memory. This asks it to end
+/// them, which it does within a few seconds.
+async fn purge_sessions(username: Option<&str>) -> Result<(), String> {
+    let db = connect().await?;
+    let user_id = match username {
+        Some(username) => Some(
+            sqlx::query_scalar!("SELECT id FROM users WHERE username = ? AND tenant_id = ?", username, DEFAULT_TENANT)
+                .fetch_optional(&db)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("no user named {}", username))?
+        ),
+        None => None,
+    };
+
+    sqlx::query!("INSERT INTO session_purges (user_id) VALUES (?)", user_id)
+        .execute(&db)
+        .await
+        .map_err(|e| e.to_string())?;
+    match username {
+        Some(username) => println!("{}'s sessions will end within seconds", username),
+        None => println!("All sessions will end within seconds"),
+    }
+    Ok(())
+}
+
+async fn export_form(id: i64) -> Result<(), String> {
+    let config: AppConfig = rocket::Config::figment().extract().map_err(|e| e.to_string())?;
+    if let Some(key) = &config.answers_encryption_key {
+        crypto::configure(key)?;
+    }
+
+    let db = connect().await?;
+    let form = sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE id = ?", id)
+        .fetch_optional(&db)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("no form with id {}", id))?;
+
+    let mut responses = sqlx::query_as!(FormResponse, "SELECT * FROM responses WHERE form_id = ? ORDER BY id", form.id)
+        .fetch_all(&db)
+        .await
+        .map_err(|e| e.to_string())?;
+    responses.iter_mut().for_each(|response| response.answers = crypto::reveal(&response.answers));
+
+    let tags = response_tags(&db, form.id).await.map_err(|status| status.to_string())?;
+    print!("{}", export::responses_csv(&schema::parse(&form.fields), &responses, &tags));
+    Ok(())
+}
+
+async fn seed() -> Result<(), String> {
+    let db = connect().await?;
+    println!("{}", seed::run(&db).await?);
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::io::{Cursor, Write};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::{Request, Response};
+use serde::Deserialize;
+
+use crate::AppConfig;
+
+const BROTLI_QUALITY: u32 = 5;
+
+const BROTLI_WINDOW: u32 = 22;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Bodies smaller than this many bytes are sent as they are.
+    pub min_size: usize,
+    pub content_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            enabled: true,
+            min_size: 1024,
+            content_types: [
+                "text/html", "text/css", "text/csv", "text/plain", "text/javascript",
+                "application/javascript", "application/json", "application/scim+json",
+            ].map(String::from).to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                encoder.write_all(body)?;
+                Ok(encoder.into_inner())
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Picks brotli over gzip when the client accepts both; `q=0` rules an
+/// encoding out.
+fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let accepted = |name: &str| accept_encoding.split(',').any(|part| {
+        let mut params = part.split(';').map(str::trim);
+        params.next().is_some_and(|token| token.eq_ignore_ascii_case(name))
+            && !params.any(|param| param.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0))
+    });
+
+    if accepted("br") {
+        Some(Encoding::Brotli)
+    } else if accepted("gzip") {
+        Some(Encoding::Gzip)
+    } else {
+        None
+    }
+}
+
+pub struct Compress;
+
+#[rocket::async_trait]
+impl Fairing for Compress {
+    fn info(&self) -> Info {
+        Info { name: "Response Compression", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(config) = request.rocket().state::<AppConfig>().map(|config| &config.compression) else {
+            return;
+        };
+        if !config.enabled || request.method() == Method::Head || response.status() != Status::Ok {
+            return;
+        }
+        if response.headers().contains("Content-Encoding") {
+            return;
+        }
+
+        let Some(content_type) = response.content_type() else {
+            return;
+        };
+        let media_type = format!("{}/{}", content_type.top(), content_type.sub());
+        if !config.content_types.iter().any(|allowed| allowed.eq_ignore_ascii_case(&media_type)) {
+            return;
+        }
+
+        let Some(encoding) = request.headers().get_one("Accept-Encoding").and_then(negotiate) else {
+            return;
+        };
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+
+        let Ok(body) = response.body_mut().to_bytes().await else {
+            return;
+        };
+        if body.len() < config.min_size {
+            response.set_sized_body(body.len(), Cursor::new(body));
+            return;
+        }
+
+        let compressed = rocket::tokio::task::spawn_blocking({
+            let body = body.clone();
+            move || encoding.compress(&body)
+        })
+        .await;
+
+        match compressed {
+            Ok(Ok(compressed)) => {
+                // The representation changed, so a strong validator no longer holds.
+                let weak = response.headers().get_one("ETag")
+                    .filter(|etag| !etag.starts_with("W/"))
+                    .map(|etag| format!("W/{}", etag));
+                if let Some(weak) = weak {
+                    response.set_header(Header::new("ETag", weak));
+                }
+                response.set_header(Header::new("Content-Encoding", encoding.name()));
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+            }
+            _ => {
+                error!("Failed to {} compress a response to {}", encoding.name(), request.uri());
+                response.set_sized_body(body.len(), Cursor::new(body));
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::api;
+use crate::schema::{self, FieldDef, FieldKind};
+
+/// One logged consent with the exact text that was agreed to.
+#[derive(Debug, Serialize)]
+pub struct ConsentRecord {
+    pub id: i64,
+    pub response_id: Option<i64>,
+    pub field_key: String,
+    pub text: String,
+    pub text_hash: String,
+    pub consented_at: String,
+}
+
+/// A consent field ticked in a submission, with the wording it showed.
+pub struct GivenConsent {
+    pub field_key: String,
+    pub text: String,
+}
+
+/// The consent fields ticked in `answers`. Worked out before the answers
+/// are encrypted, and logged with the response by [`record`].
+pub fn given(fields: &[FieldDef], answers: &HashMap<String, String>) -> Vec<GivenConsent> {
+    fields.iter()
+        .filter(|field| {
+            field.kind == FieldKind::Consent
+                && field.visible(answers)
+                && answers.get(&field.key).is_some_and(|value| schema::checked(value))
+        })
+        .map(|field| GivenConsent {
+            field_key: field.key.clone(),
+            text: field.legal_text.clone().unwrap_or_else(|| field.label.clone()),
+        })
+        .collect()
+}
+
+/// Logs `consents` in the caller's transaction, so a response is never
+/// stored without its consent records. Each distinct wording is stored
+/// once, so editing a field's legal text starts a new version and earlier
+/// records keep pointing at what those respondents actually saw.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    form_id: i64,
+    response_id: i64,
+    consents: &[GivenConsent]
+) -> Result<(), sqlx::Error> {
+    for consent in consents {
+        let text_hash = api::hash_token(&consent.text);
+        sqlx::query!(
+            "INSERT OR IGNORE INTO consent_texts (form_id, field_key, text, text_hash) VALUES (?, ?, ?, ?)",
+            form_id,
+            consent.field_key,
+            consent.text,
+            text_hash
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO consent_log (form_id, response_id, field_key, consent_text_id)
+             SELECT ?1, ?2, ?3, id FROM consent_texts WHERE form_id = ?1 AND field_key = ?3 AND text_hash = ?4",
+            form_id,
+            response_id,
+            consent.field_key,
+            text_hash
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn log(db: &SqlitePool, form_id: i64) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(ConsentRecord,
+        r#"SELECT l.id AS "id!: i64", l.response_id, l.field_key, t.text, t.text_hash, l.consented_at
+           FROM consent_log l JOIN consent_texts t ON t.id = l.consent_text_id
+           WHERE l.form_id = ? ORDER BY l.id"#,
+        form_id
+    )
+    .fetch_all(db)
+    .await
+}
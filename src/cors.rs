@@ -0,0 +1,94 @@
+use std::io::Cursor;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::{Request, Response};
+use serde::Deserialize;
+
+use crate::AppConfig;
+
+const CORS_PATHS: [&str; 3] = ["/api/", "/graphql", "/f/"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    pub exposed_headers: Vec<String>,
+    pub allow_credentials: bool,
+    pub max_age: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: ["GET", "POST", "PUT", "DELETE"].map(String::from).to_vec(),
+            allowed_headers: ["Authorization", "Content-Type", "If-Match", "If-None-Match"].map(String::from).to_vec(),
+            exposed_headers: vec!["ETag".to_string()],
+            allow_credentials: false,
+            max_age: 86400,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Browsers refuse credentials with a wildcard origin, and echoing each
+    /// origin back instead would let any site make credentialed requests.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.allow_credentials && self.allowed_origins.iter().any(|allowed| allowed == "*") {
+            return Err("allow_credentials cannot be combined with the \"*\" origin".to_string());
+        }
+        Ok(())
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+    }
+}
+
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info { name: "CORS", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let path = request.uri().path();
+        if !CORS_PATHS.iter().any(|prefix| path.starts_with(prefix)) {
+            return;
+        }
+
+        let Some(config) = request.rocket().state::<AppConfig>().map(|config| &config.cors) else {
+            return;
+        };
+
+        let Some(origin) = request.headers().get_one("Origin") else {
+            return;
+        };
+
+        response.adjoin_header(Header::new("Vary", "Origin"));
+        if !config.allows(origin) {
+            return;
+        }
+
+        response.set_header(Header::new("Access-Control-Allow-Origin", origin.to_string()));
+        if config.allow_credentials {
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        }
+        if !config.exposed_headers.is_empty() {
+            response.set_header(Header::new("Access-Control-Expose-Headers", config.exposed_headers.join(", ")));
+        }
+
+        if request.method() == Method::Options && request.headers().contains("Access-Control-Request-Method") {
+            response.set_header(Header::new("Access-Control-Allow-Methods", config.allowed_methods.join(", ")));
+            response.set_header(Header::new("Access-Control-Allow-Headers", config.allowed_headers.join(", ")));
+            response.set_header(Header::new("Access-Control-Max-Age", config.max_age.to_string()));
+            response.set_status(Status::NoContent);
+            response.set_sized_body(0, Cursor::new(""));
+        }
+    }
+}
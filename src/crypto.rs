@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use aes_gcm::aead::{Aead, AeadCore, OsRng};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::schema::FieldDef;
+
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+const NONCE_LENGTH: usize = 12;
+
+const UNREADABLE: &str = "[encrypted]";
+
+/// Crockford's base32: no I, L, O or U, so a code read off one screen can be
+/// typed into another.
+const CODE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+const CODE_GROUP: usize = 5;
+
+/// What the fingerprint key is derived from the encryption key with, so the
+/// two keys are never the same.
+const FINGERPRINT_LABEL: &[u8] = b"forms_system answers fingerprint";
+
+static CIPHER: OnceLock<Aes256Gcm> = OnceLock::new();
+
+static FINGERPRINT_KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+pub fn configure(key: &str) -> Result<(), String> {
+    let key = STANDARD.decode(key.trim()).map_err(|e| e.to_string())?;
+    let cipher = Aes256Gcm::new_from_slice(&key).map_err(|_| "key must be 32 bytes".to_string())?;
+    CIPHER.set(cipher).map_err(|_| "answer encryption is already configured".to_string())?;
+    FINGERPRINT_KEY.set(mac(&key, FINGERPRINT_LABEL)).map_err(|_| "answer encryption is already configured".to_string())
+}
+
+fn mac(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(value);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// A keyed digest of `value`, for telling equal values apart without
+/// storing them. With encryption configured nobody without the key can
+/// check a guess against it. Without, the values it is taken from are
+/// stored in the clear anyway, so the fixed key gives nothing away.
+pub fn fingerprint(value: &str) -> String {
+    let key = FINGERPRINT_KEY.get().map(Vec::as_slice).unwrap_or(FINGERPRINT_LABEL);
+    mac(key, value.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn seal(cipher: &Aes256Gcm, value: &str) -> Result<String, String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, value.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(sealed)))
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &str) -> Result<String, String> {
+    let sealed = STANDARD.decode(sealed).map_err(|e| e.to_string())?;
+    if sealed.len() < NONCE_LENGTH {
+        return Err("ciphertext is truncated".to_string());
+    }
+
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|e| e.to_string())?;
+    String::from_utf8(plaintext).map_err(|e| e.to_string())
+}
+
+fn encrypt(value: &str) -> Result<String, String> {
+    let cipher = CIPHER.get().ok_or("no answers_encryption_key is configured")?;
+    seal(cipher, value)
+}
+
+/// A single stored answer in the clear. Answers stored unencrypted are
+/// returned as they are.
+pub fn decrypt(value: &str) -> Result<String, String> {
+    let Some(sealed) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok(value.to_string());
+    };
+
+    let cipher = CIPHER.get().ok_or("no answers_encryption_key is configured")?;
+    open(cipher, sealed)
+}
+
+/// Encrypts with a key of the caller's rather than the configured one, for
+/// data only the holder of that key should be able to read back.
+pub fn encrypt_with(key: &[u8], value: &str) -> Result<String, String> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| "key must be 32 bytes".to_string())?;
+    seal(&cipher, value)
+}
+
+pub fn decrypt_with(key: &[u8], value: &str) -> Result<String, String> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| "key must be 32 bytes".to_string())?;
+    let sealed = value.strip_prefix(ENCRYPTED_PREFIX).ok_or("value is not encrypted")?;
+    open(&cipher, sealed)
+}
+
+/// A random code of `length` characters for people to copy by hand, written
+/// in dash-separated groups of five. At most 32 characters.
+pub fn readable_code(length: usize) -> String {
+    let random = [Uuid::new_v4().as_bytes().as_slice(), Uuid::new_v4().as_bytes()].concat();
+    let code: String = random.iter().take(length).map(|byte| CODE_ALPHABET[(*byte & 31) as usize] as char).collect();
+    group_code(&code)
+}
+
+/// Codes as people retype them: any case, with or without the dashes, and
+/// with the letters Crockford's alphabet leaves out read as the digits they
+/// look like.
+pub fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        })
+        .collect()
+}
+
+/// Writes a normalized code back out the way `readable_code` does.
+pub fn group_code(code: &str) -> String {
+    code.as_bytes()
+        .chunks(CODE_GROUP)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+pub fn encrypt_answers(fields: &[FieldDef], answers: &HashMap<String, String>) -> Result<HashMap<String, String>, String> {
+    answers.iter()
+        .map(|(key, value)| {
+            let encrypted = fields.iter().any(|field| field.encrypted && &field.key == key);
+            let value = if encrypted { encrypt(value)? } else { value.clone() };
+            Ok((key.clone(), value))
+        })
+        .collect()
+}
+
+pub fn decrypt_answers(answers: &str) -> HashMap<String, String> {
+    let answers: HashMap<String, String> = serde_json::from_str(answers).unwrap_or_default();
+    answers.into_iter()
+        .map(|(key, value)| {
+            let value = decrypt(&value).unwrap_or_else(|e| {
+                warn!("Failed to decrypt answer to {}: {}", key, e);
+                UNREADABLE.to_string()
+            });
+            (key, value)
+        })
+        .collect()
+}
+
+pub fn reveal(answers: &str) -> String {
+    serde_json::to_string(&decrypt_answers(answers)).unwrap_or_default()
+}
@@ -0,0 +1,430 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::time::Duration;
+
+use rocket::futures::TryStreamExt;
+use rocket::futures::stream::BoxStream;
+use rocket::http::Status;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+
+use crate::{crypto, payments};
+use crate::cache::FormCache;
+use crate::models::{ClosedReason, FormResponse, FormSchedule, ResponseEventKind, ResponseFilter, ResponseStatus, WebForm};
+use crate::tenant::Tenant;
+
+pub const DATABASE_URL: &str = "sqlite:forms.db";
+
+/// The `[database]` configuration table. It is read before Rocket starts,
+/// since the pool has to exist when the app is built.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    pub acquire_timeout_secs: u64,
+    pub busy_timeout_ms: u64,
+    /// A separate database for heavy reads such as exports, typically a
+    /// replica. It may lag behind `url`. Unset, reads share the main pool.
+    pub read_url: Option<String>,
+    pub read_max_connections: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: DATABASE_URL.to_string(),
+            max_connections: 10,
+            acquire_timeout_secs: 30,
+            busy_timeout_ms: 5000,
+            read_url: None,
+            read_max_connections: 4,
+        }
+    }
+}
+
+/// Opens the pool eagerly, so a missing or unreadable database fails at
+/// startup rather than on the first request. Every connection uses WAL mode
+/// and enforces foreign keys.
+pub async fn connect(config: &DatabaseConfig) -> Result<SqlitePool, sqlx::Error> {
+    let options = SqliteConnectOptions::from_str(&config.url)?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .foreign_keys(true)
+        .busy_timeout(Duration::from_millis(config.busy_timeout_ms));
+
+    SqlitePoolOptions::new()
+        .max_connections(config.max_connections)
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+        .connect_with(options)
+        .await
+}
+
+/// The pool behind the response list, and behind CSV and NDJSON exports.
+/// Keeping it apart means a large export cannot take the connections that
+/// submissions need.
+pub struct ReadPool(pub SqlitePool);
+
+/// Opens the `read_url` pool read-only. Without a `read_url` this returns
+/// `db` itself, so a single in-memory database still sees its own writes.
+pub async fn connect_read(config: &DatabaseConfig, db: &SqlitePool) -> Result<ReadPool, sqlx::Error> {
+    let Some(url) = &config.read_url else {
+        return Ok(ReadPool(db.clone()));
+    };
+
+    let options = SqliteConnectOptions::from_str(url)?
+        .read_only(true)
+        .busy_timeout(Duration::from_millis(config.busy_timeout_ms));
+
+    let reads = SqlitePoolOptions::new()
+        .max_connections(config.read_max_connections)
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+        .connect_with(options)
+        .await?;
+    Ok(ReadPool(reads))
+}
+
+pub async fn published_form(db: &SqlitePool, cache: &FormCache, tenant: &Tenant, id: i64) -> Result<Option<WebForm>, Status> {
+    if let Some(form) = cache.get(tenant.id, id) {
+        return Ok(Some(form));
+    }
+
+    let form = sqlx::query_as!(WebForm,
+        "SELECT * FROM forms WHERE id = ? AND published = true AND tenant_id = ?",
+        id,
+        tenant.id
+    )
+        .fetch_optional(db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    if let Some(form) = &form {
+        cache.insert(tenant.id, form.clone());
+    }
+    Ok(form)
+}
+
+pub async fn form_schedule(db: &SqlitePool, form: &WebForm) -> Result<FormSchedule, Status> {
+    let mut schedule = FormSchedule {
+        opens_at: form.opens_at.clone(),
+        closes_at: form.closes_at.clone(),
+        seconds_until_open: None,
+        spots_left: None,
+        closed: None,
+    };
+
+    // Unpaid responses only hold a place while their Checkout session can
+    // still be paid.
+    if let Some(cap) = form.response_cap {
+        let checkout_open = format!("-{} minutes", payments::CHECKOUT_EXPIRY_MINUTES);
+        let accepted = sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM responses WHERE form_id = ? AND is_test = false AND spam = false AND waitlisted = false
+             AND (payment_status IS NULL OR payment_status IN ('paid')
+                  OR (payment_status = 'pending' AND created_at > datetime('now', ?)))",
+            form.id,
+            checkout_open
+        )
+        .fetch_one(db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+        schedule.spots_left = Some((cap - accepted).max(0));
+        if schedule.spots_left == Some(0) && !form.waitlist {
+            schedule.closed = Some(ClosedReason::Full);
+        }
+    }
+    if form.opens_at.is_none() && form.closes_at.is_none() {
+        return Ok(schedule);
+    }
+
+    let state = sqlx::query!(
+        r#"SELECT CASE WHEN ?1 > CURRENT_TIMESTAMP THEN 'upcoming' WHEN ?2 <= CURRENT_TIMESTAMP THEN 'ended' END AS "state: String",
+                  CAST((julianday(?1) - julianday('now')) * 86400 AS INTEGER) AS "seconds_until_open: i64""#,
+        form.opens_at,
+        form.closes_at
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    schedule.closed = match state.state.as_deref() {
+        Some("upcoming") => Some(ClosedReason::Upcoming),
+        Some("ended") => Some(ClosedReason::Ended),
+        _ => schedule.closed,
+    };
+    schedule.seconds_until_open = state.seconds_until_open.filter(|seconds| *seconds > 0);
+    Ok(schedule)
+}
+
+/// Notifications that go out by email unless the user turned that off,
+/// rather than only once they turn it on.
+pub const EMAILED_BY_DEFAULT: [&str; 1] = ["assignment"];
+
+/// Notifications whose emails go out through the integrations dispatcher,
+/// with its retries, rather than straight from `notify`.
+const EMAILED_BY_INTEGRATIONS: [&str; 1] = ["submission"];
+
+/// How a user wants to hear about one kind of notification. None when the
+/// form is muted or the user is gone.
+struct NotificationRoute {
+    in_app: bool,
+    email: Option<String>,
+}
+
+async fn notification_route(
+    db: &SqlitePool,
+    user_id: i64,
+    kind: &str,
+    form_id: Option<i64>
+) -> Result<Option<NotificationRoute>, sqlx::Error> {
+    let muted = sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM muted_forms WHERE user_id = ? AND form_id = ?)",
+        user_id,
+        form_id
+    )
+    .fetch_one(db)
+    .await?;
+
+    if muted != 0 {
+        return Ok(None);
+    }
+
+    let preference = sqlx::query!(
+        "SELECT p.in_app, p.email, u.email AS address, u.digest
+         FROM users u LEFT JOIN notification_preferences p ON p.user_id = u.id AND p.kind = ?
+         WHERE u.id = ?",
+        kind,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(preference.map(|preference| {
+        let digested = kind == "submission" && preference.digest.is_some();
+        let email = preference.email.unwrap_or(EMAILED_BY_DEFAULT.contains(&kind)) && !digested;
+        NotificationRoute {
+            in_app: preference.in_app.unwrap_or(true),
+            email: preference.address.filter(|_| email),
+        }
+    }))
+}
+
+async fn queue_notification_email(db: &SqlitePool, address: &str, message: &str, link: &str) -> Result<(), sqlx::Error> {
+    let body = format!("{}\n\n{}", message, link);
+    sqlx::query!(
+        "INSERT INTO email_queue (recipient, subject, body) VALUES (?, ?, ?)",
+        address,
+        message,
+        body
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn notify(
+    db: &SqlitePool,
+    user_id: i64,
+    kind: &str,
+    message: &str,
+    link: &str,
+    form_id: Option<i64>
+) -> Result<(), Status> {
+    let Some(route) = notification_route(db, user_id, kind, form_id).await.map_err(|_| Status::InternalServerError)? else {
+        return Ok(());
+    };
+
+    if route.in_app {
+        sqlx::query!(
+            "INSERT INTO notifications (user_id, kind, message, link) VALUES (?, ?, ?, ?)",
+            user_id,
+            kind,
+            message,
+            link
+        )
+        .execute(db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    }
+
+    if let (Some(address), false) = (route.email, EMAILED_BY_INTEGRATIONS.contains(&kind)) {
+        queue_notification_email(db, &address, message, link).await.map_err(|_| Status::InternalServerError)?;
+    }
+
+    Ok(())
+}
+
+/// Emails the notification `notify` left to the integrations dispatcher,
+/// if the user still wants it by email.
+pub async fn email_notification(
+    db: &SqlitePool,
+    user_id: i64,
+    kind: &str,
+    message: &str,
+    link: &str,
+    form_id: Option<i64>
+) -> Result<(), sqlx::Error> {
+    if let Some(NotificationRoute { email: Some(address), .. }) = notification_route(db, user_id, kind, form_id).await? {
+        queue_notification_email(db, &address, message, link).await?;
+    }
+
+    Ok(())
+}
+
+/// Keyed, so that the hash stored beside encrypted answers cannot be used
+/// to guess them.
+pub fn answers_hash(answers: &HashMap<String, String>) -> Result<String, Status> {
+    let sorted: BTreeMap<_, _> = answers.iter().map(|(key, value)| (key, value.trim())).collect();
+    Ok(crypto::fingerprint(&serde_json::to_string(&sorted).map_err(|_| Status::InternalServerError)?))
+}
+
+pub async fn record_response_event(
+    db: &SqlitePool,
+    response_id: i64,
+    actor_id: Option<i64>,
+    kind: ResponseEventKind,
+    detail: &str
+) -> Result<(), Status> {
+    let kind = kind.as_str();
+    sqlx::query!(
+        "INSERT INTO response_events (response_id, actor_id, kind, detail) VALUES (?, ?, ?, ?)",
+        response_id,
+        actor_id,
+        kind,
+        detail
+    )
+    .execute(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(())
+}
+
+/// Records the same event for each response in `ids`, a JSON array, that
+/// belongs to a form `author_id` owns.
+pub async fn record_response_events(
+    db: &SqlitePool,
+    form_id: i64,
+    author_id: i64,
+    ids: &str,
+    kind: ResponseEventKind,
+    detail: &str
+) -> Result<(), Status> {
+    let kind = kind.as_str();
+    sqlx::query!(
+        "INSERT INTO response_events (response_id, actor_id, kind, detail)
+         SELECT r.id, ?1, ?2, ?3 FROM responses r JOIN forms f ON f.id = r.form_id
+         WHERE r.id IN (SELECT value FROM json_each(?4)) AND f.id = ?5 AND f.author_id = ?1",
+        author_id,
+        kind,
+        detail,
+        ids,
+        form_id
+    )
+    .execute(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(())
+}
+
+/// The response an earlier submission with the same idempotency key
+/// created, with its answers decrypted.
+pub async fn replayed_response(db: &SqlitePool, form_id: i64, key: &str) -> Result<Option<FormResponse>, Status> {
+    let response = sqlx::query_as!(FormResponse,
+        "SELECT * FROM responses WHERE form_id = ? AND idempotency_key = ?",
+        form_id,
+        key
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(response.map(|mut response| {
+        response.answers = crypto::reveal(&response.answers);
+        response
+    }))
+}
+
+/// The form's responses matching a [`ResponseFilter`], newest first. Every
+/// listing and export of responses reads through this, so they all apply
+/// the same filter.
+pub struct FilteredResponses<'a> {
+    form_id: i64,
+    status: Option<&'static str>,
+    assignee: Option<i64>,
+    filter: &'a ResponseFilter,
+}
+
+impl<'a> FilteredResponses<'a> {
+    /// `user_id` is who "mine" means.
+    pub fn new(form_id: i64, user_id: i64, filter: &'a ResponseFilter) -> Self {
+        FilteredResponses {
+            form_id,
+            status: filter.status.map(ResponseStatus::as_str),
+            assignee: filter.mine.then_some(user_id),
+            filter,
+        }
+    }
+
+    /// The rows as the cursor reaches them, answers still as stored.
+    pub fn rows(&'a self, db: &'a SqlitePool) -> BoxStream<'a, Result<FormResponse, sqlx::Error>> {
+        sqlx::query_as!(FormResponse,
+            "SELECT r.* FROM responses r
+             WHERE r.form_id = ?1 AND (?2 IS NULL OR r.status = ?2) AND (?3 IS NULL OR r.assigned_to = ?3)
+             AND (?4 = false OR r.duplicate_of IS NOT NULL)
+             AND (?5 IS NULL OR EXISTS (SELECT 1 FROM response_tags t WHERE t.response_id = r.id AND t.tag = ?5))
+             AND (?6 IS NULL OR r.created_at >= datetime(?6))
+             AND (?7 IS NULL OR r.created_at < datetime(?7, '+1 day'))
+             AND (?8 = false OR r.waitlisted = true)
+             AND (?9 IS NULL OR r.payment_status = ?9)
+             AND r.spam = ?10
+             ORDER BY r.id DESC",
+            self.form_id,
+            self.status,
+            self.assignee,
+            self.filter.duplicates,
+            self.filter.tag,
+            self.filter.since,
+            self.filter.until,
+            self.filter.waitlisted,
+            self.filter.payment,
+            self.filter.spam
+        )
+        .fetch(db)
+    }
+}
+
+pub async fn filtered_responses(
+    db: &SqlitePool,
+    form_id: i64,
+    user_id: i64,
+    filter: &ResponseFilter
+) -> Result<Vec<FormResponse>, Status> {
+    let mut responses: Vec<FormResponse> = FilteredResponses::new(form_id, user_id, filter)
+        .rows(db)
+        .try_collect()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    responses.iter_mut().for_each(|response| response.answers = crypto::reveal(&response.answers));
+    Ok(responses)
+}
+
+pub async fn response_tags(db: &SqlitePool, form_id: i64) -> Result<HashMap<i64, Vec<String>>, Status> {
+    let tagged = sqlx::query!(
+        "SELECT t.response_id, t.tag FROM response_tags t JOIN responses r ON r.id = t.response_id
+         WHERE r.form_id = ? ORDER BY t.tag",
+        form_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in tagged {
+        tags.entry(row.response_id).or_default().push(row.tag);
+    }
+    Ok(tags)
+}
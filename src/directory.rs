@@ -0,0 +1,98 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+/// Longest tag kept; anything past it is cut off.
+const MAX_TAG_LENGTH: usize = 32;
+
+/// A form as the public directory lists it.
+#[derive(Debug, Serialize)]
+pub struct ListedForm {
+    pub id: i64,
+    pub title: String,
+    pub updated_at: String,
+    pub tags: Vec<String>,
+}
+
+/// Tags from a comma-separated list, lowercased, in order and without
+/// repeats.
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(|tag| tag.trim().to_lowercase().chars().take(MAX_TAG_LENGTH).collect::<String>())
+        .filter(|tag| !tag.is_empty())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+pub async fn tags(db: &SqlitePool, form_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT tag FROM form_tags WHERE form_id = ? ORDER BY tag", form_id)
+        .fetch_all(db)
+        .await
+}
+
+/// Lists or unlists the author's form and replaces its tags. Returns false
+/// when the author has no such form.
+pub async fn set_listing(db: &SqlitePool, form_id: i64, author_id: i64, listed: bool, tags: &[String]) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let updated = sqlx::query!("UPDATE forms SET listed = ? WHERE id = ? AND author_id = ?", listed, form_id, author_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    if updated == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!("DELETE FROM form_tags WHERE form_id = ?", form_id)
+        .execute(&mut *tx)
+        .await?;
+    for tag in tags {
+        sqlx::query!("INSERT INTO form_tags (form_id, tag) VALUES (?, ?)", form_id, tag)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// The organization's listed, published forms whose title contains `query`
+/// and that carry `tag`, most recently edited first. Unlisted forms never
+/// show up here, however they are searched for.
+pub async fn search(db: &SqlitePool, tenant_id: i64, query: Option<&str>, tag: Option<&str>) -> Result<Vec<ListedForm>, sqlx::Error> {
+    let query = query.map(|query| query.trim().to_lowercase()).unwrap_or_default();
+    let rows = sqlx::query!(
+        r#"SELECT f.id, f.title, f.updated_at, (SELECT group_concat(t.tag, ',') FROM form_tags t WHERE t.form_id = f.id) AS "tags?: String"
+         FROM forms f
+         WHERE f.tenant_id = ?1 AND f.published = true AND f.listed = true
+         AND instr(lower(f.title), ?2) > 0
+         AND (?3 IS NULL OR EXISTS (SELECT 1 FROM form_tags t WHERE t.form_id = f.id AND t.tag = ?3))
+         ORDER BY f.updated_at DESC, f.id DESC"#,
+        tenant_id,
+        query,
+        tag
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter()
+        .map(|row| ListedForm {
+            id: row.id,
+            title: row.title,
+            updated_at: row.updated_at,
+            tags: row.tags.as_deref().map(parse_tags).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Every tag on the organization's listed forms, for browsing by tag.
+pub async fn listed_tags(db: &SqlitePool, tenant_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT DISTINCT t.tag FROM form_tags t JOIN forms f ON f.id = t.form_id
+         WHERE f.tenant_id = ? AND f.published = true AND f.listed = true ORDER BY t.tag",
+        tenant_id
+    )
+    .fetch_all(db)
+    .await
+}
@@ -0,0 +1,75 @@
+use rocket::tokio::sync::broadcast::{self, Receiver, Sender, error::RecvError};
+
+use crate::models::FormResponse;
+
+const CAPACITY: usize = 1024;
+
+/// Something that happened that other features may want to react to.
+/// Whoever makes it happen publishes it; notifications, hooks, live
+/// results and the audit log subscribe.
+#[derive(Debug, Clone)]
+pub enum DomainEvent {
+    FormPublished { form_id: i64 },
+    FormUnpublished { form_id: i64 },
+    FormDeleted { form_id: i64, author_id: i64 },
+    /// A complete response: stored, paid for if it owes anything and not
+    /// held as spam. Answers are decrypted.
+    ResponseSubmitted(FormResponse),
+    /// `via` is how the account was created: `password`, `oidc` or `scim`.
+    UserRegistered { user_id: i64, via: &'static str },
+}
+
+impl DomainEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DomainEvent::FormPublished { .. } => "form.published",
+            DomainEvent::FormUnpublished { .. } => "form.unpublished",
+            DomainEvent::FormDeleted { .. } => "form.deleted",
+            DomainEvent::ResponseSubmitted(_) => "response.submitted",
+            DomainEvent::UserRegistered { .. } => "user.registered",
+        }
+    }
+
+    pub fn form_id(&self) -> Option<i64> {
+        match self {
+            DomainEvent::FormPublished { form_id }
+            | DomainEvent::FormUnpublished { form_id }
+            | DomainEvent::FormDeleted { form_id, .. } => Some(*form_id),
+            DomainEvent::ResponseSubmitted(response) => Some(response.form_id),
+            DomainEvent::UserRegistered { .. } => None,
+        }
+    }
+}
+
+/// In-process only: events published while nothing is subscribed, or that a
+/// subscriber falls too far behind on, are gone.
+#[derive(Clone)]
+pub struct EventBus(Sender<DomainEvent>);
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus(broadcast::channel(CAPACITY).0)
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: DomainEvent) {
+        let _ = self.0.send(event);
+    }
+
+    pub fn subscribe(&self) -> Receiver<DomainEvent> {
+        self.0.subscribe()
+    }
+}
+
+/// The next event for `subscriber`, logging any it fell behind on. `None`
+/// once the bus is gone.
+pub async fn next(events: &mut Receiver<DomainEvent>, subscriber: &str) -> Option<DomainEvent> {
+    loop {
+        match events.recv().await {
+            Ok(event) => return Some(event),
+            Err(RecvError::Lagged(skipped)) => warn!("{} lagged, skipped {} events", subscriber, skipped),
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::Cursor;
+
+use rocket::http::{ContentType, Header};
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+
+use crate::analytics::CrossTab;
+use crate::consent::ConsentRecord;
+use crate::schema::FieldDef;
+use crate::models::FormResponse;
+use crate::revisions::Introduction;
+
+pub struct Csv {
+    pub filename: String,
+    pub body: String,
+}
+
+impl<'r> Responder<'r, 'static> for Csv {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(ContentType::CSV)
+            .header(Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", self.filename)))
+            .sized_body(self.body.len(), Cursor::new(self.body))
+            .ok()
+    }
+}
+
+/// Wraps a streamed body, typically a `TextStream`, as a newline-delimited
+/// JSON download.
+pub struct Ndjson<R> {
+    pub filename: String,
+    pub body: R,
+}
+
+impl<'r, R: Responder<'r, 'r>> Responder<'r, 'r> for Ndjson<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'r> {
+        let mut response = self.body.respond_to(request)?;
+        response.set_header(ContentType::new("application", "x-ndjson"));
+        response.set_header(Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", self.filename)));
+        Ok(response)
+    }
+}
+
+fn escape(value: &str) -> String {
+    let value = match value.chars().next() {
+        Some('=' | '+' | '-' | '@') => format!("'{}", value),
+        _ => value.to_string(),
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn row<'a>(values: impl IntoIterator<Item = &'a str>) -> String {
+    let mut line = values.into_iter().map(escape).collect::<Vec<_>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+pub fn responses_csv(fields: &[FieldDef], responses: &[FormResponse], tags: &HashMap<i64, Vec<String>>) -> String {
+    let answers: Vec<HashMap<String, String>> = responses.iter()
+        .map(|response| serde_json::from_str(&response.answers).unwrap_or_default())
+        .collect();
+
+    let keys: Vec<String> = if fields.is_empty() {
+        answers.iter().flat_map(|answers| answers.keys().cloned()).collect::<BTreeSet<_>>().into_iter().collect()
+    } else {
+        fields.iter().map(|field| field.key.clone()).collect()
+    };
+
+    let mut body = row(["id", "created_at", "status", "tags"].into_iter().chain(keys.iter().map(String::as_str)));
+    for (response, answers) in responses.iter().zip(&answers) {
+        let id = response.id.to_string();
+        let tags = tags.get(&response.id).map(|tags| tags.join("; ")).unwrap_or_default();
+        let values: Vec<String> = keys.iter()
+            .map(|key| {
+                let value = answers.get(key).map(String::as_str).unwrap_or_default();
+                match fields.iter().find(|field| &field.key == key) {
+                    Some(field) if !value.is_empty() => field.field_type().export_value(field, value),
+                    _ => value.to_string(),
+                }
+            })
+            .collect();
+        body.push_str(&row([id.as_str(), &response.created_at, &response.status, &tags].into_iter().chain(values.iter().map(String::as_str))));
+    }
+    body
+}
+
+/// One response as a single JSON line. Answers are expected to be decrypted
+/// already.
+pub fn response_line(response: &FormResponse) -> String {
+    let answers: HashMap<String, String> = serde_json::from_str(&response.answers).unwrap_or_default();
+    let mut line = serde_json::json!({
+        "id": response.id,
+        "form_id": response.form_id,
+        "created_at": response.created_at,
+        "updated_at": response.updated_at,
+        "status": response.status,
+        "assigned_to": response.assigned_to,
+        "respondent_email": response.respondent_email,
+        "duplicate_of": response.duplicate_of,
+        "is_test": response.is_test,
+        "payment_status": response.payment_status,
+        "payment_amount": response.payment_amount,
+        "payment_refunded": response.payment_refunded,
+        "answers": answers,
+    })
+    .to_string();
+    line.push('\n');
+    line
+}
+
+/// Counts per column, the row total, then each count as a row percentage.
+pub fn cross_tab_csv(table: &CrossTab) -> String {
+    let corner = format!("{} / {}", table.row_field, table.column_field);
+    let percent_headers: Vec<String> = table.columns.iter().map(|column| format!("{} %", column)).collect();
+    let mut body = row([corner.as_str()].into_iter()
+        .chain(table.columns.iter().map(String::as_str))
+        .chain(["total"])
+        .chain(percent_headers.iter().map(String::as_str)));
+
+    for cross_tab_row in &table.rows {
+        let counts: Vec<String> = cross_tab_row.counts.iter().map(i64::to_string).collect();
+        let total = cross_tab_row.total.to_string();
+        let percentages: Vec<String> = cross_tab_row.percentages.iter().map(|percentage| format!("{:.1}", percentage)).collect();
+        body.push_str(&row([cross_tab_row.value.as_str()].into_iter()
+            .chain(counts.iter().map(String::as_str))
+            .chain([total.as_str()])
+            .chain(percentages.iter().map(String::as_str))));
+    }
+
+    let column_totals: Vec<String> = table.column_totals.iter().map(i64::to_string).collect();
+    let total = table.total.to_string();
+    body.push_str(&row(["total"].into_iter().chain(column_totals.iter().map(String::as_str)).chain([total.as_str()])));
+    body
+}
+
+/// A codebook of the form's fields for whoever analyses the responses:
+/// what each column of the responses export holds, each option exactly as
+/// answers store it (one per line), the rules and payment bounds answers
+/// were checked against, and when the field was added. Amounts are in the
+/// currency's minor unit.
+pub fn dictionary_csv(fields: &[FieldDef], introductions: &HashMap<String, Introduction>) -> String {
+    let mut body = row([
+        "key", "label", "description", "type", "options", "required", "min", "max", "pattern", "amount", "min_amount", "max_amount",
+        "shown_if", "encrypted", "introduced_in", "introduced_at",
+    ]);
+    for field in fields {
+        let options = field.options.join("\n");
+        let min = field.min.map(|min| min.to_string()).unwrap_or_default();
+        let max = field.max.map(|max| max.to_string()).unwrap_or_default();
+        let amount = field.amount.map(|amount| amount.to_string()).unwrap_or_default();
+        let min_amount = field.min_amount.map(|amount| amount.to_string()).unwrap_or_default();
+        let max_amount = field.max_amount.map(|amount| amount.to_string()).unwrap_or_default();
+        let shown_if = field.show_if.as_ref()
+            .map(|condition| format!("{} = {}", condition.field, condition.equals))
+            .unwrap_or_default();
+        let introduction = introductions.get(&field.key);
+        let version = introduction.map(|introduction| introduction.version.to_string()).unwrap_or_default();
+        body.push_str(&row([
+            field.key.as_str(),
+            field.label.as_str(),
+            field.description.as_deref().unwrap_or_default(),
+            field.kind.name(),
+            options.as_str(),
+            if field.required { "yes" } else { "no" },
+            min.as_str(),
+            max.as_str(),
+            field.pattern.as_deref().unwrap_or_default(),
+            amount.as_str(),
+            min_amount.as_str(),
+            max_amount.as_str(),
+            shown_if.as_str(),
+            if field.encrypted { "yes" } else { "no" },
+            version.as_str(),
+            introduction.map_or("", |introduction| introduction.at.as_str()),
+        ]));
+    }
+    body
+}
+
+pub fn consent_log_csv(records: &[ConsentRecord]) -> String {
+    let mut body = row(["id", "response_id", "field", "consented_at", "text_hash", "text"]);
+    for record in records {
+        let id = record.id.to_string();
+        let response_id = record.response_id.map(|id| id.to_string()).unwrap_or_default();
+        body.push_str(&row([
+            id.as_str(),
+            response_id.as_str(),
+            record.field_key.as_str(),
+            record.consented_at.as_str(),
+            record.text_hash.as_str(),
+            record.text.as_str(),
+        ]));
+    }
+    body
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::schema::{FieldDef, FieldKind, escape_attribute};
+
+mod checkbox;
+mod choice;
+mod date;
+mod number;
+mod payment;
+mod slot;
+mod text;
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// How a field is drawn on the printed blank form: lines to write the
+/// answer on, or a box to tick beside each choice.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrintLayout {
+    pub lines: usize,
+    pub boxes: Vec<String>,
+}
+
+impl PrintLayout {
+    pub fn lines(lines: usize) -> Self {
+        PrintLayout { lines, boxes: Vec::new() }
+    }
+
+    pub fn boxes(boxes: Vec<String>) -> Self {
+        PrintLayout { lines: 0, boxes }
+    }
+
+    /// Roughly how many lines of the page the field takes up.
+    pub fn height(&self) -> usize {
+        self.lines.max(self.boxes.len()).max(1)
+    }
+}
+
+/// Everything the app does with one type of field. A new type is a module
+/// implementing this, added to `Registry::builtin`; field definitions then
+/// use its `name` as their `type`. Type-specific settings beyond the common
+/// ones on `FieldDef` go in the field's `settings`.
+pub trait FieldType: Send + Sync {
+    /// The `type` in field definitions.
+    fn name(&self) -> &'static str;
+
+    /// An `<input>` type, or `textarea`, `select` or `payment` for the
+    /// templates that draw those themselves.
+    fn input_type(&self, field: &FieldDef) -> &'static str;
+
+    /// HTML attributes beyond the name, id, type, `required` and
+    /// `show_if` ones every field gets.
+    fn attributes(&self, _field: &FieldDef) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Anything else the public form template needs to draw the field.
+    fn render_context(&self, _field: &FieldDef) -> Value {
+        Value::Null
+    }
+
+    /// The field's property in the form's JSON Schema, without its title.
+    fn json_schema(&self, _field: &FieldDef) -> Value {
+        json!({ "type": "string" })
+    }
+
+    /// Whether a non-empty `value` counts as an answer to a required field.
+    fn answered(&self, _field: &FieldDef, _value: &str) -> bool {
+        true
+    }
+
+    /// Checks a trimmed, non-empty answer.
+    fn validate(&self, field: &FieldDef, value: &str) -> Result<(), String>;
+
+    /// How an answer appears in exports.
+    fn export_value(&self, _field: &FieldDef, value: &str) -> String {
+        value.to_string()
+    }
+
+    /// Whether answers can be aggregated by value, for charts and cross
+    /// tabs.
+    fn aggregate(&self, _field: &FieldDef) -> bool {
+        false
+    }
+
+    /// How the field appears on paper; `None` leaves it off, for fields
+    /// that cannot be answered there.
+    fn print(&self, _field: &FieldDef) -> Option<PrintLayout> {
+        Some(PrintLayout::lines(1))
+    }
+}
+
+#[derive(Default)]
+pub struct Registry {
+    types: HashMap<&'static str, Box<dyn FieldType>>,
+}
+
+impl Registry {
+    pub fn builtin() -> Self {
+        Registry::default()
+            .with(text::Text)
+            .with(text::Textarea)
+            .with(text::Email)
+            .with(number::Number)
+            .with(choice::Choice)
+            .with(checkbox::Checkbox)
+            .with(checkbox::Consent)
+            .with(date::Date)
+            .with(slot::Slot)
+            .with(payment::Payment)
+    }
+
+    /// A type with the name of one already registered replaces it.
+    pub fn with(mut self, field_type: impl FieldType + 'static) -> Self {
+        self.types.insert(field_type.name(), Box::new(field_type));
+        self
+    }
+
+    /// Types nothing is registered for are treated as text.
+    pub fn get(&self, kind: &FieldKind) -> &dyn FieldType {
+        match self.types.get(kind.name()) {
+            Some(field_type) => field_type.as_ref(),
+            None => &text::Text,
+        }
+    }
+}
+
+pub fn get(kind: &FieldKind) -> &'static dyn FieldType {
+    REGISTRY.get_or_init(Registry::builtin).get(kind)
+}
+
+/// The `pattern` attribute of the text-like types.
+fn pattern_attribute(field: &FieldDef) -> Option<String> {
+    field.pattern.as_ref().map(|pattern| format!("pattern=\"{}\"", escape_attribute(pattern)))
+}
+
+fn check_pattern(field: &FieldDef, value: &str) -> Result<(), String> {
+    let Some(pattern) = &field.pattern else {
+        return Ok(());
+    };
+    match Regex::new(&format!("^(?:{})$", pattern)) {
+        Ok(pattern) if !pattern.is_match(value) => Err("is not in the expected format".to_string()),
+        Ok(_) => Ok(()),
+        Err(e) => {
+            warn!("Ignoring invalid pattern on field {}: {}", field.key, e);
+            Ok(())
+        }
+    }
+}
+
+fn with_pattern(field: &FieldDef, mut schema: Value) -> Value {
+    if let Some(pattern) = &field.pattern {
+        schema["pattern"] = json!(format!("^(?:{})$", pattern));
+    }
+    schema
+}
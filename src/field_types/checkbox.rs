@@ -0,0 +1,74 @@
+use serde_json::{Value, json};
+
+use super::{FieldType, PrintLayout};
+use crate::schema::{self, FieldDef};
+
+fn check_boolean(value: &str) -> Result<(), String> {
+    if !matches!(value, "true" | "false" | "on") {
+        return Err("must be true or false".to_string());
+    }
+    Ok(())
+}
+
+pub struct Checkbox;
+
+impl FieldType for Checkbox {
+    fn name(&self) -> &'static str {
+        "checkbox"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "checkbox"
+    }
+
+    fn json_schema(&self, _field: &FieldDef) -> Value {
+        json!({ "type": "boolean" })
+    }
+
+    fn validate(&self, _field: &FieldDef, value: &str) -> Result<(), String> {
+        check_boolean(value)
+    }
+
+    fn aggregate(&self, _field: &FieldDef) -> bool {
+        true
+    }
+
+    fn print(&self, _field: &FieldDef) -> Option<PrintLayout> {
+        Some(PrintLayout::boxes(vec!["Yes".to_string()]))
+    }
+}
+
+/// A checkbox agreeing to `legal_text`. Each submission that ticks it is
+/// logged with the exact text it agreed to.
+pub struct Consent;
+
+impl FieldType for Consent {
+    fn name(&self) -> &'static str {
+        "consent"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "checkbox"
+    }
+
+    fn json_schema(&self, field: &FieldDef) -> Value {
+        let mut schema = json!({ "type": "boolean" });
+        if let Some(legal_text) = &field.legal_text {
+            schema["description"] = json!(legal_text);
+        }
+        schema
+    }
+
+    /// Only a ticked box agrees.
+    fn answered(&self, _field: &FieldDef, value: &str) -> bool {
+        schema::checked(value)
+    }
+
+    fn validate(&self, _field: &FieldDef, value: &str) -> Result<(), String> {
+        check_boolean(value)
+    }
+
+    fn print(&self, _field: &FieldDef) -> Option<PrintLayout> {
+        Some(PrintLayout::boxes(vec!["I agree".to_string()]))
+    }
+}
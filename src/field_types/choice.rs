@@ -0,0 +1,35 @@
+use serde_json::{Value, json};
+
+use super::{FieldType, PrintLayout};
+use crate::schema::FieldDef;
+
+pub struct Choice;
+
+impl FieldType for Choice {
+    fn name(&self) -> &'static str {
+        "choice"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "select"
+    }
+
+    fn json_schema(&self, field: &FieldDef) -> Value {
+        json!({ "type": "string", "enum": field.options })
+    }
+
+    fn validate(&self, field: &FieldDef, value: &str) -> Result<(), String> {
+        if !field.options.iter().any(|option| option == value) {
+            return Err(format!("must be one of: {}", field.options.join(", ")));
+        }
+        Ok(())
+    }
+
+    fn aggregate(&self, _field: &FieldDef) -> bool {
+        true
+    }
+
+    fn print(&self, field: &FieldDef) -> Option<PrintLayout> {
+        Some(PrintLayout::boxes(field.options.clone()))
+    }
+}
@@ -0,0 +1,30 @@
+use serde_json::{Value, json};
+
+use super::FieldType;
+use crate::schema::FieldDef;
+
+pub struct Date;
+
+impl FieldType for Date {
+    fn name(&self) -> &'static str {
+        "date"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "date"
+    }
+
+    fn json_schema(&self, _field: &FieldDef) -> Value {
+        json!({ "type": "string", "format": "date" })
+    }
+
+    fn validate(&self, _field: &FieldDef, value: &str) -> Result<(), String> {
+        let parts: Vec<&str> = value.split('-').collect();
+        let valid = parts.len() == 3
+            && [4, 2, 2].iter().zip(&parts).all(|(len, part)| part.len() == *len && part.chars().all(|c| c.is_ascii_digit()));
+        if !valid {
+            return Err("must be a date (YYYY-MM-DD)".to_string());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use serde_json::{Value, json};
+
+use super::FieldType;
+use crate::schema::FieldDef;
+
+pub struct Number;
+
+impl FieldType for Number {
+    fn name(&self) -> &'static str {
+        "number"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "number"
+    }
+
+    fn attributes(&self, field: &FieldDef) -> Vec<String> {
+        let mut attributes = vec!["step=\"any\"".to_string()];
+        if let Some(min) = field.min {
+            attributes.push(format!("min=\"{}\"", min));
+        }
+        if let Some(max) = field.max {
+            attributes.push(format!("max=\"{}\"", max));
+        }
+        attributes
+    }
+
+    fn json_schema(&self, field: &FieldDef) -> Value {
+        let mut schema = json!({ "type": "number" });
+        if let Some(min) = field.min {
+            schema["minimum"] = json!(min);
+        }
+        if let Some(max) = field.max {
+            schema["maximum"] = json!(max);
+        }
+        schema
+    }
+
+    fn validate(&self, field: &FieldDef, value: &str) -> Result<(), String> {
+        let number: f64 = value.parse().map_err(|_| "must be a number".to_string())?;
+        if let Some(min) = field.min.filter(|min| number < *min) {
+            return Err(format!("must be at least {}", min));
+        }
+        if let Some(max) = field.max.filter(|max| number > *max) {
+            return Err(format!("must be at most {}", max));
+        }
+        Ok(())
+    }
+}
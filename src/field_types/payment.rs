@@ -0,0 +1,70 @@
+use serde_json::{Value, json};
+
+use super::{FieldType, PrintLayout};
+use crate::schema::{FieldDef, format_amount, parse_amount};
+
+/// Charges through Stripe Checkout once the rest of the form is valid.
+/// With `amount` set the respondent does not answer it; without, they
+/// choose the amount, e.g. `25.50`.
+pub struct Payment;
+
+impl FieldType for Payment {
+    fn name(&self) -> &'static str {
+        "payment"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "payment"
+    }
+
+    fn attributes(&self, field: &FieldDef) -> Vec<String> {
+        if field.amount.is_some() {
+            return Vec::new();
+        }
+        let mut attributes = vec![
+            "type=\"number\" step=\"0.01\"".to_string(),
+            format!("min=\"{}\"", format_amount(field.min_amount.unwrap_or(1))),
+        ];
+        if let Some(max) = field.max_amount {
+            attributes.push(format!("max=\"{}\"", format_amount(max)));
+        }
+        attributes
+    }
+
+    /// Amounts as the respondent sees them.
+    fn render_context(&self, field: &FieldDef) -> Value {
+        json!({
+            "amount": field.amount.map(format_amount),
+            "presets": field.presets.iter().copied().map(format_amount).collect::<Vec<_>>(),
+        })
+    }
+
+    fn json_schema(&self, field: &FieldDef) -> Value {
+        if field.amount.is_some() {
+            json!({ "type": "string", "readOnly": true })
+        } else {
+            json!({ "type": "string", "pattern": "^[0-9]+(\\.[0-9]{1,2})?$" })
+        }
+    }
+
+    fn validate(&self, field: &FieldDef, value: &str) -> Result<(), String> {
+        // A fixed amount ignores whatever was posted for it.
+        if field.amount.is_some() {
+            return Ok(());
+        }
+        let amount = parse_amount(value).ok_or("must be an amount such as 25.00")?;
+        let min = field.min_amount.unwrap_or(1);
+        if amount < min {
+            return Err(format!("must be at least {}", format_amount(min)));
+        }
+        if let Some(max) = field.max_amount.filter(|max| amount > *max) {
+            return Err(format!("must be at most {}", format_amount(max)));
+        }
+        Ok(())
+    }
+
+    /// Paper cannot take a card, so printed forms leave the payment out.
+    fn print(&self, _field: &FieldDef) -> Option<PrintLayout> {
+        None
+    }
+}
@@ -0,0 +1,29 @@
+use serde_json::{Value, json};
+
+use super::FieldType;
+use crate::schema::FieldDef;
+
+/// A choice of the form's bookable time slots, answered with a slot id.
+/// Whether the slot is still free is checked when it is booked.
+pub struct Slot;
+
+impl FieldType for Slot {
+    fn name(&self) -> &'static str {
+        "slot"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "select"
+    }
+
+    fn json_schema(&self, _field: &FieldDef) -> Value {
+        json!({ "type": "integer" })
+    }
+
+    fn validate(&self, _field: &FieldDef, value: &str) -> Result<(), String> {
+        if value.parse::<i64>().is_err() {
+            return Err("must be one of the available slots".to_string());
+        }
+        Ok(())
+    }
+}
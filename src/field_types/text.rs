@@ -0,0 +1,124 @@
+use serde_json::{Value, json};
+
+use super::{FieldType, PrintLayout, check_pattern, pattern_attribute, with_pattern};
+use crate::schema::FieldDef;
+
+const TEXTAREA_PRINT_LINES: usize = 5;
+
+/// `min` and `max` bound the length in characters.
+fn length_attributes(field: &FieldDef) -> Vec<String> {
+    let mut attributes = Vec::new();
+    if let Some(min) = field.min {
+        attributes.push(format!("minlength=\"{}\"", min as u64));
+    }
+    if let Some(max) = field.max {
+        attributes.push(format!("maxlength=\"{}\"", max as u64));
+    }
+    attributes.extend(pattern_attribute(field));
+    attributes
+}
+
+fn length_schema(field: &FieldDef) -> Value {
+    let mut schema = json!({ "type": "string" });
+    if let Some(min) = field.min.or(field.required.then_some(1.0)) {
+        schema["minLength"] = json!(min as u64);
+    }
+    if let Some(max) = field.max {
+        schema["maxLength"] = json!(max as u64);
+    }
+    with_pattern(field, schema)
+}
+
+fn check_length(field: &FieldDef, value: &str) -> Result<(), String> {
+    check_pattern(field, value)?;
+    let length = value.chars().count() as f64;
+    if let Some(min) = field.min.filter(|min| length < *min) {
+        return Err(format!("must be at least {} characters", min));
+    }
+    if let Some(max) = field.max.filter(|max| length > *max) {
+        return Err(format!("must be at most {} characters", max));
+    }
+    Ok(())
+}
+
+pub struct Text;
+
+impl FieldType for Text {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "text"
+    }
+
+    fn attributes(&self, field: &FieldDef) -> Vec<String> {
+        length_attributes(field)
+    }
+
+    fn json_schema(&self, field: &FieldDef) -> Value {
+        length_schema(field)
+    }
+
+    fn validate(&self, field: &FieldDef, value: &str) -> Result<(), String> {
+        check_length(field, value)
+    }
+}
+
+pub struct Textarea;
+
+impl FieldType for Textarea {
+    fn name(&self) -> &'static str {
+        "textarea"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "textarea"
+    }
+
+    fn attributes(&self, field: &FieldDef) -> Vec<String> {
+        length_attributes(field)
+    }
+
+    fn json_schema(&self, field: &FieldDef) -> Value {
+        length_schema(field)
+    }
+
+    fn validate(&self, field: &FieldDef, value: &str) -> Result<(), String> {
+        check_length(field, value)
+    }
+
+    fn print(&self, _field: &FieldDef) -> Option<PrintLayout> {
+        Some(PrintLayout::lines(TEXTAREA_PRINT_LINES))
+    }
+}
+
+pub struct Email;
+
+impl FieldType for Email {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn input_type(&self, _field: &FieldDef) -> &'static str {
+        "email"
+    }
+
+    fn attributes(&self, field: &FieldDef) -> Vec<String> {
+        pattern_attribute(field).into_iter().collect()
+    }
+
+    fn json_schema(&self, field: &FieldDef) -> Value {
+        with_pattern(field, json!({ "type": "string", "format": "email" }))
+    }
+
+    fn validate(&self, field: &FieldDef, value: &str) -> Result<(), String> {
+        check_pattern(field, value)?;
+        let valid = value.split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.') && !domain.starts_with('.'));
+        if !valid {
+            return Err("must be an email address".to_string());
+        }
+        Ok(())
+    }
+}
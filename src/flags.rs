@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+/// Toggles made in the admin page reach other processes within this long.
+const FLAG_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Capabilities that can be switched off for the whole deployment, in the
+/// `[flags]` configuration table, or for one organization from the admin
+/// page. A flag the configuration does not mention is on, so existing
+/// deployments keep working as before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Payments,
+    Scripting,
+    LiveResults,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 3] = [Flag::Payments, Flag::Scripting, Flag::LiveResults];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Flag::Payments => "payments",
+            Flag::Scripting => "scripting",
+            Flag::LiveResults => "live_results",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Flag::Payments => "Payment fields charge through Stripe Checkout",
+            Flag::Scripting => "Forms run their custom validation scripts",
+            Flag::LiveResults => "Forms can show their results live to the public",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Flag> {
+        Flag::ALL.into_iter().find(|flag| flag.name() == name)
+    }
+
+    fn deployment_default(self, config: &HashMap<String, bool>) -> bool {
+        config.get(self.name()).copied().unwrap_or(true)
+    }
+}
+
+/// One flag as the admin page shows it.
+#[derive(Debug, Serialize)]
+pub struct FlagSetting {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub deployment: bool,
+    /// The organization's own choice, if it made one.
+    pub organization: Option<bool>,
+    pub enabled: bool,
+}
+
+/// Each organization's overrides, so checking a flag on every submission
+/// skips the database.
+#[derive(Default)]
+pub struct FlagCache {
+    tenants: RwLock<HashMap<i64, (Instant, HashMap<String, bool>)>>,
+}
+
+impl FlagCache {
+    async fn overrides(&self, db: &SqlitePool, tenant_id: i64) -> Result<HashMap<String, bool>, sqlx::Error> {
+        let cached = self.tenants.read().unwrap()
+            .get(&tenant_id)
+            .filter(|(cached_at, _)| cached_at.elapsed() < FLAG_CACHE_TTL)
+            .map(|(_, overrides)| overrides.clone());
+        if let Some(overrides) = cached {
+            return Ok(overrides);
+        }
+
+        let overrides: HashMap<String, bool> = sqlx::query!("SELECT flag, enabled FROM feature_flags WHERE tenant_id = ?", tenant_id)
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|row| (row.flag, row.enabled))
+            .collect();
+        self.tenants.write().unwrap().insert(tenant_id, (Instant::now(), overrides.clone()));
+        Ok(overrides)
+    }
+
+    pub async fn enabled(&self, db: &SqlitePool, config: &HashMap<String, bool>, tenant_id: i64, flag: Flag) -> Result<bool, sqlx::Error> {
+        let overrides = self.overrides(db, tenant_id).await?;
+        Ok(overrides.get(flag.name()).copied().unwrap_or_else(|| flag.deployment_default(config)))
+    }
+
+    /// As [`FlagCache::enabled`], for the organization whose member wrote
+    /// `form_id`. A form that is gone gets the deployment's setting.
+    pub async fn enabled_for_form(&self, db: &SqlitePool, config: &HashMap<String, bool>, form_id: i64, flag: Flag) -> Result<bool, sqlx::Error> {
+        let tenant_id = sqlx::query_scalar!("SELECT tenant_id FROM forms WHERE id = ?", form_id)
+            .fetch_optional(db)
+            .await?;
+
+        match tenant_id {
+            Some(tenant_id) => self.enabled(db, config, tenant_id, flag).await,
+            None => Ok(flag.deployment_default(config)),
+        }
+    }
+
+    pub async fn settings(&self, db: &SqlitePool, config: &HashMap<String, bool>, tenant_id: i64) -> Result<Vec<FlagSetting>, sqlx::Error> {
+        let overrides = self.overrides(db, tenant_id).await?;
+        Ok(Flag::ALL.into_iter()
+            .map(|flag| {
+                let deployment = flag.deployment_default(config);
+                let organization = overrides.get(flag.name()).copied();
+                FlagSetting {
+                    name: flag.name(),
+                    description: flag.description(),
+                    deployment,
+                    organization,
+                    enabled: organization.unwrap_or(deployment),
+                }
+            })
+            .collect())
+    }
+
+    /// Sets the organization's choice for `flag`; `None` goes back to the
+    /// deployment's setting.
+    pub async fn set(&self, db: &SqlitePool, tenant_id: i64, flag: Flag, enabled: Option<bool>, user_id: i64) -> Result<(), sqlx::Error> {
+        let name = flag.name();
+        match enabled {
+            Some(enabled) => sqlx::query!(
+                "INSERT INTO feature_flags (tenant_id, flag, enabled, updated_by) VALUES (?, ?, ?, ?)
+                 ON CONFLICT (tenant_id, flag) DO UPDATE SET enabled = excluded.enabled, updated_by = excluded.updated_by, updated_at = CURRENT_TIMESTAMP",
+                tenant_id,
+                name,
+                enabled,
+                user_id
+            )
+            .execute(db)
+            .await?,
+            None => sqlx::query!("DELETE FROM feature_flags WHERE tenant_id = ? AND flag = ?", tenant_id, name)
+                .execute(db)
+                .await?,
+        };
+        self.invalidate(tenant_id);
+
+        Ok(())
+    }
+
+    pub fn invalidate(&self, tenant_id: i64) {
+        self.tenants.write().unwrap().remove(&tenant_id);
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::schema;
+
+#[derive(Debug, Serialize)]
+pub struct FormTemplate {
+    pub form_id: i64,
+    pub title: String,
+    pub locked_fields: Vec<String>,
+}
+
+/// Field keys from a comma-separated list, in order and without repeats.
+pub fn parse_locked(fields: &str) -> Vec<String> {
+    fields.split(',')
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// The organization's templates, offered on every member's "New form"
+/// screen.
+pub async fn available(db: &SqlitePool, tenant_id: i64) -> Result<Vec<FormTemplate>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT f.id, f.title, (SELECT group_concat(l.field, ',') FROM form_locked_fields l WHERE l.form_id = f.id) AS "locked?: String"
+         FROM form_templates t JOIN forms f ON f.id = t.form_id
+         WHERE f.tenant_id = ? ORDER BY f.title, f.id"#,
+        tenant_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter()
+        .map(|row| FormTemplate {
+            form_id: row.id,
+            title: row.title,
+            locked_fields: row.locked.as_deref().map(parse_locked).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Makes one of the organization's forms a template, or changes which of
+/// its fields are locked if it already is one. Keys the form does not have
+/// are dropped. Returns false when the form is not the organization's.
+pub async fn designate(db: &SqlitePool, tenant_id: i64, form_id: i64, locked: &[String]) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let fields = sqlx::query_scalar!(
+        "SELECT fields FROM forms WHERE id = ? AND tenant_id = ?",
+        form_id,
+        tenant_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(fields) = fields else {
+        return Ok(false);
+    };
+
+    sqlx::query!("INSERT INTO form_templates (form_id) VALUES (?) ON CONFLICT (form_id) DO NOTHING", form_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM form_locked_fields WHERE form_id = ?", form_id)
+        .execute(&mut *tx)
+        .await?;
+    let fields = schema::parse(&fields);
+    for field in locked.iter().filter(|key| fields.iter().any(|field| &field.key == *key)) {
+        sqlx::query!("INSERT INTO form_locked_fields (form_id, field) VALUES (?, ?)", form_id, field)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Existing copies keep their locks.
+pub async fn withdraw(db: &SqlitePool, tenant_id: i64, form_id: i64) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let withdrawn = sqlx::query!(
+        "DELETE FROM form_templates WHERE form_id = (SELECT id FROM forms WHERE id = ? AND tenant_id = ?)",
+        form_id,
+        tenant_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if withdrawn > 0 {
+        sqlx::query!("DELETE FROM form_locked_fields WHERE form_id = ?", form_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+pub async fn is_template(db: &SqlitePool, tenant_id: i64, form_id: i64) -> Result<bool, sqlx::Error> {
+    let template = sqlx::query_scalar!(
+        "SELECT t.form_id FROM form_templates t JOIN forms f ON f.id = t.form_id
+         WHERE t.form_id = ? AND f.tenant_id = ?",
+        form_id,
+        tenant_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(template.is_some())
+}
+
+/// The fields an edit must leave as they are. Templates lock fields only in
+/// their copies, so their own are never locked.
+pub async fn locked_fields(db: &SqlitePool, form_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT field FROM form_locked_fields WHERE form_id = ?1 AND NOT EXISTS (SELECT 1 FROM form_templates WHERE form_id = ?1)
+         ORDER BY field",
+        form_id
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// A field as a lock holds it: everything but its wording.
+fn locked_shape(field: &schema::FieldDef) -> Option<serde_json::Value> {
+    let mut field = field.clone();
+    field.label.clear();
+    field.description = None;
+    serde_json::to_value(field).ok()
+}
+
+/// The locked fields that replacing the form's fields with `fields` would
+/// remove or change in any way but their wording: type, whether it is
+/// required, when it shows, its options and its validation all stay.
+/// Rewording a locked field's label or description is allowed, so a typo in
+/// a template can still be fixed in its copies.
+pub async fn broken_locks(db: &SqlitePool, form_id: i64, fields: &str) -> Result<Vec<String>, sqlx::Error> {
+    let locked = locked_fields(db, form_id).await?;
+    if locked.is_empty() {
+        return Ok(locked);
+    }
+
+    let current = sqlx::query_scalar!("SELECT fields FROM forms WHERE id = ?", form_id)
+        .fetch_one(db)
+        .await?;
+    let current = schema::parse(&current);
+    let updated = schema::parse(fields);
+    let shape = |fields: &[schema::FieldDef], key: &str| {
+        fields.iter().find(|field| field.key == key).and_then(locked_shape)
+    };
+
+    Ok(locked.into_iter()
+        .filter(|key| shape(&updated, key) != shape(&current, key))
+        .collect())
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use async_graphql::connection::{self, Connection, Edge};
+use async_graphql::{ComplexObject, Context, EmptyMutation, EmptySubscription, InputObject, Json, Object, Result, Schema, SimpleObject};
+use async_graphql_rocket::{GraphQLRequest, GraphQLResponse};
+use rocket::http::Status;
+use rocket::State;
+use sqlx::SqlitePool;
+
+use crate::api::{ApiUser, Scope};
+use crate::crypto;
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+
+const MAX_PAGE_SIZE: usize = 100;
+
+pub type FormsSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+struct Viewer(i64);
+
+pub struct QueryRoot;
+
+#[derive(SimpleObject)]
+#[graphql(name = "Form", complex)]
+struct FormNode {
+    id: i64,
+    title: String,
+    published: bool,
+    created_at: String,
+    updated_at: String,
+    #[graphql(skip)]
+    fields: String,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Response")]
+struct ResponseNode {
+    id: i64,
+    status: String,
+    created_at: String,
+    updated_at: String,
+    answers: Json<HashMap<String, String>>,
+}
+
+#[derive(InputObject)]
+struct AnswerFilter {
+    field: String,
+    equals: String,
+}
+
+pub fn schema(db: SqlitePool) -> FormsSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(db)
+        .finish()
+}
+
+#[Object]
+impl QueryRoot {
+    async fn forms(&self, ctx: &Context<'_>, published: Option<bool>) -> Result<Vec<FormNode>> {
+        let db = ctx.data::<SqlitePool>()?;
+        let Viewer(user_id) = ctx.data::<Viewer>()?;
+
+        let forms = sqlx::query_as!(FormNode,
+            "SELECT id, title, published, created_at, updated_at, fields FROM forms WHERE author_id = ?1 AND (?2 IS NULL OR published = ?2) ORDER BY id",
+            user_id,
+            published
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(forms)
+    }
+
+    async fn form(&self, ctx: &Context<'_>, id: i64) -> Result<Option<FormNode>> {
+        let db = ctx.data::<SqlitePool>()?;
+        let Viewer(user_id) = ctx.data::<Viewer>()?;
+
+        let form = sqlx::query_as!(FormNode,
+            "SELECT id, title, published, created_at, updated_at, fields FROM forms WHERE id = ? AND author_id = ?",
+            id,
+            user_id
+        )
+        .fetch_optional(db)
+        .await?;
+
+        Ok(form)
+    }
+}
+
+#[ComplexObject]
+impl FormNode {
+    async fn fields(&self) -> Json<serde_json::Value> {
+        Json(serde_json::from_str(&self.fields).unwrap_or(serde_json::Value::String(self.fields.clone())))
+    }
+
+    async fn responses(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        first: Option<i32>,
+        filter: Option<Vec<AnswerFilter>>
+    ) -> Result<Connection<i64, ResponseNode>> {
+        let db = ctx.data::<SqlitePool>()?.clone();
+        let form_id = self.id;
+        let filters: Vec<serde_json::Value> = filter.unwrap_or_default()
+            .into_iter()
+            .map(|filter| serde_json::json!({
+                "path": format!("$.\"{}\"", filter.field.replace('"', "")),
+                "value": filter.equals,
+            }))
+            .collect();
+        let filters = serde_json::to_string(&filters)?;
+
+        connection::query(after, None, first, None, |after: Option<i64>, _before: Option<i64>, first, _last| async move {
+            let limit = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+            let fetch = (limit + 1) as i64;
+            let after = after.unwrap_or(0);
+
+            let mut rows = sqlx::query!(
+                "SELECT r.id, r.status, r.created_at, r.updated_at, r.answers FROM responses r
+                 WHERE r.form_id = ?1 AND r.id > ?2 AND r.is_test = false
+                 AND NOT EXISTS (
+                     SELECT 1 FROM json_each(?3) f
+                     WHERE json_extract(r.answers, json_extract(f.value, '$.path')) IS NOT json_extract(f.value, '$.value')
+                 )
+                 ORDER BY r.id LIMIT ?4",
+                form_id,
+                after,
+                filters,
+                fetch
+            )
+            .fetch_all(&db)
+            .await?;
+
+            let has_next = rows.len() > limit;
+            rows.truncate(limit);
+
+            let mut connection = Connection::new(after > 0, has_next);
+            connection.edges.extend(rows.into_iter().map(|row| Edge::new(row.id, ResponseNode {
+                id: row.id,
+                status: row.status,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                answers: Json(crypto::decrypt_answers(&row.answers)),
+            })));
+
+            Ok::<_, async_graphql::Error>(connection)
+        })
+        .await
+    }
+}
+
+#[post("/graphql", data = "<request>", format = "application/json")]
+pub async fn graphql_request(
+    schema: &State<FormsSchema>,
+    user: ApiUser,
+    request: GraphQLRequest
+) -> Result<GraphQLResponse, Status> {
+    user.require_all_forms(Scope::ReadForms)?;
+    user.require_all_forms(Scope::ReadResponses)?;
+    Ok(request.data(Viewer(user.0)).execute(schema.inner()).await)
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use rocket::http::{CookieJar, Status, private::PrivateCookies};
+use rocket::outcome::IntoOutcome;
+use rocket::request::{FromRequest, Outcome};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use sqlx::SqlitePool;
+
+use crate::{AppConfig, legal};
+use crate::models::Attribution;
+use crate::repository::UserRepository;
+use crate::tenant::Tenant;
+
+/// A signed-in user who has accepted the current terms of service and
+/// privacy policy, when the deployment configures them. Users with
+/// documents to accept are forwarded with `428 Precondition Required`,
+/// which redirects them to accept.
+pub struct AuthenticatedUser(pub i64);
+
+/// A signed-in user whether or not they have accepted the current legal
+/// documents. Only the pages for accepting them should take this.
+pub struct SignedInUser(pub i64);
+
+/// For pages anyone may see that show more to the signed-in. Unlike
+/// `Option<AuthenticatedUser>`, which would quietly show the page signed
+/// out, a user with legal documents to accept fails with `428` and is
+/// redirected to accept them.
+pub struct OptionalUser(pub Option<AuthenticatedUser>);
+
+pub struct Approver(pub i64);
+
+/// Refuses, with `403 Forbidden`, requests from a session impersonating
+/// another user. Taken alongside the user guard by routes that act for the
+/// account holder alone, such as minting credentials or accepting terms.
+pub struct NotImpersonating;
+
+/// The `Idempotency-Key` header, so a retried submission returns the
+/// response it already created. Browsers send the same key as a hidden
+/// [`IDEMPOTENCY_KEY_FIELD`] instead.
+pub struct IdempotencyKey(pub Option<String>);
+
+pub const IDEMPOTENCY_KEY_FIELD: &str = "_idempotency_key";
+
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+const MAX_ATTRIBUTION_LEN: usize = 512;
+
+/// A signed-in browser session.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub user_id: i64,
+    pub tenant_id: i64,
+    /// Set while an approver is acting as another user for support.
+    pub impersonating: Option<Impersonation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Impersonation {
+    pub user_id: i64,
+    pub username: String,
+    pub expires_at: Instant,
+}
+
+impl Session {
+    pub fn new(user_id: i64, tenant_id: i64) -> Self {
+        Session { user_id, tenant_id, impersonating: None }
+    }
+
+    /// The impersonation in force, if it has not expired.
+    pub fn impersonation(&self) -> Option<&Impersonation> {
+        self.impersonating.as_ref().filter(|impersonation| impersonation.expires_at > Instant::now())
+    }
+
+    /// Who requests in this session act as.
+    pub fn acting_user(&self) -> i64 {
+        self.impersonation().map_or(self.user_id, |impersonation| impersonation.user_id)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct SessionStore(pub Arc<RwLock<HashMap<String, Session>>>);
+
+/// How often the server looks for sessions `admin purge-sessions` ended.
+const SESSION_PURGE_INTERVAL: Duration = Duration::from_secs(5);
+
+impl SessionStore {
+    /// The session named by the `session_id` cookie, and its id.
+    pub fn current(&self, cookies: &CookieJar<'_>) -> Option<(String, Session)> {
+        let session_id = cookies.get_private("session_id")?.value().to_string();
+        let session = self.0.read().unwrap().get(&session_id)?.clone();
+        Some((session_id, session))
+    }
+
+    /// Signs the user out everywhere, and ends any impersonation of them.
+    pub fn end_user(&self, user_id: i64) {
+        let mut sessions = self.0.write().unwrap();
+        sessions.retain(|_, session| session.user_id != user_id);
+        sessions.values_mut()
+            .filter(|session| session.impersonating.as_ref().is_some_and(|impersonation| impersonation.user_id == user_id))
+            .for_each(|session| session.impersonating = None);
+    }
+
+    /// Signs everyone out.
+    pub fn end_all(&self) {
+        self.0.write().unwrap().clear();
+    }
+}
+
+/// Ends the sessions `admin purge-sessions` asked to end. The command runs
+/// in another process and cannot reach this one's memory, so it leaves a
+/// row in session_purges for this to pick up. Rows from before startup are
+/// skipped: no session they meant survived the restart.
+pub async fn watch_session_purges(db: SqlitePool, sessions: SessionStore) {
+    let seen = sqlx::query_scalar!(r#"SELECT COALESCE(MAX(id), 0) AS "id!: i64" FROM session_purges"#)
+        .fetch_one(&db)
+        .await;
+    let mut seen = match seen {
+        Ok(seen) => seen,
+        Err(e) => {
+            error!("Failed to read session purges, purge-sessions will not take effect: {}", e);
+            return;
+        }
+    };
+
+    let mut interval = rocket::tokio::time::interval(SESSION_PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let purges = match sqlx::query!("SELECT id, user_id FROM session_purges WHERE id > ? ORDER BY id", seen)
+            .fetch_all(&db)
+            .await
+        {
+            Ok(purges) => purges,
+            Err(e) => {
+                warn!("Failed to read session purges: {}", e);
+                continue;
+            }
+        };
+
+        for purge in purges {
+            match purge.user_id {
+                Some(user_id) => sessions.end_user(user_id),
+                None => sessions.end_all(),
+            }
+            seen = purge.id;
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SignedInUser {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        let session_store = request.rocket().state::<SessionStore>().unwrap();
+        let Outcome::Success(tenant) = request.guard::<Tenant>().await else {
+            return Outcome::Forward(());
+        };
+
+        session_store.current(request.cookies())
+            .filter(|(_, session)| session.tenant_id == tenant.id)
+            .map(|(_, session)| SignedInUser(session.acting_user()))
+            .or_forward(())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedUser {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        let SignedInUser(user_id) = match request.guard::<SignedInUser>().await {
+            Outcome::Success(user) => user,
+            Outcome::Error((status, _)) => return Outcome::Error((status, ())),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+
+        let config = &request.rocket().state::<AppConfig>().unwrap().legal;
+        let db = request.rocket().state::<SqlitePool>().unwrap();
+        // Cached so the several guards of one request check only once.
+        let accepted = request.local_cache_async(async {
+            legal::outstanding(db, config, user_id).await.map(|outstanding| outstanding.is_empty()).ok()
+        })
+        .await;
+
+        match accepted {
+            Some(true) => Outcome::Success(AuthenticatedUser(user_id)),
+            Some(false) => Outcome::Forward(Status::PreconditionRequired),
+            None => Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for OptionalUser {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.guard::<AuthenticatedUser>().await {
+            Outcome::Success(user) => Outcome::Success(OptionalUser(Some(user))),
+            Outcome::Forward(status) if status == Status::PreconditionRequired => Outcome::Error((status, ())),
+            Outcome::Forward(_) => Outcome::Success(OptionalUser(None)),
+            Outcome::Error((status, _)) => Outcome::Error((status, ())),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Approver {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        let AuthenticatedUser(user_id) = match request.guard::<AuthenticatedUser>().await {
+            Outcome::Success(user) => user,
+            Outcome::Error((status, _)) => return Outcome::Error((status, ())),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+
+        let db = request.rocket().state::<SqlitePool>().unwrap();
+        match db.is_approver(user_id).await {
+            Ok(Some(true)) => Outcome::Success(Approver(user_id)),
+            Ok(_) => Outcome::Forward(Status::Forbidden),
+            Err(_) => Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for NotImpersonating {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        let session_store = request.rocket().state::<SessionStore>().unwrap();
+        match session_store.current(request.cookies()) {
+            Some((_, session)) if session.impersonation().is_some() => Outcome::Error((Status::Forbidden, ())),
+            _ => Outcome::Success(NotImpersonating),
+        }
+    }
+}
+
+impl IdempotencyKey {
+    /// Blank or overlong keys are ignored rather than rejected, so a broken
+    /// client still gets its submission stored.
+    pub fn parse(key: &str) -> Option<String> {
+        let key = key.trim();
+        (!key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN).then(|| key.to_string())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IdempotencyKey {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(IdempotencyKey(request.headers().get_one("Idempotency-Key").and_then(IdempotencyKey::parse)))
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for IdempotencyKey {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+fn attribution_value(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.chars().take(MAX_ATTRIBUTION_LEN).collect())
+}
+
+/// Reads the `utm_*` query parameters and the `Referer` header.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Attribution {
+    type Error = ();
+
+    async fn from_request(request: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
+        let param = |name: &str| request.query_value::<&str>(name).and_then(Result::ok).and_then(attribution_value);
+        Outcome::Success(Attribution {
+            utm_source: param("utm_source"),
+            utm_medium: param("utm_medium"),
+            utm_campaign: param("utm_campaign"),
+            referrer: request.headers().get_one("Referer").and_then(attribution_value),
+        })
+    }
+}
@@ -0,0 +1,51 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, Method, Status};
+use rocket::{Request, Response};
+
+const STATIC_MAX_AGE: u64 = 3600;
+
+/// Sets Cache-Control on every response that does not choose its own.
+/// - Static assets can be cached by anyone for an hour.
+/// - API responses carry their own ETags and are revalidated privately.
+/// - Everything else, including exports and signed-in pages, is never stored.
+///   That includes public form pages: each render carries a fresh submission
+///   token, so no two renders share an ETag, and a stored copy would keep
+///   serving a token after it expires.
+pub struct HttpCache;
+
+fn is_static(content_type: Option<ContentType>) -> bool {
+    content_type.is_some_and(|content_type| {
+        content_type.is_css()
+            || content_type.is_javascript()
+            || content_type.top() == "image"
+            || content_type.top() == "font"
+    })
+}
+
+#[rocket::async_trait]
+impl Fairing for HttpCache {
+    fn info(&self) -> Info {
+        Info { name: "HTTP Cache Headers", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.headers().contains("Cache-Control") {
+            return;
+        }
+
+        let cacheable = matches!(request.method(), Method::Get | Method::Head)
+            && response.status() == Status::Ok
+            && !response.headers().contains("Set-Cookie");
+        let path = request.uri().path();
+
+        if !cacheable {
+            response.set_header(Header::new("Cache-Control", "no-store"));
+        } else if is_static(response.content_type()) {
+            response.set_header(Header::new("Cache-Control", format!("public, max-age={}", STATIC_MAX_AGE)));
+        } else if path.starts_with("/api/") {
+            response.set_header(Header::new("Cache-Control", "private, no-cache"));
+        } else {
+            response.set_header(Header::new("Cache-Control", "private, no-store"));
+        }
+    }
+}
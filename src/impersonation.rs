@@ -0,0 +1,142 @@
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, CookieJar, Method, Status};
+use rocket::response::Redirect;
+use rocket::{Request, Response, State};
+use sqlx::SqlitePool;
+
+use crate::guards::{Approver, Impersonation, SessionStore};
+use crate::schema::escape_attribute;
+use crate::tenant::Tenant;
+
+/// Impersonation ends on its own after this long, in case support forgets.
+const IMPERSONATION_TTL: Duration = Duration::from_secs(30 * 60);
+
+async fn record(db: &SqlitePool, event: &str, user_id: i64, impersonator_id: i64, detail: Option<String>) {
+    let logged = sqlx::query!(
+        "INSERT INTO audit_log (event, user_id, impersonator_id, detail) VALUES (?, ?, ?, ?)",
+        event,
+        user_id,
+        impersonator_id,
+        detail
+    )
+    .execute(db)
+    .await;
+    if let Err(e) = logged {
+        error!("Failed to record {} in the audit log: {}", event, e);
+    }
+}
+
+/// Lets an approver act as another user of their organization, to see what
+/// that user reports. Approvers cannot be impersonated, and an approver
+/// already impersonating someone has to stop first.
+#[post("/admin/users/<user_id>/impersonate")]
+pub async fn start(
+    db: &State<SqlitePool>,
+    sessions: &State<SessionStore>,
+    cookies: &CookieJar<'_>,
+    tenant: Tenant,
+    approver: Approver,
+    user_id: i64
+) -> Result<Redirect, Status> {
+    let (session_id, session) = sessions.current(cookies).ok_or(Status::Unauthorized)?;
+    if session.impersonation().is_some() {
+        return Err(Status::Conflict);
+    }
+    let username = sqlx::query_scalar!(
+        "SELECT username FROM users WHERE id = ? AND tenant_id = ? AND active = true AND is_approver = false",
+        user_id,
+        tenant.id
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .ok_or(Status::NotFound)?;
+
+    let impersonation = Impersonation { user_id, username, expires_at: Instant::now() + IMPERSONATION_TTL };
+    match sessions.0.write().unwrap().get_mut(&session_id) {
+        Some(session) => session.impersonating = Some(impersonation),
+        None => return Err(Status::Unauthorized),
+    }
+    record(db, "impersonation.started", user_id, approver.0, None).await;
+
+    Ok(Redirect::to(uri!(crate::routes::forms::index(_))))
+}
+
+/// Takes no user guard: while impersonating, those resolve to the
+/// impersonated user.
+#[post("/admin/impersonate/stop")]
+pub async fn stop(db: &State<SqlitePool>, sessions: &State<SessionStore>, cookies: &CookieJar<'_>) -> Redirect {
+    let ended = sessions.current(cookies).and_then(|(session_id, _)| {
+        let mut sessions = sessions.0.write().unwrap();
+        let session = sessions.get_mut(&session_id)?;
+        let impersonation = session.impersonating.take()?;
+        Some((session.user_id, impersonation))
+    });
+    if let Some((impersonator_id, impersonation)) = ended {
+        record(db, "impersonation.ended", impersonation.user_id, impersonator_id, None).await;
+    }
+
+    Redirect::to(uri!(crate::routes::forms::index(_)))
+}
+
+fn banner(impersonation: &Impersonation) -> String {
+    let minutes = impersonation.expires_at.saturating_duration_since(Instant::now()).as_secs().div_ceil(60);
+    format!(
+        "<div class=\"impersonation-banner\" role=\"alert\">You are signed in as <strong>{}</strong> for support; this ends in {} min. \
+         Everything you do is recorded in the audit log. \
+         <form method=\"post\" action=\"{}\"><button type=\"submit\">Stop impersonating</button></form></div>",
+        escape_attribute(&impersonation.username),
+        minutes,
+        uri!(stop)
+    )
+}
+
+/// Inserts `banner` just inside `<body>`.
+fn with_banner(page: &str, banner: &str) -> Option<String> {
+    let body = page.find("<body")?;
+    let end = body + page[body..].find('>')? + 1;
+    Some(format!("{}{}{}", &page[..end], banner, &page[end..]))
+}
+
+/// While an approver impersonates someone, every page shows a banner saying
+/// so, and every request that can change something is written to the audit
+/// log against both of them. Attached before compression, so pages are
+/// still plain HTML here.
+pub struct ImpersonationAudit;
+
+#[rocket::async_trait]
+impl Fairing for ImpersonationAudit {
+    fn info(&self) -> Info {
+        Info { name: "Impersonation Audit", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(sessions) = request.rocket().state::<SessionStore>() else {
+            return;
+        };
+        let Some((_, session)) = sessions.current(request.cookies()) else {
+            return;
+        };
+        let Some(impersonation) = session.impersonation() else {
+            return;
+        };
+
+        if !matches!(request.method(), Method::Get | Method::Head) {
+            let db = request.rocket().state::<SqlitePool>().expect("database pool is managed");
+            let detail = format!("{} {} -> {}", request.method(), request.uri(), response.status().code);
+            record(db, "impersonation.request", impersonation.user_id, session.user_id, Some(detail)).await;
+        }
+
+        if response.status() != Status::Ok || response.content_type() != Some(ContentType::HTML) {
+            return;
+        }
+        let Ok(page) = response.body_mut().to_string().await else {
+            return;
+        };
+        let page = with_banner(&page, &banner(impersonation)).unwrap_or(page);
+        response.set_sized_body(page.len(), Cursor::new(page));
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::schema::{self, FieldDef, FieldError, FieldKind};
+
+#[derive(Debug, Serialize)]
+pub struct RowError {
+    pub row: usize,
+    pub errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum FormSource {
+    #[field(value = "google")]
+    GoogleForms,
+    Typeform,
+}
+
+pub struct ImportedForm {
+    pub title: String,
+    pub fields: Vec<FieldDef>,
+    pub unmapped: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldMapping {
+    pub from: String,
+    pub to: Option<String>,
+}
+
+pub struct CsvRows {
+    pub rows: Vec<(usize, HashMap<String, String>)>,
+    pub unmapped: Vec<String>,
+}
+
+fn map_header(fields: &[FieldDef], header: &str) -> Option<String> {
+    let header = header.trim();
+    if fields.is_empty() {
+        return (!header.is_empty()).then(|| header.to_string());
+    }
+
+    fields.iter()
+        .find(|field| field.key == header)
+        .or_else(|| fields.iter().find(|field| !field.label.is_empty() && field.label.eq_ignore_ascii_case(header)))
+        .map(|field| field.key.clone())
+}
+
+pub fn field_mapping(source: &[FieldDef], target: &[FieldDef]) -> Vec<FieldMapping> {
+    source.iter()
+        .map(|field| FieldMapping {
+            from: field.key.clone(),
+            to: map_header(target, &field.key).or_else(|| map_header(target, &field.label)),
+        })
+        .collect()
+}
+
+pub fn responses_from_csv(fields: &[FieldDef], data: &str) -> Result<CsvRows, String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+
+    let columns: Vec<Option<String>> = headers.iter().map(|header| map_header(fields, header)).collect();
+    let unmapped = headers.iter()
+        .zip(&columns)
+        .filter(|(_, column)| column.is_none())
+        .map(|(header, _)| header.to_string())
+        .collect();
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let row = index + 2;
+        let record = record.map_err(|e| format!("row {}: {}", row, e))?;
+        let answers = columns.iter()
+            .zip(record.iter())
+            .filter_map(|(column, value)| Some((column.clone()?, value.to_string())))
+            .filter(|(_, value)| !value.trim().is_empty())
+            .collect();
+        rows.push((row, answers));
+    }
+
+    Ok(CsvRows { rows, unmapped })
+}
+
+fn field(key: String, label: &str, description: Option<&str>, kind: FieldKind, required: bool) -> FieldDef {
+    FieldDef {
+        key,
+        label: label.to_string(),
+        description: description.filter(|description| !description.is_empty()).map(str::to_string),
+        kind,
+        required,
+        options: Vec::new(),
+        encrypted: false,
+        min: None,
+        max: None,
+        pattern: None,
+        show_if: None,
+        legal_text: None,
+        amount: None,
+        amount_field: None,
+        min_amount: None,
+        max_amount: None,
+        presets: Vec::new(),
+        settings: Map::new(),
+    }
+}
+
+/// Keys are generated rather than taken from the question, so the imported
+/// questions can be reworded freely.
+fn field_key(taken: &[FieldDef]) -> String {
+    schema::new_field_key(&taken.iter().map(|field| field.key.clone()).collect())
+}
+
+fn strings(value: &Value, key: &str) -> Vec<String> {
+    value.as_array()
+        .map(|items| items.iter().filter_map(|item| item[key].as_str().map(str::to_string)).collect())
+        .unwrap_or_default()
+}
+
+pub fn form_from_export(source: FormSource, data: &str) -> Result<ImportedForm, String> {
+    let export: Value = serde_json::from_str(data).map_err(|e| e.to_string())?;
+    match source {
+        FormSource::GoogleForms => google_forms(&export),
+        FormSource::Typeform => typeform(&export),
+    }
+}
+
+fn google_forms(export: &Value) -> Result<ImportedForm, String> {
+    let items = export["items"].as_array().ok_or("export has no \"items\" list")?;
+    let title = export["info"]["title"].as_str().unwrap_or("Imported form").to_string();
+
+    let mut fields = Vec::new();
+    let mut unmapped = Vec::new();
+    for item in items {
+        let label = item["title"].as_str().unwrap_or_default();
+        let description = item["description"].as_str();
+        let Some(question) = item["questionItem"]["question"].as_object() else {
+            let kind = item.as_object()
+                .and_then(|item| item.keys().find(|key| key.ends_with("Item")).cloned())
+                .unwrap_or_else(|| "unknown item".to_string());
+            unmapped.push(format!("\"{}\": {} is not supported", label, kind));
+            continue;
+        };
+
+        let required = question.get("required").and_then(Value::as_bool).unwrap_or(false);
+        let mut def = field(field_key(&fields), label, description, FieldKind::Text, required);
+
+        if let Some(text) = question.get("textQuestion") {
+            if text["paragraph"].as_bool().unwrap_or(false) {
+                def.kind = FieldKind::Textarea;
+            }
+        } else if let Some(choice) = question.get("choiceQuestion") {
+            def.kind = FieldKind::Choice;
+            def.options = strings(&choice["options"], "value");
+            if choice["type"].as_str() == Some("CHECKBOX") {
+                unmapped.push(format!("\"{}\": multiple selection was imported as a single choice", label));
+            }
+            if choice["options"].as_array().is_some_and(|options| options.iter().any(|option| option["isOther"].as_bool() == Some(true))) {
+                unmapped.push(format!("\"{}\": the \"Other\" option was dropped", label));
+            }
+        } else if let Some(scale) = question.get("scaleQuestion") {
+            def.kind = FieldKind::Number;
+            def.min = scale["low"].as_f64();
+            def.max = scale["high"].as_f64();
+        } else if question.contains_key("dateQuestion") {
+            def.kind = FieldKind::Date;
+        } else {
+            let kind = question.keys().find(|key| key.ends_with("Question")).cloned().unwrap_or_default();
+            unmapped.push(format!("\"{}\": {} was imported as a text field", label, kind));
+        }
+
+        fields.push(def);
+    }
+
+    Ok(ImportedForm { title, fields, unmapped })
+}
+
+fn typeform(export: &Value) -> Result<ImportedForm, String> {
+    let items = export["fields"].as_array().ok_or("export has no \"fields\" list")?;
+    let title = export["title"].as_str().unwrap_or("Imported form").to_string();
+
+    let mut fields = Vec::new();
+    let mut unmapped = Vec::new();
+    for item in items {
+        let label = item["title"].as_str().unwrap_or_default();
+        let kind = item["type"].as_str().unwrap_or_default();
+        let properties = &item["properties"];
+        let validations = &item["validations"];
+        let required = validations["required"].as_bool().unwrap_or(false);
+
+        let kind = match kind {
+            "short_text" | "website" | "phone_number" => FieldKind::Text,
+            "long_text" => FieldKind::Textarea,
+            "email" => FieldKind::Email,
+            "number" | "rating" | "opinion_scale" => FieldKind::Number,
+            "multiple_choice" | "dropdown" => FieldKind::Choice,
+            "yes_no" => FieldKind::Checkbox,
+            "legal" => FieldKind::Consent,
+            "date" => FieldKind::Date,
+            other => {
+                unmapped.push(format!("\"{}\": {} is not supported", label, other));
+                continue;
+            }
+        };
+
+        let key = item["ref"].as_str()
+            .filter(|reference| !fields.iter().any(|field: &FieldDef| field.key == *reference))
+            .map(str::to_string)
+            .unwrap_or_else(|| field_key(&fields));
+        let mut def = field(key, label, properties["description"].as_str(), kind, required);
+
+        match item["type"].as_str() {
+            Some("multiple_choice" | "dropdown") => {
+                def.options = strings(&properties["choices"], "label");
+                if properties["allow_multiple_selection"].as_bool() == Some(true) {
+                    unmapped.push(format!("\"{}\": multiple selection was imported as a single choice", label));
+                }
+            }
+            Some("legal") => def.legal_text = Some(label.to_string()),
+            Some("rating") => {
+                def.min = Some(1.0);
+                def.max = properties["steps"].as_f64();
+            }
+            Some("opinion_scale") => {
+                def.min = Some(if properties["start_at_one"].as_bool() == Some(true) { 1.0 } else { 0.0 });
+                def.max = properties["steps"].as_f64().map(|steps| def.min.unwrap_or_default() + steps - 1.0);
+            }
+            _ => {
+                def.min = validations["min_value"].as_f64();
+                def.max = validations["max_value"].as_f64().or(validations["max_length"].as_f64());
+            }
+        }
+
+        fields.push(def);
+    }
+
+    if export["logic"].as_array().is_some_and(|logic| !logic.is_empty()) {
+        unmapped.push("logic jumps were not imported".to_string());
+    }
+
+    Ok(ImportedForm { title, fields, unmapped })
+}
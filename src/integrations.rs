@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use lettre::message::Mailbox;
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+
+use crate::crypto;
+use crate::db::{email_notification, notify};
+use crate::models::FormResponse;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MAX_ATTEMPTS: i64 = 5;
+
+const DISPATCH_BATCH_SIZE: i64 = 100;
+
+/// How long a claimed delivery stays with its dispatcher. One still
+/// 'sending' after this was abandoned, by a crash say, and is claimed again.
+const CLAIM_LEASE: &str = "+5 minutes";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormIntegration {
+    pub id: i64,
+    pub form_id: i64,
+    pub kind: String,
+    pub config: String,
+    pub cadence: String,
+    pub last_delivered_at: Option<String>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntegrationDelivery {
+    pub id: i64,
+    /// None for the email to the form's author.
+    pub integration_id: Option<i64>,
+    pub response_id: i64,
+    pub status: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub next_attempt_at: String,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum IntegrationKind {
+    Slack,
+    Discord,
+    Webhook,
+    Email,
+}
+
+impl IntegrationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IntegrationKind::Slack => "slack",
+            IntegrationKind::Discord => "discord",
+            IntegrationKind::Webhook => "webhook",
+            IntegrationKind::Email => "email",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum IntegrationCadence {
+    Submission,
+    Digest,
+}
+
+impl IntegrationCadence {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IntegrationCadence::Submission => "submission",
+            IntegrationCadence::Digest => "digest",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubmissionEvent {
+    pub form_id: i64,
+    pub form_title: String,
+    pub response_id: i64,
+    pub submitted_at: String,
+    pub answers: HashMap<String, String>,
+}
+
+impl SubmissionEvent {
+    fn selected_answers(&self, fields: &[String]) -> Vec<(&String, &String)> {
+        let mut answers: Vec<_> = self.answers.iter()
+            .filter(|(key, _)| fields.is_empty() || fields.contains(key))
+            .collect();
+        answers.sort();
+        answers
+    }
+
+    fn summary(&self, fields: &[String]) -> String {
+        let mut message = format!("New response to \"{}\"", self.form_title);
+        for (key, value) in self.selected_answers(fields) {
+            message.push_str(&format!("\n{}: {}", key, value));
+        }
+        message
+    }
+}
+
+#[rocket::async_trait]
+pub trait Integration: Send + Sync {
+    async fn deliver(&self, event: SubmissionEvent) -> Result<(), String>;
+
+    async fn deliver_digest(&self, form_title: &str, count: i64) -> Result<(), String>;
+}
+
+#[derive(Debug, Deserialize)]
+struct UrlConfig {
+    url: String,
+    #[serde(default)]
+    fields: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmailConfig {
+    to: String,
+    #[serde(default)]
+    fields: Vec<String>,
+}
+
+struct ChatIntegration {
+    client: reqwest::Client,
+    config: UrlConfig,
+    content_key: &'static str,
+}
+
+impl ChatIntegration {
+    async fn post(&self, message: String) -> Result<(), String> {
+        let mut payload = serde_json::Map::new();
+        payload.insert(self.content_key.to_string(), message.into());
+        post_json(&self.client, &self.config.url, &payload).await
+    }
+}
+
+#[rocket::async_trait]
+impl Integration for ChatIntegration {
+    async fn deliver(&self, event: SubmissionEvent) -> Result<(), String> {
+        self.post(event.summary(&self.config.fields)).await
+    }
+
+    async fn deliver_digest(&self, form_title: &str, count: i64) -> Result<(), String> {
+        self.post(format!("{} new responses to \"{}\" in the last day", count, form_title)).await
+    }
+}
+
+struct WebhookIntegration {
+    client: reqwest::Client,
+    config: UrlConfig,
+}
+
+#[rocket::async_trait]
+impl Integration for WebhookIntegration {
+    async fn deliver(&self, event: SubmissionEvent) -> Result<(), String> {
+        let answers: HashMap<_, _> = event.selected_answers(&self.config.fields).into_iter().collect();
+        let payload = serde_json::json!({
+            "event": "response.submitted",
+            "form_id": event.form_id,
+            "form_title": event.form_title,
+            "response_id": event.response_id,
+            "submitted_at": event.submitted_at,
+            "answers": answers,
+        });
+        post_json(&self.client, &self.config.url, &payload).await
+    }
+
+    async fn deliver_digest(&self, form_title: &str, count: i64) -> Result<(), String> {
+        let payload = serde_json::json!({
+            "event": "responses.digest",
+            "form_title": form_title,
+            "count": count,
+        });
+        post_json(&self.client, &self.config.url, &payload).await
+    }
+}
+
+struct EmailIntegration {
+    db: SqlitePool,
+    config: EmailConfig,
+}
+
+impl EmailIntegration {
+    async fn enqueue(&self, subject: &str, body: &str) -> Result<(), String> {
+        sqlx::query!(
+            "INSERT INTO email_queue (recipient, subject, body) VALUES (?, ?, ?)",
+            self.config.to,
+            subject,
+            body
+        )
+        .execute(&self.db)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+}
+
+#[rocket::async_trait]
+impl Integration for EmailIntegration {
+    async fn deliver(&self, event: SubmissionEvent) -> Result<(), String> {
+        let subject = format!("New response to \"{}\"", event.form_title);
+        self.enqueue(&subject, &event.summary(&self.config.fields)).await
+    }
+
+    async fn deliver_digest(&self, form_title: &str, count: i64) -> Result<(), String> {
+        let subject = format!("Daily summary for \"{}\"", form_title);
+        let body = format!("{} new responses to \"{}\" in the last day", count, form_title);
+        self.enqueue(&subject, &body).await
+    }
+}
+
+/// The new-response email to the form's author. It goes through the
+/// dispatcher like any other delivery, but follows the author's
+/// notification preferences rather than a per-form config.
+struct AuthorEmail {
+    db: SqlitePool,
+    author_id: i64,
+}
+
+#[rocket::async_trait]
+impl Integration for AuthorEmail {
+    async fn deliver(&self, event: SubmissionEvent) -> Result<(), String> {
+        let message = format!("New response to \"{}\"", event.form_title);
+        let link = uri!(crate::routes::responses::response_detail(event.form_id, event.response_id)).to_string();
+        email_notification(&self.db, self.author_id, "submission", &message, &link, Some(event.form_id))
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn deliver_digest(&self, _form_title: &str, _count: i64) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+async fn post_json<T: Serialize + ?Sized + Sync>(client: &reqwest::Client, url: &str, payload: &T) -> Result<(), String> {
+    client.post(url)
+        .timeout(DELIVERY_TIMEOUT)
+        .json(payload)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+pub fn config(kind: IntegrationKind, target: &str, fields: &str) -> Result<String, String> {
+    let target = target.trim();
+    let fields: Vec<&str> = fields.split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .collect();
+
+    let config = match kind {
+        IntegrationKind::Email => {
+            target.parse::<Mailbox>().map_err(|e| e.to_string())?;
+            serde_json::json!({ "to": target, "fields": fields })
+        }
+        _ => {
+            if !target.starts_with("https://") {
+                return Err("webhook URL must use https".to_string());
+            }
+            serde_json::json!({ "url": target, "fields": fields })
+        }
+    };
+
+    Ok(config.to_string())
+}
+
+pub fn build(integration: &FormIntegration, db: &SqlitePool, client: &reqwest::Client) -> Result<Box<dyn Integration>, String> {
+    let integration: Box<dyn Integration> = match integration.kind.as_str() {
+        "slack" | "discord" => Box::new(ChatIntegration {
+            client: client.clone(),
+            config: serde_json::from_str(&integration.config).map_err(|e| e.to_string())?,
+            content_key: if integration.kind == "discord" { "content" } else { "text" },
+        }),
+        "webhook" => Box::new(WebhookIntegration {
+            client: client.clone(),
+            config: serde_json::from_str(&integration.config).map_err(|e| e.to_string())?,
+        }),
+        "email" => Box::new(EmailIntegration {
+            db: db.clone(),
+            config: serde_json::from_str(&integration.config).map_err(|e| e.to_string())?,
+        }),
+        kind => return Err(format!("unknown integration kind {}", kind)),
+    };
+
+    Ok(integration)
+}
+
+async fn record_result(db: &SqlitePool, integration: &FormIntegration, result: &Result<(), String>) -> Result<(), sqlx::Error> {
+    match result {
+        Ok(()) => sqlx::query!(
+            "UPDATE form_integrations SET last_delivered_at = CURRENT_TIMESTAMP, last_error = NULL WHERE id = ?",
+            integration.id
+        )
+        .execute(db)
+        .await?,
+        Err(e) => sqlx::query!(
+            "UPDATE form_integrations SET last_error = ?, last_error_at = CURRENT_TIMESTAMP WHERE id = ?",
+            e,
+            integration.id
+        )
+        .execute(db)
+        .await?,
+    };
+
+    Ok(())
+}
+
+async fn report_failure(db: &SqlitePool, integration: &FormIntegration, error: &str) -> Result<(), sqlx::Error> {
+    let author_id = sqlx::query_scalar!("SELECT author_id FROM forms WHERE id = ?", integration.form_id)
+        .fetch_one(db)
+        .await?;
+
+    let message = format!("{} integration delivery failed: {}", integration.kind, error);
+    let link = uri!(crate::routes::settings::form_integrations(integration.form_id)).to_string();
+    if notify(db, author_id, "webhook_failure", &message, &link, Some(integration.form_id)).await.is_err() {
+        error!("Failed to record delivery failure notification for integration {}", integration.id);
+    }
+
+    Ok(())
+}
+
+pub async fn enqueue_deliveries(db: &SqlitePool, response: &FormResponse) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO integration_deliveries (integration_id, response_id)
+         SELECT id, ? FROM form_integrations WHERE form_id = ? AND cadence = 'submission'",
+        response.id,
+        response.form_id
+    )
+    .execute(db)
+    .await?;
+
+    if !response.is_test {
+        sqlx::query!("INSERT INTO integration_deliveries (integration_id, response_id) VALUES (NULL, ?)", response.id)
+            .execute(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Takes due deliveries for this dispatcher in one statement, so another
+/// dispatcher running at the same time cannot take them too.
+async fn claim_due(db: &SqlitePool) -> Result<Vec<IntegrationDelivery>, sqlx::Error> {
+    sqlx::query_as!(IntegrationDelivery,
+        "UPDATE integration_deliveries SET status = 'sending', next_attempt_at = datetime('now', ?)
+         WHERE id IN (
+             SELECT id FROM integration_deliveries
+             WHERE status IN ('pending', 'sending') AND next_attempt_at <= CURRENT_TIMESTAMP
+             ORDER BY id LIMIT ?
+         )
+         RETURNING *",
+        CLAIM_LEASE,
+        DISPATCH_BATCH_SIZE
+    )
+    .fetch_all(db)
+    .await
+}
+
+pub async fn dispatch_due(db: &SqlitePool, client: &reqwest::Client) -> Result<(), sqlx::Error> {
+    for delivery in claim_due(db).await? {
+        let integration = match delivery.integration_id {
+            Some(id) => Some(
+                sqlx::query_as!(FormIntegration, "SELECT * FROM form_integrations WHERE id = ?", id)
+                    .fetch_one(db)
+                    .await?
+            ),
+            None => None,
+        };
+        let response = sqlx::query!(
+            "SELECT r.id, r.form_id, r.answers, r.created_at, f.title, f.author_id
+             FROM responses r JOIN forms f ON f.id = r.form_id WHERE r.id = ?",
+            delivery.response_id
+        )
+        .fetch_one(db)
+        .await?;
+
+        let event = SubmissionEvent {
+            form_id: response.form_id,
+            form_title: response.title,
+            response_id: response.id,
+            submitted_at: response.created_at,
+            answers: crypto::decrypt_answers(&response.answers),
+        };
+
+        let result = match &integration {
+            Some(integration) => match build(integration, db, client) {
+                Ok(target) => target.deliver(event).await,
+                Err(e) => Err(e),
+            },
+            None => AuthorEmail { db: db.clone(), author_id: response.author_id }.deliver(event).await,
+        };
+        if let Some(integration) = &integration {
+            record_result(db, integration, &result).await?;
+        }
+
+        let attempts = delivery.attempts + 1;
+        match result {
+            Ok(()) => {
+                sqlx::query!(
+                    "UPDATE integration_deliveries
+                     SET status = 'delivered', attempts = ?, last_error = NULL, delivered_at = CURRENT_TIMESTAMP
+                     WHERE id = ?",
+                    attempts,
+                    delivery.id
+                )
+                .execute(db)
+                .await?;
+            }
+            Err(e) if attempts >= MAX_ATTEMPTS => {
+                sqlx::query!(
+                    "UPDATE integration_deliveries SET status = 'failed', attempts = ?, last_error = ? WHERE id = ?",
+                    attempts,
+                    e,
+                    delivery.id
+                )
+                .execute(db)
+                .await?;
+                match &integration {
+                    Some(integration) => report_failure(db, integration, &e).await?,
+                    None => error!("Gave up emailing the author about response {}: {}", delivery.response_id, e),
+                }
+            }
+            Err(e) => {
+                let backoff = format!("+{} minutes", 1 << attempts);
+                sqlx::query!(
+                    "UPDATE integration_deliveries
+                     SET status = 'pending', attempts = ?, last_error = ?, next_attempt_at = datetime('now', ?)
+                     WHERE id = ?",
+                    attempts,
+                    e,
+                    backoff,
+                    delivery.id
+                )
+                .execute(db)
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn send_digests(db: &SqlitePool, client: &reqwest::Client) -> Result<(), sqlx::Error> {
+    let due = sqlx::query_as!(FormIntegration,
+        "SELECT * FROM form_integrations
+         WHERE cadence = 'digest'
+         AND (last_delivered_at IS NULL OR last_delivered_at <= datetime('now', '-1 day'))
+         AND (last_error_at IS NULL OR last_error_at <= datetime('now', '-1 hour'))"
+    )
+    .fetch_all(db)
+    .await?;
+
+    for integration in due {
+        let summary = sqlx::query!(
+            r#"SELECT f.title, COUNT(r.id) AS "count!: i64"
+             FROM forms f LEFT JOIN responses r ON r.form_id = f.id AND r.is_test = false
+                 AND r.created_at > COALESCE(?, datetime('now', '-1 day'))
+             WHERE f.id = ? GROUP BY f.id"#,
+            integration.last_delivered_at,
+            integration.form_id
+        )
+        .fetch_one(db)
+        .await?;
+
+        let result = match build(&integration, db, client) {
+            Ok(target) => target.deliver_digest(&summary.title, summary.count).await,
+            Err(e) => Err(e),
+        };
+        record_result(db, &integration, &result).await?;
+
+        if let Err(e) = result {
+            report_failure(db, &integration, &e).await?;
+        }
+    }
+
+    Ok(())
+}
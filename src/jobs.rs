@@ -0,0 +1,526 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use lettre::message::header::ContentType as MailContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message as Email, Tokio1Executor};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::{AppConfig, account, analytics, branding, charts, crypto, export, integrations, report, schema, storage, tenant};
+use crate::db::{filtered_responses, response_tags};
+use crate::localtime::TimePreferences;
+use crate::models::{DigestFrequency, EntryFilter, ExportSchedule, FormResponse, ReportSchedule, ResponseFilter, SheetSync, WebForm};
+
+const BACKGROUND_JOB_INTERVAL: Duration = Duration::from_secs(60);
+
+const DIGEST_NOTABLE_RESPONSES: i64 = 3;
+
+const DIGEST_SPARKLINE_DAYS: i64 = 14;
+
+/// The Content-ID that a queued email's HTML part uses for its inline image.
+const INLINE_IMAGE_ID: &str = "chart";
+
+const SHEETS_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SHEETS_SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+
+const SHEETS_BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+struct GoogleSheets {
+    key: ServiceAccountKey,
+    token: Option<(String, Instant)>,
+}
+
+impl GoogleSheets {
+    fn load(path: &str) -> Result<Self, String> {
+        let key = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let key = serde_json::from_str(&key).map_err(|e| e.to_string())?;
+        Ok(GoogleSheets { key, token: None })
+    }
+
+    async fn access_token(&mut self, client: &reqwest::Client) -> Result<String, String> {
+        if let Some((token, expires)) = &self.token {
+            if Instant::now() < *expires {
+                return Ok(token.clone());
+            }
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?.as_secs();
+        let claims = serde_json::json!({
+            "iss": self.key.client_email,
+            "scope": SHEETS_SCOPE,
+            "aud": self.key.token_uri,
+            "iat": now,
+            "exp": now + 3600,
+        });
+        let signing_key = EncodingKey::from_rsa_pem(self.key.private_key.as_bytes()).map_err(|e| e.to_string())?;
+        let assertion = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &signing_key)
+            .map_err(|e| e.to_string())?;
+
+        let token: AccessToken = client.post(&self.key.token_uri)
+            .form(&[("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"), ("assertion", assertion.as_str())])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let expires = Instant::now() + Duration::from_secs(token.expires_in.saturating_sub(60));
+        self.token = Some((token.access_token.clone(), expires));
+        Ok(token.access_token)
+    }
+
+    async fn append_rows(
+        &mut self,
+        client: &reqwest::Client,
+        sync: &SheetSync,
+        rows: Vec<Vec<String>>
+    ) -> Result<(), String> {
+        let token = self.access_token(client).await?;
+
+        let mut url = reqwest::Url::parse("https://sheets.googleapis.com/v4/spreadsheets").map_err(|e| e.to_string())?;
+        url.path_segments_mut()
+            .map_err(|_| "invalid Sheets API URL".to_string())?
+            .push(&sync.spreadsheet_id)
+            .push("values")
+            .push(&format!("{}:append", sync.sheet_name));
+        url.query_pairs_mut()
+            .append_pair("valueInputOption", "RAW")
+            .append_pair("insertDataOption", "INSERT_ROWS");
+
+        client.post(url)
+            .bearer_auth(token)
+            .timeout(SHEETS_TIMEOUT)
+            .json(&serde_json::json!({ "values": rows }))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+async fn purge_expired_verifications(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM email_verifications WHERE expires_at <= CURRENT_TIMESTAMP")
+        .execute(db)
+        .await?;
+    sqlx::query!("DELETE FROM receipt_links WHERE expires_at <= CURRENT_TIMESTAMP")
+        .execute(db)
+        .await?;
+    sqlx::query!("DELETE FROM receipt_requests WHERE created_at <= datetime('now', '-1 day')")
+        .execute(db)
+        .await?;
+    sqlx::query!("DELETE FROM partial_responses WHERE expires_at <= CURRENT_TIMESTAMP")
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+async fn send_digests(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    let due = sqlx::query!(
+        r#"SELECT id, email AS "email!", digest AS "digest!" FROM users
+         WHERE email IS NOT NULL AND digest IS NOT NULL
+         AND (digest_sent_at IS NULL
+              OR (digest = 'daily' AND digest_sent_at <= datetime('now', '-1 day'))
+              OR (digest = 'weekly' AND digest_sent_at <= datetime('now', '-7 days')))"#
+    )
+    .fetch_all(db)
+    .await?;
+
+    for user in due {
+        let window = DigestFrequency::window(&user.digest);
+        let forms = sqlx::query!(
+            r#"SELECT f.id, f.title, COUNT(r.id) AS "count!: i64"
+             FROM forms f JOIN responses r ON r.form_id = f.id
+             WHERE f.author_id = ? AND r.is_test = false AND r.created_at > datetime('now', ?)
+             GROUP BY f.id ORDER BY f.title"#,
+            user.id,
+            window
+        )
+        .fetch_all(db)
+        .await?;
+
+        if !forms.is_empty() {
+            let mut body = format!("Your {} summary of new responses:\n", user.digest);
+            for form in &forms {
+                body.push_str(&format!("\n{} — {} new\n", form.title, form.count));
+
+                let notable = sqlx::query_scalar!(
+                    "SELECT answers FROM responses
+                     WHERE form_id = ? AND is_test = false AND created_at > datetime('now', ?)
+                     ORDER BY id DESC LIMIT ?",
+                    form.id,
+                    window,
+                    DIGEST_NOTABLE_RESPONSES
+                )
+                .fetch_all(db)
+                .await?;
+
+                for answers in notable {
+                    body.push_str(&format!("  • {}\n", crypto::reveal(&answers)));
+                }
+
+                body.push_str(&format!("  {}\n", uri!(crate::routes::responses::form_responses(form.id, _))));
+            }
+
+            // The digest still goes out without its chart if that fails.
+            let daily = analytics::daily_submissions(db, user.id, None, DIGEST_SPARKLINE_DAYS, EntryFilter::All).await?;
+            let values: Vec<i64> = daily.into_iter().map(|(_, count)| count).collect();
+            let sparkline = charts::sparkline(&values)
+                .map_err(|e| warn!("Failed to draw the digest sparkline for user {}: {}", user.id, e))
+                .ok();
+
+            let subject = format!("Your {} forms digest", user.digest);
+            sqlx::query!(
+                "INSERT INTO email_queue (recipient, subject, body, inline_image) VALUES (?, ?, ?, ?)",
+                user.email,
+                subject,
+                body,
+                sparkline
+            )
+            .execute(db)
+            .await?;
+        }
+
+        sqlx::query!("UPDATE users SET digest_sent_at = CURRENT_TIMESTAMP WHERE id = ?", user.id)
+            .execute(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn run_export_schedules(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    let due = sqlx::query_as!(ExportSchedule,
+        "SELECT * FROM export_schedules
+         WHERE last_run_at IS NULL
+         OR (frequency = 'daily' AND last_run_at <= datetime('now', '-1 day'))
+         OR (frequency = 'weekly' AND last_run_at <= datetime('now', '-7 days'))"
+    )
+    .fetch_all(db)
+    .await?;
+
+    for schedule in due {
+        let form = sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE id = ?", schedule.form_id)
+            .fetch_one(db)
+            .await?;
+
+        let mut responses = match filtered_responses(db, form.id, schedule.user_id, &ResponseFilter::default()).await {
+            Ok(responses) => responses,
+            Err(status) => {
+                warn!("Skipping scheduled export {}: {}", schedule.id, status);
+                continue;
+            }
+        };
+        let tags = match response_tags(db, form.id).await {
+            Ok(tags) => tags,
+            Err(status) => {
+                warn!("Skipping scheduled export {}: {}", schedule.id, status);
+                continue;
+            }
+        };
+
+        // In the schedule owner's time, as when they export by hand.
+        let time = TimePreferences::load(db, schedule.user_id).await?;
+        for response in &mut responses {
+            time.localize(&mut response.created_at);
+            time.localize(&mut response.updated_at);
+        }
+
+        let fields = schema::parse(&form.fields);
+        let attachment = export::responses_csv(&fields, &responses, &tags);
+        let attachment_name = format!("form-{}-responses.csv", form.id);
+        let subject = format!("Your {} export of {}", schedule.frequency, form.title);
+        let body = format!("Attached are all {} responses to {}.\n", responses.len(), form.title);
+
+        sqlx::query!(
+            "INSERT INTO email_queue (recipient, subject, body, attachment_name, attachment) VALUES (?, ?, ?, ?, ?)",
+            schedule.recipient,
+            subject,
+            body,
+            attachment_name,
+            attachment
+        )
+        .execute(db)
+        .await?;
+
+        sqlx::query!("UPDATE export_schedules SET last_run_at = CURRENT_TIMESTAMP WHERE id = ?", schedule.id)
+            .execute(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn run_report_schedules(db: &SqlitePool, config: &AppConfig) -> Result<(), sqlx::Error> {
+    let due = sqlx::query_as!(ReportSchedule,
+        "SELECT * FROM report_schedules WHERE last_run_at IS NULL OR last_run_at <= datetime('now', '-7 days')"
+    )
+    .fetch_all(db)
+    .await?;
+
+    // One broken schedule must not hold back everyone else's reports. It
+    // stays due, so it is retried on the next run.
+    for schedule in due {
+        if let Err(e) = send_report(db, config, &schedule).await {
+            error!("Failed to send weekly report {} for form {}: {}", schedule.id, schedule.form_id, e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn send_report(db: &SqlitePool, config: &AppConfig, schedule: &ReportSchedule) -> Result<(), sqlx::Error> {
+    let form = sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE id = ?", schedule.form_id)
+        .fetch_one(db)
+        .await?;
+
+    let fields: Vec<String> = serde_json::from_str(&schedule.fields).unwrap_or_default();
+    let link = tenant::url(db, config, form.tenant_id, uri!(crate::routes::analytics::form_analytics(form.id, _, _, _))).await?;
+    let report = report::weekly(db, &form, &fields, &link).await?;
+
+    sqlx::query!(
+        "INSERT INTO email_queue (recipient, subject, body, inline_image) VALUES (?, ?, ?, ?)",
+        schedule.recipient,
+        report.subject,
+        report.body,
+        report.chart
+    )
+    .execute(db)
+    .await?;
+
+    sqlx::query!("UPDATE report_schedules SET last_run_at = CURRENT_TIMESTAMP WHERE id = ?", schedule.id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+async fn deliver_queued_emails(
+    db: &SqlitePool,
+    mailer: &AsyncSmtpTransport<Tokio1Executor>,
+    from: &Mailbox,
+    branding: &branding::Branding
+) -> Result<(), sqlx::Error> {
+    let queued = sqlx::query!(
+        "SELECT id, recipient, subject, body, attachment_name, attachment, attachment_type, inline_image FROM email_queue
+         WHERE sent_at IS NULL ORDER BY id LIMIT 50"
+    )
+    .fetch_all(db)
+    .await?;
+
+    for mut email in queued {
+        email.body.push_str(&branding.email_footer());
+        let message = match email.recipient.parse::<Mailbox>() {
+            Ok(to) => {
+                let builder = Email::builder().from(from.clone()).to(to).subject(email.subject);
+                // An inline image needs an HTML alternative to show it in.
+                let content = email.inline_image.map(|image| {
+                    let html = format!(
+                        "<pre>{}</pre><img src=\"cid:{}\" alt=\"\">",
+                        ammonia::clean_text(&email.body),
+                        INLINE_IMAGE_ID
+                    );
+                    MultiPart::alternative()
+                        .singlepart(SinglePart::plain(email.body.clone()))
+                        .multipart(MultiPart::related()
+                            .singlepart(SinglePart::html(html))
+                            .singlepart(Attachment::new_inline(INLINE_IMAGE_ID.to_string())
+                                .body(image, MailContentType::parse("image/png").expect("image/png is a valid content type"))))
+                });
+                match (email.attachment_name, email.attachment, content) {
+                    (Some(name), Some(attachment), content) => {
+                        let content_type = MailContentType::parse(&email.attachment_type)
+                            .unwrap_or_else(|_| MailContentType::parse("application/octet-stream").expect("application/octet-stream is a valid content type"));
+                        let attachment = Attachment::new(name).body(attachment, content_type);
+                        let mixed = match content {
+                            Some(content) => MultiPart::mixed().multipart(content),
+                            None => MultiPart::mixed().singlepart(SinglePart::plain(email.body)),
+                        };
+                        builder.multipart(mixed.singlepart(attachment))
+                    }
+                    (_, _, Some(content)) => builder.multipart(content),
+                    _ => builder.body(email.body),
+                }
+            }
+            Err(e) => {
+                warn!("Dropping queued email {} with invalid recipient: {}", email.id, e);
+                sqlx::query!("DELETE FROM email_queue WHERE id = ?", email.id).execute(db).await?;
+                continue;
+            }
+        };
+
+        match message {
+            Ok(message) => match mailer.send(message).await {
+                Ok(_) => {
+                    sqlx::query!("UPDATE email_queue SET sent_at = CURRENT_TIMESTAMP WHERE id = ?", email.id)
+                        .execute(db)
+                        .await?;
+                }
+                Err(e) => warn!("Failed to send queued email {}: {}", email.id, e),
+            },
+            Err(e) => warn!("Failed to build queued email {}: {}", email.id, e),
+        }
+    }
+
+    Ok(())
+}
+
+async fn sync_sheets(db: &SqlitePool, client: &reqwest::Client, sheets: &mut GoogleSheets) -> Result<(), sqlx::Error> {
+    let syncs = sqlx::query_as!(SheetSync, "SELECT * FROM sheet_syncs ORDER BY id")
+        .fetch_all(db)
+        .await?;
+
+    for sync in syncs {
+        let responses = sqlx::query_as!(FormResponse,
+            "SELECT * FROM responses WHERE form_id = ? AND is_test = false AND id > ? ORDER BY id LIMIT ?",
+            sync.form_id,
+            sync.last_synced_response_id,
+            SHEETS_BATCH_SIZE
+        )
+        .fetch_all(db)
+        .await?;
+
+        let Some(last) = responses.last().map(|response| response.id) else {
+            continue;
+        };
+
+        let columns: Vec<String> = serde_json::from_str(&sync.columns).unwrap_or_default();
+        let mut rows = Vec::with_capacity(responses.len() + 1);
+        if sync.last_synced_response_id == 0 {
+            rows.push(["response_id".to_string(), "submitted_at".to_string()].into_iter()
+                .chain(columns.iter().cloned())
+                .collect());
+        }
+
+        for response in &responses {
+            let answers = crypto::decrypt_answers(&response.answers);
+            rows.push([response.id.to_string(), response.created_at.clone()].into_iter()
+                .chain(columns.iter().map(|column| answers.get(column).cloned().unwrap_or_default()))
+                .collect());
+        }
+
+        match sheets.append_rows(client, &sync, rows).await {
+            Ok(()) => {
+                sqlx::query!(
+                    "UPDATE sheet_syncs SET last_synced_response_id = ?, last_synced_at = CURRENT_TIMESTAMP, last_error = NULL
+                     WHERE id = ?",
+                    last,
+                    sync.id
+                )
+                .execute(db)
+                .await?;
+            }
+            Err(e) => {
+                sqlx::query!("UPDATE sheet_syncs SET last_error = ? WHERE id = ?", e, sync.id)
+                    .execute(db)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_background_jobs(db: SqlitePool, client: reqwest::Client, config: AppConfig) {
+    let mailer = match (&config.smtp_url, &config.mail_from) {
+        (Some(url), Some(from)) => match (AsyncSmtpTransport::<Tokio1Executor>::from_url(url), from.parse::<Mailbox>()) {
+            (Ok(transport), Ok(from)) => {
+                let from = Mailbox::new(from.name.or(Some(config.branding.product_name.clone())), from.email);
+                Some((transport.build(), from))
+            }
+            (Err(e), _) => {
+                error!("Invalid smtp_url, outgoing email is disabled: {}", e);
+                None
+            }
+            (_, Err(e)) => {
+                error!("Invalid mail_from, outgoing email is disabled: {}", e);
+                None
+            }
+        },
+        _ => {
+            warn!("smtp_url or mail_from is not configured, emails will stay queued");
+            None
+        }
+    };
+
+    let mut sheets = config.google_service_account_key.as_deref().and_then(|path| match GoogleSheets::load(path) {
+        Ok(sheets) => Some(sheets),
+        Err(e) => {
+            error!("Failed to load Google service account key, Sheets sync is disabled: {}", e);
+            None
+        }
+    });
+
+    let uploads = match storage::Storage::open(&config.uploads).await {
+        Ok(uploads) => Some(uploads),
+        Err(e) => {
+            error!("Failed to open the uploads directory, uploads will stay in quarantine: {}", e);
+            None
+        }
+    };
+
+    let mut interval = rocket::tokio::time::interval(BACKGROUND_JOB_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        if let Some(uploads) = &uploads {
+            if let Err(e) = storage::scan_pending(&db, uploads).await {
+                error!("Failed to scan uploads: {}", e);
+            }
+        }
+
+        if let Some(sheets) = &mut sheets {
+            if let Err(e) = sync_sheets(&db, &client, sheets).await {
+                error!("Failed to sync Google Sheets: {}", e);
+            }
+        }
+
+        if let Err(e) = purge_expired_verifications(&db).await {
+            error!("Failed to purge expired email verifications: {}", e);
+        }
+
+        if let Err(e) = account::purge_deleted(&db, &config.accounts).await {
+            error!("Failed to purge deleted accounts: {}", e);
+        }
+
+        if let Err(e) = run_export_schedules(&db).await {
+            error!("Failed to run scheduled exports: {}", e);
+        }
+
+        if let Err(e) = run_report_schedules(&db, &config).await {
+            error!("Failed to send scheduled reports: {}", e);
+        }
+
+        if let Err(e) = send_digests(&db).await {
+            error!("Failed to send digests: {}", e);
+        }
+
+        if let Err(e) = integrations::send_digests(&db, &client).await {
+            error!("Failed to send integration digests: {}", e);
+        }
+
+        if let Some((mailer, from)) = &mailer {
+            if let Err(e) = deliver_queued_emails(&db, mailer, from, &config.branding).await {
+                error!("Failed to deliver queued emails: {}", e);
+            }
+        }
+    }
+}
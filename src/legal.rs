@@ -0,0 +1,72 @@
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LegalDocument {
+    /// Changing this makes every user accept the document again.
+    pub version: String,
+    pub url: String,
+}
+
+/// The `[legal]` configuration table. Documents left out are not asked for.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LegalConfig {
+    pub terms: Option<LegalDocument>,
+    pub privacy: Option<LegalDocument>,
+}
+
+/// A configured document as the templates show it.
+#[derive(Debug, Serialize)]
+pub struct Document<'a> {
+    pub name: &'static str,
+    pub version: &'a str,
+    pub url: &'a str,
+}
+
+impl LegalConfig {
+    pub fn documents(&self) -> Vec<Document<'_>> {
+        [("terms", &self.terms), ("privacy", &self.privacy)].into_iter()
+            .filter_map(|(name, document)| document.as_ref().map(|document| Document {
+                name,
+                version: &document.version,
+                url: &document.url,
+            }))
+            .collect()
+    }
+}
+
+/// The documents whose current version `user_id` has not accepted yet.
+pub async fn outstanding<'a>(db: &SqlitePool, config: &'a LegalConfig, user_id: i64) -> Result<Vec<Document<'a>>, sqlx::Error> {
+    let mut outstanding = Vec::new();
+    for document in config.documents() {
+        let accepted = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM legal_acceptances WHERE user_id = ? AND document = ? AND version = ?) AS "accepted!: bool""#,
+            user_id,
+            document.name,
+            document.version
+        )
+        .fetch_one(db)
+        .await?;
+
+        if !accepted {
+            outstanding.push(document);
+        }
+    }
+    Ok(outstanding)
+}
+
+/// Records that `user_id` accepted the current version of every document.
+pub async fn accept(db: &SqlitePool, config: &LegalConfig, user_id: i64) -> Result<(), sqlx::Error> {
+    for document in config.documents() {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO legal_acceptances (user_id, document, version) VALUES (?, ?, ?)",
+            user_id,
+            document.name,
+            document.version
+        )
+        .execute(db)
+        .await?;
+    }
+    Ok(())
+}
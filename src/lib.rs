@@ -0,0 +1,299 @@
+#[macro_use] extern crate rocket;
+
+mod access;
+mod account;
+mod analytics;
+mod api;
+mod audit;
+mod auth;
+mod branding;
+mod cache;
+mod captcha;
+mod charts;
+pub mod cli;
+mod compression;
+mod consent;
+mod cors;
+mod crypto;
+mod db;
+mod directory;
+mod events;
+mod export;
+mod field_types;
+mod flags;
+mod form_templates;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod guards;
+mod http_cache;
+mod impersonation;
+mod import;
+mod integrations;
+mod jobs;
+mod legal;
+mod localtime;
+mod models;
+mod outbox;
+mod payments;
+mod pipeline;
+mod quota;
+mod report;
+mod repository;
+mod resume;
+mod revisions;
+mod routes;
+mod rsvp;
+mod schema;
+mod scim;
+#[cfg(feature = "scripting")]
+mod scripting;
+mod seed;
+mod service_auth;
+mod sharing;
+mod slots;
+mod spam;
+mod storage;
+mod submission_token;
+mod tenant;
+#[cfg(test)]
+mod tests;
+mod write_buffer;
+
+use rocket::fs::{FileServer, relative};
+use rocket_dyn_templates::Template;
+use rocket_okapi::openapi_get_routes;
+use rocket_okapi::swagger_ui::{make_swagger_ui, SwaggerUIConfig};
+use rocket::figment::Figment;
+use rocket::{Rocket, Build};
+use rocket::fairing::{self, AdHoc};
+use sqlx::SqlitePool;
+use serde::Deserialize;
+use std::collections::HashMap;
+use access::{GeoIp, TrustedProxies};
+use guards::SessionStore;
+
+pub use db::{DatabaseConfig, connect};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct AppConfig {
+    require_publish_approval: bool,
+    public_directory: bool,
+    smtp_url: Option<String>,
+    mail_from: Option<String>,
+    google_service_account_key: Option<String>,
+    cors: cors::CorsConfig,
+    compression: compression::CompressionConfig,
+    answers_encryption_key: Option<String>,
+    geoip_database: Option<String>,
+    /// IP addresses or CIDR ranges of reverse proxies whose forwarded
+    /// client address is believed.
+    trusted_proxies: Vec<String>,
+    service_jwt: service_auth::ServiceJwtConfig,
+    oidc: auth::oidc::OidcConfig,
+    ldap: auth::ldap::LdapConfig,
+    scim_token: Option<String>,
+    plans: HashMap<String, quota::PlanLimits>,
+    flags: HashMap<String, bool>,
+    tenant_domain: Option<String>,
+    /// Where the deployment is reached, e.g. `https://forms.example.com`,
+    /// for the links in emails. Tenants get their subdomain of it.
+    base_url: Option<String>,
+    branding: branding::Branding,
+    write_buffer: write_buffer::WriteBufferConfig,
+    submission_tokens: submission_token::SubmissionTokenConfig,
+    legal: legal::LegalConfig,
+    resume: resume::ResumeConfig,
+    accounts: account::AccountConfig,
+    uploads: storage::StorageConfig,
+    stripe: payments::StripeConfig,
+    captcha: captcha::CaptchaConfig,
+    spam: spam::SpamConfig,
+    pipeline: pipeline::PipelineConfig,
+    #[cfg(feature = "scripting")]
+    scripting: scripting::ScriptConfig,
+}
+
+async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
+    let db = rocket.state::<SqlitePool>().expect("database pool is managed");
+    match sqlx::migrate!().run(db).await {
+        Ok(()) => Ok(rocket),
+        Err(e) => {
+            error!("Failed to run database migrations: {}", e);
+            Err(rocket)
+        }
+    }
+}
+
+async fn configure_encryption(rocket: Rocket<Build>) -> fairing::Result {
+    let config = rocket.state::<AppConfig>().expect("app config is managed");
+    let Some(key) = &config.answers_encryption_key else {
+        return Ok(rocket);
+    };
+
+    match crypto::configure(key) {
+        Ok(()) => Ok(rocket),
+        Err(e) => {
+            error!("Invalid answers_encryption_key: {}", e);
+            Err(rocket)
+        }
+    }
+}
+
+async fn check_cors(rocket: Rocket<Build>) -> fairing::Result {
+    let config = rocket.state::<AppConfig>().expect("app config is managed");
+    match config.cors.validate() {
+        Ok(()) => Ok(rocket),
+        Err(e) => {
+            error!("Invalid cors configuration: {}", e);
+            Err(rocket)
+        }
+    }
+}
+
+async fn load_geoip(rocket: Rocket<Build>) -> fairing::Result {
+    let config = rocket.state::<AppConfig>().expect("app config is managed");
+    match GeoIp::load(config.geoip_database.as_deref()) {
+        Ok(geoip) => Ok(rocket.manage(geoip)),
+        Err(e) => {
+            error!("Failed to open geoip_database: {}", e);
+            Err(rocket)
+        }
+    }
+}
+
+async fn load_trusted_proxies(rocket: Rocket<Build>) -> fairing::Result {
+    let config = rocket.state::<AppConfig>().expect("app config is managed");
+    match TrustedProxies::parse(&config.trusted_proxies) {
+        Ok(proxies) => Ok(rocket.manage(proxies)),
+        Err(e) => {
+            error!("Invalid trusted_proxies: {}", e);
+            Err(rocket)
+        }
+    }
+}
+
+async fn load_submission_tokens(rocket: Rocket<Build>) -> fairing::Result {
+    let config = rocket.state::<AppConfig>().expect("app config is managed");
+    let tokens = submission_token::SubmissionTokens::new(&config.submission_tokens);
+    Ok(rocket.manage(tokens))
+}
+
+async fn start_write_buffer(rocket: Rocket<Build>) -> fairing::Result {
+    let db = rocket.state::<SqlitePool>().expect("database pool is managed").clone();
+    let config = rocket.state::<AppConfig>().expect("app config is managed");
+    let writes = write_buffer::WriteBuffer::start(db, &config.write_buffer);
+    Ok(rocket.manage(writes))
+}
+
+async fn open_upload_storage(rocket: Rocket<Build>) -> fairing::Result {
+    let config = rocket.state::<AppConfig>().expect("app config is managed");
+    match storage::Storage::open(&config.uploads).await {
+        Ok(storage) => Ok(rocket.manage(storage)),
+        Err(e) => {
+            error!("Failed to open the uploads directory: {}", e);
+            Err(rocket)
+        }
+    }
+}
+
+async fn connect_read_pool(rocket: Rocket<Build>) -> fairing::Result {
+    let db = rocket.state::<SqlitePool>().expect("database pool is managed").clone();
+    let config: DatabaseConfig = match rocket.figment().focus("database").extract() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Invalid database configuration: {}", e);
+            return Err(rocket);
+        }
+    };
+
+    match db::connect_read(&config, &db).await {
+        Ok(reads) => Ok(rocket.manage(reads)),
+        Err(e) => {
+            error!("Failed to connect to read_url: {}", e);
+            Err(rocket)
+        }
+    }
+}
+
+/// Builds the application from `figment` around `db`. Migrations run on
+/// ignite, so an in-memory pool (`sqlite::memory:` with a single connection)
+/// starts out with the full schema, which is what a local test client needs.
+pub fn build_rocket(figment: Figment, db: SqlitePool) -> Rocket<Build> {
+    #[cfg(feature = "graphql")]
+    let graphql_db = db.clone();
+
+    let rocket = rocket::custom(figment)
+        .mount("/", FileServer::from(relative!("static")))
+        .mount("/", routes::account::routes())
+        .mount("/", routes::analytics::routes())
+        .mount("/", routes::auth::routes())
+        .mount("/", routes::forms::routes())
+        .mount("/", routes::paper::routes())
+        .mount("/", routes::payments::routes())
+        .mount("/", routes::public::routes())
+        .mount("/", routes::receipts::routes())
+        .mount("/", routes::responses::routes())
+        .mount("/", routes::settings::routes())
+        .mount("/", routes::slots::routes())
+        .mount("/api/v1", openapi_get_routes![
+            api::list_forms, api::get_form, api::update_form, api::quick_actions,
+            api::list_responses, api::get_response, api::form_schema, api::submit_response, api::list_hooks,
+            api::subscribe_hook, api::unsubscribe_hook, api::sample_hook_payload
+        ])
+        .mount("/api/v1/docs", make_swagger_ui(&SwaggerUIConfig {
+            url: "../openapi.json".to_string(),
+            ..Default::default()
+        }))
+        .register("/", catchers![routes::auth::legal_required])
+        .register("/api/v1", catchers![api::api_error])
+        .mount("/scim/v2", routes![
+            scim::list_users, scim::get_user, scim::create_user, scim::replace_user, scim::patch_user, scim::deactivate_user
+        ])
+        .register("/scim/v2", catchers![scim::scim_error])
+        .mount("/", routes![impersonation::start, impersonation::stop])
+        .manage(db)
+        .manage(reqwest::Client::new())
+        .manage(events::EventBus::default())
+        .manage(SessionStore::default())
+        .manage(service_auth::Jwks::default())
+        .manage(cache::FormCache::default())
+        .manage(flags::FlagCache::default())
+        .manage(pipeline::Pipeline::standard())
+        .attach(AdHoc::config::<AppConfig>())
+        .attach(impersonation::ImpersonationAudit)
+        .attach(cors::Cors)
+        .attach(http_cache::HttpCache)
+        .attach(compression::Compress)
+        .attach(AdHoc::try_on_ignite("Database Migrations", run_migrations))
+        .attach(AdHoc::try_on_ignite("CORS Configuration", check_cors))
+        .attach(AdHoc::try_on_ignite("Read Pool", connect_read_pool))
+        .attach(AdHoc::try_on_ignite("Answer Encryption", configure_encryption))
+        .attach(AdHoc::try_on_ignite("GeoIP Database", load_geoip))
+        .attach(AdHoc::try_on_ignite("Trusted Proxies", load_trusted_proxies))
+        .attach(AdHoc::try_on_ignite("Response Write Buffer", start_write_buffer))
+        .attach(AdHoc::try_on_ignite("Submission Tokens", load_submission_tokens))
+        .attach(AdHoc::try_on_ignite("Upload Storage", open_upload_storage))
+        .attach(AdHoc::on_liftoff("Background Jobs", |rocket| Box::pin(async move {
+            let db = rocket.state::<SqlitePool>().expect("database pool is managed").clone();
+            let config = rocket.state::<AppConfig>().expect("app config is managed").clone();
+            let events = rocket.state::<events::EventBus>().expect("event bus is managed");
+            let client = rocket.state::<reqwest::Client>().expect("HTTP client is managed").clone();
+            let sessions = rocket.state::<SessionStore>().expect("session store is managed").clone();
+            rocket::tokio::spawn(guards::watch_session_purges(db.clone(), sessions));
+            rocket::tokio::spawn(outbox::run(db.clone(), client.clone(), events.subscribe()));
+            rocket::tokio::spawn(audit::run(db.clone(), events.subscribe()));
+            rocket::tokio::spawn(jobs::run_background_jobs(db, client, config));
+        })))
+        .attach(Template::custom(|engines| branding::Branding::load().register(&mut engines.tera)));
+
+    #[cfg(feature = "graphql")]
+    let rocket = rocket
+        .manage(graphql::schema(graphql_db))
+        .mount("/", routes![graphql::graphql_request]);
+
+    #[cfg(feature = "scripting")]
+    let rocket = rocket.mount("/", routes![scripting::update_form_script]);
+
+    rocket
+}
@@ -0,0 +1,135 @@
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use sqlx::SqlitePool;
+
+use crate::guards::SignedInUser;
+
+/// How SQLite's `CURRENT_TIMESTAMP` stores times, always in UTC.
+const STORED_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// How dates and times are written out. Without a choice they stay as
+/// stored, which is also what spreadsheets parse best.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Locale {
+    #[default]
+    Iso,
+    EnUs,
+    EnGb,
+    De,
+    Fr,
+    Es,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 6] = [Locale::Iso, Locale::EnUs, Locale::EnGb, Locale::De, Locale::Fr, Locale::Es];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::Iso => "iso",
+            Locale::EnUs => "en-US",
+            Locale::EnGb => "en-GB",
+            Locale::De => "de-DE",
+            Locale::Fr => "fr-FR",
+            Locale::Es => "es-ES",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Locale> {
+        Locale::ALL.into_iter().find(|locale| locale.code().eq_ignore_ascii_case(code))
+    }
+
+    fn pattern(self) -> &'static str {
+        match self {
+            Locale::Iso => STORED_FORMAT,
+            Locale::EnUs => "%m/%d/%Y %-I:%M %p",
+            Locale::EnGb | Locale::Fr | Locale::Es => "%d/%m/%Y %H:%M",
+            Locale::De => "%d.%m.%Y %H:%M",
+        }
+    }
+}
+
+fn parse_stored(stored: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(stored, STORED_FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(stored, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+}
+
+/// How long ago a stored timestamp was, e.g. "2 hours ago", for "last
+/// edited" displays. Needs no preferences, since a duration reads the same
+/// in every timezone.
+pub fn ago(stored: &str) -> Option<String> {
+    let seconds = (Utc::now().naive_utc() - parse_stored(stored)?).num_seconds().max(0);
+    let (count, unit) = match seconds {
+        0..=59 => return Some("just now".to_string()),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86_399 => (seconds / 3600, "hour"),
+        86_400..=2_591_999 => (seconds / 86_400, "day"),
+        2_592_000..=31_535_999 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
+    };
+    Some(format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" }))
+}
+
+/// A user's timezone and locale. Timestamps are stored in UTC and only
+/// converted when they are shown or exported; everything that puts one in
+/// front of a user goes through [`TimePreferences::format`].
+#[derive(Debug, Clone, Copy)]
+pub struct TimePreferences {
+    pub timezone: Tz,
+    pub locale: Locale,
+}
+
+impl Default for TimePreferences {
+    fn default() -> Self {
+        TimePreferences { timezone: Tz::UTC, locale: Locale::Iso }
+    }
+}
+
+impl TimePreferences {
+    /// Unset or unknown preferences fall back to UTC as stored.
+    pub async fn load(db: &SqlitePool, user_id: i64) -> Result<Self, sqlx::Error> {
+        let row = sqlx::query!("SELECT timezone, locale FROM user_profiles WHERE user_id = ?", user_id)
+            .fetch_optional(db)
+            .await?;
+
+        let Some(row) = row else {
+            return Ok(TimePreferences::default());
+        };
+        Ok(TimePreferences {
+            timezone: row.timezone.and_then(|timezone| timezone.parse().ok()).unwrap_or(Tz::UTC),
+            locale: row.locale.as_deref().and_then(Locale::parse).unwrap_or_default(),
+        })
+    }
+
+    /// Values that are not stored timestamps come back unchanged.
+    pub fn format(&self, stored: &str) -> String {
+        match parse_stored(stored) {
+            Some(utc) => self.timezone.from_utc_datetime(&utc).format(self.locale.pattern()).to_string(),
+            None => stored.to_string(),
+        }
+    }
+
+    pub fn localize(&self, stored: &mut String) {
+        *stored = self.format(stored);
+    }
+}
+
+/// The signed-in user's preferences; visitors get the defaults.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TimePreferences {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Outcome::Success(SignedInUser(user_id)) = request.guard::<SignedInUser>().await else {
+            return Outcome::Success(TimePreferences::default());
+        };
+
+        let db = request.rocket().state::<SqlitePool>().unwrap();
+        match TimePreferences::load(db, user_id).await {
+            Ok(preferences) => Outcome::Success(preferences),
+            Err(_) => Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
+}
@@ -0,0 +1,743 @@
+use std::collections::HashMap;
+
+use rocket::fs::TempFile;
+use rocket_ws::Message;
+use serde::{Serialize, Deserialize};
+
+use crate::{api, import};
+use crate::integrations::{IntegrationKind, IntegrationCadence};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebForm {
+    pub id: i64,
+    pub title: String,
+    pub fields: String,
+    pub published: bool,
+    pub author_id: i64,
+    pub live_results: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    pub version: i64,
+    pub verify_email: bool,
+    pub duplicate_policy: DuplicatePolicy,
+    pub duplicate_window_hours: i64,
+    /// Responses keep no respondent email, referrer or campaign, whoever
+    /// submits them.
+    pub anonymous: bool,
+    /// UTC, as SQLite `datetime()` writes it. Before this the form shows its
+    /// closed page instead of taking responses.
+    pub opens_at: Option<String>,
+    pub closes_at: Option<String>,
+    /// Shown on the closed page instead of the default wording.
+    pub closed_message: Option<String>,
+    /// Counts down to `opens_at` on the closed page.
+    pub show_countdown: bool,
+    /// Most non-test responses the form accepts.
+    pub response_cap: Option<i64>,
+    /// Past the cap, responses are still taken but waitlisted.
+    pub waitlist: bool,
+    pub captcha: bool,
+    /// CAPTCHA scores at or above this are accepted without a challenge.
+    pub captcha_accept_score: f64,
+    /// CAPTCHA scores below this are rejected outright.
+    pub captcha_reject_score: f64,
+    /// Shown in the public directory while published. Unlisted forms are
+    /// only reachable by their link.
+    pub listed: bool,
+    pub tenant_id: i64,
+}
+
+/// What happens to a submission whose answers match a recent response.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, FromFormField)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+    #[default]
+    Off,
+    /// Taken, and marked as a duplicate of the earlier response.
+    Flag,
+    Reject,
+}
+
+impl DuplicatePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DuplicatePolicy::Off => "off",
+            DuplicatePolicy::Flag => "flag",
+            DuplicatePolicy::Reject => "reject",
+        }
+    }
+}
+
+/// The column's CHECK constraint keeps it to the three policies.
+impl From<String> for DuplicatePolicy {
+    fn from(policy: String) -> Self {
+        match policy.as_str() {
+            "flag" => DuplicatePolicy::Flag,
+            "reject" => DuplicatePolicy::Reject,
+            _ => DuplicatePolicy::Off,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClosedReason {
+    Upcoming,
+    Ended,
+    /// The author's plan has no responses left this month.
+    Full,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FormSchedule {
+    pub opens_at: Option<String>,
+    pub closes_at: Option<String>,
+    pub seconds_until_open: Option<i64>,
+    /// Accepted responses left under the form's cap, if it has one.
+    pub spots_left: Option<i64>,
+    /// Set when the form is not taking responses right now.
+    pub closed: Option<ClosedReason>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormResponse {
+    pub id: i64,
+    pub form_id: i64,
+    pub answers: String,
+    pub is_test: bool,
+    pub created_at: String,
+    pub device: Option<String>,
+    pub status: String,
+    pub assigned_to: Option<i64>,
+    pub updated_at: String,
+    pub version: i64,
+    pub respondent_email: Option<String>,
+    pub answers_hash: Option<String>,
+    pub duplicate_of: Option<i64>,
+    #[serde(skip)]
+    pub idempotency_key: Option<String>,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub referrer: Option<String>,
+    pub waitlisted: bool,
+    /// `pending` until Stripe confirms the payment, then `paid` or `failed`,
+    /// and `refunded` once all of it is returned. Unset for responses that
+    /// owe nothing.
+    pub payment_status: Option<String>,
+    /// In the currency's minor unit. Once paid, what Stripe actually charged.
+    pub payment_amount: Option<i64>,
+    #[serde(skip)]
+    pub payment_session: Option<String>,
+    #[serde(skip)]
+    pub payment_intent: Option<String>,
+    /// In the currency's minor unit.
+    pub payment_refunded: i64,
+    /// The CAPTCHA provider's score, from 0.0 (a bot) to 1.0.
+    pub captcha_score: Option<f64>,
+    /// Kept out of notifications and integrations until marked not spam.
+    pub spam: bool,
+    pub spam_reason: Option<String>,
+    /// The author who keyed the response in by hand, if one did.
+    pub entered_by: Option<i64>,
+    pub tenant_id: i64,
+}
+
+/// Where a respondent came from, as seen when the public form was first
+/// loaded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Attribution {
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub referrer: Option<String>,
+}
+
+impl Attribution {
+    pub fn is_empty(&self) -> bool {
+        self.utm_source.is_none() && self.utm_medium.is_none() && self.utm_campaign.is_none() && self.referrer.is_none()
+    }
+}
+
+/// What the submission checks made of a response before it was stored.
+#[derive(Debug, Clone, Default)]
+pub struct Screening {
+    pub captcha_score: Option<f64>,
+    /// Set when the response looks like spam, saying why.
+    pub spam_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum ResponseStatus {
+    New,
+    #[field(value = "in-progress")]
+    InProgress,
+    Resolved,
+    Rejected,
+}
+
+impl ResponseStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ResponseStatus::New => "new",
+            ResponseStatus::InProgress => "in-progress",
+            ResponseStatus::Resolved => "resolved",
+            ResponseStatus::Rejected => "rejected",
+        }
+    }
+}
+
+#[derive(Debug, Default, FromForm)]
+pub struct ResponseFilter {
+    pub status: Option<ResponseStatus>,
+    pub mine: bool,
+    pub duplicates: bool,
+    pub tag: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub waitlisted: bool,
+    /// `pending`, `paid`, `failed` or `refunded`.
+    pub payment: Option<String>,
+    /// Lists only the responses marked as spam, which are otherwise hidden.
+    pub spam: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedFilter {
+    pub id: i64,
+    pub user_id: i64,
+    pub form_id: i64,
+    pub name: String,
+    pub status: Option<String>,
+    pub tag: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewSavedFilter {
+    #[field(validate = len(1..))]
+    pub name: String,
+    pub status: Option<ResponseStatus>,
+    pub tag: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct TagUpdate {
+    #[field(validate = len(1..=40))]
+    pub tag: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct FormImport {
+    pub source: import::FormSource,
+    #[field(validate = len(1..))]
+    pub file: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct MergeRequest {
+    pub source: i64,
+}
+
+#[derive(Debug, FromForm)]
+pub struct CsvImport {
+    #[field(validate = len(1..))]
+    pub file: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct BulkSelection {
+    pub ids: Vec<i64>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct BulkTagUpdate {
+    pub ids: Vec<i64>,
+    #[field(validate = len(1..=40))]
+    pub tag: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct StatusUpdate {
+    pub ids: Vec<i64>,
+    pub status: ResponseStatus,
+}
+
+#[derive(Debug, FromForm)]
+pub struct AssignmentUpdate {
+    pub ids: Vec<i64>,
+    pub assignee: String,
+}
+
+/// Entries on a response's activity timeline. Features that change a
+/// response append one through `db::record_response_event` or
+/// `db::record_response_events`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseEventKind {
+    Submitted,
+    StatusChanged,
+    Assigned,
+    Commented,
+    Exported,
+    Promoted,
+    Payment,
+    NotSpam,
+}
+
+impl ResponseEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ResponseEventKind::Submitted => "submitted",
+            ResponseEventKind::StatusChanged => "status-changed",
+            ResponseEventKind::Assigned => "assigned",
+            ResponseEventKind::Commented => "commented",
+            ResponseEventKind::Exported => "exported",
+            ResponseEventKind::Promoted => "promoted",
+            ResponseEventKind::Payment => "payment",
+            ResponseEventKind::NotSpam => "not_spam",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseEvent {
+    pub id: i64,
+    pub kind: String,
+    pub detail: String,
+    pub actor: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseComment {
+    pub id: i64,
+    pub response_id: i64,
+    pub parent_id: Option<i64>,
+    pub author_id: i64,
+    pub author: String,
+    pub body: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewComment {
+    #[field(validate = len(1..))]
+    pub body: String,
+    pub parent_id: Option<i64>,
+}
+
+/// Counts shown to anyone watching a form's results, so only fields whose
+/// answers are categories are tallied; names, emails and free text never
+/// leave the server.
+#[derive(Debug, Default, Serialize)]
+pub struct LiveResults {
+    pub total: u64,
+    pub counts: HashMap<String, HashMap<String, u64>>,
+    /// The keys of the fields `analytics::tabulable` allows.
+    #[serde(skip)]
+    pub tabulated: Vec<String>,
+}
+
+impl LiveResults {
+    /// Test, spam, waitlisted and unpaid responses are left out.
+    pub fn record(&mut self, response: &FormResponse) {
+        let unpaid = matches!(response.payment_status.as_deref(), Some(status) if status != "paid");
+        if response.is_test || response.spam || response.waitlisted || unpaid {
+            return;
+        }
+        let Ok(answers) = serde_json::from_str::<HashMap<String, String>>(&response.answers) else {
+            return;
+        };
+
+        self.total += 1;
+        for (field, answer) in answers {
+            if !self.tabulated.contains(&field) {
+                continue;
+            }
+            *self.counts.entry(field).or_default().entry(answer).or_default() += 1;
+        }
+    }
+
+    pub fn message(&self) -> Message {
+        Message::Text(serde_json::to_string(self).unwrap())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub password_hash: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct Registration {
+    pub username: String,
+    pub password_hash: String,
+    /// Required when the deployment configures legal documents.
+    pub accept_legal: bool,
+}
+
+/// A user as the admin page lists them.
+#[derive(Debug, Serialize)]
+pub struct UserAccount {
+    pub id: i64,
+    pub username: String,
+    pub email: Option<String>,
+    pub is_approver: bool,
+    pub active: bool,
+    pub deleted_at: Option<String>,
+}
+
+/// Leaving `avatar` empty keeps the current one; `remove_avatar` drops it.
+#[derive(Debug, FromForm)]
+pub struct ProfileUpdate<'r> {
+    #[field(validate = len(..=100))]
+    pub display_name: String,
+    #[field(validate = len(..=1000))]
+    pub bio: String,
+    /// An IANA name such as `Europe/Berlin`; blank means UTC.
+    pub timezone: String,
+    /// Blank keeps timestamps as stored.
+    pub locale: String,
+    pub show_on_forms: bool,
+    pub avatar: Option<TempFile<'r>>,
+    pub remove_avatar: bool,
+}
+
+/// Deleting an account asks for the password again.
+#[derive(Debug, FromForm)]
+pub struct AccountDeletion {
+    pub password: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct LegalAcceptance {
+    pub accept: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublishRequest {
+    pub id: i64,
+    pub form_id: i64,
+    pub requested_by: i64,
+    pub status: String,
+    pub reviewer_id: Option<i64>,
+    pub review_comment: Option<String>,
+    pub created_at: String,
+    pub reviewed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PendingPublishRequest {
+    pub id: i64,
+    pub form_id: i64,
+    pub form_title: String,
+    pub requested_by: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: i64,
+    pub user_id: i64,
+    pub kind: String,
+    pub message: String,
+    pub link: String,
+    pub is_read: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationPreference {
+    pub kind: String,
+    pub in_app: bool,
+    pub email: bool,
+}
+
+#[derive(Debug, FromForm)]
+pub struct ChannelChoice {
+    pub in_app: bool,
+    pub email: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum DigestFrequency {
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
+
+    pub fn window(frequency: &str) -> &'static str {
+        match frequency {
+            "weekly" => "-7 days",
+            _ => "-1 day",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportSchedule {
+    pub id: i64,
+    pub form_id: i64,
+    pub user_id: i64,
+    pub frequency: String,
+    pub recipient: String,
+    pub last_run_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewExportSchedule {
+    pub frequency: DigestFrequency,
+    #[field(validate = contains('@'))]
+    pub recipient: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportSchedule {
+    pub id: i64,
+    pub form_id: i64,
+    pub user_id: i64,
+    pub recipient: String,
+    /// JSON array of the field keys whose top answers the report lists.
+    pub fields: String,
+    pub last_run_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewReportSchedule {
+    #[field(validate = contains('@'))]
+    pub recipient: String,
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NotificationSettings {
+    pub email: String,
+    pub digest: Option<DigestFrequency>,
+    pub preferences: HashMap<String, ChannelChoice>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewIntegration {
+    pub kind: IntegrationKind,
+    pub target: String,
+    pub fields: String,
+    pub cadence: IntegrationCadence,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SheetSync {
+    pub id: i64,
+    pub form_id: i64,
+    pub spreadsheet_id: String,
+    pub sheet_name: String,
+    pub columns: String,
+    pub last_synced_response_id: i64,
+    pub last_synced_at: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewSheetSync {
+    #[field(validate = len(1..))]
+    pub spreadsheet_id: String,
+    #[field(validate = len(1..))]
+    pub sheet_name: String,
+    #[field(validate = len(1..))]
+    pub columns: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewApiToken {
+    #[field(validate = len(1..))]
+    pub name: String,
+    #[field(validate = len(1..))]
+    pub scopes: Vec<api::Scope>,
+    pub form_ids: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewServiceAccount {
+    #[field(validate = len(1..))]
+    pub name: String,
+    #[field(validate = len(1..))]
+    pub subject: String,
+    #[field(validate = len(1..))]
+    pub scopes: Vec<api::Scope>,
+    pub form_ids: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum PageEvent {
+    View,
+    Complete,
+}
+
+impl PageEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PageEvent::View => "view",
+            PageEvent::Complete => "complete",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField)]
+pub enum AnalyticsChart {
+    Submissions,
+    Funnel,
+    Sources,
+}
+
+/// Which responses analytics counts, by how they came in.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, FromFormField)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryFilter {
+    #[default]
+    All,
+    /// Submitted by respondents themselves.
+    Online,
+    /// Keyed in by the author.
+    Manual,
+}
+
+impl EntryFilter {
+    /// Whether to count only manually entered responses, only the others,
+    /// or (`None`) both.
+    pub fn manual(self) -> Option<bool> {
+        match self {
+            EntryFilter::All => None,
+            EntryFilter::Online => Some(false),
+            EntryFilter::Manual => Some(true),
+        }
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct PageProgress {
+    #[field(validate = range(1..=100))]
+    pub page: i64,
+    pub event: PageEvent,
+}
+
+#[derive(Debug, FromForm)]
+pub struct ResumeLookup {
+    pub token: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct ReceiptLookup {
+    pub email: String,
+}
+
+/// A past submission as its respondent sees it on the receipts page.
+#[derive(Debug, Serialize)]
+pub struct Receipt {
+    pub response_id: i64,
+    pub form_id: i64,
+    pub form_title: String,
+    pub answers: HashMap<String, String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct EmailVerificationRequest {
+    pub email: String,
+    pub device: Option<String>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct EmailVerificationCode {
+    pub email: String,
+    pub code: String,
+    pub device: Option<String>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewSlot {
+    pub field_key: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    #[field(validate = range(1..))]
+    pub capacity: i64,
+}
+
+/// Turns one optional submission stage on or off for a form.
+#[derive(Debug, FromForm)]
+pub struct StageUpdate {
+    pub stage: String,
+    pub enabled: bool,
+}
+
+/// Sets a feature flag for the organization. Leaving `enabled` out goes back
+/// to the deployment's setting.
+#[derive(Debug, FromForm)]
+pub struct FlagUpdate {
+    pub flag: String,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct TemplateDesignation {
+    pub form_id: i64,
+    /// Comma-separated field keys.
+    pub locked_fields: String,
+}
+
+/// Leaving `enabled` off takes the form out of RSVP mode.
+#[derive(Debug, FromForm)]
+pub struct RsvpUpdate {
+    pub enabled: bool,
+    pub title: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub location: String,
+    pub choice_field: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct RestrictionsUpdate {
+    pub blocked_ranges: String,
+    pub allowed_countries: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct ListingUpdate {
+    pub listed: bool,
+    /// Comma-separated.
+    pub tags: String,
+}
+
+/// An edit held back because it removes or retypes answered fields, with
+/// where each of those fields' answers should go: another field's key, or
+/// nothing to archive them.
+#[derive(Debug, FromForm)]
+pub struct SchemaMigration {
+    pub form: WebForm,
+    pub mapping: HashMap<String, String>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct ShareRedemption {
+    pub code: String,
+}
+
+#[derive(Debug, FromForm)]
+pub struct PublishReview {
+    pub comment: String,
+}
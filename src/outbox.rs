@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::Receiver;
+use rocket::tokio::time::sleep;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::{api, crypto, integrations};
+use crate::events::{self, DomainEvent};
+use crate::models::FormResponse;
+
+/// How often the dispatcher looks for events nothing woke it for, such as
+/// ones written just before a crash.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+const DISPATCH_BATCH_SIZE: i64 = 100;
+
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+/// Dispatched events are kept this long, for looking into deliveries.
+const RETENTION: &str = "-7 days";
+
+/// Events that leave the app, through REST hooks and integrations.
+#[derive(Debug, Clone, Copy)]
+pub enum OutboxEvent {
+    ResponseSubmitted { form_id: i64, response_id: i64 },
+    FormPublished { form_id: i64 },
+}
+
+/// Queues `event` for delivery. Pass the transaction making the change the
+/// event reports, so either both are committed or neither is.
+pub async fn record(conn: &mut SqliteConnection, event: OutboxEvent) -> Result<(), sqlx::Error> {
+    let (name, form_id, response_id) = match event {
+        OutboxEvent::ResponseSubmitted { form_id, response_id } => ("response.submitted", form_id, Some(response_id)),
+        OutboxEvent::FormPublished { form_id } => ("form.published", form_id, None),
+    };
+    sqlx::query!(
+        "INSERT INTO outbox (event, form_id, response_id) VALUES (?, ?, ?)",
+        name,
+        form_id,
+        response_id
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+struct PendingEvent {
+    id: i64,
+    event: String,
+    form_id: i64,
+    response_id: Option<i64>,
+    attempts: i64,
+}
+
+/// Hands the event to the hook and integration queues. Integration
+/// deliveries are only enqueued here; a failure after that but before the
+/// event is marked dispatched enqueues them again, so delivery is at least
+/// once.
+async fn deliver(db: &SqlitePool, client: &reqwest::Client, pending: &PendingEvent) -> Result<(), sqlx::Error> {
+    match (pending.event.as_str(), pending.response_id) {
+        ("response.submitted", Some(response_id)) => {
+            // Deleted since, so there is nothing left to report.
+            let Some(mut response) = sqlx::query_as!(FormResponse, "SELECT * FROM responses WHERE id = ?", response_id)
+                .fetch_optional(db)
+                .await?
+            else {
+                return Ok(());
+            };
+            integrations::enqueue_deliveries(db, &response).await?;
+            let answers = crypto::decrypt_answers(&response.answers);
+            response.answers = serde_json::to_string(&answers).unwrap_or_default();
+            api::response_submitted(db, client, &response).await
+        }
+        ("form.published", _) => api::form_published(db, client, pending.form_id).await,
+        (event, _) => {
+            warn!("Dropping unknown outbox event {} ({})", pending.id, event);
+            Ok(())
+        }
+    }
+}
+
+async fn dispatch_due(db: &SqlitePool, client: &reqwest::Client) -> Result<(), sqlx::Error> {
+    let due = sqlx::query_as!(PendingEvent,
+        "SELECT id, event, form_id, response_id, attempts FROM outbox
+         WHERE dispatched_at IS NULL AND next_attempt_at <= CURRENT_TIMESTAMP
+         ORDER BY id LIMIT ?",
+        DISPATCH_BATCH_SIZE
+    )
+    .fetch_all(db)
+    .await?;
+
+    for pending in due {
+        match deliver(db, client, &pending).await {
+            Ok(()) => {
+                sqlx::query!("UPDATE outbox SET dispatched_at = CURRENT_TIMESTAMP WHERE id = ?", pending.id)
+                    .execute(db)
+                    .await?;
+            }
+            Err(e) => {
+                let backoff = format!("+{} seconds", (30 << pending.attempts.min(10)).min(MAX_BACKOFF_SECS));
+                let error = e.to_string();
+                warn!("Failed to dispatch outbox event {}: {}", pending.id, error);
+                sqlx::query!(
+                    "UPDATE outbox SET attempts = attempts + 1, last_error = ?, next_attempt_at = datetime('now', ?) WHERE id = ?",
+                    error,
+                    backoff,
+                    pending.id
+                )
+                .execute(db)
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn purge_dispatched(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM outbox WHERE dispatched_at < datetime('now', ?)", RETENTION)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Delivers the outbox and then the integration deliveries due, the only
+/// place either is dispatched. Events on the bus wake it straight away; the
+/// poll picks up whatever the bus lost and retries coming due.
+pub async fn run(db: SqlitePool, client: reqwest::Client, mut events: Receiver<DomainEvent>) {
+    loop {
+        if let Err(e) = dispatch_due(&db, &client).await {
+            error!("Failed to dispatch the outbox: {}", e);
+        }
+        // Every pass, not only after new events, so retries come due on
+        // the poll too.
+        if let Err(e) = integrations::dispatch_due(&db, &client).await {
+            error!("Failed to dispatch integration deliveries: {}", e);
+        }
+        if let Err(e) = purge_dispatched(&db).await {
+            error!("Failed to purge the outbox: {}", e);
+        }
+
+        select! {
+            event = events::next(&mut events, "Outbox dispatch") => {
+                if event.is_none() {
+                    break;
+                }
+            }
+            _ = sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
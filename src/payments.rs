@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use hmac::{Hmac, Mac};
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use rocket::time::OffsetDateTime;
+use serde::Deserialize;
+use sha2::Sha256;
+use sqlx::SqlitePool;
+
+use crate::models::{FormResponse, WebForm};
+use crate::schema::{self, FieldDef, FieldError, FieldKind};
+
+const CHECKOUT_SESSIONS_URL: &str = "https://api.stripe.com/v1/checkout/sessions";
+
+/// How long a Checkout session stays open. Until then a pending response
+/// holds its place under the form's cap.
+pub const CHECKOUT_EXPIRY_MINUTES: i64 = 60;
+
+/// How old a webhook signature may be, to limit replays.
+const SIGNATURE_TOLERANCE_SECS: i64 = 300;
+
+/// The `[stripe]` configuration table. Forms with a payment field reject
+/// submissions until both keys are set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StripeConfig {
+    pub secret_key: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub currency: String,
+    /// Where Checkout sends the respondent back to, e.g. `https://forms.example.com`.
+    pub base_url: String,
+}
+
+impl Default for StripeConfig {
+    fn default() -> Self {
+        StripeConfig {
+            secret_key: None,
+            webhook_secret: None,
+            currency: "usd".to_string(),
+            base_url: "http://localhost:8000".to_string(),
+        }
+    }
+}
+
+impl StripeConfig {
+    pub fn enabled(&self) -> bool {
+        self.secret_key.is_some() && self.webhook_secret.is_some()
+    }
+}
+
+#[derive(Deserialize)]
+struct CheckoutSession {
+    id: String,
+    url: String,
+}
+
+/// The most of anything one response can pay for.
+const MAX_QUANTITY: i64 = 1000;
+
+/// The total the payment fields ask for, or `None` when the form charges
+/// nothing. A missing quantity counts as one; an unanswered
+/// respondent-chosen amount counts as nothing. A quantity that isn't a whole
+/// number from 1 to `MAX_QUANTITY`, or a total too large to charge, is an
+/// error on the field it came from.
+pub fn amount_due(fields: &[FieldDef], answers: &HashMap<String, String>) -> Result<Option<i64>, FieldError> {
+    let mut total: i64 = 0;
+    for field in fields.iter().filter(|field| field.kind == FieldKind::Payment) {
+        let quantity = match field.amount_field.as_ref().and_then(|key| answers.get(key).map(|value| (key, value.trim()))) {
+            None => 1,
+            Some((key, value)) => value.parse::<i64>().ok()
+                .filter(|quantity| (1..=MAX_QUANTITY).contains(quantity))
+                .ok_or_else(|| FieldError::new(key, format!("must be a whole number from 1 to {}", MAX_QUANTITY)))?,
+        };
+        let Some(amount) = field.amount.or_else(|| answers.get(&field.key).and_then(|value| schema::parse_amount(value))) else {
+            continue;
+        };
+        total = amount.checked_mul(quantity)
+            .and_then(|due| total.checked_add(due))
+            .ok_or_else(|| FieldError::new(&field.key, "is more than can be charged"))?;
+    }
+    Ok((total > 0).then_some(total))
+}
+
+/// Opens a Checkout session for a pending response and returns the URL to
+/// send the respondent to.
+pub async fn checkout(
+    db: &SqlitePool,
+    client: &reqwest::Client,
+    config: &StripeConfig,
+    form: &WebForm,
+    response: &FormResponse
+) -> Result<String, String> {
+    let secret_key = config.secret_key.as_deref().ok_or("no stripe secret key is configured")?;
+    let amount = response.payment_amount.ok_or("the response owes nothing")?;
+    let base_url = config.base_url.trim_end_matches('/');
+    let success_url = format!("{}{}", base_url, uri!(crate::routes::payments::payment_complete(form.id)));
+    let cancel_url = format!("{}{}", base_url, uri!(crate::routes::public::public_form(form.id)));
+    let amount = amount.to_string();
+    let response_id = response.id.to_string();
+    let expires_at = (OffsetDateTime::now_utc().unix_timestamp() + CHECKOUT_EXPIRY_MINUTES * 60).to_string();
+
+    let mut params = vec![
+        ("mode", "payment"),
+        ("success_url", success_url.as_str()),
+        ("cancel_url", cancel_url.as_str()),
+        ("client_reference_id", response_id.as_str()),
+        ("expires_at", expires_at.as_str()),
+        ("line_items[0][quantity]", "1"),
+        ("line_items[0][price_data][currency]", config.currency.as_str()),
+        ("line_items[0][price_data][unit_amount]", amount.as_str()),
+        ("line_items[0][price_data][product_data][name]", form.title.as_str()),
+    ];
+    if let Some(email) = &response.respondent_email {
+        params.push(("customer_email", email.as_str()));
+    }
+
+    let session: CheckoutSession = client.post(CHECKOUT_SESSIONS_URL)
+        .basic_auth(secret_key, None::<&str>)
+        .form(&params)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sqlx::query!("UPDATE responses SET payment_session = ? WHERE id = ?", session.id, response.id)
+        .execute(db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(session.url)
+}
+
+/// Checks a `Stripe-Signature` header, `t=<timestamp>,v1=<hex>,...`,
+/// against the raw request body.
+pub fn verify_signature(secret: &str, header: &str, payload: &str) -> bool {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {}
+        }
+    }
+    let Some(timestamp) = timestamp else {
+        return false;
+    };
+    if (OffsetDateTime::now_utc().unix_timestamp() - timestamp).abs() > SIGNATURE_TOLERANCE_SECS {
+        return false;
+    }
+
+    signatures.into_iter().any(|signature| {
+        let Some(expected) = decode_hex(signature) else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&expected).is_ok()
+    })
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The raw `Stripe-Signature` header of a webhook delivery.
+pub struct StripeSignature(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for StripeSignature {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match request.headers().get_one("Stripe-Signature") {
+            Some(header) => Outcome::Success(StripeSignature(header.to_string())),
+            None => Outcome::Error((rocket::http::Status::BadRequest, ())),
+        }
+    }
+}
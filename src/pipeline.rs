@@ -0,0 +1,696 @@
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+
+use rocket::http::Status;
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+
+use crate::{AppConfig, consent, crypto, payments, quota, rsvp, schema, slots};
+use crate::captcha::Verdict;
+use crate::db::{answers_hash, form_schedule, notify, replayed_response};
+use crate::events::{DomainEvent, EventBus};
+use crate::flags::{Flag, FlagCache};
+use crate::guards::AuthenticatedUser;
+use crate::models::{Attribution, ClosedReason, DuplicatePolicy, FormResponse, Screening, WebForm};
+use crate::schema::{FieldDef, FieldError};
+use crate::write_buffer::{NewResponse, WriteBuffer};
+
+/// The `[pipeline]` configuration table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    /// Optional stages that are off unless a form turns them on.
+    pub disabled: Vec<String>,
+}
+
+/// Everything a stage may need from the running app.
+pub struct Services<'a> {
+    pub db: &'a SqlitePool,
+    pub config: &'a AppConfig,
+    pub client: &'a reqwest::Client,
+    pub events: &'a EventBus,
+    pub writes: &'a WriteBuffer,
+    pub flags: &'a FlagCache,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source<'a> {
+    Web,
+    Kiosk(&'a str),
+    Api,
+    /// Keyed in by the author, with their id, from a response taken by
+    /// phone or on paper.
+    Manual(i64),
+}
+
+impl Source<'_> {
+    pub fn device(&self) -> Option<&str> {
+        match self {
+            Source::Kiosk(device) => Some(device),
+            Source::Web | Source::Api | Source::Manual(_) => None,
+        }
+    }
+
+    pub fn entered_by(&self) -> Option<i64> {
+        match self {
+            Source::Manual(user_id) => Some(*user_id),
+            Source::Web | Source::Kiosk(_) | Source::Api => None,
+        }
+    }
+}
+
+/// Why a submission was turned away. Each route decides how to show it.
+#[derive(Debug)]
+pub enum Rejection {
+    Invalid(Vec<FieldError>),
+    Closed(ClosedReason),
+    Duplicate,
+    SlotTaken,
+    /// The CAPTCHA score calls for the interactive challenge.
+    Challenge,
+    /// The CAPTCHA score, or where the submission came from, rules out a
+    /// person.
+    Bot,
+    /// The form needs something this deployment or submission path cannot
+    /// do, such as taking a payment.
+    Unavailable,
+    /// Turned away by the form's own script, with its message for the
+    /// respondent.
+    Refused(String),
+    Error(Status),
+}
+
+impl From<Status> for Rejection {
+    fn from(status: Status) -> Self {
+        Rejection::Error(status)
+    }
+}
+
+/// One submission on its way through the pipeline. The route fills in what
+/// it knows; checks fill in the rest.
+pub struct Submission<'a> {
+    pub form: &'a WebForm,
+    pub fields: Vec<FieldDef>,
+    pub answers: HashMap<String, String>,
+    pub source: Source<'a>,
+    pub is_test: bool,
+    pub respondent_email: Option<&'a str>,
+    pub idempotency_key: Option<&'a str>,
+    pub attribution: Attribution,
+    pub ip: Option<IpAddr>,
+    pub captcha_token: Option<String>,
+    pub challenge_token: Option<String>,
+    pub screening: Screening,
+    pub waitlisted: bool,
+    pub duplicate_of: Option<i64>,
+    pub payment_amount: Option<i64>,
+    pub booked: Vec<i64>,
+}
+
+impl<'a> Submission<'a> {
+    pub fn new(form: &'a WebForm, source: Source<'a>, answers: HashMap<String, String>) -> Self {
+        Submission {
+            form,
+            fields: schema::parse(&form.fields),
+            answers,
+            source,
+            is_test: false,
+            respondent_email: None,
+            idempotency_key: None,
+            attribution: Attribution::default(),
+            ip: None,
+            captcha_token: None,
+            challenge_token: None,
+            screening: Screening::default(),
+            waitlisted: false,
+            duplicate_of: None,
+            payment_amount: None,
+            booked: Vec::new(),
+        }
+    }
+
+    /// The form's author submitting is a test.
+    pub fn user(mut self, user: Option<AuthenticatedUser>) -> Self {
+        self.is_test = user.is_some_and(|AuthenticatedUser(user_id)| user_id == self.form.author_id);
+        self
+    }
+
+    pub fn respondent_email(mut self, email: Option<&'a str>) -> Self {
+        self.respondent_email = email;
+        self
+    }
+
+    pub fn idempotency_key(mut self, key: Option<&'a str>) -> Self {
+        self.idempotency_key = key;
+        self
+    }
+
+    pub fn attribution(mut self, attribution: Attribution) -> Self {
+        self.attribution = attribution;
+        self
+    }
+
+    pub fn ip(mut self, ip: Option<IpAddr>) -> Self {
+        self.ip = ip;
+        self
+    }
+
+    pub fn captcha(mut self, token: Option<String>, challenge_token: Option<String>) -> Self {
+        self.captcha_token = token;
+        self.challenge_token = challenge_token;
+        self
+    }
+}
+
+/// Runs before the response is stored and may turn it away.
+#[rocket::async_trait]
+pub trait Check: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Required checks run whatever the deployment or form says.
+    fn required(&self) -> bool {
+        false
+    }
+
+    async fn run(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection>;
+
+    /// Reverses what `run` did when a later step turns the submission away.
+    async fn undo(&self, _services: &Services<'_>, _submission: &Submission<'_>) {}
+}
+
+/// Runs once a response is complete: stored, paid for if it owes anything,
+/// and not held as spam. Failures are the action's own to report.
+#[rocket::async_trait]
+pub trait Action: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// `response.answers` is decrypted.
+    async fn run(&self, services: &Services<'_>, form: &WebForm, response: &FormResponse, answers: &HashMap<String, String>) -> Result<(), Status>;
+}
+
+/// How one stage is set for a form, for the form settings page.
+#[derive(Debug, Serialize)]
+pub struct StageSetting {
+    pub name: &'static str,
+    pub required: bool,
+    pub enabled: bool,
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    checks: Vec<Box<dyn Check>>,
+    actions: Vec<Box<dyn Action>>,
+}
+
+impl Pipeline {
+    /// Checks run in the order they are added.
+    pub fn check(mut self, check: impl Check + 'static) -> Self {
+        self.checks.push(Box::new(check));
+        self
+    }
+
+    pub fn action(mut self, action: impl Action + 'static) -> Self {
+        self.actions.push(Box::new(action));
+        self
+    }
+
+    pub fn standard() -> Self {
+        let pipeline = Pipeline::default()
+            .check(Validate)
+            .check(Schedule)
+            .check(Quota);
+        #[cfg(feature = "scripting")]
+        let pipeline = pipeline.check(crate::scripting::Script);
+        pipeline
+            .check(Captcha)
+            .check(Spam)
+            .check(Dedup)
+            .check(Payment)
+            .check(Slots)
+            .action(Notify)
+            .action(RsvpConfirmation)
+            .action(Integrations)
+    }
+
+    fn optional(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.checks.iter().filter(|check| !check.required()).map(|check| check.name())
+            .chain(self.actions.iter().map(|action| action.name()))
+    }
+
+    pub fn is_optional(&self, stage: &str) -> bool {
+        self.optional().any(|name| name == stage)
+    }
+
+    /// Whether a complete response to `form_id` goes out through hooks and
+    /// integrations, and so needs an outbox event.
+    pub async fn delivers(&self, db: &SqlitePool, config: &AppConfig, form_id: i64) -> Result<bool, Status> {
+        Ok(!self.disabled(db, config, form_id).await?.contains(Integrations.name()))
+    }
+
+    /// The optional stages turned off for `form_id`: the deployment's
+    /// defaults, overridden by the form's own settings.
+    async fn disabled(&self, db: &SqlitePool, config: &AppConfig, form_id: i64) -> Result<HashSet<&'static str>, Status> {
+        let overrides: HashMap<String, bool> = sqlx::query!("SELECT stage, enabled FROM form_stages WHERE form_id = ?", form_id)
+            .fetch_all(db)
+            .await
+            .map_err(|_| Status::InternalServerError)?
+            .into_iter()
+            .map(|row| (row.stage, row.enabled))
+            .collect();
+
+        Ok(self.optional()
+            .filter(|name| match overrides.get(*name) {
+                Some(enabled) => !enabled,
+                None => config.pipeline.disabled.iter().any(|disabled| disabled == name),
+            })
+            .collect())
+    }
+
+    pub async fn settings(&self, db: &SqlitePool, config: &AppConfig, form_id: i64) -> Result<Vec<StageSetting>, Status> {
+        let disabled = self.disabled(db, config, form_id).await?;
+        Ok(self.checks.iter().map(|check| (check.name(), check.required()))
+            .chain(self.actions.iter().map(|action| (action.name(), false)))
+            .map(|(name, required)| StageSetting { name, required, enabled: !disabled.contains(name) })
+            .collect())
+    }
+
+    /// Checks, stores and announces a submission. On `Rejection::Invalid`
+    /// and `Rejection::Challenge` the answers are still in `submission`, so
+    /// the form can be shown again filled in.
+    pub async fn submit(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<FormResponse, Rejection> {
+        let db = services.db;
+        let form = submission.form;
+        if let Some(key) = submission.idempotency_key {
+            if let Some(response) = replayed_response(db, form.id, key).await? {
+                return Ok(response);
+            }
+        }
+
+        // Enforced here rather than by each route, so no submission path can
+        // attach anything that identifies the respondent to an anonymous form.
+        if form.anonymous {
+            submission.respondent_email = None;
+            submission.attribution = Attribution::default();
+        }
+
+        let disabled = self.disabled(db, services.config, form.id).await?;
+        let mut ran: Vec<&dyn Check> = Vec::new();
+        for check in self.checks.iter().filter(|check| !disabled.contains(check.name())) {
+            if let Err(rejection) = check.run(services, submission).await {
+                undo(&ran, services, submission).await;
+                return Err(rejection);
+            }
+            ran.push(check.as_ref());
+        }
+
+        // Only now, since checks may rewrite answers.
+        let (stored, answers_hash) = match stored_answers(submission) {
+            Ok(stored) => stored,
+            Err(status) => {
+                undo(&ran, services, submission).await;
+                return Err(Rejection::Error(status));
+            }
+        };
+        let response = services.writes.insert(db, NewResponse {
+            form_id: form.id,
+            answers: stored,
+            is_test: submission.is_test,
+            device: submission.source.device().map(String::from),
+            respondent_email: submission.respondent_email.map(String::from),
+            answers_hash,
+            duplicate_of: submission.duplicate_of,
+            idempotency_key: submission.idempotency_key.map(String::from),
+            attribution: submission.attribution.clone(),
+            waitlisted: submission.waitlisted,
+            payment_status: submission.payment_amount.map(|_| "pending".to_string()),
+            payment_amount: submission.payment_amount,
+            captcha_score: submission.screening.captcha_score,
+            spam_reason: submission.screening.spam_reason.clone(),
+            entered_by: submission.source.entered_by(),
+            consents: consent::given(&submission.fields, &submission.answers),
+            booked: submission.booked.clone(),
+            announce: !submission.is_test
+                && submission.payment_amount.is_none()
+                && submission.screening.spam_reason.is_none()
+                && !disabled.contains(Integrations.name()),
+        })
+        .await;
+        if response.is_err() {
+            undo(&ran, services, submission).await;
+        }
+        let mut response = match (response, submission.idempotency_key) {
+            (Ok(response), _) => response,
+            // A concurrent retry with the same key got there first.
+            (Err(e), Some(key)) if e.as_database_error().is_some_and(|e| e.is_unique_violation()) => {
+                return replayed_response(db, form.id, key).await?.ok_or(Rejection::Error(Status::InternalServerError));
+            }
+            (Err(e), _) => {
+                error!("Failed to store a response to form {}: {}", form.id, e);
+                return Err(Rejection::Error(Status::InternalServerError));
+            }
+        };
+
+        let answers = &submission.answers;
+        response.answers = serde_json::to_string(answers).map_err(|_| Status::InternalServerError)?;
+
+        // A response waiting on payment is announced by the Stripe webhook
+        // once it is paid, and spam only once the author says it is not.
+        if response.payment_status.is_none() && !response.spam {
+            self.announce(services, form, &response, answers).await?;
+        }
+        Ok(response)
+    }
+
+    /// Runs the form's actions for a complete response. `response.answers`
+    /// must already be decrypted.
+    pub async fn announce(&self, services: &Services<'_>, form: &WebForm, response: &FormResponse, answers: &HashMap<String, String>) -> Result<(), Status> {
+        let disabled = self.disabled(services.db, services.config, form.id).await?;
+        for action in self.actions.iter().filter(|action| !disabled.contains(action.name())) {
+            action.run(services, form, response, answers).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn undo(ran: &[&dyn Check], services: &Services<'_>, submission: &Submission<'_>) {
+    for check in ran.iter().rev() {
+        check.undo(services, submission).await;
+    }
+}
+
+/// The answers as stored, encrypted where the form asks, and their hash.
+fn stored_answers(submission: &Submission<'_>) -> Result<(String, String), Status> {
+    let stored = crypto::encrypt_answers(&submission.fields, &submission.answers).map_err(|e| {
+        error!("Failed to encrypt answers for form {}: {}", submission.form.id, e);
+        Status::InternalServerError
+    })?;
+    let stored = serde_json::to_string(&stored).map_err(|_| Status::InternalServerError)?;
+    Ok((stored, answers_hash(&submission.answers)?))
+}
+
+pub struct Validate;
+
+#[rocket::async_trait]
+impl Check for Validate {
+    fn name(&self) -> &'static str {
+        "validate"
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, _services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection> {
+        let errors = schema::validate(&submission.fields, &submission.answers);
+        if errors.is_empty() { Ok(()) } else { Err(Rejection::Invalid(errors)) }
+    }
+}
+
+/// Opening times and the response cap.
+pub struct Schedule;
+
+#[rocket::async_trait]
+impl Check for Schedule {
+    fn name(&self) -> &'static str {
+        "schedule"
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection> {
+        let schedule = form_schedule(services.db, submission.form).await?;
+        match schedule.closed {
+            Some(ClosedReason::Full) if submission.is_test => {}
+            // Responses taken by phone or on paper are keyed in after they
+            // were collected, which may be once the form has closed.
+            Some(ClosedReason::Upcoming | ClosedReason::Ended) if matches!(submission.source, Source::Manual(_)) => {}
+            Some(reason) => return Err(Rejection::Closed(reason)),
+            None => {}
+        }
+        // Two submissions racing for the last spot may both be accepted; the
+        // cap is a soft limit.
+        submission.waitlisted = !submission.is_test && schedule.spots_left == Some(0);
+        Ok(())
+    }
+}
+
+/// The author's plan limits.
+pub struct Quota;
+
+#[rocket::async_trait]
+impl Check for Quota {
+    fn name(&self) -> &'static str {
+        "quota"
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection> {
+        let form = submission.form;
+        if !submission.is_test && !quota::can_accept_response(services.db, &services.config.plans, form.id, form.author_id).await? {
+            return Err(Rejection::Closed(ClosedReason::Full));
+        }
+        Ok(())
+    }
+}
+
+/// Score-based CAPTCHA for forms that ask for it. Kiosk submissions are
+/// scored like any other, since the kiosk URL is open to anyone; API
+/// clients cannot answer, so they are refused.
+pub struct Captcha;
+
+#[rocket::async_trait]
+impl Check for Captcha {
+    fn name(&self) -> &'static str {
+        "captcha"
+    }
+
+    async fn run(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection> {
+        let captcha = &services.config.captcha;
+        let form = submission.form;
+        if !form.captcha || !captcha.enabled() {
+            return Ok(());
+        }
+        match submission.source {
+            Source::Manual(_) => return Ok(()),
+            Source::Api => return Err(Rejection::Bot),
+            Source::Web | Source::Kiosk(_) => {}
+        }
+
+        let token = submission.captcha_token.as_deref().unwrap_or_default();
+        let score = captcha.score(services.client, token, submission.ip).await.map_err(|e| {
+            error!("Failed to verify a CAPTCHA for form {}: {}", form.id, e);
+            Status::BadGateway
+        })?;
+        match captcha.verdict(form, score) {
+            Verdict::Accept => {}
+            Verdict::Reject => {
+                info!("Rejected a submission to form {} with CAPTCHA score {}", form.id, score);
+                return Err(Rejection::Bot);
+            }
+            Verdict::Challenge => {
+                let passed = match &submission.challenge_token {
+                    Some(token) => captcha.passed_challenge(services.client, token, submission.ip).await.map_err(|e| {
+                        error!("Failed to verify a CAPTCHA challenge for form {}: {}", form.id, e);
+                        Status::BadGateway
+                    })?,
+                    None => false,
+                };
+                if !passed {
+                    return Err(Rejection::Challenge);
+                }
+            }
+        }
+        submission.screening.captcha_score = Some(score);
+        Ok(())
+    }
+}
+
+/// Content spam. Spam is stored, flagged, rather than turned away.
+pub struct Spam;
+
+#[rocket::async_trait]
+impl Check for Spam {
+    fn name(&self) -> &'static str {
+        "spam"
+    }
+
+    async fn run(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection> {
+        if matches!(submission.source, Source::Manual(_)) {
+            return Ok(());
+        }
+        submission.screening.spam_reason = services.config.spam
+            .check(services.client, submission.form, &submission.answers, submission.ip, submission.respondent_email)
+            .await;
+        Ok(())
+    }
+}
+
+/// Repeat submissions within the form's window, per its duplicate policy.
+pub struct Dedup;
+
+#[rocket::async_trait]
+impl Check for Dedup {
+    fn name(&self) -> &'static str {
+        "dedup"
+    }
+
+    async fn run(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection> {
+        let form = submission.form;
+        if form.duplicate_policy == DuplicatePolicy::Off || submission.is_test {
+            return Ok(());
+        }
+
+        let answers_hash = answers_hash(&submission.answers)?;
+        let window = format!("-{} hours", form.duplicate_window_hours);
+        submission.duplicate_of = sqlx::query_scalar!(
+            "SELECT id FROM responses
+             WHERE form_id = ? AND answers_hash = ? AND is_test = false AND created_at > datetime('now', ?)
+             ORDER BY id LIMIT 1",
+            form.id,
+            answers_hash,
+            window
+        )
+        .fetch_optional(services.db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+        if submission.duplicate_of.is_some() && form.duplicate_policy == DuplicatePolicy::Reject {
+            return Err(Rejection::Duplicate);
+        }
+        Ok(())
+    }
+}
+
+/// The amount a form with payment fields charges. Only the public form can
+/// send the respondent to Checkout.
+pub struct Payment;
+
+#[rocket::async_trait]
+impl Check for Payment {
+    fn name(&self) -> &'static str {
+        "payment"
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection> {
+        submission.payment_amount = payments::amount_due(&submission.fields, &submission.answers)
+            .map_err(|error| Rejection::Invalid(vec![error]))?;
+        if submission.payment_amount.is_none() {
+            return Ok(());
+        }
+        if submission.source != Source::Web {
+            return Err(Rejection::Unavailable);
+        }
+        let form = submission.form;
+        let enabled = services.flags.enabled_for_form(services.db, &services.config.flags, form.id, Flag::Payments)
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        if !enabled {
+            return Err(Rejection::Unavailable);
+        }
+        if !services.config.stripe.enabled() {
+            warn!("Form {} takes payments but Stripe is not configured", form.id);
+            return Err(Rejection::Unavailable);
+        }
+        Ok(())
+    }
+}
+
+/// Books the chosen time slots. Runs last of the standard checks, since it
+/// is the one with something to undo.
+pub struct Slots;
+
+#[rocket::async_trait]
+impl Check for Slots {
+    fn name(&self) -> &'static str {
+        "slots"
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection> {
+        let form = submission.form;
+        match slots::book(services.db, form.id, &submission.fields, &submission.answers).await {
+            Ok(Ok(booked)) => {
+                submission.booked = booked;
+                Ok(())
+            }
+            Ok(Err(field)) => {
+                info!("Slot for field {} of form {} is full", field, form.id);
+                Err(Rejection::SlotTaken)
+            }
+            Err(e) => {
+                error!("Failed to book slots for form {}: {}", form.id, e);
+                Err(Rejection::Error(Status::InternalServerError))
+            }
+        }
+    }
+
+    async fn undo(&self, services: &Services<'_>, submission: &Submission<'_>) {
+        slots::release(services.db, &submission.booked).await;
+    }
+}
+
+/// Tells the author about the new response in the app. The email, if they
+/// want one, is an integration delivery, so it is retried like the rest.
+pub struct Notify;
+
+#[rocket::async_trait]
+impl Action for Notify {
+    fn name(&self) -> &'static str {
+        "notify"
+    }
+
+    async fn run(&self, services: &Services<'_>, form: &WebForm, response: &FormResponse, _answers: &HashMap<String, String>) -> Result<(), Status> {
+        if response.is_test {
+            return Ok(());
+        }
+        let message = format!("New response to \"{}\"", form.title);
+        let link = uri!(crate::routes::responses::response_detail(form.id, response.id)).to_string();
+        notify(services.db, form.author_id, "submission", &message, &link, Some(form.id)).await
+    }
+}
+
+pub struct RsvpConfirmation;
+
+#[rocket::async_trait]
+impl Action for RsvpConfirmation {
+    fn name(&self) -> &'static str {
+        "rsvp"
+    }
+
+    async fn run(&self, services: &Services<'_>, form: &WebForm, response: &FormResponse, _answers: &HashMap<String, String>) -> Result<(), Status> {
+        // The response is already stored, so a failed confirmation is only
+        // logged.
+        if let Err(e) = rsvp::confirm(services.db, form, response, response.respondent_email.as_deref()).await {
+            warn!("Failed to queue the RSVP confirmation for response {}: {}", response.id, e);
+        }
+        Ok(())
+    }
+}
+
+/// Publishes the response for integrations, API hooks, live results and
+/// the audit log.
+pub struct Integrations;
+
+#[rocket::async_trait]
+impl Action for Integrations {
+    fn name(&self) -> &'static str {
+        "integrations"
+    }
+
+    async fn run(&self, services: &Services<'_>, _form: &WebForm, response: &FormResponse, _answers: &HashMap<String, String>) -> Result<(), Status> {
+        services.events.publish(DomainEvent::ResponseSubmitted(response.clone()));
+        Ok(())
+    }
+}
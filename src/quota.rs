@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use rocket::http::Status;
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlanLimits {
+    pub max_forms: Option<i64>,
+    pub max_monthly_responses: Option<i64>,
+    /// Bytes a user may keep in uploads, avatars included.
+    pub max_upload_bytes: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub user_id: i64,
+    pub username: String,
+    pub plan: String,
+    pub forms: i64,
+    pub busiest_form_responses: i64,
+    pub upload_bytes: i64,
+    pub limits: PlanLimits,
+}
+
+async fn limits(db: &SqlitePool, plans: &HashMap<String, PlanLimits>, user_id: i64) -> Result<PlanLimits, Status> {
+    let plan = sqlx::query_scalar!("SELECT plan FROM users WHERE id = ?", user_id)
+        .fetch_one(db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(plans.get(&plan).cloned().unwrap_or_default())
+}
+
+pub async fn can_create_form(db: &SqlitePool, plans: &HashMap<String, PlanLimits>, user_id: i64) -> Result<bool, Status> {
+    let Some(max_forms) = limits(db, plans, user_id).await?.max_forms else {
+        return Ok(true);
+    };
+
+    let forms = sqlx::query_scalar!("SELECT COUNT(*) FROM forms WHERE author_id = ?", user_id)
+        .fetch_one(db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(forms < max_forms)
+}
+
+pub async fn can_accept_response(
+    db: &SqlitePool,
+    plans: &HashMap<String, PlanLimits>,
+    form_id: i64,
+    author_id: i64
+) -> Result<bool, Status> {
+    let Some(max_responses) = limits(db, plans, author_id).await?.max_monthly_responses else {
+        return Ok(true);
+    };
+
+    let responses = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM responses WHERE form_id = ? AND is_test = false AND created_at >= datetime('now', 'start of month')",
+        form_id
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(responses < max_responses)
+}
+
+/// Whether `size` more bytes fit in the user's uploads once the upload at
+/// `replacing`, if any, is gone.
+pub async fn can_store_upload(
+    db: &SqlitePool,
+    plans: &HashMap<String, PlanLimits>,
+    user_id: i64,
+    size: u64,
+    replacing: Option<&str>
+) -> Result<bool, Status> {
+    let Some(max_upload_bytes) = limits(db, plans, user_id).await?.max_upload_bytes else {
+        return Ok(true);
+    };
+
+    let stored = sqlx::query_scalar!(
+        r#"SELECT COALESCE(SUM(size), 0) AS "stored!: i64" FROM uploads WHERE user_id = ? AND key IS NOT ?"#,
+        user_id,
+        replacing
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(stored.saturating_add(i64::try_from(size).unwrap_or(i64::MAX)) <= max_upload_bytes)
+}
+
+pub async fn usage(
+    db: &SqlitePool,
+    plans: &HashMap<String, PlanLimits>,
+    tenant_id: i64,
+    user_id: Option<i64>
+) -> Result<Vec<Usage>, Status> {
+    let rows = sqlx::query!(
+        r#"SELECT u.id, u.username, u.plan,
+             (SELECT COUNT(*) FROM forms f WHERE f.author_id = u.id) AS "forms!: i64",
+             (SELECT COALESCE(MAX(monthly), 0) FROM (
+                 SELECT COUNT(*) AS monthly FROM responses r JOIN forms f ON f.id = r.form_id
+                 WHERE f.author_id = u.id AND r.is_test = false AND r.created_at >= datetime('now', 'start of month')
+                 GROUP BY r.form_id
+             )) AS "busiest_form_responses!: i64",
+             (SELECT COALESCE(SUM(size), 0) FROM uploads WHERE user_id = u.id) AS "upload_bytes!: i64"
+         FROM users u WHERE u.tenant_id = ?2 AND (?1 IS NULL OR u.id = ?1) ORDER BY u.username"#,
+        user_id,
+        tenant_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(rows.into_iter()
+        .map(|row| Usage {
+            limits: plans.get(&row.plan).cloned().unwrap_or_default(),
+            user_id: row.id,
+            username: row.username,
+            plan: row.plan,
+            forms: row.forms,
+            busiest_form_responses: row.busiest_form_responses,
+            upload_bytes: row.upload_bytes,
+        })
+        .collect())
+}
@@ -0,0 +1,71 @@
+use sqlx::SqlitePool;
+
+use crate::{analytics, charts, schema};
+use crate::charts::ChartFormat;
+use crate::models::{EntryFilter, WebForm};
+
+const REPORT_DAYS: i64 = 7;
+
+const REPORT_WINDOW: &str = "-7 days";
+
+const REPORT_TOP_ANSWERS: i64 = 5;
+
+/// A rendered report, ready for the email queue.
+pub struct Report {
+    pub subject: String,
+    pub body: String,
+    /// A PNG trend chart, left out when it could not be drawn.
+    pub chart: Option<Vec<u8>>,
+}
+
+/// Submission counts for the past week, the top answers to `field_keys`
+/// and a chart of submissions per day, ending with `link` to the form's
+/// analytics. Encrypted fields and keys no longer in the form are skipped.
+pub async fn weekly(db: &SqlitePool, form: &WebForm, field_keys: &[String], link: &str) -> Result<Report, sqlx::Error> {
+    let daily = analytics::daily_submissions(db, form.author_id, Some(form.id), REPORT_DAYS, EntryFilter::All).await?;
+    let this_week: i64 = daily.iter().map(|(_, count)| count).sum();
+    let all_time = sqlx::query_scalar!("SELECT COUNT(*) FROM responses WHERE form_id = ? AND is_test = false AND spam = false", form.id)
+        .fetch_one(db)
+        .await?;
+
+    let mut body = format!(
+        "Weekly report for \"{}\"\n\n{} new responses this week, {} in total.\n\n",
+        form.title,
+        this_week,
+        all_time
+    );
+    for (day, count) in &daily {
+        body.push_str(&format!("  {}  {}\n", day, count));
+    }
+
+    let fields = schema::parse(&form.fields);
+    for key in field_keys {
+        let Some(field) = fields.iter().find(|field| &field.key == key && !field.encrypted) else {
+            continue;
+        };
+        let label = if field.label.is_empty() { &field.key } else { &field.label };
+        body.push_str(&format!("\nTop answers to {}:\n", label));
+
+        let answers = analytics::top_answers(db, form.id, field, REPORT_WINDOW, REPORT_TOP_ANSWERS).await?;
+        if answers.is_empty() {
+            body.push_str("  (no answers this week)\n");
+        }
+        for (answer, count) in answers {
+            body.push_str(&format!("  {} — {}\n", answer, count));
+        }
+    }
+
+    body.push_str(&format!("\n{}\n", link));
+
+    let (labels, values): (Vec<String>, Vec<i64>) = daily.into_iter().unzip();
+    let chart = charts::line_chart(ChartFormat::Png, "Submissions this week", &labels, &values)
+        .map_err(|e| warn!("Failed to draw the weekly report chart for form {}: {}", form.id, e))
+        .ok()
+        .map(|chart| chart.body);
+
+    Ok(Report {
+        subject: format!("Weekly report: {}", form.title),
+        body,
+        chart,
+    })
+}
@@ -0,0 +1,430 @@
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::models::{FormResponse, ResponseStatus, User, WebForm};
+use crate::outbox::{self, OutboxEvent};
+use crate::schema;
+
+#[cfg(test)]
+pub mod memory;
+
+/// Storage for forms, scoped to their author. Handlers depend on this trait
+/// rather than on SQL so the backing store can be swapped or mocked.
+#[rocket::async_trait]
+pub trait FormRepository: Send + Sync {
+    async fn forms_by_author(&self, author_id: i64) -> Result<Vec<WebForm>, sqlx::Error>;
+
+    async fn owned_form(&self, id: i64, author_id: i64) -> Result<Option<WebForm>, sqlx::Error>;
+
+    async fn create_form(&self, form: &WebForm, author_id: i64, published: bool) -> Result<(), sqlx::Error>;
+
+    async fn create_draft(&self, title: &str, fields: &str, author_id: i64) -> Result<WebForm, sqlx::Error>;
+
+    /// When `require_approval` is set, an update can unpublish a form but
+    /// never publish it.
+    async fn update_form(&self, id: i64, author_id: i64, form: &WebForm, require_approval: bool) -> Result<(), sqlx::Error>;
+
+    /// Returns whether the published flag actually changed.
+    async fn set_published(&self, id: i64, author_id: i64, published: bool) -> Result<bool, sqlx::Error>;
+
+    /// Copies the form and its access restrictions as one unit; a failure
+    /// part-way leaves no half-made clone behind.
+    async fn clone_form(&self, id: i64, author_id: i64) -> Result<(), sqlx::Error>;
+
+    /// Copies a form someone else shared with `author_id`, or one of the
+    /// organization's templates, the same way a clone is made. Returns the
+    /// copy's id, if the form was still there.
+    async fn copy_shared_form(&self, id: i64, author_id: i64) -> Result<Option<i64>, sqlx::Error>;
+
+    /// Returns whether the form was there to delete.
+    async fn delete_form(&self, id: i64, author_id: i64) -> Result<bool, sqlx::Error>;
+}
+
+/// Storage for responses, scoped to the author of their form.
+#[rocket::async_trait]
+pub trait ResponseRepository: Send + Sync {
+    async fn owned_response(&self, id: i64, form_id: i64, author_id: i64) -> Result<Option<FormResponse>, sqlx::Error>;
+
+    /// Returns how many of `ids` were on the form to delete.
+    async fn delete_responses(&self, form_id: i64, author_id: i64, ids: &[i64]) -> Result<u64, sqlx::Error>;
+
+    /// Returns how many of `ids` were on the form to update.
+    async fn set_status(&self, form_id: i64, author_id: i64, ids: &[i64], status: ResponseStatus) -> Result<u64, sqlx::Error>;
+
+    /// Deletes the form's test responses, returning how many there were.
+    async fn purge_test_responses(&self, form_id: i64, author_id: i64) -> Result<u64, sqlx::Error>;
+}
+
+#[rocket::async_trait]
+pub trait UserRepository: Send + Sync {
+    /// Looks up an active user by username within a tenant.
+    async fn user_by_username(&self, tenant_id: i64, username: &str) -> Result<Option<User>, sqlx::Error>;
+
+    async fn create_user(&self, tenant_id: i64, username: &str, password_hash: &str, is_approver: bool) -> Result<i64, sqlx::Error>;
+
+    /// Returns false when no user in the tenant has that username.
+    async fn set_password(&self, tenant_id: i64, username: &str, password_hash: &str) -> Result<bool, sqlx::Error>;
+
+    /// Returns false when no user in the tenant has that username.
+    async fn promote(&self, tenant_id: i64, username: &str) -> Result<bool, sqlx::Error>;
+
+    /// Links the account to the directory entry LDAP logins will arrive as.
+    /// Returns false when no user in the tenant has that username.
+    async fn link_ldap(&self, tenant_id: i64, username: &str, dn: &str) -> Result<bool, sqlx::Error>;
+
+    async fn is_approver(&self, id: i64) -> Result<Option<bool>, sqlx::Error>;
+}
+
+#[rocket::async_trait]
+impl FormRepository for SqlitePool {
+    async fn forms_by_author(&self, author_id: i64) -> Result<Vec<WebForm>, sqlx::Error> {
+        sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE author_id = ?", author_id)
+            .fetch_all(self)
+            .await
+    }
+
+    async fn owned_form(&self, id: i64, author_id: i64) -> Result<Option<WebForm>, sqlx::Error> {
+        sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE id = ? AND author_id = ?", id, author_id)
+            .fetch_optional(self)
+            .await
+    }
+
+    async fn create_form(&self, form: &WebForm, author_id: i64, published: bool) -> Result<(), sqlx::Error> {
+        let fields = schema::assign_keys(&form.fields);
+        let duplicate_policy = form.duplicate_policy.as_str();
+        sqlx::query!(
+            "INSERT INTO forms (title, fields, published, author_id, live_results, verify_email, duplicate_policy, duplicate_window_hours, anonymous,
+             opens_at, closes_at, closed_message, show_countdown, response_cap, waitlist, captcha, captcha_accept_score, captcha_reject_score, tenant_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, datetime(?10), datetime(?11), NULLIF(?12, ''), ?13, ?14, ?15, ?16, ?17, ?18,
+             (SELECT tenant_id FROM users WHERE id = ?4))",
+            form.title,
+            fields,
+            published,
+            author_id,
+            form.live_results,
+            form.verify_email,
+            duplicate_policy,
+            form.duplicate_window_hours,
+            form.anonymous,
+            form.opens_at,
+            form.closes_at,
+            form.closed_message,
+            form.show_countdown,
+            form.response_cap,
+            form.waitlist,
+            form.captcha,
+            form.captcha_accept_score,
+            form.captcha_reject_score
+        )
+        .execute(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn create_draft(&self, title: &str, fields: &str, author_id: i64) -> Result<WebForm, sqlx::Error> {
+        let fields = schema::assign_keys(fields);
+        sqlx::query_as!(WebForm,
+            "INSERT INTO forms (title, fields, published, author_id, created_at, updated_at, tenant_id)
+             VALUES (?1, ?2, false, ?3, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, (SELECT tenant_id FROM users WHERE id = ?3)) RETURNING *",
+            title,
+            fields,
+            author_id
+        )
+        .fetch_one(self)
+        .await
+    }
+
+    async fn update_form(&self, id: i64, author_id: i64, form: &WebForm, require_approval: bool) -> Result<(), sqlx::Error> {
+        let mut conn = self.acquire().await?;
+        save_form(&mut *conn, id, author_id, form, require_approval).await
+    }
+
+    async fn set_published(&self, id: i64, author_id: i64, published: bool) -> Result<bool, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        let changed = sqlx::query!(
+            "UPDATE forms SET published = ?1 WHERE id = ?2 AND author_id = ?3 AND published != ?1",
+            published,
+            id,
+            author_id
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if changed > 0 && published {
+            outbox::record(&mut *tx, OutboxEvent::FormPublished { form_id: id }).await?;
+        }
+        tx.commit().await?;
+
+        Ok(changed > 0)
+    }
+
+    async fn clone_form(&self, id: i64, author_id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.begin().await?;
+        copy_form(&mut *tx, id, Some(author_id), author_id, " (Clone)").await?;
+        tx.commit().await
+    }
+
+    async fn copy_shared_form(&self, id: i64, author_id: i64) -> Result<Option<i64>, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        let copy_id = copy_form(&mut *tx, id, None, author_id, "").await?;
+        tx.commit().await?;
+
+        Ok(copy_id)
+    }
+
+    async fn delete_form(&self, id: i64, author_id: i64) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM forms WHERE id = ? AND author_id = ?", id, author_id)
+            .execute(self)
+            .await?
+            .rows_affected();
+
+        Ok(deleted > 0)
+    }
+}
+
+#[rocket::async_trait]
+impl ResponseRepository for SqlitePool {
+    async fn owned_response(&self, id: i64, form_id: i64, author_id: i64) -> Result<Option<FormResponse>, sqlx::Error> {
+        sqlx::query_as!(FormResponse,
+            "SELECT r.* FROM responses r JOIN forms f ON f.id = r.form_id
+             WHERE r.id = ? AND f.id = ? AND f.author_id = ?",
+            id,
+            form_id,
+            author_id
+        )
+        .fetch_optional(self)
+        .await
+    }
+
+    async fn delete_responses(&self, form_id: i64, author_id: i64, ids: &[i64]) -> Result<u64, sqlx::Error> {
+        let ids = serde_json::to_string(ids).unwrap_or_default();
+        let deleted = sqlx::query!(
+            "DELETE FROM responses
+             WHERE id IN (SELECT value FROM json_each(?))
+             AND form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+            ids,
+            form_id,
+            author_id
+        )
+        .execute(self)
+        .await?
+        .rows_affected();
+
+        Ok(deleted)
+    }
+
+    async fn set_status(&self, form_id: i64, author_id: i64, ids: &[i64], status: ResponseStatus) -> Result<u64, sqlx::Error> {
+        let ids = serde_json::to_string(ids).unwrap_or_default();
+        let status = status.as_str();
+        let updated = sqlx::query!(
+            "UPDATE responses SET status = ?
+             WHERE id IN (SELECT value FROM json_each(?))
+             AND form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+            status,
+            ids,
+            form_id,
+            author_id
+        )
+        .execute(self)
+        .await?
+        .rows_affected();
+
+        Ok(updated)
+    }
+
+    async fn purge_test_responses(&self, form_id: i64, author_id: i64) -> Result<u64, sqlx::Error> {
+        let purged = sqlx::query!(
+            "DELETE FROM responses WHERE is_test = true AND form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+            form_id,
+            author_id
+        )
+        .execute(self)
+        .await?
+        .rows_affected();
+
+        Ok(purged)
+    }
+}
+
+/// What [`FormRepository::update_form`] does, inside a caller's
+/// transaction.
+pub async fn save_form(
+    conn: &mut SqliteConnection,
+    id: i64,
+    author_id: i64,
+    form: &WebForm,
+    require_approval: bool
+) -> Result<(), sqlx::Error> {
+    let fields = schema::assign_keys(&form.fields);
+    let duplicate_policy = form.duplicate_policy.as_str();
+    sqlx::query!(
+        "UPDATE forms SET title = ?1, fields = ?2, live_results = ?3, verify_email = ?4,
+         duplicate_policy = ?9, duplicate_window_hours = ?10, anonymous = ?11,
+         opens_at = datetime(?12), closes_at = datetime(?13), closed_message = NULLIF(?14, ''), show_countdown = ?15,
+         response_cap = ?16, waitlist = ?17, captcha = ?18, captcha_accept_score = ?19, captcha_reject_score = ?20,
+         published = CASE WHEN ?5 THEN published AND ?6 ELSE ?6 END
+         WHERE id = ?7 AND author_id = ?8",
+        form.title,
+        fields,
+        form.live_results,
+        form.verify_email,
+        require_approval,
+        form.published,
+        id,
+        author_id,
+        duplicate_policy,
+        form.duplicate_window_hours,
+        form.anonymous,
+        form.opens_at,
+        form.closes_at,
+        form.closed_message,
+        form.show_countdown,
+        form.response_cap,
+        form.waitlist,
+        form.captcha,
+        form.captcha_accept_score,
+        form.captcha_reject_score
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Copies form `id` to `author_id`, restricted to forms `owner_id` wrote
+/// when given. Responses stay behind; locked fields stay locked.
+async fn copy_form(
+    conn: &mut SqliteConnection,
+    id: i64,
+    owner_id: Option<i64>,
+    author_id: i64,
+    title_suffix: &str
+) -> Result<Option<i64>, sqlx::Error> {
+    let clone_id = sqlx::query_scalar!(
+        "INSERT INTO forms (title, fields, published, author_id, live_results, verify_email, duplicate_policy, duplicate_window_hours, anonymous,
+         opens_at, closes_at, closed_message, show_countdown, response_cap, waitlist, captcha, captcha_accept_score, captcha_reject_score, tenant_id)
+         SELECT title || ?1, fields, false, ?2, live_results, verify_email, duplicate_policy, duplicate_window_hours, anonymous,
+         opens_at, closes_at, closed_message, show_countdown, response_cap, waitlist, captcha, captcha_accept_score, captcha_reject_score, tenant_id
+         FROM forms WHERE id = ?3 AND (?4 IS NULL OR author_id = ?4) AND tenant_id = (SELECT tenant_id FROM users WHERE id = ?2)
+         RETURNING id",
+        title_suffix,
+        author_id,
+        id,
+        owner_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if let Some(clone_id) = clone_id {
+        sqlx::query!(
+            "INSERT INTO form_restrictions (form_id, blocked_ranges, allowed_countries)
+             SELECT ?, blocked_ranges, allowed_countries FROM form_restrictions WHERE form_id = ?",
+            clone_id,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO form_rsvp (form_id, title, starts_at, ends_at, location, choice_field)
+             SELECT ?, title, starts_at, ends_at, location, choice_field FROM form_rsvp WHERE form_id = ?",
+            clone_id,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!("INSERT INTO form_tags (form_id, tag) SELECT ?, tag FROM form_tags WHERE form_id = ?", clone_id, id)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query!(
+            "INSERT INTO form_stages (form_id, stage, enabled) SELECT ?, stage, enabled FROM form_stages WHERE form_id = ?",
+            clone_id,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO form_scripts (form_id, source) SELECT ?, source FROM form_scripts WHERE form_id = ?",
+            clone_id,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO form_locked_fields (form_id, field) SELECT ?, field FROM form_locked_fields WHERE form_id = ?",
+            clone_id,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(clone_id)
+}
+
+#[rocket::async_trait]
+impl UserRepository for SqlitePool {
+    async fn user_by_username(&self, tenant_id: i64, username: &str) -> Result<Option<User>, sqlx::Error> {
+        sqlx::query_as!(User,
+            "SELECT id, username, password_hash FROM users WHERE username = ? AND tenant_id = ? AND active = true",
+            username,
+            tenant_id
+        )
+        .fetch_optional(self)
+        .await
+    }
+
+    async fn create_user(&self, tenant_id: i64, username: &str, password_hash: &str, is_approver: bool) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar!(
+            "INSERT INTO users (username, password_hash, is_approver, tenant_id) VALUES (?, ?, ?, ?) RETURNING id",
+            username,
+            password_hash,
+            is_approver,
+            tenant_id
+        )
+        .fetch_one(self)
+        .await
+    }
+
+    async fn set_password(&self, tenant_id: i64, username: &str, password_hash: &str) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!(
+            "UPDATE users SET password_hash = ? WHERE username = ? AND tenant_id = ?",
+            password_hash,
+            username,
+            tenant_id
+        )
+        .execute(self)
+        .await?
+        .rows_affected();
+
+        Ok(updated > 0)
+    }
+
+    async fn promote(&self, tenant_id: i64, username: &str) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!("UPDATE users SET is_approver = true WHERE username = ? AND tenant_id = ?", username, tenant_id)
+            .execute(self)
+            .await?
+            .rows_affected();
+
+        Ok(updated > 0)
+    }
+
+    async fn link_ldap(&self, tenant_id: i64, username: &str, dn: &str) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!("UPDATE users SET ldap_dn = ? WHERE username = ? AND tenant_id = ?", dn, username, tenant_id)
+            .execute(self)
+            .await?
+            .rows_affected();
+
+        Ok(updated > 0)
+    }
+
+    async fn is_approver(&self, id: i64) -> Result<Option<bool>, sqlx::Error> {
+        sqlx::query_scalar!("SELECT is_approver FROM users WHERE id = ?", id)
+            .fetch_optional(self)
+            .await
+    }
+}
@@ -0,0 +1,422 @@
+//! An in-memory stand-in for the SQLite repositories, so code written
+//! against the traits can be tested without a database. It keeps the same
+//! author and tenant scoping the queries do.
+
+use std::sync::Mutex;
+
+use chrono::Utc;
+
+use super::{FormRepository, ResponseRepository, UserRepository};
+use crate::models::{DuplicatePolicy, FormResponse, ResponseStatus, User, WebForm};
+use crate::schema;
+use crate::tenant::DEFAULT_TENANT;
+
+struct StoredUser {
+    user: User,
+    tenant_id: i64,
+    is_approver: bool,
+    active: bool,
+    ldap_dn: Option<String>,
+}
+
+#[derive(Default)]
+struct Tables {
+    forms: Vec<WebForm>,
+    responses: Vec<FormResponse>,
+    users: Vec<StoredUser>,
+    last_id: i64,
+}
+
+impl Tables {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn tenant_of(&self, user_id: i64) -> i64 {
+        self.users.iter()
+            .find(|stored| stored.user.id == user_id)
+            .map_or(DEFAULT_TENANT, |stored| stored.tenant_id)
+    }
+
+    fn owns(&self, form_id: i64, author_id: i64) -> bool {
+        self.forms.iter().any(|form| form.id == form_id && form.author_id == author_id)
+    }
+
+    fn copy_form(&mut self, id: i64, author_id: i64, title_suffix: &str) -> Option<i64> {
+        let tenant_id = self.tenant_of(author_id);
+        let mut copy = self.forms.iter().find(|form| form.id == id && form.tenant_id == tenant_id)?.clone();
+        copy.id = self.next_id();
+        copy.title.push_str(title_suffix);
+        copy.author_id = author_id;
+        copy.published = false;
+        copy.version = 1;
+        copy.created_at = now();
+        copy.updated_at = now();
+        let copy_id = copy.id;
+        self.forms.push(copy);
+        Some(copy_id)
+    }
+}
+
+fn now() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// A form as a new row comes out of the database, with the column
+/// defaults.
+fn new_form(id: i64, title: &str, fields: &str, author_id: i64, tenant_id: i64) -> WebForm {
+    WebForm {
+        id,
+        title: title.to_string(),
+        fields: schema::assign_keys(fields),
+        published: false,
+        author_id,
+        live_results: false,
+        created_at: now(),
+        updated_at: now(),
+        version: 1,
+        verify_email: false,
+        duplicate_policy: DuplicatePolicy::Off,
+        duplicate_window_hours: 24,
+        anonymous: false,
+        opens_at: None,
+        closes_at: None,
+        closed_message: None,
+        show_countdown: false,
+        response_cap: None,
+        waitlist: false,
+        captcha: false,
+        captcha_accept_score: 0.5,
+        captcha_reject_score: 0.1,
+        listed: false,
+        tenant_id,
+    }
+}
+
+/// `stored` with `form`'s settings, as an insert or update from the editor
+/// writes them.
+fn with_settings(stored: WebForm, form: &WebForm) -> WebForm {
+    WebForm {
+        live_results: form.live_results,
+        verify_email: form.verify_email,
+        duplicate_policy: form.duplicate_policy,
+        duplicate_window_hours: form.duplicate_window_hours,
+        anonymous: form.anonymous,
+        opens_at: form.opens_at.clone(),
+        closes_at: form.closes_at.clone(),
+        closed_message: form.closed_message.clone().filter(|message| !message.is_empty()),
+        show_countdown: form.show_countdown,
+        response_cap: form.response_cap,
+        waitlist: form.waitlist,
+        captcha: form.captcha,
+        captcha_accept_score: form.captcha_accept_score,
+        captcha_reject_score: form.captcha_reject_score,
+        ..stored
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryRepository(Mutex<Tables>);
+
+impl MemoryRepository {
+    /// Stores a response to the form, as a respondent's submission would,
+    /// and returns its id.
+    pub fn add_response(&self, form_id: i64, answers: &str, is_test: bool) -> i64 {
+        let mut tables = self.0.lock().unwrap();
+        let id = tables.next_id();
+        let tenant_id = tables.forms.iter().find(|form| form.id == form_id).map_or(DEFAULT_TENANT, |form| form.tenant_id);
+        tables.responses.push(FormResponse {
+            id,
+            form_id,
+            answers: answers.to_string(),
+            is_test,
+            created_at: now(),
+            device: None,
+            status: ResponseStatus::New.as_str().to_string(),
+            assigned_to: None,
+            updated_at: now(),
+            version: 1,
+            respondent_email: None,
+            answers_hash: None,
+            duplicate_of: None,
+            idempotency_key: None,
+            utm_source: None,
+            utm_medium: None,
+            utm_campaign: None,
+            referrer: None,
+            waitlisted: false,
+            payment_status: None,
+            payment_amount: None,
+            payment_session: None,
+            payment_intent: None,
+            payment_refunded: 0,
+            captcha_score: None,
+            spam: false,
+            spam_reason: None,
+            entered_by: None,
+            tenant_id,
+        });
+        id
+    }
+}
+
+#[rocket::async_trait]
+impl FormRepository for MemoryRepository {
+    async fn forms_by_author(&self, author_id: i64) -> Result<Vec<WebForm>, sqlx::Error> {
+        let tables = self.0.lock().unwrap();
+        Ok(tables.forms.iter().filter(|form| form.author_id == author_id).cloned().collect())
+    }
+
+    async fn owned_form(&self, id: i64, author_id: i64) -> Result<Option<WebForm>, sqlx::Error> {
+        let tables = self.0.lock().unwrap();
+        Ok(tables.forms.iter().find(|form| form.id == id && form.author_id == author_id).cloned())
+    }
+
+    async fn create_form(&self, form: &WebForm, author_id: i64, published: bool) -> Result<(), sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        let id = tables.next_id();
+        let tenant_id = tables.tenant_of(author_id);
+        let created = WebForm { published, ..new_form(id, &form.title, &form.fields, author_id, tenant_id) };
+        tables.forms.push(with_settings(created, form));
+        Ok(())
+    }
+
+    async fn create_draft(&self, title: &str, fields: &str, author_id: i64) -> Result<WebForm, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        let id = tables.next_id();
+        let form = new_form(id, title, fields, author_id, tables.tenant_of(author_id));
+        tables.forms.push(form.clone());
+        Ok(form)
+    }
+
+    async fn update_form(&self, id: i64, author_id: i64, form: &WebForm, require_approval: bool) -> Result<(), sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        if let Some(stored) = tables.forms.iter_mut().find(|stored| stored.id == id && stored.author_id == author_id) {
+            let published = if require_approval { stored.published && form.published } else { form.published };
+            let updated = WebForm {
+                title: form.title.clone(),
+                fields: schema::assign_keys(&form.fields),
+                published,
+                version: stored.version + 1,
+                updated_at: now(),
+                ..stored.clone()
+            };
+            *stored = with_settings(updated, form);
+        }
+        Ok(())
+    }
+
+    async fn set_published(&self, id: i64, author_id: i64, published: bool) -> Result<bool, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        let form = tables.forms.iter_mut()
+            .find(|form| form.id == id && form.author_id == author_id && form.published != published);
+        Ok(form.map(|form| form.published = published).is_some())
+    }
+
+    async fn clone_form(&self, id: i64, author_id: i64) -> Result<(), sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        if tables.owns(id, author_id) {
+            tables.copy_form(id, author_id, " (Clone)");
+        }
+        Ok(())
+    }
+
+    async fn copy_shared_form(&self, id: i64, author_id: i64) -> Result<Option<i64>, sqlx::Error> {
+        Ok(self.0.lock().unwrap().copy_form(id, author_id, ""))
+    }
+
+    async fn delete_form(&self, id: i64, author_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        if !tables.owns(id, author_id) {
+            return Ok(false);
+        }
+        tables.forms.retain(|form| form.id != id);
+        tables.responses.retain(|response| response.form_id != id);
+        Ok(true)
+    }
+}
+
+#[rocket::async_trait]
+impl ResponseRepository for MemoryRepository {
+    async fn owned_response(&self, id: i64, form_id: i64, author_id: i64) -> Result<Option<FormResponse>, sqlx::Error> {
+        let tables = self.0.lock().unwrap();
+        if !tables.owns(form_id, author_id) {
+            return Ok(None);
+        }
+        Ok(tables.responses.iter().find(|response| response.id == id && response.form_id == form_id).cloned())
+    }
+
+    async fn delete_responses(&self, form_id: i64, author_id: i64, ids: &[i64]) -> Result<u64, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        if !tables.owns(form_id, author_id) {
+            return Ok(0);
+        }
+        let before = tables.responses.len();
+        tables.responses.retain(|response| response.form_id != form_id || !ids.contains(&response.id));
+        Ok((before - tables.responses.len()) as u64)
+    }
+
+    async fn set_status(&self, form_id: i64, author_id: i64, ids: &[i64], status: ResponseStatus) -> Result<u64, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        if !tables.owns(form_id, author_id) {
+            return Ok(0);
+        }
+        let mut updated = 0;
+        for response in tables.responses.iter_mut().filter(|response| response.form_id == form_id && ids.contains(&response.id)) {
+            response.status = status.as_str().to_string();
+            updated += 1;
+        }
+        Ok(updated)
+    }
+
+    async fn purge_test_responses(&self, form_id: i64, author_id: i64) -> Result<u64, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        if !tables.owns(form_id, author_id) {
+            return Ok(0);
+        }
+        let before = tables.responses.len();
+        tables.responses.retain(|response| response.form_id != form_id || !response.is_test);
+        Ok((before - tables.responses.len()) as u64)
+    }
+}
+
+#[rocket::async_trait]
+impl UserRepository for MemoryRepository {
+    async fn user_by_username(&self, tenant_id: i64, username: &str) -> Result<Option<User>, sqlx::Error> {
+        let tables = self.0.lock().unwrap();
+        Ok(tables.users.iter()
+            .find(|stored| stored.tenant_id == tenant_id && stored.user.username == username && stored.active)
+            .map(|stored| User {
+                id: stored.user.id,
+                username: stored.user.username.clone(),
+                password_hash: stored.user.password_hash.clone(),
+            }))
+    }
+
+    async fn create_user(&self, tenant_id: i64, username: &str, password_hash: &str, is_approver: bool) -> Result<i64, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        if tables.users.iter().any(|stored| stored.tenant_id == tenant_id && stored.user.username == username) {
+            return Err(sqlx::Error::Protocol(format!("{} is taken", username)));
+        }
+        let id = tables.next_id();
+        tables.users.push(StoredUser {
+            user: User { id, username: username.to_string(), password_hash: password_hash.to_string() },
+            tenant_id,
+            is_approver,
+            active: true,
+            ldap_dn: None,
+        });
+        Ok(id)
+    }
+
+    async fn set_password(&self, tenant_id: i64, username: &str, password_hash: &str) -> Result<bool, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        let user = tables.users.iter_mut().find(|stored| stored.tenant_id == tenant_id && stored.user.username == username);
+        Ok(user.map(|stored| stored.user.password_hash = password_hash.to_string()).is_some())
+    }
+
+    async fn promote(&self, tenant_id: i64, username: &str) -> Result<bool, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        let user = tables.users.iter_mut().find(|stored| stored.tenant_id == tenant_id && stored.user.username == username);
+        Ok(user.map(|stored| stored.is_approver = true).is_some())
+    }
+
+    async fn link_ldap(&self, tenant_id: i64, username: &str, dn: &str) -> Result<bool, sqlx::Error> {
+        let mut tables = self.0.lock().unwrap();
+        let user = tables.users.iter_mut().find(|stored| stored.tenant_id == tenant_id && stored.user.username == username);
+        Ok(user.map(|stored| stored.ldap_dn = Some(dn.to_string())).is_some())
+    }
+
+    async fn is_approver(&self, id: i64) -> Result<Option<bool>, sqlx::Error> {
+        let tables = self.0.lock().unwrap();
+        Ok(tables.users.iter().find(|stored| stored.user.id == id).map(|stored| stored.is_approver))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &str = r#"[{"key":"name","kind":"text","label":"Your name"}]"#;
+
+    async fn author(repository: &MemoryRepository, tenant_id: i64, username: &str) -> i64 {
+        repository.create_user(tenant_id, username, "hash", false).await.unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn forms_are_only_visible_to_their_author() {
+        let repository = MemoryRepository::default();
+        let author_id = author(&repository, DEFAULT_TENANT, "author").await;
+        let other_id = author(&repository, DEFAULT_TENANT, "other").await;
+        let form = repository.create_draft("Feedback", FIELDS, author_id).await.unwrap();
+
+        assert!(repository.owned_form(form.id, author_id).await.unwrap().is_some());
+        assert!(repository.owned_form(form.id, other_id).await.unwrap().is_none());
+        assert!(repository.forms_by_author(other_id).await.unwrap().is_empty());
+        assert!(!repository.delete_form(form.id, other_id).await.unwrap());
+        assert!(repository.delete_form(form.id, author_id).await.unwrap());
+    }
+
+    #[rocket::async_test]
+    async fn updates_awaiting_approval_can_unpublish_but_never_publish() {
+        let repository = MemoryRepository::default();
+        let author_id = author(&repository, DEFAULT_TENANT, "author").await;
+        let form = repository.create_draft("Feedback", FIELDS, author_id).await.unwrap();
+
+        repository.update_form(form.id, author_id, &WebForm { published: true, ..form.clone() }, true).await.unwrap();
+        assert!(!repository.owned_form(form.id, author_id).await.unwrap().unwrap().published);
+
+        assert!(repository.set_published(form.id, author_id, true).await.unwrap());
+        assert!(!repository.set_published(form.id, author_id, true).await.unwrap());
+        repository.update_form(form.id, author_id, &WebForm { published: false, ..form.clone() }, true).await.unwrap();
+        assert!(!repository.owned_form(form.id, author_id).await.unwrap().unwrap().published);
+    }
+
+    #[rocket::async_test]
+    async fn shared_forms_are_only_copied_within_the_tenant() {
+        let repository = MemoryRepository::default();
+        let author_id = author(&repository, DEFAULT_TENANT, "author").await;
+        let neighbour_id = author(&repository, DEFAULT_TENANT, "neighbour").await;
+        let outsider_id = author(&repository, DEFAULT_TENANT + 1, "outsider").await;
+        let form = repository.create_draft("Feedback", FIELDS, author_id).await.unwrap();
+
+        let copy_id = repository.copy_shared_form(form.id, neighbour_id).await.unwrap().unwrap();
+        assert_eq!(repository.owned_form(copy_id, neighbour_id).await.unwrap().unwrap().title, "Feedback");
+        assert_eq!(repository.copy_shared_form(form.id, outsider_id).await.unwrap(), None);
+    }
+
+    #[rocket::async_test]
+    async fn response_changes_stay_on_the_authors_form() {
+        let repository = MemoryRepository::default();
+        let author_id = author(&repository, DEFAULT_TENANT, "author").await;
+        let other_id = author(&repository, DEFAULT_TENANT, "other").await;
+        let form = repository.create_draft("Feedback", FIELDS, author_id).await.unwrap();
+        let response_id = repository.add_response(form.id, "{}", false);
+        let test_id = repository.add_response(form.id, "{}", true);
+
+        assert_eq!(repository.set_status(form.id, other_id, &[response_id], ResponseStatus::Resolved).await.unwrap(), 0);
+        assert_eq!(repository.delete_responses(form.id, other_id, &[response_id]).await.unwrap(), 0);
+        assert!(repository.owned_response(response_id, form.id, other_id).await.unwrap().is_none());
+
+        assert_eq!(repository.set_status(form.id, author_id, &[response_id], ResponseStatus::Resolved).await.unwrap(), 1);
+        let response = repository.owned_response(response_id, form.id, author_id).await.unwrap().unwrap();
+        assert_eq!(response.status, "resolved");
+
+        assert_eq!(repository.purge_test_responses(form.id, author_id).await.unwrap(), 1);
+        assert!(repository.owned_response(test_id, form.id, author_id).await.unwrap().is_none());
+        assert!(repository.owned_response(response_id, form.id, author_id).await.unwrap().is_some());
+    }
+
+    #[rocket::async_test]
+    async fn usernames_are_scoped_to_the_tenant() {
+        let repository = MemoryRepository::default();
+        author(&repository, DEFAULT_TENANT, "author").await;
+
+        assert!(repository.create_user(DEFAULT_TENANT, "author", "hash", false).await.is_err());
+        let other_id = author(&repository, DEFAULT_TENANT + 1, "author").await;
+        assert_eq!(repository.user_by_username(DEFAULT_TENANT + 1, "author").await.unwrap().unwrap().id, other_id);
+        assert!(repository.promote(DEFAULT_TENANT + 1, "author").await.unwrap());
+        assert_eq!(repository.is_approver(other_id).await.unwrap(), Some(true));
+    }
+}
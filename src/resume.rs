@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use crate::{api, crypto};
+
+/// The field a submission carries its resume token in, so the saved
+/// progress is dropped once the response is in.
+pub const RESUME_TOKEN_FIELD: &str = "_resume_token";
+
+/// 100 bits, written in groups of five.
+const TOKEN_LENGTH: usize = 20;
+
+/// The `[resume]` configuration table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ResumeConfig {
+    /// Saved progress is kept this long after it was last saved.
+    pub ttl_days: i64,
+}
+
+impl Default for ResumeConfig {
+    fn default() -> Self {
+        ResumeConfig { ttl_days: 7 }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SavedProgress {
+    pub token: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Progress {
+    pub answers: HashMap<String, String>,
+    pub page: i64,
+}
+
+/// The answers are encrypted under the token, which the server keeps only
+/// a hash of, so saved progress cannot be read without it.
+fn answers_key(token: &str) -> Vec<u8> {
+    Sha256::digest(format!("resume:{}", token).as_bytes()).to_vec()
+}
+
+/// Saves the answers so far. An unknown or expired `token` gets a new one,
+/// as does a first save; saving again pushes the expiry back.
+pub async fn save(
+    db: &SqlitePool,
+    config: &ResumeConfig,
+    form_id: i64,
+    token: Option<&str>,
+    answers: &HashMap<String, String>,
+    page: i64
+) -> Result<SavedProgress, sqlx::Error> {
+    let ttl = format!("+{} days", config.ttl_days);
+    let answers = serde_json::to_string(answers).unwrap_or_default();
+
+    if let Some(token) = token {
+        let normalized = crypto::normalize_code(token);
+        let sealed = crypto::encrypt_with(&answers_key(&normalized), &answers).map_err(sqlx::Error::Protocol)?;
+        let token_hash = api::hash_token(&normalized);
+        let expires_at = sqlx::query_scalar!(
+            "UPDATE partial_responses SET answers = ?, page = ?, updated_at = CURRENT_TIMESTAMP, expires_at = datetime('now', ?)
+             WHERE token_hash = ? AND form_id = ? AND expires_at > CURRENT_TIMESTAMP
+             RETURNING expires_at",
+            sealed,
+            page,
+            ttl,
+            token_hash,
+            form_id
+        )
+        .fetch_optional(db)
+        .await?;
+        if let Some(expires_at) = expires_at {
+            return Ok(SavedProgress { token: token.to_string(), expires_at });
+        }
+    }
+
+    let token = crypto::readable_code(TOKEN_LENGTH);
+    let normalized = crypto::normalize_code(&token);
+    let sealed = crypto::encrypt_with(&answers_key(&normalized), &answers).map_err(sqlx::Error::Protocol)?;
+    let token_hash = api::hash_token(&normalized);
+    let expires_at = sqlx::query_scalar!(
+        "INSERT INTO partial_responses (form_id, token_hash, answers, page, expires_at) VALUES (?, ?, ?, ?, datetime('now', ?))
+         RETURNING expires_at",
+        form_id,
+        token_hash,
+        sealed,
+        page,
+        ttl
+    )
+    .fetch_one(db)
+    .await?;
+
+    Ok(SavedProgress { token, expires_at })
+}
+
+/// The progress saved under `token`, unless it expired.
+pub async fn restore(db: &SqlitePool, form_id: i64, token: &str) -> Result<Option<Progress>, sqlx::Error> {
+    let normalized = crypto::normalize_code(token);
+    let token_hash = api::hash_token(&normalized);
+    let saved = sqlx::query!(
+        "SELECT answers, page FROM partial_responses WHERE token_hash = ? AND form_id = ? AND expires_at > CURRENT_TIMESTAMP",
+        token_hash,
+        form_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    let Some(saved) = saved else {
+        return Ok(None);
+    };
+    let answers = match crypto::decrypt_with(&answers_key(&normalized), &saved.answers) {
+        Ok(answers) => serde_json::from_str(&answers).unwrap_or_default(),
+        Err(e) => {
+            warn!("Failed to decrypt saved progress on form {}: {}", form_id, e);
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(Progress { answers, page: saved.page }))
+}
+
+pub async fn discard(db: &SqlitePool, form_id: i64, token: &str) -> Result<(), sqlx::Error> {
+    let token_hash = api::hash_token(&crypto::normalize_code(token));
+    sqlx::query!("DELETE FROM partial_responses WHERE token_hash = ? AND form_id = ?", token_hash, form_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
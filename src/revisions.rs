@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::{crypto, slots};
+use crate::schema::{self, FieldDef};
+
+/// An edit that would leave a field's existing answers without a field to
+/// belong to, or under a field of another type.
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub key: String,
+    pub label: String,
+    pub kind: String,
+    /// `None` when the field was removed.
+    pub new_kind: Option<String>,
+    pub answers: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Revision {
+    pub id: i64,
+    pub edited_by: Option<String>,
+    pub fields: Vec<FieldDef>,
+    pub mapping: Option<HashMap<String, String>>,
+    pub created_at: String,
+}
+
+/// The version of the form's fields a field first appeared in and has been
+/// in ever since. Version 1 is the oldest schema on record.
+#[derive(Debug, Clone, Serialize)]
+pub struct Introduction {
+    pub version: usize,
+    pub at: String,
+}
+
+/// The answered fields that replacing `current` with `updated` removes or
+/// changes the type of. Fields nobody has answered can change freely.
+pub async fn changes(db: &SqlitePool, form_id: i64, current: &str, updated: &str) -> Result<Vec<FieldChange>, sqlx::Error> {
+    let updated = schema::parse(updated);
+    let changed: Vec<(FieldDef, Option<String>)> = schema::parse(current)
+        .into_iter()
+        .filter_map(|field| match updated.iter().find(|new| new.key == field.key) {
+            None => Some((field, None)),
+            Some(new) if new.kind != field.kind => Some((field, Some(new.kind.name().to_string()))),
+            Some(_) => None,
+        })
+        .collect();
+    if changed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let answered: HashMap<String, i64> = sqlx::query!(
+        r#"SELECT a.key AS "key!: String", COUNT(*) AS "answers!: i64" FROM responses r, json_each(r.answers) a
+         WHERE r.form_id = ? GROUP BY a.key"#,
+        form_id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|row| (row.key, row.answers))
+    .collect();
+
+    Ok(changed.into_iter()
+        .filter_map(|(field, new_kind)| {
+            let answers = *answered.get(&field.key)?;
+            Some(FieldChange {
+                label: if field.label.is_empty() { field.key.clone() } else { field.label.clone() },
+                kind: field.kind.name().to_string(),
+                key: field.key,
+                new_kind,
+                answers,
+            })
+        })
+        .collect())
+}
+
+async fn record(
+    conn: &mut SqliteConnection,
+    form_id: i64,
+    edited_by: i64,
+    fields: &str,
+    mapping: Option<&HashMap<String, String>>
+) -> Result<i64, sqlx::Error> {
+    let mapping = mapping.and_then(|mapping| serde_json::to_string(mapping).ok());
+    sqlx::query_scalar!(
+        "INSERT INTO form_revisions (form_id, edited_by, fields, mapping) VALUES (?, ?, ?, ?) RETURNING id",
+        form_id,
+        edited_by,
+        fields,
+        mapping
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Keeps `previous`, the fields as they were before an edit that lost no
+/// answers, in the form's history.
+pub async fn record_edit(db: &SqlitePool, form_id: i64, edited_by: i64, previous: &str) -> Result<(), sqlx::Error> {
+    let mut conn = db.acquire().await?;
+    record(&mut *conn, form_id, edited_by, previous, None).await?;
+
+    Ok(())
+}
+
+/// Moves each changed field's answers to the field `mapping` names for it,
+/// ahead of the form's fields being replaced with `updated`. Moved answers
+/// are decrypted and encrypted again as their new field asks. Answers mapped
+/// to nothing, or to a field the response already answered, are archived
+/// against the revision rather than dropped, and either way any slot they
+/// booked is freed. The mapping is kept with the revision. The caller saves
+/// `updated` in the same transaction, so the whole edit lands or none of it
+/// does.
+pub async fn migrate(
+    conn: &mut SqliteConnection,
+    form_id: i64,
+    edited_by: i64,
+    previous: &str,
+    updated: &str,
+    changes: &[FieldChange],
+    mapping: &HashMap<String, String>
+) -> Result<(), sqlx::Error> {
+    let targets = schema::parse(updated);
+    let mapping: HashMap<String, String> = changes.iter()
+        .map(|change| {
+            let target = mapping.get(&change.key)
+                .filter(|target| targets.iter().any(|field| &field.key == *target))
+                .cloned()
+                .unwrap_or_default();
+            (change.key.clone(), target)
+        })
+        .collect();
+
+    let revision_id = record(&mut *conn, form_id, edited_by, previous, Some(&mapping)).await?;
+
+    let responses = sqlx::query!("SELECT id, answers FROM responses WHERE form_id = ?", form_id)
+        .fetch_all(&mut *conn)
+        .await?;
+    for response in responses {
+        let mut answers: HashMap<String, String> = serde_json::from_str(&response.answers).unwrap_or_default();
+        let mut moved = false;
+        for (key, target) in &mapping {
+            if key == target {
+                continue;
+            }
+            let Some(value) = answers.remove(key) else {
+                continue;
+            };
+            moved = true;
+
+            slots::release_answer(&mut *conn, response.id, form_id, key).await?;
+            let plain = crypto::decrypt(&value).ok();
+            if !target.is_empty() && !answers.contains_key(target) {
+                // An answer that can't be read back can't be encrypted for
+                // its new field either, so it is archived as it was.
+                let reencrypted = plain.and_then(|plain| {
+                    crypto::encrypt_answers(&targets, &HashMap::from([(target.clone(), plain)])).ok()?.remove(target)
+                });
+                if let Some(reencrypted) = reencrypted {
+                    answers.insert(target.clone(), reencrypted);
+                    continue;
+                }
+            }
+            sqlx::query!(
+                "INSERT INTO archived_answers (response_id, revision_id, field_key, value) VALUES (?, ?, ?, ?)",
+                response.id,
+                revision_id,
+                key,
+                value
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        if moved {
+            let answers = serde_json::to_string(&answers).unwrap_or_default();
+            sqlx::query!("UPDATE responses SET answers = ? WHERE id = ?", answers, response.id)
+                .execute(&mut *conn)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The form's earlier versions, newest first.
+pub async fn history(db: &SqlitePool, form_id: i64) -> Result<Vec<Revision>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT r.id, u.username AS "edited_by?", r.fields, r.mapping, r.created_at
+         FROM form_revisions r LEFT JOIN users u ON u.id = r.edited_by
+         WHERE r.form_id = ? ORDER BY r.id DESC"#,
+        form_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter()
+        .map(|row| Revision {
+            id: row.id,
+            edited_by: row.edited_by,
+            fields: schema::parse(&row.fields),
+            mapping: row.mapping.as_deref().and_then(|mapping| serde_json::from_str(mapping).ok()),
+            created_at: row.created_at,
+        })
+        .collect())
+}
+
+/// When each of `current`'s fields was introduced, given the form's
+/// `history` newest first. A field that was removed and added back counts
+/// from when it came back. Forms edited before revisions were recorded
+/// start from the oldest schema there is, dated when the form was created.
+pub fn introductions(history: &[Revision], current: &[FieldDef], created_at: &str) -> HashMap<String, Introduction> {
+    let oldest_first: Vec<&Revision> = history.iter().rev().collect();
+    let versions: Vec<&[FieldDef]> = oldest_first.iter().map(|revision| revision.fields.as_slice()).chain([current]).collect();
+    let introduced_at = |version: usize| match version {
+        1 => created_at.to_string(),
+        version => oldest_first[version - 2].created_at.clone(),
+    };
+
+    current.iter()
+        .map(|field| {
+            let present = versions.iter()
+                .rev()
+                .take_while(|fields| fields.iter().any(|other| other.key == field.key))
+                .count();
+            let version = versions.len() - present + 1;
+            (field.key.clone(), Introduction { version, at: introduced_at(version) })
+        })
+        .collect()
+}
+
+/// The answers a response lost to schema changes, decrypted like its
+/// current ones.
+pub async fn archived_answers(db: &SqlitePool, response_id: i64) -> Result<HashMap<String, String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT field_key, value FROM archived_answers WHERE response_id = ? ORDER BY id",
+        response_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let archived: HashMap<String, String> = rows.into_iter().map(|row| (row.field_key, row.value)).collect();
+    Ok(crypto::decrypt_answers(&serde_json::to_string(&archived).unwrap_or_default()))
+}
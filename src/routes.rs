@@ -0,0 +1,11 @@
+pub mod account;
+pub mod analytics;
+pub mod auth;
+pub mod forms;
+pub mod paper;
+pub mod payments;
+pub mod public;
+pub mod receipts;
+pub mod responses;
+pub mod settings;
+pub mod slots;
@@ -0,0 +1,234 @@
+use bcrypt::verify;
+use chrono_tz::Tz;
+use rocket::form::Form;
+use rocket::fs::NamedFile;
+use rocket::http::{Cookie, CookieJar, Status, private::PrivateCookies};
+use rocket::response::Redirect;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use sqlx::SqlitePool;
+
+use crate::{AppConfig, account, quota};
+use crate::cache::FormCache;
+use crate::events::{DomainEvent, EventBus};
+use crate::guards::{Approver, AuthenticatedUser, NotImpersonating, OptionalUser, SessionStore};
+use crate::localtime::Locale;
+use crate::models::{AccountDeletion, ProfileUpdate, UserAccount};
+use crate::storage::{self, Storage, UploadError};
+use crate::tenant::Tenant;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        account_page, update_profile, avatar, delete_account, admin_users, deactivate_user, reactivate_user
+    ]
+}
+
+#[get("/account?<wrong_password>&<upload_error>")]
+pub async fn account_page(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    wrong_password: bool,
+    upload_error: Option<&str>
+) -> Result<Template, Status> {
+    let username = sqlx::query_scalar!("SELECT username FROM users WHERE id = ?", user.0)
+        .fetch_one(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let profile = account::profile(db, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("account", context! {
+        user_id: user.0,
+        username: username,
+        profile: profile,
+        locales: Locale::ALL.map(Locale::code),
+        wrong_password: wrong_password,
+        upload_error: upload_error
+    }))
+}
+
+fn blank_to_none(value: &str) -> Option<&str> {
+    Some(value.trim()).filter(|value| !value.is_empty())
+}
+
+#[post("/account/profile", data = "<update>")]
+pub async fn update_profile(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    storage: &State<Storage>,
+    user: AuthenticatedUser,
+    mut update: Form<ProfileUpdate<'_>>
+) -> Result<Redirect, Status> {
+    let update = &mut *update;
+    let current = account::profile(db, user.0).await.map_err(|_| Status::InternalServerError)?;
+    // Both are picked from lists, so anything else is a broken client.
+    let timezone = blank_to_none(&update.timezone);
+    let locale = blank_to_none(&update.locale);
+    if timezone.is_some_and(|timezone| timezone.parse::<Tz>().is_err()) || locale.is_some_and(|locale| Locale::parse(locale).is_none()) {
+        return Err(Status::UnprocessableEntity);
+    }
+    let refused = |reason: &str| Redirect::to(uri!(account_page(wrong_password = false, upload_error = Some(reason))));
+
+    let uploaded = match update.avatar.as_mut().filter(|file| file.len() > 0) {
+        Some(file) if !quota::can_store_upload(db, &config.plans, user.0, file.len(), current.avatar.as_deref()).await? => {
+            return Ok(refused("over_quota"));
+        }
+        Some(file) => match storage.save_image(file).await {
+            Ok(key) => {
+                storage::record(db, storage, user.0, &key, file.len()).await.map_err(|_| Status::InternalServerError)?;
+                Some(key)
+            }
+            Err(UploadError::TooLarge) => return Ok(refused("too_large")),
+            Err(UploadError::UnsupportedType) => return Ok(refused("unsupported_type")),
+            Err(UploadError::Io(e)) => {
+                error!("Failed to store an avatar for user {}: {}", user.0, e);
+                return Err(Status::InternalServerError);
+            }
+        },
+        None => None,
+    };
+    let avatar = match (&uploaded, update.remove_avatar) {
+        (Some(key), _) => Some(key.clone()),
+        (None, true) => None,
+        (None, false) => current.avatar.clone(),
+    };
+
+    let display_name = blank_to_none(&update.display_name);
+    let bio = blank_to_none(&update.bio);
+    sqlx::query!(
+        "INSERT INTO user_profiles (user_id, display_name, avatar, bio, timezone, locale, show_on_forms) VALUES (?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT (user_id) DO UPDATE SET display_name = excluded.display_name, avatar = excluded.avatar, bio = excluded.bio,
+         timezone = excluded.timezone, locale = excluded.locale, show_on_forms = excluded.show_on_forms, updated_at = CURRENT_TIMESTAMP",
+        user.0,
+        display_name,
+        avatar,
+        bio,
+        timezone,
+        locale,
+        update.show_on_forms
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    if let Some(old) = current.avatar.filter(|old| avatar.as_ref() != Some(old)) {
+        if let Err(e) = storage::discard(db, storage, &old).await {
+            warn!("Failed to remove the old avatar {}: {}", old, e);
+        }
+    }
+
+    Ok(Redirect::to(uri!(account_page(wrong_password = false, upload_error = None::<&str>))))
+}
+
+/// Served to anyone only when the user shows their profile on their forms.
+#[get("/users/<id>/avatar")]
+pub async fn avatar(
+    db: &State<SqlitePool>,
+    storage: &State<Storage>,
+    user: OptionalUser,
+    id: i64
+) -> Result<NamedFile, Status> {
+    let profile = account::profile(db, id).await.map_err(|_| Status::InternalServerError)?;
+    let own = user.0.is_some_and(|user| user.0 == id);
+    if !own && !profile.show_on_forms {
+        return Err(Status::NotFound);
+    }
+    let key = profile.avatar.ok_or(Status::NotFound)?;
+
+    storage.get(&key).await.ok_or(Status::NotFound)
+}
+
+/// Deactivates the account at once; its data goes after the grace period.
+/// Not available while impersonating.
+#[post("/account/delete", data = "<confirmation>")]
+pub async fn delete_account(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    bus: &State<EventBus>,
+    storage: &State<Storage>,
+    sessions: &State<SessionStore>,
+    cookies: &CookieJar<'_>,
+    user: AuthenticatedUser,
+    _own: NotImpersonating,
+    confirmation: Form<AccountDeletion>
+) -> Result<Redirect, Status> {
+    let password_hash = sqlx::query_scalar!("SELECT password_hash FROM users WHERE id = ?", user.0)
+        .fetch_one(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    if !verify(&confirmation.password, &password_hash).unwrap_or(false) {
+        return Ok(Redirect::to(uri!(account_page(wrong_password = true, upload_error = None::<&str>))));
+    }
+
+    let deletion = account::schedule_deletion(db, user.0).await.map_err(|_| Status::InternalServerError)?;
+    for form_id in deletion.unpublished {
+        cache.invalidate(form_id);
+        bus.publish(DomainEvent::FormUnpublished { form_id });
+    }
+    if let Some(avatar) = deletion.avatar {
+        if let Err(e) = storage::discard(db, storage, &avatar).await {
+            warn!("Failed to remove the avatar {}: {}", avatar, e);
+        }
+    }
+
+    sessions.end_user(user.0);
+    cookies.remove_private(Cookie::named("session_id"));
+    Ok(Redirect::to(uri!(super::auth::login_page)))
+}
+
+#[get("/admin/users")]
+pub async fn admin_users(db: &State<SqlitePool>, tenant: Tenant, _approver: Approver) -> Result<Template, Status> {
+    let users = sqlx::query_as!(UserAccount,
+        "SELECT id, username, email, is_approver, active, deleted_at FROM users WHERE tenant_id = ? ORDER BY username",
+        tenant.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("admin_users", context! { users: users }))
+}
+
+/// Signs the user out everywhere. Approvers cannot deactivate themselves.
+#[post("/admin/users/<id>/deactivate")]
+pub async fn deactivate_user(
+    db: &State<SqlitePool>,
+    sessions: &State<SessionStore>,
+    tenant: Tenant,
+    approver: Approver,
+    id: i64
+) -> Result<Redirect, Status> {
+    if id == approver.0 {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    let updated = sqlx::query!("UPDATE users SET active = false WHERE id = ? AND tenant_id = ?", id, tenant.id)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .rows_affected();
+    if updated == 0 {
+        return Err(Status::NotFound);
+    }
+
+    sessions.end_user(id);
+    Ok(Redirect::to(uri!(admin_users)))
+}
+
+/// Accounts their owners deleted stay deleted.
+#[post("/admin/users/<id>/reactivate")]
+pub async fn reactivate_user(db: &State<SqlitePool>, tenant: Tenant, _approver: Approver, id: i64) -> Result<Redirect, Status> {
+    let updated = sqlx::query!(
+        "UPDATE users SET active = true WHERE id = ? AND tenant_id = ? AND deleted_at IS NULL",
+        id,
+        tenant.id
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .rows_affected();
+    if updated == 0 {
+        return Err(Status::NotFound);
+    }
+
+    Ok(Redirect::to(uri!(admin_users)))
+}
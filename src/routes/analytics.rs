@@ -0,0 +1,163 @@
+use rocket::http::Status;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use sqlx::SqlitePool;
+
+use crate::{analytics, charts, export, rsvp, schema};
+use crate::charts::{Chart, ChartFormat};
+use crate::db::ReadPool;
+use crate::guards::AuthenticatedUser;
+use crate::models::{AnalyticsChart, EntryFilter, WebForm};
+use crate::schema::{FieldDef, FieldKind};
+
+const CHART_DAYS: i64 = 30;
+
+pub fn routes() -> Vec<Route> {
+    routes![form_analytics, export_cross_tab, analytics_chart]
+}
+
+async fn owned_form(db: &SqlitePool, id: i64, author_id: i64) -> Result<Option<WebForm>, Status> {
+    sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE id = ? AND author_id = ?", id, author_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| Status::InternalServerError)
+}
+
+fn tabulable_field<'a>(fields: &'a [FieldDef], key: &str) -> Option<&'a FieldDef> {
+    fields.iter().find(|field| field.key == key && analytics::tabulable(field))
+}
+
+/// `entries` narrows the response counts to those submitted online or those
+/// keyed in by hand.
+#[get("/form/<id>/analytics?<row>&<column>&<entries>")]
+pub async fn form_analytics(
+    db: &State<SqlitePool>,
+    reads: &State<ReadPool>,
+    user: AuthenticatedUser,
+    id: i64,
+    row: Option<&str>,
+    column: Option<&str>,
+    entries: Option<EntryFilter>
+) -> Result<Template, Status> {
+    let Some(form) = owned_form(db, id, user.0).await? else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let entries = entries.unwrap_or_default();
+    let fields = schema::parse(&form.fields);
+    let selected = row.and_then(|row| tabulable_field(&fields, row)).zip(column.and_then(|column| tabulable_field(&fields, column)));
+    let cross_tab = match selected {
+        Some((row, column)) => Some(analytics::cross_tab(&reads.0, form.id, row, column, entries).await.map_err(|_| Status::InternalServerError)?),
+        None => None,
+    };
+    let choice_fields: Vec<&FieldDef> = fields.iter().filter(|field| analytics::tabulable(field)).collect();
+    let funnel = analytics::funnel(&reads.0, form.id).await.map_err(|_| Status::InternalServerError)?;
+    let conversion = analytics::conversions(&reads.0, user.0, Some(form.id))
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .pop();
+    let sources = analytics::sources(&reads.0, form.id, entries).await.map_err(|_| Status::InternalServerError)?;
+    let rsvp = rsvp::event(&reads.0, form.id).await.map_err(|_| Status::InternalServerError)?;
+    let headcount = match rsvp.as_ref().and_then(|rsvp| rsvp.choice_field.as_deref()).and_then(|key| tabulable_field(&fields, key)) {
+        Some(field) => Some(analytics::headcount(&reads.0, form.id, field).await.map_err(|_| Status::InternalServerError)?),
+        None => None,
+    };
+    let payments = if fields.iter().any(|field| field.kind == FieldKind::Payment) {
+        Some(analytics::payment_totals(&reads.0, form.id).await.map_err(|_| Status::InternalServerError)?)
+    } else {
+        None
+    };
+
+    Ok(Template::render("form_analytics", context! {
+        form: form,
+        choice_fields: choice_fields,
+        row: row,
+        column: column,
+        entries: entries,
+        cross_tab: cross_tab,
+        funnel: funnel,
+        conversion: conversion,
+        sources: sources,
+        rsvp: rsvp,
+        headcount: headcount,
+        payments: payments
+    }))
+}
+
+#[get("/form/<id>/analytics/crosstab.csv?<row>&<column>&<entries>")]
+pub async fn export_cross_tab(
+    db: &State<SqlitePool>,
+    reads: &State<ReadPool>,
+    user: AuthenticatedUser,
+    id: i64,
+    row: &str,
+    column: &str,
+    entries: Option<EntryFilter>
+) -> Result<export::Csv, Status> {
+    let form = owned_form(db, id, user.0).await?.ok_or(Status::NotFound)?;
+    let fields = schema::parse(&form.fields);
+    let row_field = tabulable_field(&fields, row).ok_or(Status::NotFound)?;
+    let column_field = tabulable_field(&fields, column).ok_or(Status::NotFound)?;
+
+    let table = analytics::cross_tab(&reads.0, form.id, row_field, column_field, entries.unwrap_or_default())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(export::Csv {
+        filename: format!("form-{}-crosstab.csv", form.id),
+        body: export::cross_tab_csv(&table),
+    })
+}
+
+/// The analytics page's aggregate charts as downloadable images, SVG unless
+/// `format=png` is asked for.
+#[get("/form/<id>/analytics/chart?<kind>&<format>&<entries>")]
+pub async fn analytics_chart(
+    db: &State<SqlitePool>,
+    reads: &State<ReadPool>,
+    user: AuthenticatedUser,
+    id: i64,
+    kind: AnalyticsChart,
+    format: Option<ChartFormat>,
+    entries: Option<EntryFilter>
+) -> Result<Chart, Status> {
+    let form = owned_form(db, id, user.0).await?.ok_or(Status::NotFound)?;
+    let format = format.unwrap_or(ChartFormat::Svg);
+    let entries = entries.unwrap_or_default();
+
+    let chart = match kind {
+        AnalyticsChart::Submissions => {
+            let days = analytics::daily_submissions(&reads.0, user.0, Some(form.id), CHART_DAYS, entries)
+                .await
+                .map_err(|_| Status::InternalServerError)?;
+            let (labels, values): (Vec<String>, Vec<i64>) = days.into_iter().unzip();
+            charts::line_chart(format, "Submissions per day", &labels, &values)
+        }
+        AnalyticsChart::Funnel => {
+            let funnel = analytics::funnel(&reads.0, form.id).await.map_err(|_| Status::InternalServerError)?;
+            let labels: Vec<String> = funnel.iter().map(|step| format!("Page {}", step.page)).collect();
+            let values: Vec<i64> = funnel.iter().map(|step| step.reached).collect();
+            charts::bar_chart(format, "Visitors reaching each page", &labels, &values)
+        }
+        AnalyticsChart::Sources => {
+            let sources = analytics::sources(&reads.0, form.id, entries).await.map_err(|_| Status::InternalServerError)?;
+            let mut labels: Vec<String> = Vec::new();
+            let mut values: Vec<i64> = Vec::new();
+            for source in sources {
+                match labels.iter().position(|label| *label == source.source) {
+                    Some(i) => values[i] += source.submissions,
+                    None => {
+                        labels.push(source.source);
+                        values.push(source.submissions);
+                    }
+                }
+            }
+            charts::bar_chart(format, "Submissions by source", &labels, &values)
+        }
+    };
+
+    chart.map_err(|e| {
+        error!("Failed to render the {:?} chart for form {}: {}", kind, form.id, e);
+        Status::InternalServerError
+    })
+}
@@ -0,0 +1,281 @@
+use bcrypt::{hash, verify, DEFAULT_COST};
+use rocket::form::Form;
+use rocket::http::{Cookie, CookieJar, Status, private::PrivateCookies};
+use rocket::response::Redirect;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::{AppConfig, api, auth, legal};
+use crate::events::{DomainEvent, EventBus};
+use crate::guards::{NotImpersonating, Session, SessionStore, SignedInUser};
+use crate::models::{LegalAcceptance, Registration, User};
+use crate::repository::UserRepository;
+use crate::tenant::Tenant;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        login_page, login, oidc_login, oidc_callback, logout, register_page, register, legal_page, accept_legal
+    ]
+}
+
+#[get("/login")]
+pub fn login_page(config: &State<AppConfig>, tenant: Tenant) -> Template {
+    Template::render("login", context! { sso: config.oidc.enabled(), sso_only: sso_only(config), tenant: tenant })
+}
+
+fn sso_only(config: &AppConfig) -> bool {
+    config.oidc.enabled() && config.oidc.sso_only
+}
+
+fn start_session(session_store: &SessionStore, cookies: &CookieJar<'_>, tenant: &Tenant, user_id: i64) {
+    let session_id = Uuid::new_v4().to_string();
+    session_store.0.write().unwrap().insert(session_id.clone(), Session::new(user_id, tenant.id));
+    cookies.add_private(Cookie::new("session_id", session_id));
+}
+
+#[post("/login", data = "<login_form>")]
+pub async fn login(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    session_store: &State<SessionStore>,
+    cookies: &CookieJar<'_>,
+    tenant: Tenant,
+    login_form: Form<User>
+) -> Result<Redirect, Status> {
+    if sso_only(config) {
+        return Ok(Redirect::to(uri!(login_page)));
+    }
+
+    if config.ldap.enabled() {
+        match auth::ldap::authenticate(&config.ldap, &login_form.username, &login_form.password_hash).await {
+            Ok(Some(ldap_user)) => {
+                let user_id = ldap_user_id(db, &config.ldap, &tenant, &login_form.username, &ldap_user).await?;
+                start_session(session_store, cookies, &tenant, user_id);
+                return Ok(Redirect::to(uri!(super::forms::index(_))));
+            }
+            Ok(None) => {}
+            Err(e) => error!("LDAP authentication failed: {}", e),
+        }
+    }
+
+    let user = db.user_by_username(tenant.id, &login_form.username)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    if let Some(user) = user {
+        if verify(&login_form.password_hash, &user.password_hash).map_err(|_| Status::InternalServerError)? {
+            start_session(session_store, cookies, &tenant, user.id);
+            return Ok(Redirect::to(uri!(super::forms::index(_))));
+        }
+    }
+
+    Ok(Redirect::to(uri!(login_page)))
+}
+
+/// The account linked to the directory entry, created on its first login.
+/// A local account that already has the username is never taken over. With
+/// an admin_group configured, the directory decides who is an approver.
+async fn ldap_user_id(
+    db: &SqlitePool,
+    config: &auth::ldap::LdapConfig,
+    tenant: &Tenant,
+    username: &str,
+    ldap_user: &auth::ldap::LdapUser
+) -> Result<i64, Status> {
+    let user = sqlx::query!("SELECT id, active FROM users WHERE ldap_dn = ? AND tenant_id = ?", ldap_user.dn, tenant.id)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    match user {
+        Some(user) if !user.active => Err(Status::Forbidden),
+        Some(user) => {
+            if config.admin_group.is_some() {
+                sqlx::query!("UPDATE users SET is_approver = ? WHERE id = ?", ldap_user.is_admin, user.id)
+                    .execute(db)
+                    .await
+                    .map_err(|_| Status::InternalServerError)?;
+            }
+            Ok(user.id)
+        }
+        None => {
+            let password_hash = hash(Uuid::new_v4().to_string(), DEFAULT_COST).map_err(|_| Status::InternalServerError)?;
+            sqlx::query_scalar!(
+                "INSERT INTO users (username, password_hash, is_approver, ldap_dn, tenant_id) VALUES (?, ?, ?, ?, ?) RETURNING id",
+                username,
+                password_hash,
+                ldap_user.is_admin,
+                ldap_user.dn,
+                tenant.id
+            )
+            .fetch_one(db)
+            .await
+            .map_err(|e| match e.as_database_error() {
+                Some(e) if e.is_unique_violation() => Status::Conflict,
+                _ => Status::InternalServerError,
+            })
+        }
+    }
+}
+
+#[get("/auth/oidc/login")]
+pub async fn oidc_login(
+    config: &State<AppConfig>,
+    client: &State<reqwest::Client>,
+    cookies: &CookieJar<'_>
+) -> Result<Redirect, Status> {
+    if !config.oidc.enabled() {
+        return Err(Status::NotFound);
+    }
+
+    let (url, flow) = auth::oidc::authorization_url(&config.oidc, client).await.map_err(|e| {
+        error!("Failed to start OIDC login: {}", e);
+        Status::BadGateway
+    })?;
+    let flow = serde_json::to_string(&flow).map_err(|_| Status::InternalServerError)?;
+    cookies.add_private(Cookie::new("oidc_flow", flow));
+
+    Ok(Redirect::to(url))
+}
+
+#[get("/auth/oidc/callback?<code>&<state>")]
+pub async fn oidc_callback(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    client: &State<reqwest::Client>,
+    bus: &State<EventBus>,
+    session_store: &State<SessionStore>,
+    cookies: &CookieJar<'_>,
+    tenant: Tenant,
+    code: &str,
+    state: &str
+) -> Result<Redirect, Status> {
+    let flow: auth::oidc::Flow = cookies.get_private("oidc_flow")
+        .and_then(|cookie| serde_json::from_str(cookie.value()).ok())
+        .ok_or(Status::BadRequest)?;
+    cookies.remove_private(Cookie::named("oidc_flow"));
+
+    if flow.state != state {
+        return Err(Status::BadRequest);
+    }
+
+    let identity = auth::oidc::complete(&config.oidc, client, &flow, code).await.map_err(|e| {
+        warn!("OIDC login failed: {}", e);
+        Status::Unauthorized
+    })?;
+
+    let user = sqlx::query!(
+        "SELECT id, active FROM users WHERE oidc_subject = ? AND tenant_id = ?",
+        identity.subject,
+        tenant.id
+    )
+        .fetch_optional(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let user_id = match user {
+        Some(user) if !user.active => return Err(Status::Forbidden),
+        Some(user) => user.id,
+        None => {
+            let password_hash = hash(Uuid::new_v4().to_string(), DEFAULT_COST).map_err(|_| Status::InternalServerError)?;
+            let taken = sqlx::query_scalar!("SELECT id FROM users WHERE username = ? AND tenant_id = ?", identity.username, tenant.id)
+                .fetch_optional(db.inner())
+                .await
+                .map_err(|_| Status::InternalServerError)?
+                .is_some();
+            let username = if taken {
+                format!("{}-{}", identity.username, &api::hash_token(&identity.subject)[..8])
+            } else {
+                identity.username
+            };
+
+            let user_id = sqlx::query_scalar!(
+                "INSERT INTO users (username, password_hash, oidc_subject, tenant_id) VALUES (?, ?, ?, ?) RETURNING id",
+                username,
+                password_hash,
+                identity.subject,
+                tenant.id
+            )
+            .fetch_one(db.inner())
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+            bus.publish(DomainEvent::UserRegistered { user_id, via: "oidc" });
+            user_id
+        }
+    };
+
+    start_session(session_store, cookies, &tenant, user_id);
+    Ok(Redirect::to(uri!(super::forms::index(_))))
+}
+
+#[post("/logout")]
+pub fn logout(session_store: &State<SessionStore>, cookies: &CookieJar<'_>) -> Redirect {
+    if let Some(session_id) = cookies.get_private("session_id") {
+        session_store.0.write().unwrap().remove(session_id.value());
+    }
+    cookies.remove_private(Cookie::named("session_id"));
+    Redirect::to(uri!(super::forms::index(_)))
+}
+
+#[get("/register")]
+pub fn register_page(config: &State<AppConfig>) -> Template {
+    Template::render("register", context! { legal_documents: config.legal.documents() })
+}
+
+#[post("/register", data = "<register_form>")]
+pub async fn register(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    bus: &State<EventBus>,
+    tenant: Tenant,
+    register_form: Form<Registration>
+) -> Result<Redirect, Status> {
+    if sso_only(config) {
+        return Err(Status::Forbidden);
+    }
+    if !register_form.accept_legal && !config.legal.documents().is_empty() {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    let password_hash = hash(&register_form.password_hash, DEFAULT_COST).map_err(|_| Status::InternalServerError)?;
+    let user_id = match db.create_user(tenant.id, &register_form.username, &password_hash, false).await {
+        Ok(user_id) => user_id,
+        Err(e) if e.as_database_error().is_some_and(|e| e.is_unique_violation()) => return Err(Status::Conflict),
+        Err(_) => return Err(Status::InternalServerError),
+    };
+    legal::accept(db, &config.legal, user_id).await.map_err(|_| Status::InternalServerError)?;
+    bus.publish(DomainEvent::UserRegistered { user_id, via: "password" });
+
+    Ok(Redirect::to(uri!(login_page)))
+}
+
+/// Where signed-in users land when a legal document they have not accepted
+/// stands between them and the page they asked for.
+#[catch(428)]
+pub fn legal_required() -> Redirect {
+    Redirect::to(uri!(legal_page))
+}
+
+#[get("/legal")]
+pub async fn legal_page(db: &State<SqlitePool>, config: &State<AppConfig>, user: SignedInUser) -> Result<Template, Status> {
+    let outstanding = legal::outstanding(db, &config.legal, user.0).await.map_err(|_| Status::InternalServerError)?;
+    Ok(Template::render("legal_accept", context! { documents: outstanding }))
+}
+
+#[post("/legal", data = "<acceptance>")]
+pub async fn accept_legal(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    user: SignedInUser,
+    _own: NotImpersonating,
+    acceptance: Form<LegalAcceptance>
+) -> Result<Redirect, Status> {
+    if !acceptance.accept {
+        return Ok(Redirect::to(uri!(legal_page)));
+    }
+
+    legal::accept(db, &config.legal, user.0).await.map_err(|_| Status::InternalServerError)?;
+    Ok(Redirect::to(uri!(super::forms::index(_))))
+}
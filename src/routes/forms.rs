@@ -0,0 +1,922 @@
+use std::collections::HashMap;
+
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::response::Redirect;
+use rocket::serde::json::Json;
+use rocket::{Either, Route, State};
+use rocket_dyn_templates::{Template, context};
+use sqlx::SqlitePool;
+
+use crate::{AppConfig, access, analytics, directory, form_templates, import, localtime, outbox, quota, revisions, rsvp, schema, sharing};
+use crate::access::FormRestriction;
+use crate::cache::{CacheStats, FormCache};
+use crate::db::{ReadPool, notify};
+use crate::events::{DomainEvent, EventBus};
+use crate::flags::{Flag, FlagCache};
+use crate::guards::{Approver, AuthenticatedUser, OptionalUser};
+use crate::localtime::TimePreferences;
+use crate::models::{ExportSchedule, FlagUpdate, FormImport, ListingUpdate, NewExportSchedule, NewReportSchedule, PendingPublishRequest, PublishRequest, PublishReview, ReportSchedule, RestrictionsUpdate, RsvpUpdate, SchemaMigration, ShareRedemption, StageUpdate, TemplateDesignation, WebForm};
+use crate::outbox::OutboxEvent;
+use crate::pipeline::Pipeline;
+use crate::repository::{self, FormRepository};
+use crate::tenant::Tenant;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        index, new_form, create_form, create_from_template, edit_form, update_form, migrate_form, form_revisions, update_form_restrictions, update_form_listing, update_form_rsvp,
+        update_form_stage, create_export_schedule, delete_export_schedule, create_report_schedule, delete_report_schedule, import_form, publish_form, approvals, quota_usage,
+        admin_quotas, admin_flags, update_flag, admin_templates, designate_template, withdraw_template, cache_stats, approve_publish, request_publish_changes, unpublish_form, clone_form,
+        share_form, unshare_form, redeem_share_code, delete_form
+    ]
+}
+
+#[get("/?<published>")]
+pub async fn index(
+    reads: &State<ReadPool>,
+    tenant: Tenant,
+    time: TimePreferences,
+    user: OptionalUser,
+    published: Option<bool>
+) -> Template {
+    // Nothing here writes, so it all comes from the read pool.
+    let db = &reads.0;
+    let (forms, edited, conversions, summary, unread_notifications) = if let Some(AuthenticatedUser(user_id)) = user.0 {
+        let mut forms = db.forms_by_author(user_id).await.unwrap_or_default();
+        if let Some(published) = published {
+            forms.retain(|form| form.published == published);
+        }
+        let edited: HashMap<i64, String> = forms.iter()
+            .filter_map(|form| Some((form.id, localtime::ago(&form.updated_at)?)))
+            .collect();
+        forms.iter_mut().for_each(|form| time.localize(&mut form.updated_at));
+        let conversions = analytics::conversions(db, user_id, None).await.unwrap_or_default();
+        let summary = analytics::summary(db, user_id).await.ok();
+        let unread = sqlx::query_scalar!("SELECT COUNT(*) FROM notifications WHERE user_id = ? AND is_read = false", user_id)
+            .fetch_one(db)
+            .await
+            .unwrap_or_default();
+        (forms, edited, conversions, summary, unread)
+    } else {
+        (Vec::new(), HashMap::new(), Vec::new(), None, 0)
+    };
+
+    // Each total links to the list it counts.
+    let summary_links = summary.as_ref().map(|summary| {
+        let top_form = summary.top_form.as_ref().map(|top| uri!(super::responses::form_responses(top.id, _)).to_string());
+        context! {
+            forms: uri!(index(None::<bool>)).to_string(),
+            published: uri!(index(Some(true))).to_string(),
+            top_form_this_week: top_form.as_ref().map(|top_form| format!("{}?since={}", top_form, summary.week_start)),
+            top_form: top_form
+        }
+    });
+
+    Template::render("index", context! {
+        forms: forms,
+        edited: edited,
+        conversions: conversions,
+        summary: summary,
+        summary_links: summary_links,
+        published: published,
+        logged_in: user.0.is_some(),
+        unread_notifications: unread_notifications,
+        tenant: tenant
+    })
+}
+
+#[get("/form/new")]
+pub async fn new_form(db: &State<SqlitePool>, tenant: Tenant, user: AuthenticatedUser) -> Result<Template, Status> {
+    let templates = form_templates::available(db, tenant.id).await.map_err(|_| Status::InternalServerError)?;
+    Ok(Template::render("form_edit", context! { form: None::<WebForm>, templates: templates }))
+}
+
+#[post("/form", data = "<form_data>")]
+pub async fn create_form(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    user: AuthenticatedUser,
+    form_data: Form<WebForm>
+) -> Result<Redirect, Status> {
+    if !quota::can_create_form(db, &config.plans, user.0).await? {
+        return Ok(Redirect::to(uri!(quota_usage(Some("forms")))));
+    }
+
+    let form = form_data.into_inner();
+    let published = form.published && !config.require_publish_approval;
+    db.create_form(&form, user.0, published).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(index(_))))
+}
+
+/// Starts a new form as a copy of one of the organization's templates,
+/// with the fields the template locks locked in the copy too.
+#[post("/form/new/template/<id>")]
+pub async fn create_from_template(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    tenant: Tenant,
+    user: AuthenticatedUser,
+    id: i64
+) -> Result<Redirect, Status> {
+    if !quota::can_create_form(db, &config.plans, user.0).await? {
+        return Ok(Redirect::to(uri!(quota_usage(Some("forms")))));
+    }
+
+    if !form_templates::is_template(db, tenant.id, id).await.map_err(|_| Status::InternalServerError)? {
+        return Err(Status::NotFound);
+    }
+    let copy_id = db.copy_shared_form(id, user.0)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+
+    Ok(Redirect::to(uri!(edit_form(copy_id))))
+}
+
+#[get("/form/<id>")]
+pub async fn edit_form(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    pipeline: &State<Pipeline>,
+    flags: &State<FlagCache>,
+    tenant: Tenant,
+    user: AuthenticatedUser,
+    id: i64
+) -> Result<Template, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    let Some(form) = form else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    editor(db, config, pipeline, flags, &tenant, form, Vec::new()).await
+}
+
+/// The form editor for `form`, which may hold edits not saved yet. `broken`
+/// lists the locked fields those edits would have removed or retyped.
+async fn editor(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    pipeline: &State<Pipeline>,
+    flags: &State<FlagCache>,
+    tenant: &Tenant,
+    form: WebForm,
+    broken: Vec<String>
+) -> Result<Template, Status> {
+    let publish_request = sqlx::query_as!(PublishRequest,
+        "SELECT * FROM publish_requests WHERE form_id = ? ORDER BY id DESC LIMIT 1",
+        form.id
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let restriction = sqlx::query_as!(FormRestriction, "SELECT * FROM form_restrictions WHERE form_id = ?", form.id)
+        .fetch_optional(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let export_schedules = sqlx::query_as!(ExportSchedule,
+        "SELECT * FROM export_schedules WHERE form_id = ? ORDER BY id",
+        form.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let report_schedules = sqlx::query_as!(ReportSchedule,
+        "SELECT * FROM report_schedules WHERE form_id = ? ORDER BY id",
+        form.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let rsvp = rsvp::event(db, form.id).await.map_err(|_| Status::InternalServerError)?;
+    let tags = directory::tags(db, form.id).await.map_err(|_| Status::InternalServerError)?;
+    let share_code = sharing::code(db, form.id).await.map_err(|_| Status::InternalServerError)?;
+    let locked_fields = form_templates::locked_fields(db, form.id).await.map_err(|_| Status::InternalServerError)?;
+    let field_keys = schema::keys(&schema::parse(&form.fields));
+    let stages = pipeline.settings(db, config, form.id).await?;
+    let script = sqlx::query_scalar!("SELECT source FROM form_scripts WHERE form_id = ?", form.id)
+        .fetch_optional(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let features: HashMap<&str, bool> = flags.settings(db, &config.flags, tenant.id)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .into_iter()
+        .map(|setting| (setting.name, setting.enabled))
+        .collect();
+
+    let edited = localtime::ago(&form.updated_at);
+
+    Ok(Template::render("form_edit", context! {
+        form: form,
+        edited: edited,
+        publish_request: publish_request,
+        restriction: restriction,
+        tags: tags,
+        directory: config.public_directory,
+        share_code: share_code,
+        locked_fields: locked_fields,
+        broken: broken,
+        field_keys: field_keys,
+        rsvp: rsvp,
+        stages: stages,
+        script: script,
+        scripting: cfg!(feature = "scripting"),
+        features: features,
+        export_schedules: export_schedules,
+        report_schedules: report_schedules
+    }))
+}
+
+/// An edit that removes or changes the type of fields with answers is held
+/// back, and the author is asked where those answers should go before
+/// `migrate_form` saves it. One that breaks a locked field goes back to the
+/// editor, unsaved, with the broken fields listed.
+#[post("/form/<id>", data = "<form_data>")]
+pub async fn update_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    config: &State<AppConfig>,
+    pipeline: &State<Pipeline>,
+    flags: &State<FlagCache>,
+    tenant: Tenant,
+    user: AuthenticatedUser,
+    id: i64,
+    form_data: Form<WebForm>
+) -> Result<Either<Redirect, Template>, Status> {
+    let mut form = form_data.into_inner();
+    let current = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?.ok_or(Status::NotFound)?;
+    let broken = form_templates::broken_locks(db, id, &form.fields).await.map_err(|_| Status::InternalServerError)?;
+    if !broken.is_empty() {
+        form.id = current.id;
+        return editor(db, config, pipeline, flags, &tenant, form, broken).await.map(Either::Right);
+    }
+
+    form.fields = schema::assign_keys(&form.fields);
+    let changes = revisions::changes(db, id, &current.fields, &form.fields).await.map_err(|_| Status::InternalServerError)?;
+    if !changes.is_empty() {
+        let targets = schema::keys(&schema::parse(&form.fields));
+        return Ok(Either::Right(Template::render("form_migrate", context! {
+            form: form,
+            changes: changes,
+            targets: targets
+        })));
+    }
+
+    db.update_form(id, user.0, &form, config.require_publish_approval)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    cache.invalidate(id);
+    if form.fields != current.fields {
+        revisions::record_edit(db, id, user.0, &current.fields).await.map_err(|_| Status::InternalServerError)?;
+    }
+
+    Ok(Either::Left(Redirect::to(uri!(index(_)))))
+}
+
+/// Saves an edit `update_form` held back, moving or archiving the answers
+/// of the fields it removes or retypes as the author mapped them. The
+/// changes are worked out again rather than trusted from the page, in case
+/// new responses came in meanwhile.
+#[post("/form/<id>/migrate", data = "<migration>")]
+pub async fn migrate_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    config: &State<AppConfig>,
+    pipeline: &State<Pipeline>,
+    flags: &State<FlagCache>,
+    tenant: Tenant,
+    user: AuthenticatedUser,
+    id: i64,
+    migration: Form<SchemaMigration>
+) -> Result<Either<Redirect, Template>, Status> {
+    let SchemaMigration { mut form, mapping } = migration.into_inner();
+    let current = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?.ok_or(Status::NotFound)?;
+    let broken = form_templates::broken_locks(db, id, &form.fields).await.map_err(|_| Status::InternalServerError)?;
+    if !broken.is_empty() {
+        form.id = current.id;
+        return editor(db, config, pipeline, flags, &tenant, form, broken).await.map(Either::Right);
+    }
+
+    form.fields = schema::assign_keys(&form.fields);
+    let changes = revisions::changes(db, id, &current.fields, &form.fields).await.map_err(|_| Status::InternalServerError)?;
+    if changes.is_empty() {
+        db.update_form(id, user.0, &form, config.require_publish_approval)
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        if form.fields != current.fields {
+            revisions::record_edit(db, id, user.0, &current.fields).await.map_err(|_| Status::InternalServerError)?;
+        }
+    } else {
+        let mut tx = db.begin().await.map_err(|_| Status::InternalServerError)?;
+        revisions::migrate(&mut *tx, id, user.0, &current.fields, &form.fields, &changes, &mapping)
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        repository::save_form(&mut *tx, id, user.0, &form, config.require_publish_approval)
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        tx.commit().await.map_err(|_| Status::InternalServerError)?;
+    }
+    cache.invalidate(id);
+
+    Ok(Either::Left(Redirect::to(uri!(index(_)))))
+}
+
+#[get("/form/<id>/revisions")]
+pub async fn form_revisions(db: &State<SqlitePool>, time: TimePreferences, user: AuthenticatedUser, id: i64) -> Result<Template, Status> {
+    let Some(form) = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)? else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let mut revisions = revisions::history(db, form.id).await.map_err(|_| Status::InternalServerError)?;
+    revisions.iter_mut().for_each(|revision| time.localize(&mut revision.created_at));
+
+    Ok(Template::render("form_revisions", context! { form: form, revisions: revisions }))
+}
+
+#[post("/form/<id>/restrictions", data = "<restrictions>")]
+pub async fn update_form_restrictions(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    restrictions: Form<RestrictionsUpdate>
+) -> Result<Redirect, Status> {
+    let blocked_ranges = access::parse_ranges(&restrictions.blocked_ranges).map_err(|_| Status::UnprocessableEntity)?;
+    let allowed_countries = access::parse_countries(&restrictions.allowed_countries).map_err(|_| Status::UnprocessableEntity)?;
+    let blocked_ranges = serde_json::to_string(&blocked_ranges).map_err(|_| Status::InternalServerError)?;
+    let allowed_countries = serde_json::to_string(&allowed_countries).map_err(|_| Status::InternalServerError)?;
+
+    sqlx::query!(
+        "INSERT INTO form_restrictions (form_id, blocked_ranges, allowed_countries)
+         SELECT id, ?, ? FROM forms WHERE id = ? AND author_id = ?
+         ON CONFLICT (form_id) DO UPDATE SET blocked_ranges = excluded.blocked_ranges, allowed_countries = excluded.allowed_countries",
+        blocked_ranges,
+        allowed_countries,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+#[post("/form/<id>/listing", data = "<listing>")]
+pub async fn update_form_listing(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    user: AuthenticatedUser,
+    id: i64,
+    listing: Form<ListingUpdate>
+) -> Result<Redirect, Status> {
+    let tags = directory::parse_tags(&listing.tags);
+    let updated = directory::set_listing(db, id, user.0, listing.listed, &tags)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    if !updated {
+        return Err(Status::NotFound);
+    }
+    cache.invalidate(id);
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+#[post("/form/<id>/rsvp", data = "<rsvp>")]
+pub async fn update_form_rsvp(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    rsvp: Form<RsvpUpdate>
+) -> Result<Redirect, Status> {
+    if !rsvp.enabled {
+        sqlx::query!(
+            "DELETE FROM form_rsvp WHERE form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+            id,
+            user.0
+        )
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+        return Ok(Redirect::to(uri!(edit_form(id))));
+    }
+
+    let title = rsvp.title.trim();
+    if title.is_empty() {
+        return Err(Status::UnprocessableEntity);
+    }
+    // Parsed the way the insert below stores them, so an unreadable time is
+    // refused rather than written as NULL.
+    let valid_times = sqlx::query_scalar!(
+        r#"SELECT datetime(?1) IS NOT NULL AND (?2 = '' OR datetime(?2) IS NOT NULL) AS "valid!: bool""#,
+        rsvp.starts_at,
+        rsvp.ends_at
+    )
+    .fetch_one(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+    if !valid_times {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    sqlx::query!(
+        "INSERT INTO form_rsvp (form_id, title, starts_at, ends_at, location, choice_field)
+         SELECT id, ?, datetime(?), datetime(?), NULLIF(?, ''), NULLIF(?, '') FROM forms WHERE id = ? AND author_id = ?
+         ON CONFLICT (form_id) DO UPDATE SET title = excluded.title, starts_at = excluded.starts_at, ends_at = excluded.ends_at,
+         location = excluded.location, choice_field = excluded.choice_field",
+        title,
+        rsvp.starts_at,
+        rsvp.ends_at,
+        rsvp.location,
+        rsvp.choice_field,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+/// Required stages cannot be turned off.
+#[post("/form/<id>/stages", data = "<update>")]
+pub async fn update_form_stage(
+    db: &State<SqlitePool>,
+    pipeline: &State<Pipeline>,
+    user: AuthenticatedUser,
+    id: i64,
+    update: Form<StageUpdate>
+) -> Result<Redirect, Status> {
+    if !pipeline.is_optional(&update.stage) {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    sqlx::query!(
+        "INSERT INTO form_stages (form_id, stage, enabled)
+         SELECT id, ?, ? FROM forms WHERE id = ? AND author_id = ?
+         ON CONFLICT (form_id, stage) DO UPDATE SET enabled = excluded.enabled",
+        update.stage,
+        update.enabled,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+#[post("/form/<id>/exports", data = "<schedule>")]
+pub async fn create_export_schedule(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    schedule: Form<NewExportSchedule>
+) -> Result<Redirect, Status> {
+    let frequency = schedule.frequency.as_str();
+    let recipient = schedule.recipient.trim();
+
+    sqlx::query!(
+        "INSERT INTO export_schedules (form_id, user_id, frequency, recipient)
+         SELECT id, author_id, ?, ? FROM forms WHERE id = ? AND author_id = ?",
+        frequency,
+        recipient,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+#[post("/form/<id>/exports/<schedule_id>/delete")]
+pub async fn delete_export_schedule(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    schedule_id: i64
+) -> Result<Redirect, Status> {
+    sqlx::query!(
+        "DELETE FROM export_schedules WHERE id = ? AND form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+        schedule_id,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+#[post("/form/<id>/reports", data = "<schedule>")]
+pub async fn create_report_schedule(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    schedule: Form<NewReportSchedule>
+) -> Result<Redirect, Status> {
+    let recipient = schedule.recipient.trim();
+    let fields = serde_json::to_string(&schedule.fields).map_err(|_| Status::InternalServerError)?;
+
+    sqlx::query!(
+        "INSERT INTO report_schedules (form_id, user_id, recipient, fields)
+         SELECT id, author_id, ?, ? FROM forms WHERE id = ? AND author_id = ?",
+        recipient,
+        fields,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+#[post("/form/<id>/reports/<schedule_id>/delete")]
+pub async fn delete_report_schedule(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    schedule_id: i64
+) -> Result<Redirect, Status> {
+    sqlx::query!(
+        "DELETE FROM report_schedules WHERE id = ? AND form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+        schedule_id,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+#[post("/form/import", data = "<upload>")]
+pub async fn import_form(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    user: AuthenticatedUser,
+    upload: Form<FormImport>
+) -> Result<Template, Status> {
+    if !quota::can_create_form(db, &config.plans, user.0).await? {
+        return Ok(Template::render("form_import", context! {
+            form: None::<WebForm>,
+            error: "You have reached the maximum number of forms for your plan.",
+            unmapped: Vec::<String>::new()
+        }));
+    }
+
+    let imported = match import::form_from_export(upload.source, &upload.file) {
+        Ok(imported) => imported,
+        Err(error) => {
+            return Ok(Template::render("form_import", context! {
+                form: None::<WebForm>,
+                error: error,
+                unmapped: Vec::<String>::new()
+            }));
+        }
+    };
+
+    let fields = serde_json::to_string(&imported.fields).map_err(|_| Status::InternalServerError)?;
+    let form = db.create_draft(&imported.title, &fields, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("form_import", context! {
+        form: form,
+        error: None::<String>,
+        unmapped: imported.unmapped
+    }))
+}
+
+#[post("/form/<id>/publish")]
+pub async fn publish_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    bus: &State<EventBus>,
+    config: &State<AppConfig>,
+    user: AuthenticatedUser,
+    id: i64
+) -> Result<Redirect, Status> {
+    if config.require_publish_approval {
+        let requested = sqlx::query!(
+            "INSERT INTO publish_requests (form_id, requested_by)
+             SELECT id, author_id FROM forms
+             WHERE id = ? AND author_id = ? AND published = false
+             AND NOT EXISTS (SELECT 1 FROM publish_requests WHERE form_id = forms.id AND status = 'pending')",
+            id,
+            user.0
+        )
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .rows_affected() > 0;
+
+        if requested {
+            let title = sqlx::query_scalar!("SELECT title FROM forms WHERE id = ?", id)
+                .fetch_one(db.inner())
+                .await
+                .map_err(|_| Status::InternalServerError)?;
+            let approvers = sqlx::query_scalar!(
+                "SELECT id FROM users WHERE is_approver = true AND tenant_id = (SELECT tenant_id FROM users WHERE id = ?)",
+                user.0
+            )
+            .fetch_all(db.inner())
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+
+            let message = format!("Publish requested for \"{}\"", title);
+            let link = uri!(approvals).to_string();
+            for approver in approvers {
+                notify(db, approver, "approval_request", &message, &link, Some(id)).await?;
+            }
+        }
+
+        return Ok(Redirect::to(uri!(edit_form(id))));
+    }
+
+    let published = db.set_published(id, user.0, true).await.map_err(|_| Status::InternalServerError)?;
+
+    if published {
+        cache.invalidate(id);
+        bus.publish(DomainEvent::FormPublished { form_id: id });
+    }
+
+    Ok(Redirect::to(uri!(index(_))))
+}
+
+#[get("/approvals")]
+pub async fn approvals(db: &State<SqlitePool>, tenant: Tenant, time: TimePreferences, _approver: Approver) -> Result<Template, Status> {
+    let mut requests = sqlx::query_as!(PendingPublishRequest,
+        "SELECT p.id, p.form_id, f.title AS form_title, u.username AS requested_by, p.created_at
+         FROM publish_requests p
+         JOIN forms f ON f.id = p.form_id
+         JOIN users u ON u.id = p.requested_by
+         WHERE p.status = 'pending' AND f.tenant_id = ? ORDER BY p.id",
+        tenant.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    requests.iter_mut().for_each(|request| time.localize(&mut request.created_at));
+
+    Ok(Template::render("approvals", context! { requests: requests }))
+}
+
+#[get("/settings/quota?<exceeded>")]
+pub async fn quota_usage(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    tenant: Tenant,
+    user: AuthenticatedUser,
+    exceeded: Option<&str>
+) -> Result<Template, Status> {
+    let usage = quota::usage(db, &config.plans, tenant.id, Some(user.0)).await?.pop().ok_or(Status::NotFound)?;
+    Ok(Template::render("quota", context! { usage: usage, exceeded: exceeded }))
+}
+
+#[get("/admin/quotas")]
+pub async fn admin_quotas(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    tenant: Tenant,
+    _approver: Approver
+) -> Result<Template, Status> {
+    let usage = quota::usage(db, &config.plans, tenant.id, None).await?;
+    Ok(Template::render("admin_quotas", context! { usage: usage }))
+}
+
+#[get("/admin/flags")]
+pub async fn admin_flags(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    flags: &State<FlagCache>,
+    tenant: Tenant,
+    _approver: Approver
+) -> Result<Template, Status> {
+    let flags = flags.settings(db, &config.flags, tenant.id).await.map_err(|_| Status::InternalServerError)?;
+    Ok(Template::render("admin_flags", context! { flags: flags }))
+}
+
+#[post("/admin/flags", data = "<update>")]
+pub async fn update_flag(
+    db: &State<SqlitePool>,
+    flags: &State<FlagCache>,
+    tenant: Tenant,
+    approver: Approver,
+    update: Form<FlagUpdate>
+) -> Result<Redirect, Status> {
+    let flag = Flag::parse(&update.flag).ok_or(Status::UnprocessableEntity)?;
+    flags.set(db, tenant.id, flag, update.enabled, approver.0)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(admin_flags)))
+}
+
+#[get("/admin/templates")]
+pub async fn admin_templates(db: &State<SqlitePool>, tenant: Tenant, _approver: Approver) -> Result<Template, Status> {
+    let templates = form_templates::available(db, tenant.id).await.map_err(|_| Status::InternalServerError)?;
+    Ok(Template::render("admin_templates", context! { templates: templates }))
+}
+
+/// Designating a form that is already a template replaces its locked
+/// fields. Copies made before keep the locks they were made with.
+#[post("/admin/templates", data = "<designation>")]
+pub async fn designate_template(
+    db: &State<SqlitePool>,
+    tenant: Tenant,
+    _approver: Approver,
+    designation: Form<TemplateDesignation>
+) -> Result<Redirect, Status> {
+    let locked = form_templates::parse_locked(&designation.locked_fields);
+    let designated = form_templates::designate(db, tenant.id, designation.form_id, &locked)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    if !designated {
+        return Err(Status::NotFound);
+    }
+
+    Ok(Redirect::to(uri!(admin_templates)))
+}
+
+#[post("/admin/templates/<id>/withdraw")]
+pub async fn withdraw_template(db: &State<SqlitePool>, tenant: Tenant, _approver: Approver, id: i64) -> Result<Redirect, Status> {
+    form_templates::withdraw(db, tenant.id, id).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(admin_templates)))
+}
+
+#[get("/admin/cache")]
+pub fn cache_stats(cache: &State<FormCache>, _approver: Approver) -> Json<CacheStats> {
+    Json(cache.stats())
+}
+
+#[post("/approvals/<request_id>/approve", data = "<review>")]
+pub async fn approve_publish(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    bus: &State<EventBus>,
+    approver: Approver,
+    request_id: i64,
+    review: Form<PublishReview>
+) -> Result<Redirect, Status> {
+    let mut tx = db.begin().await.map_err(|_| Status::InternalServerError)?;
+    let form_id = sqlx::query_scalar!(
+        "UPDATE publish_requests
+         SET status = 'approved', reviewer_id = ?1, review_comment = ?2, reviewed_at = CURRENT_TIMESTAMP
+         WHERE id = ?3 AND status = 'pending'
+         AND requested_by IN (SELECT id FROM users WHERE tenant_id = (SELECT tenant_id FROM users WHERE id = ?1))
+         RETURNING form_id",
+        approver.0,
+        review.comment,
+        request_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .ok_or(Status::NotFound)?;
+
+    sqlx::query!("UPDATE forms SET published = true WHERE id = ?", form_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    outbox::record(&mut *tx, OutboxEvent::FormPublished { form_id })
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    tx.commit().await.map_err(|_| Status::InternalServerError)?;
+    cache.invalidate(form_id);
+
+    bus.publish(DomainEvent::FormPublished { form_id });
+
+    Ok(Redirect::to(uri!(approvals)))
+}
+
+#[post("/approvals/<request_id>/request-changes", data = "<review>")]
+pub async fn request_publish_changes(
+    db: &State<SqlitePool>,
+    approver: Approver,
+    request_id: i64,
+    review: Form<PublishReview>
+) -> Result<Redirect, Status> {
+    sqlx::query!(
+        "UPDATE publish_requests
+         SET status = 'changes_requested', reviewer_id = ?1, review_comment = ?2, reviewed_at = CURRENT_TIMESTAMP
+         WHERE id = ?3 AND status = 'pending'
+         AND requested_by IN (SELECT id FROM users WHERE tenant_id = (SELECT tenant_id FROM users WHERE id = ?1))",
+        approver.0,
+        review.comment,
+        request_id
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(approvals)))
+}
+
+#[post("/form/<id>/unpublish")]
+pub async fn unpublish_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    bus: &State<EventBus>,
+    user: AuthenticatedUser,
+    id: i64
+) -> Result<Redirect, Status> {
+    let unpublished = db.set_published(id, user.0, false).await.map_err(|_| Status::InternalServerError)?;
+    cache.invalidate(id);
+    if unpublished {
+        bus.publish(DomainEvent::FormUnpublished { form_id: id });
+    }
+
+    Ok(Redirect::to(uri!(index(_))))
+}
+
+#[post("/form/<id>/clone")]
+pub async fn clone_form(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    user: AuthenticatedUser,
+    id: i64
+) -> Result<Redirect, Status> {
+    if !quota::can_create_form(db, &config.plans, user.0).await? {
+        return Ok(Redirect::to(uri!(quota_usage(Some("forms")))));
+    }
+
+    db.clone_form(id, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(index(_))))
+}
+
+/// Issues a code anyone else in the tenant can redeem for their own copy of
+/// the form's questions and settings. Responses are never shared.
+#[post("/form/<id>/share")]
+pub async fn share_form(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64) -> Result<Redirect, Status> {
+    sharing::share(db, id, user.0)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+/// Copies already made from the code are left alone.
+#[post("/form/<id>/unshare")]
+pub async fn unshare_form(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64) -> Result<Redirect, Status> {
+    sharing::unshare(db, id, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(edit_form(id))))
+}
+
+#[post("/forms/redeem", data = "<redemption>")]
+pub async fn redeem_share_code(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    tenant: Tenant,
+    user: AuthenticatedUser,
+    redemption: Form<ShareRedemption>
+) -> Result<Redirect, Status> {
+    if !quota::can_create_form(db, &config.plans, user.0).await? {
+        return Ok(Redirect::to(uri!(quota_usage(Some("forms")))));
+    }
+
+    let form_id = sharing::shared_form(db, tenant.id, &redemption.code)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+    let copy_id = db.copy_shared_form(form_id, user.0)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+
+    Ok(Redirect::to(uri!(edit_form(copy_id))))
+}
+
+#[post("/form/<id>/delete")]
+pub async fn delete_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    bus: &State<EventBus>,
+    user: AuthenticatedUser,
+    id: i64
+) -> Result<Redirect, Status> {
+    let deleted = db.delete_form(id, user.0).await.map_err(|_| Status::InternalServerError)?;
+    cache.invalidate(id);
+    if deleted {
+        bus.publish(DomainEvent::FormDeleted { form_id: id, author_id: user.0 });
+    }
+
+    Ok(Redirect::to(uri!(index(_))))
+}
@@ -0,0 +1,30 @@
+use rocket::http::Status;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use sqlx::SqlitePool;
+
+use crate::{schema, slots};
+use crate::guards::AuthenticatedUser;
+use crate::repository::FormRepository;
+
+pub fn routes() -> Vec<Route> {
+    routes![print_form]
+}
+
+/// A blank copy of the form to print and fill in by hand, e.g. where there
+/// is no connection; the answers are keyed in afterwards with
+/// `responses::new_response`. Drafts print too, so they can be proofread.
+#[get("/form/<id>/print")]
+pub async fn print_form(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64) -> Result<Template, Status> {
+    let Some(form) = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)? else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let pages = schema::printable(schema::parse(&form.fields));
+    let slots = slots::available(db, form.id).await.map_err(|_| Status::InternalServerError)?;
+    Ok(Template::render("form_print", context! {
+        form: form,
+        pages: pages,
+        slots: slots
+    }))
+}
@@ -0,0 +1,158 @@
+use rocket::http::Status;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::{AppConfig, crypto, outbox, payments, schema};
+use crate::cache::FormCache;
+use crate::db::{published_form, record_response_event};
+use crate::events::EventBus;
+use crate::flags::FlagCache;
+use crate::models::{FormResponse, ResponseEventKind, WebForm};
+use crate::outbox::OutboxEvent;
+use crate::payments::StripeSignature;
+use crate::pipeline::{Pipeline, Services};
+use crate::tenant::Tenant;
+use crate::write_buffer::WriteBuffer;
+
+pub fn routes() -> Vec<Route> {
+    routes![stripe_webhook, payment_complete]
+}
+
+#[derive(Deserialize)]
+struct StripeEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    data: StripeEventData,
+}
+
+#[derive(Deserialize)]
+struct StripeEventData {
+    object: StripeObject,
+}
+
+/// The fields used from either a Checkout session or, for refunds, a charge.
+#[derive(Deserialize)]
+struct StripeObject {
+    id: String,
+    payment_status: Option<String>,
+    payment_intent: Option<String>,
+    amount_total: Option<i64>,
+    #[serde(default)]
+    amount_refunded: i64,
+    #[serde(default)]
+    refunded: bool,
+}
+
+/// Marks the response behind a Checkout session paid or failed, and records
+/// refunds against it. Events for payments this app did not create are
+/// acknowledged and ignored.
+#[post("/payments/stripe/webhook", data = "<payload>")]
+pub async fn stripe_webhook(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    events: &State<EventBus>,
+    writes: &State<WriteBuffer>,
+    flags: &State<FlagCache>,
+    client: &State<reqwest::Client>,
+    pipeline: &State<Pipeline>,
+    signature: StripeSignature,
+    payload: String
+) -> Result<Status, Status> {
+    let secret = config.stripe.webhook_secret.as_deref().ok_or(Status::NotFound)?;
+    if !payments::verify_signature(secret, &signature.0, &payload) {
+        return Err(Status::BadRequest);
+    }
+    let event: StripeEvent = serde_json::from_str(&payload).map_err(|_| Status::BadRequest)?;
+    let session = event.data.object;
+    if event.kind == "charge.refunded" {
+        return record_refund(db, &session).await;
+    }
+
+    let status = match event.kind.as_str() {
+        // Delayed payment methods complete the session before the money
+        // arrives; those are settled by the async events.
+        "checkout.session.completed" if session.payment_status.as_deref() == Some("paid") => "paid",
+        "checkout.session.async_payment_succeeded" => "paid",
+        "checkout.session.async_payment_failed" | "checkout.session.expired" => "failed",
+        _ => return Ok(Status::Ok),
+    };
+
+    // Only pending responses move, so a redelivered event changes nothing.
+    let mut tx = db.begin().await.map_err(|_| Status::InternalServerError)?;
+    let Some(mut response) = sqlx::query_as!(
+        FormResponse,
+        "UPDATE responses SET payment_status = ?1, payment_amount = COALESCE(?2, payment_amount),
+             payment_intent = ?3, updated_at = CURRENT_TIMESTAMP
+         WHERE payment_session = ?4 AND payment_status = 'pending' RETURNING *",
+        status,
+        session.amount_total,
+        session.payment_intent,
+        session.id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    else {
+        return Ok(Status::Ok);
+    };
+    let complete = status == "paid" && !response.spam;
+    if complete && !response.is_test && pipeline.delivers(db, config, response.form_id).await? {
+        let event = OutboxEvent::ResponseSubmitted { form_id: response.form_id, response_id: response.id };
+        outbox::record(&mut *tx, event).await.map_err(|_| Status::InternalServerError)?;
+    }
+    tx.commit().await.map_err(|_| Status::InternalServerError)?;
+    record_response_event(db, response.id, None, ResponseEventKind::Payment, status).await?;
+
+    if complete {
+        let form = sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE id = ?", response.form_id)
+            .fetch_one(db.inner())
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        let answers = crypto::decrypt_answers(&response.answers);
+        response.answers = serde_json::to_string(&answers).map_err(|_| Status::InternalServerError)?;
+        let services = Services { db, config, client, events, writes, flags };
+        pipeline.announce(&services, &form, &response, &answers).await?;
+    }
+
+    Ok(Status::Ok)
+}
+
+/// `amount_refunded` is the charge's running total, so partial refunds and
+/// redelivered events both leave the right figure.
+async fn record_refund(db: &SqlitePool, charge: &StripeObject) -> Result<Status, Status> {
+    let Some(payment_intent) = &charge.payment_intent else {
+        return Ok(Status::Ok);
+    };
+    let refunded = sqlx::query_scalar!(
+        r#"UPDATE responses
+           SET payment_refunded = ?1, payment_status = CASE WHEN ?2 THEN 'refunded' ELSE payment_status END,
+               updated_at = CURRENT_TIMESTAMP
+           WHERE payment_intent = ?3 AND payment_status IN ('paid', 'refunded') AND payment_refunded != ?1
+           RETURNING id AS "id!: i64""#,
+        charge.amount_refunded,
+        charge.refunded,
+        payment_intent
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    if let Some(response_id) = refunded {
+        let detail = format!("refunded {}", schema::format_amount(charge.amount_refunded));
+        record_response_event(db, response_id, None, ResponseEventKind::Payment, &detail).await?;
+    }
+    Ok(Status::Ok)
+}
+
+/// Where Checkout returns the respondent. The webhook may not have arrived
+/// yet, so this only thanks them.
+#[get("/f/<id>/paid")]
+pub async fn payment_complete(db: &State<SqlitePool>, cache: &State<FormCache>, tenant: Tenant, id: i64) -> Result<Template, Status> {
+    let Some(form) = published_form(db, cache, &tenant, id).await? else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    Ok(Template::render("form_submitted", context! { form: form, waitlisted: false, paid: true }))
+}
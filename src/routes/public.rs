@@ -0,0 +1,730 @@
+use std::collections::HashMap;
+
+use lettre::message::Mailbox;
+use rocket::Shutdown;
+use rocket::form::Form;
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::http::{Cookie, CookieJar, SameSite, Status, private::PrivateCookies};
+use rocket::serde::json::Json;
+use rocket::time::Duration;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use rocket_ws::{WebSocket, Channel};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::{AppConfig, access, account, analytics, api, directory, payments, resume, schema, slots};
+use crate::access::{ClientIp, GeoIp};
+use crate::cache::FormCache;
+use crate::captcha::{CAPTCHA_TOKEN_FIELD, CHALLENGE_TOKEN_FIELD, CaptchaPrompt};
+use crate::db::{form_schedule, published_form};
+use crate::events::{DomainEvent, EventBus};
+use crate::flags::{Flag, FlagCache};
+use crate::guards::{AuthenticatedUser, IDEMPOTENCY_KEY_FIELD, IdempotencyKey};
+use crate::localtime::TimePreferences;
+use crate::models::{Attribution, ClosedReason, EmailVerificationCode, FormSchedule, EmailVerificationRequest, FormResponse, LiveResults, PageEvent, PageProgress, ResumeLookup, WebForm};
+use crate::pipeline::{Pipeline, Rejection, Services, Source, Submission};
+use crate::resume::{Progress, RESUME_TOKEN_FIELD, SavedProgress};
+use crate::submission_token::{SUBMISSION_TOKEN_FIELD, SubmissionTokens};
+use crate::tenant::Tenant;
+use crate::write_buffer::WriteBuffer;
+
+const KIOSK_RESET_SECONDS: u64 = 5;
+
+const VERIFICATION_CODE_TTL: &str = "+10 minutes";
+
+const VERIFICATION_MAX_ATTEMPTS: i64 = 5;
+
+const VISITOR_COOKIE: &str = "visitor";
+
+const VISITOR_COOKIE_DAYS: i64 = 30;
+
+const FIRST_PAGE: i64 = 1;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        public_form, request_email_code, verify_email_code, submit_form, kiosk_form, submit_kiosk_form,
+        record_progress, save_progress, restore_progress, form_directory, live_results, live_results_socket
+    ]
+}
+
+#[get("/f/<id>")]
+pub async fn public_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    config: &State<AppConfig>,
+    tokens: &State<SubmissionTokens>,
+    geoip: &State<GeoIp>,
+    ip: ClientIp,
+    cookies: &CookieJar<'_>,
+    attribution: Attribution,
+    tenant: Tenant,
+    id: i64
+) -> Result<Template, Status> {
+    let Some(form) = published_form(db, cache, &tenant, id).await? else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    if !access::allowed(db, geoip, form.id, &ip).await? {
+        return Ok(Template::render("form_unavailable", context! { form: form }));
+    }
+
+    let schedule = form_schedule(db, &form).await?;
+    if let Some(reason) = schedule.closed {
+        return Ok(closed_template(form, schedule, reason, None));
+    }
+
+    // Kept until the respondent submits, so the email verification steps
+    // in between do not lose where they came from.
+    if !attribution.is_empty() && !form.anonymous {
+        let attribution = serde_json::to_string(&attribution).map_err(|_| Status::InternalServerError)?;
+        cookies.add_private(Cookie::new(format!("attribution_{}", form.id), attribution));
+    }
+
+    if form.verify_email && verified_email(cookies, form.id).is_none() {
+        return Ok(verify_email_template(form, None, None));
+    }
+
+    track_page(db, cookies, form.id, FIRST_PAGE, PageEvent::View).await;
+    if let Err(e) = analytics::record_view(db, form.id, &visitor_token(cookies)).await {
+        warn!("Failed to count a view of form {}: {}", form.id, e);
+    }
+    let captcha = config.captcha.prompt(&form, false);
+    public_form_template(db, form, &tenant, tokens, None, HashMap::new(), Vec::new(), captcha).await
+}
+
+/// An anonymous token that ties one browser's page events together for the
+/// dropout funnel. It says nothing about who the respondent is.
+fn visitor_token(cookies: &CookieJar<'_>) -> String {
+    if let Some(cookie) = cookies.get_pending(VISITOR_COOKIE) {
+        return cookie.value().to_string();
+    }
+
+    let visitor = Uuid::new_v4().to_simple().to_string();
+    let mut cookie = Cookie::new(VISITOR_COOKIE, visitor.clone());
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_max_age(Duration::days(VISITOR_COOKIE_DAYS));
+    cookies.add(cookie);
+    visitor
+}
+
+/// Funnel tracking is best effort and never fails the page it is measuring.
+async fn track_page(db: &SqlitePool, cookies: &CookieJar<'_>, form_id: i64, page: i64, event: PageEvent) {
+    let visitor = visitor_token(cookies);
+    if let Err(e) = analytics::record_page_event(db, form_id, &visitor, page, event).await {
+        warn!("Failed to record a page {} event for form {}: {}", event.as_str(), form_id, e);
+    }
+}
+
+/// Paged forms report each page as it is shown and completed. The first page
+/// view and the final submission are recorded by the server on its own.
+#[post("/f/<id>/progress", data = "<progress>")]
+pub async fn record_progress(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    cookies: &CookieJar<'_>,
+    tenant: Tenant,
+    id: i64,
+    progress: Form<PageProgress>
+) -> Result<Status, Status> {
+    let form = published_form(db, cache, &tenant, id).await?.ok_or(Status::NotFound)?;
+    track_page(db, cookies, form.id, progress.page, progress.event).await;
+    Ok(Status::NoContent)
+}
+
+/// Saves a respondent's answers so far under a resume token, which the
+/// form's script keeps in local storage and shows so it can be typed in on
+/// another device. Only answers to the form's own fields are kept.
+#[post("/f/<id>/resume?<page>", data = "<answers>")]
+pub async fn save_progress(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    config: &State<AppConfig>,
+    tenant: Tenant,
+    id: i64,
+    page: Option<i64>,
+    answers: Form<HashMap<String, String>>
+) -> Result<Json<SavedProgress>, Status> {
+    let form = published_form(db, cache, &tenant, id).await?.ok_or(Status::NotFound)?;
+
+    let mut answers = answers.into_inner();
+    let token = answers.remove(RESUME_TOKEN_FIELD).filter(|token| !token.is_empty());
+    let fields = schema::parse(&form.fields);
+    answers.retain(|key, _| fields.iter().any(|field| &field.key == key));
+
+    let saved = resume::save(db, &config.resume, form.id, token.as_deref(), &answers, page.unwrap_or(FIRST_PAGE).clamp(1, 100))
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Json(saved))
+}
+
+/// Sent as a POST so the token stays out of logs and browser history.
+#[post("/f/<id>/resume/restore", data = "<lookup>")]
+pub async fn restore_progress(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    tenant: Tenant,
+    id: i64,
+    lookup: Form<ResumeLookup>
+) -> Result<Json<Progress>, Status> {
+    let form = published_form(db, cache, &tenant, id).await?.ok_or(Status::NotFound)?;
+    let progress = resume::restore(db, form.id, &lookup.token)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+
+    Ok(Json(progress))
+}
+
+fn verified_email(cookies: &CookieJar<'_>, form_id: i64) -> Option<String> {
+    cookies.get_private(&format!("verified_email_{}", form_id)).map(|cookie| cookie.value().to_string())
+}
+
+fn saved_attribution(cookies: &CookieJar<'_>, form_id: i64) -> Attribution {
+    cookies.get_private(&format!("attribution_{}", form_id))
+        .and_then(|cookie| serde_json::from_str(cookie.value()).ok())
+        .unwrap_or_default()
+}
+
+/// Covers every way a published form stops taking responses, with the
+/// schedule so the page can say when it opens or closed.
+fn closed_template(form: WebForm, schedule: FormSchedule, reason: ClosedReason, device: Option<&str>) -> Template {
+    let countdown = form.show_countdown && reason == ClosedReason::Upcoming && schedule.seconds_until_open.is_some();
+    Template::render("form_closed", context! {
+        form: form,
+        reason: reason,
+        schedule: schedule,
+        countdown: countdown,
+        kiosk: device.is_some(),
+        device: device,
+        reset_seconds: KIOSK_RESET_SECONDS
+    })
+}
+
+fn verify_email_template(form: WebForm, device: Option<&str>, error: Option<&str>) -> Template {
+    Template::render("form_verify_email", context! {
+        form: form,
+        kiosk: device.is_some(),
+        device: device,
+        error: error
+    })
+}
+
+#[post("/f/<id>/verify", data = "<request>")]
+pub async fn request_email_code(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    tenant: Tenant,
+    id: i64,
+    request: Form<EmailVerificationRequest>
+) -> Result<Template, Status> {
+    let Some(form) = published_form(db, cache, &tenant, id).await?.filter(|form| form.verify_email) else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let device = request.device.as_deref();
+    let email = request.email.trim();
+    if email.parse::<Mailbox>().is_err() {
+        return Ok(verify_email_template(form, device, Some("Enter a valid email address")));
+    }
+
+    let code = format!("{:06}", Uuid::new_v4().as_u128() % 1_000_000);
+    let code_hash = api::hash_token(&code);
+    sqlx::query!(
+        "INSERT INTO email_verifications (form_id, email, code_hash, expires_at) VALUES (?, ?, ?, datetime('now', ?))",
+        form.id,
+        email,
+        code_hash,
+        VERIFICATION_CODE_TTL
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let subject = format!("Your code for \"{}\"", form.title);
+    let body = format!("Your verification code is {}. It expires in 10 minutes.", code);
+    sqlx::query!("INSERT INTO email_queue (recipient, subject, body) VALUES (?, ?, ?)", email, subject, body)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("form_verify_code", context! {
+        form: form,
+        email: email,
+        kiosk: device.is_some(),
+        device: device
+    }))
+}
+
+#[post("/f/<id>/verify/code", data = "<verification>")]
+pub async fn verify_email_code(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    config: &State<AppConfig>,
+    tokens: &State<SubmissionTokens>,
+    cookies: &CookieJar<'_>,
+    tenant: Tenant,
+    id: i64,
+    verification: Form<EmailVerificationCode>
+) -> Result<Template, Status> {
+    let Some(form) = published_form(db, cache, &tenant, id).await?.filter(|form| form.verify_email) else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let device = verification.device.as_deref();
+    let email = verification.email.trim();
+    let code_hash = sqlx::query_scalar!(
+        "UPDATE email_verifications SET attempts = attempts + 1
+         WHERE id = (SELECT id FROM email_verifications
+                     WHERE form_id = ? AND email = ? AND expires_at > CURRENT_TIMESTAMP ORDER BY id DESC LIMIT 1)
+         AND attempts < ? RETURNING code_hash",
+        form.id,
+        email,
+        VERIFICATION_MAX_ATTEMPTS
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    if code_hash != Some(api::hash_token(verification.code.trim())) {
+        return Ok(Template::render("form_verify_code", context! {
+            form: form,
+            email: email,
+            kiosk: device.is_some(),
+            device: device,
+            error: "That code is wrong or has expired"
+        }));
+    }
+
+    sqlx::query!("DELETE FROM email_verifications WHERE form_id = ? AND email = ?", form.id, email)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    cookies.add_private(Cookie::new(format!("verified_email_{}", form.id), email.to_string()));
+
+    let captcha = config.captcha.prompt(&form, false);
+    public_form_template(db, form, &tenant, tokens, device, HashMap::new(), Vec::new(), captcha).await
+}
+
+async fn public_form_template(
+    db: &SqlitePool,
+    form: WebForm,
+    tenant: &Tenant,
+    tokens: &SubmissionTokens,
+    device: Option<&str>,
+    answers: HashMap<String, String>,
+    errors: Vec<schema::FieldError>,
+    captcha: CaptchaPrompt<'_>
+) -> Result<Template, Status> {
+    let fields = schema::parse(&form.fields);
+    let rules = schema::client_rules(&fields);
+    let token = tokens.issue(form.id, tenant.id);
+    // Full slots are left out so they cannot be picked.
+    let slots = slots::available(db, form.id).await.map_err(|_| Status::InternalServerError)?;
+    let author = account::public_profile(db, form.author_id).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("form_public", context! {
+        form: form,
+        fields: schema::render(fields),
+        rules: rules,
+        answers: answers,
+        errors: errors,
+        kiosk: device.is_some(),
+        device: device,
+        tenant: tenant,
+        idempotency_field: IDEMPOTENCY_KEY_FIELD,
+        idempotency_key: Uuid::new_v4().to_string(),
+        token_field: SUBMISSION_TOKEN_FIELD,
+        token: token,
+        slots: slots,
+        author: author,
+        captcha: captcha,
+        captcha_field: CAPTCHA_TOKEN_FIELD,
+        resume_field: RESUME_TOKEN_FIELD
+    }))
+}
+
+#[post("/f/<id>", data = "<answers>")]
+pub async fn submit_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    config: &State<AppConfig>,
+    events: &State<EventBus>,
+    writes: &State<WriteBuffer>,
+    flags: &State<FlagCache>,
+    tokens: &State<SubmissionTokens>,
+    geoip: &State<GeoIp>,
+    client: &State<reqwest::Client>,
+    pipeline: &State<Pipeline>,
+    ip: ClientIp,
+    cookies: &CookieJar<'_>,
+    user: Option<AuthenticatedUser>,
+    idempotency_key: IdempotencyKey,
+    tenant: Tenant,
+    id: i64,
+    answers: Form<HashMap<String, String>>
+) -> Result<Template, Status> {
+    let Some(form) = published_form(db, cache, &tenant, id).await? else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    if !access::allowed(db, geoip, form.id, &ip).await? {
+        return Ok(Template::render("form_unavailable", context! { form: form }));
+    }
+
+    let schedule = form_schedule(db, &form).await?;
+    if let Some(reason) = schedule.closed {
+        return Ok(closed_template(form, schedule, reason, None));
+    }
+
+    let respondent_email = verified_email(cookies, form.id);
+    if form.verify_email && respondent_email.is_none() {
+        return Ok(verify_email_template(form, None, None));
+    }
+
+    let mut answers = answers.into_inner();
+    let token = answers.remove(SUBMISSION_TOKEN_FIELD).unwrap_or_default();
+    if let Err(e) = tokens.verify(&token, form.id, tenant.id) {
+        info!("Rejected a submission to form {}: {}", form.id, e);
+        return Ok(Template::render("form_stale", context! { form: form }));
+    }
+
+    let idempotency_key = answers.remove(IDEMPOTENCY_KEY_FIELD)
+        .and_then(|key| IdempotencyKey::parse(&key))
+        .or(idempotency_key.0);
+    let captcha_token = answers.remove(CAPTCHA_TOKEN_FIELD);
+    let challenge_token = answers.remove(CHALLENGE_TOKEN_FIELD);
+    let resume_token = answers.remove(RESUME_TOKEN_FIELD).filter(|token| !token.is_empty());
+
+    let services = Services { db, config, client, events, writes, flags };
+    let mut submission = Submission::new(&form, Source::Web, answers)
+        .user(user)
+        .respondent_email(respondent_email.as_deref())
+        .idempotency_key(idempotency_key.as_deref())
+        .attribution(saved_attribution(cookies, form.id))
+        .ip(ip.0)
+        .captcha(captcha_token, challenge_token);
+    let response = match pipeline.submit(&services, &mut submission).await {
+        Ok(response) => response,
+        // The form comes back filled in, with the challenge added if the
+        // CAPTCHA asked for one.
+        Err(rejection @ (Rejection::Invalid(_) | Rejection::Challenge)) => {
+            let answers = submission.answers;
+            let (errors, challenge) = match rejection {
+                Rejection::Invalid(errors) => (errors, false),
+                _ => (Vec::new(), true),
+            };
+            let captcha = config.captcha.prompt(&form, challenge);
+            return public_form_template(db, form, &tenant, tokens, None, answers, errors, captcha).await;
+        }
+        Err(Rejection::Closed(reason)) => return Ok(closed_template(form, schedule, reason, None)),
+        Err(Rejection::Duplicate) => return Ok(Template::render("form_duplicate", context! { form: form })),
+        Err(Rejection::SlotTaken) => return Ok(Template::render("form_slot_taken", context! { form: form })),
+        Err(Rejection::Bot) => return Ok(Template::render("form_rejected", context! { form: form })),
+        Err(Rejection::Unavailable) => return Ok(Template::render("form_unavailable", context! { form: form })),
+        Err(Rejection::Refused(message)) => return Ok(Template::render("form_rejected", context! { form: form, message: message })),
+        Err(Rejection::Error(status)) => return Err(status),
+    };
+    cookies.remove_private(Cookie::named(format!("verified_email_{}", form.id)));
+    cookies.remove_private(Cookie::named(format!("attribution_{}", form.id)));
+    track_page(db, cookies, form.id, FIRST_PAGE, PageEvent::Complete).await;
+    if let Some(resume_token) = resume_token {
+        if let Err(e) = resume::discard(db, form.id, &resume_token).await {
+            warn!("Failed to discard saved progress on form {}: {}", form.id, e);
+        }
+    }
+
+    if response.payment_status.as_deref() == Some("pending") {
+        let checkout_url = payments::checkout(db, client, &config.stripe, &form, &response).await.map_err(|e| {
+            error!("Failed to start checkout for response {}: {}", response.id, e);
+            Status::BadGateway
+        })?;
+        return Ok(Template::render("form_payment", context! {
+            form: form,
+            amount: response.payment_amount,
+            currency: &config.stripe.currency,
+            checkout_url: checkout_url
+        }));
+    }
+
+    Ok(Template::render("form_submitted", context! { form: form, waitlisted: response.waitlisted }))
+}
+
+#[get("/f/<id>/kiosk/<device>")]
+pub async fn kiosk_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    config: &State<AppConfig>,
+    tokens: &State<SubmissionTokens>,
+    geoip: &State<GeoIp>,
+    ip: ClientIp,
+    cookies: &CookieJar<'_>,
+    tenant: Tenant,
+    id: i64,
+    device: &str
+) -> Result<Template, Status> {
+    let Some(form) = published_form(db, cache, &tenant, id).await? else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    if !access::allowed(db, geoip, form.id, &ip).await? {
+        return Ok(Template::render("form_unavailable", context! { form: form }));
+    }
+
+    let schedule = form_schedule(db, &form).await?;
+    if let Some(reason) = schedule.closed {
+        return Ok(closed_template(form, schedule, reason, Some(device)));
+    }
+
+    if form.verify_email && verified_email(cookies, form.id).is_none() {
+        return Ok(verify_email_template(form, Some(device), None));
+    }
+
+    let captcha = config.captcha.prompt(&form, false);
+    public_form_template(db, form, &tenant, tokens, Some(device), HashMap::new(), Vec::new(), captcha).await
+}
+
+#[post("/f/<id>/kiosk/<device>", data = "<answers>")]
+pub async fn submit_kiosk_form(
+    db: &State<SqlitePool>,
+    cache: &State<FormCache>,
+    config: &State<AppConfig>,
+    events: &State<EventBus>,
+    writes: &State<WriteBuffer>,
+    flags: &State<FlagCache>,
+    tokens: &State<SubmissionTokens>,
+    geoip: &State<GeoIp>,
+    client: &State<reqwest::Client>,
+    pipeline: &State<Pipeline>,
+    ip: ClientIp,
+    cookies: &CookieJar<'_>,
+    user: Option<AuthenticatedUser>,
+    idempotency_key: IdempotencyKey,
+    tenant: Tenant,
+    id: i64,
+    device: &str,
+    answers: Form<HashMap<String, String>>
+) -> Result<Template, Status> {
+    let Some(form) = published_form(db, cache, &tenant, id).await? else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    if !access::allowed(db, geoip, form.id, &ip).await? {
+        return Ok(Template::render("form_unavailable", context! { form: form }));
+    }
+
+    let schedule = form_schedule(db, &form).await?;
+    if let Some(reason) = schedule.closed {
+        return Ok(closed_template(form, schedule, reason, Some(device)));
+    }
+
+    let respondent_email = verified_email(cookies, form.id);
+    if form.verify_email && respondent_email.is_none() {
+        return Ok(verify_email_template(form, Some(device), None));
+    }
+
+    let mut answers = answers.into_inner();
+    let token = answers.remove(SUBMISSION_TOKEN_FIELD).unwrap_or_default();
+    if let Err(e) = tokens.verify(&token, form.id, tenant.id) {
+        info!("Rejected a kiosk submission to form {}: {}", form.id, e);
+        return Ok(Template::render("form_stale", context! {
+            form: form,
+            kiosk: true,
+            device: device,
+            reset_seconds: KIOSK_RESET_SECONDS
+        }));
+    }
+
+    let idempotency_key = answers.remove(IDEMPOTENCY_KEY_FIELD)
+        .and_then(|key| IdempotencyKey::parse(&key))
+        .or(idempotency_key.0);
+    // Kiosks are shared, so they never save progress for later.
+    answers.remove(RESUME_TOKEN_FIELD);
+    let captcha_token = answers.remove(CAPTCHA_TOKEN_FIELD);
+    let challenge_token = answers.remove(CHALLENGE_TOKEN_FIELD);
+
+    let services = Services { db, config, client, events, writes, flags };
+    let mut submission = Submission::new(&form, Source::Kiosk(device), answers)
+        .user(user)
+        .respondent_email(respondent_email.as_deref())
+        .idempotency_key(idempotency_key.as_deref())
+        .ip(ip.0)
+        .captcha(captcha_token, challenge_token);
+    let template = match pipeline.submit(&services, &mut submission).await {
+        Ok(response) => {
+            cookies.remove_private(Cookie::named(format!("verified_email_{}", form.id)));
+            return Ok(Template::render("form_submitted", context! {
+                form: form,
+                waitlisted: response.waitlisted,
+                kiosk: true,
+                device: device,
+                reset_seconds: KIOSK_RESET_SECONDS
+            }));
+        }
+        Err(rejection @ (Rejection::Invalid(_) | Rejection::Challenge)) => {
+            let answers = submission.answers;
+            let (errors, challenge) = match rejection {
+                Rejection::Invalid(errors) => (errors, false),
+                _ => (Vec::new(), true),
+            };
+            let captcha = config.captcha.prompt(&form, challenge);
+            return public_form_template(db, form, &tenant, tokens, Some(device), answers, errors, captcha).await;
+        }
+        Err(Rejection::Closed(reason)) => return Ok(closed_template(form, schedule, reason, Some(device))),
+        Err(Rejection::Duplicate) => "form_duplicate",
+        Err(Rejection::SlotTaken) => "form_slot_taken",
+        Err(Rejection::Bot) => "form_rejected",
+        // Kiosks are shared devices, so they do not take payments.
+        Err(Rejection::Unavailable) => "form_unavailable",
+        Err(Rejection::Refused(message)) => {
+            return Ok(Template::render("form_rejected", context! {
+                form: form,
+                message: message,
+                kiosk: true,
+                device: device,
+                reset_seconds: KIOSK_RESET_SECONDS
+            }));
+        }
+        Err(Rejection::Error(status)) => return Err(status),
+    };
+
+    Ok(Template::render(template, context! {
+        form: form,
+        kiosk: true,
+        device: device,
+        reset_seconds: KIOSK_RESET_SECONDS
+    }))
+}
+
+async fn live_results_enabled(db: &SqlitePool, config: &AppConfig, flags: &FlagCache, id: i64) -> Result<bool, Status> {
+    flags.enabled_for_form(db, &config.flags, id, Flag::LiveResults)
+        .await
+        .map_err(|_| Status::InternalServerError)
+}
+
+/// The organization's listed forms, for deployments that switch on
+/// `public_directory`. Searches by title and browses by tag.
+#[get("/directory?<q>&<tag>")]
+pub async fn form_directory(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    tenant: Tenant,
+    time: TimePreferences,
+    q: Option<String>,
+    tag: Option<String>
+) -> Result<Template, Status> {
+    if !config.public_directory {
+        return Ok(Template::render("404", context! {}));
+    }
+    let tag = tag.filter(|tag| !tag.is_empty());
+
+    let mut forms = directory::search(db, tenant.id, q.as_deref(), tag.as_deref())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    forms.iter_mut().for_each(|form| time.localize(&mut form.updated_at));
+    let tags = directory::listed_tags(db, tenant.id)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("directory", context! {
+        forms: forms,
+        tags: tags,
+        q: q,
+        tag: tag,
+        tenant: tenant
+    }))
+}
+
+#[get("/f/<id>/results/live")]
+pub async fn live_results(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    flags: &State<FlagCache>,
+    tenant: Tenant,
+    id: i64
+) -> Result<Template, Status> {
+    if !live_results_enabled(db, config, flags, id).await? {
+        return Ok(Template::render("404", context! {}));
+    }
+    let form = sqlx::query_as!(WebForm,
+        "SELECT * FROM forms WHERE id = ? AND published = true AND live_results = true AND tenant_id = ?",
+        id,
+        tenant.id
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(form.map(|form| Template::render("results_live", context! { form: form }))
+        .unwrap_or_else(|| Template::render("404", context! {})))
+}
+
+#[get("/f/<id>/results/live/ws")]
+pub async fn live_results_socket(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    flags: &State<FlagCache>,
+    events: &State<EventBus>,
+    ws: WebSocket,
+    mut end: Shutdown,
+    tenant: Tenant,
+    id: i64
+) -> Result<Channel<'static>, Status> {
+    if !live_results_enabled(db, config, flags, id).await? {
+        return Err(Status::NotFound);
+    }
+    let fields = sqlx::query_scalar!(
+        "SELECT fields FROM forms WHERE id = ? AND published = true AND live_results = true AND tenant_id = ?",
+        id,
+        tenant.id
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .ok_or(Status::NotFound)?;
+
+    let mut rx = events.subscribe();
+    let responses = sqlx::query_as!(FormResponse,
+        "SELECT * FROM responses WHERE form_id = ? AND is_test = false AND spam = false AND waitlisted = false
+         AND (payment_status IS NULL OR payment_status = 'paid') ORDER BY id",
+        id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let mut results = LiveResults {
+        tabulated: schema::parse(&fields).into_iter().filter(analytics::tabulable).map(|field| field.key).collect(),
+        ..LiveResults::default()
+    };
+    responses.iter().for_each(|response| results.record(response));
+    let last_seen = responses.last().map_or(0, |response| response.id);
+
+    Ok(ws.channel(move |mut stream| Box::pin(async move {
+        stream.send(results.message()).await?;
+
+        loop {
+            select! {
+                event = rx.recv() => match event {
+                    Ok(DomainEvent::ResponseSubmitted(response)) if response.form_id == id && !response.is_test && response.id > last_seen => {
+                        results.record(&response);
+                        stream.send(results.message()).await?;
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                message = stream.next() => match message {
+                    Some(Ok(_)) => continue,
+                    _ => break,
+                },
+                _ = &mut end => break,
+            }
+        }
+
+        Ok(())
+    })))
+}
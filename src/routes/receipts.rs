@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use lettre::message::Mailbox;
+use rocket::form::Form;
+use rocket::http::{Cookie, CookieJar, Status, private::PrivateCookies};
+use rocket::response::Redirect;
+use rocket::time::Duration;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::{AppConfig, api, crypto};
+use crate::access::ClientIp;
+use crate::db::notify;
+use crate::models::{Receipt, ReceiptLookup};
+use crate::tenant::Tenant;
+
+const RECEIPT_LINK_TTL: &str = "+30 minutes";
+
+const RECEIPT_COOKIE: &str = "receipt_email";
+
+const RECEIPT_SESSION_MINUTES: i64 = 30;
+
+/// How many links one address is sent, and how many lookups one IP may
+/// make, per `RECEIPT_REQUEST_WINDOW`.
+const RECEIPT_REQUESTS_PER_EMAIL: i64 = 3;
+const RECEIPT_REQUESTS_PER_IP: i64 = 10;
+const RECEIPT_REQUEST_WINDOW: &str = "-1 hour";
+
+pub fn routes() -> Vec<Route> {
+    routes![lookup_page, request_receipts, open_receipt_link, receipts, withdraw_response]
+}
+
+/// The address a receipt link proved, scoped to the tenant it was sent for.
+fn verified_email(cookies: &CookieJar<'_>, tenant: &Tenant) -> Option<String> {
+    cookies.get_private(RECEIPT_COOKIE)
+        .and_then(|cookie| {
+            let (tenant_id, email) = cookie.value().split_once(':')?;
+            (tenant_id.parse() == Ok(tenant.id)).then(|| email.to_string())
+        })
+}
+
+#[get("/receipts")]
+pub fn lookup_page() -> Template {
+    Template::render("receipts_lookup", context! {})
+}
+
+/// Always answers the same way, so the page cannot be used to learn whether
+/// an address has responded to anything. An address that has been sent
+/// enough links lately is quietly not sent another; an IP asking too often
+/// gets `429`.
+#[post("/receipts", data = "<lookup>")]
+pub async fn request_receipts(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    tenant: Tenant,
+    ip: ClientIp,
+    lookup: Form<ReceiptLookup>,
+) -> Result<Template, Status> {
+    let email = lookup.email.trim();
+    if email.parse::<Mailbox>().is_err() {
+        return Ok(Template::render("receipts_lookup", context! { email: email, error: "Enter a valid email address" }));
+    }
+
+    let ip = ip.0.map(|ip| ip.to_string());
+    let recent = sqlx::query!(
+        r#"SELECT
+               COALESCE(SUM(tenant_id = ?1 AND email = ?2), 0) AS "by_email!: i64",
+               COALESCE(SUM(ip = ?3), 0) AS "by_ip!: i64"
+           FROM receipt_requests WHERE created_at > datetime('now', ?4)"#,
+        tenant.id,
+        email,
+        ip,
+        RECEIPT_REQUEST_WINDOW
+    )
+    .fetch_one(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+    if recent.by_ip >= RECEIPT_REQUESTS_PER_IP {
+        return Err(Status::TooManyRequests);
+    }
+
+    sqlx::query!("INSERT INTO receipt_requests (tenant_id, email, ip) VALUES (?, ?, ?)", tenant.id, email, ip)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    if recent.by_email >= RECEIPT_REQUESTS_PER_EMAIL {
+        return Ok(Template::render("receipts_sent", context! { email: email }));
+    }
+
+    let token = Uuid::new_v4().to_simple().to_string();
+    let token_hash = api::hash_token(&token);
+    sqlx::query!(
+        "INSERT INTO receipt_links (tenant_id, email, token_hash, expires_at) VALUES (?, ?, ?, datetime('now', ?))",
+        tenant.id,
+        email,
+        token_hash,
+        RECEIPT_LINK_TTL
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let body = format!(
+        "Open this link to see the responses you submitted with this address. It expires in 30 minutes.\n\n{}\n",
+        tenant.url(config, uri!(open_receipt_link(token)))
+    );
+    sqlx::query!("INSERT INTO email_queue (recipient, subject, body) VALUES (?, ?, ?)", email, "Your form responses", body)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("receipts_sent", context! { email: email }))
+}
+
+/// Links work once; opening one starts a short session on this browser.
+#[get("/receipts/link/<token>")]
+pub async fn open_receipt_link(db: &State<SqlitePool>, cookies: &CookieJar<'_>, tenant: Tenant, token: &str) -> Result<Redirect, Status> {
+    let token_hash = api::hash_token(token);
+    let email = sqlx::query_scalar!(
+        "DELETE FROM receipt_links WHERE token_hash = ? AND tenant_id = ? AND expires_at > CURRENT_TIMESTAMP RETURNING email",
+        token_hash,
+        tenant.id
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let Some(email) = email else {
+        return Ok(Redirect::to(uri!(lookup_page)));
+    };
+
+    let mut cookie = Cookie::new(RECEIPT_COOKIE, format!("{}:{}", tenant.id, email));
+    cookie.set_max_age(Duration::minutes(RECEIPT_SESSION_MINUTES));
+    cookies.add_private(cookie);
+    Ok(Redirect::to(uri!(receipts)))
+}
+
+/// Responses are only found when their form verified the respondent's
+/// email, so the address on them is known to be theirs.
+#[get("/receipts/mine")]
+pub async fn receipts(db: &State<SqlitePool>, cookies: &CookieJar<'_>, tenant: Tenant) -> Result<Template, Status> {
+    let Some(email) = verified_email(cookies, &tenant) else {
+        return Ok(Template::render("receipts_lookup", context! { error: "That link has expired, request a new one" }));
+    };
+
+    let rows = sqlx::query!(
+        r#"SELECT r.id AS "response_id!: i64", f.id AS "form_id!: i64", f.title AS form_title, r.answers, r.created_at
+           FROM responses r JOIN forms f ON f.id = r.form_id
+           WHERE r.respondent_email = ? AND r.is_test = false AND r.tenant_id = ?
+           ORDER BY r.id DESC"#,
+        email,
+        tenant.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let receipts: Vec<Receipt> = rows.into_iter()
+        .map(|row| Receipt {
+            response_id: row.response_id,
+            form_id: row.form_id,
+            form_title: row.form_title,
+            answers: serde_json::from_str(&crypto::reveal(&row.answers)).unwrap_or_else(|_| HashMap::new()),
+            created_at: row.created_at,
+        })
+        .collect();
+
+    Ok(Template::render("receipts", context! { email: email, receipts: receipts }))
+}
+
+/// Deletes the response outright and tells the form's author it was
+/// withdrawn.
+#[post("/receipts/mine/<response_id>/withdraw")]
+pub async fn withdraw_response(db: &State<SqlitePool>, cookies: &CookieJar<'_>, tenant: Tenant, response_id: i64) -> Result<Redirect, Status> {
+    let email = verified_email(cookies, &tenant).ok_or(Status::Unauthorized)?;
+
+    let withdrawn = sqlx::query!(
+        r#"DELETE FROM responses WHERE id = ?1 AND respondent_email = ?2 AND tenant_id = ?3
+           RETURNING form_id AS "form_id!: i64""#,
+        response_id,
+        email,
+        tenant.id
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .ok_or(Status::NotFound)?;
+
+    let form = sqlx::query!("SELECT title, author_id FROM forms WHERE id = ?", withdrawn.form_id)
+        .fetch_one(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let message = format!("A respondent withdrew their response to \"{}\"", form.title);
+    let link = uri!(super::responses::form_responses(withdrawn.form_id, _)).to_string();
+    notify(db, form.author_id, "withdrawal", &message, &link, Some(withdrawn.form_id)).await?;
+
+    Ok(Redirect::to(uri!(receipts)))
+}
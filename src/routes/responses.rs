@@ -0,0 +1,1007 @@
+use std::collections::HashMap;
+
+use rocket::Shutdown;
+use rocket::form::Form;
+use rocket::http::{RawStr, Status};
+use rocket::response::Redirect;
+use rocket::futures::StreamExt;
+use rocket::response::stream::{EventStream, Event, TextStream};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::{AppConfig, consent, crypto, export, import, outbox, revisions, schema, slots};
+use crate::db::{FilteredResponses, ReadPool, answers_hash, filtered_responses, notify, record_response_event, record_response_events, response_tags};
+use crate::events::{DomainEvent, EventBus};
+use crate::flags::FlagCache;
+use crate::guards::{AuthenticatedUser, IDEMPOTENCY_KEY_FIELD, IdempotencyKey};
+use crate::localtime::TimePreferences;
+use crate::models::{AssignmentUpdate, BulkSelection, BulkTagUpdate, ClosedReason, CsvImport, FormResponse, MergeRequest, NewComment, NewSavedFilter, ResponseComment, ResponseEvent, ResponseEventKind, ResponseFilter, ResponseStatus, SavedFilter, StatusUpdate, TagUpdate, WebForm};
+use crate::outbox::OutboxEvent;
+use crate::pipeline::{Pipeline, Rejection, Services, Source, Submission};
+use crate::repository::{FormRepository, ResponseRepository};
+use crate::write_buffer::WriteBuffer;
+
+/// Exported events are written in chunks as the NDJSON export streams, so the
+/// ids it has to hold on to stay bounded too.
+const EXPORT_EVENT_CHUNK: usize = 1000;
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        form_responses, export_responses, export_responses_ndjson, export_consent_log, export_dictionary, import_responses, merge_preview, merge_responses, delete_responses,
+        tag_responses, update_response_status, promote_responses, mark_not_spam, assign_responses, new_response, create_response, response_detail, add_response_comment,
+        add_response_tag, remove_response_tag, save_response_filter, apply_saved_filter, delete_saved_filter,
+        response_stream, purge_test_responses
+    ]
+}
+
+fn mentioned_usernames(body: &str) -> Vec<&str> {
+    body.split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|name| name.trim_end_matches(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')))
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn localize_response(time: &TimePreferences, response: &mut FormResponse) {
+    time.localize(&mut response.created_at);
+    time.localize(&mut response.updated_at);
+}
+
+#[get("/form/<id>/responses?<filter..>")]
+pub async fn form_responses(
+    db: &State<SqlitePool>,
+    reads: &State<ReadPool>,
+    time: TimePreferences,
+    user: AuthenticatedUser,
+    id: i64,
+    filter: Option<ResponseFilter>
+) -> Result<Template, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    let Some(form) = form else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let filter = filter.unwrap_or_default();
+    let status = filter.status.map(ResponseStatus::as_str);
+    let mut responses = filtered_responses(&reads.0, form.id, user.0, &filter).await?;
+    responses.iter_mut().for_each(|response| localize_response(&time, response));
+    let tags = response_tags(&reads.0, form.id).await?;
+
+    let mut form_tags: Vec<&String> = tags.values().flatten().collect();
+    form_tags.sort();
+    form_tags.dedup();
+
+    let saved_filters = sqlx::query_as!(SavedFilter,
+        "SELECT * FROM saved_filters WHERE user_id = ? AND form_id = ? ORDER BY name",
+        user.0,
+        form.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let status_counts: HashMap<String, i64> = sqlx::query!(
+        "SELECT status, COUNT(*) AS \"count!: i64\" FROM responses WHERE form_id = ? GROUP BY status",
+        form.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .into_iter()
+    .map(|row| (row.status, row.count))
+    .collect();
+
+    let test_count = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM responses WHERE form_id = ? AND is_test = true",
+        form.id
+    )
+    .fetch_one(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let duplicate_count = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM responses WHERE form_id = ? AND duplicate_of IS NOT NULL",
+        form.id
+    )
+    .fetch_one(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let spam_count = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM responses WHERE form_id = ? AND spam = true",
+        form.id
+    )
+    .fetch_one(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("responses", context! {
+        form: form,
+        responses: responses,
+        status: status,
+        mine: filter.mine,
+        duplicates: filter.duplicates,
+        tag: filter.tag,
+        since: filter.since,
+        until: filter.until,
+        payment: filter.payment,
+        spam: filter.spam,
+        tags: tags,
+        form_tags: form_tags,
+        saved_filters: saved_filters,
+        status_counts: status_counts,
+        test_count: test_count,
+        duplicate_count: duplicate_count,
+        spam_count: spam_count
+    }))
+}
+
+#[get("/form/<id>/responses/export.csv?<filter..>")]
+pub async fn export_responses(
+    db: &State<SqlitePool>,
+    reads: &State<ReadPool>,
+    time: TimePreferences,
+    user: AuthenticatedUser,
+    id: i64,
+    filter: Option<ResponseFilter>
+) -> Result<export::Csv, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?.ok_or(Status::NotFound)?;
+
+    let filter = filter.unwrap_or_default();
+    let mut responses = filtered_responses(&reads.0, form.id, user.0, &filter).await?;
+    let tags = response_tags(&reads.0, form.id).await?;
+    let fields = schema::parse(&form.fields);
+
+    let ids: Vec<i64> = responses.iter().map(|response| response.id).collect();
+    let ids = serde_json::to_string(&ids).map_err(|_| Status::InternalServerError)?;
+    record_response_events(db, form.id, user.0, &ids, ResponseEventKind::Exported, "csv").await?;
+    responses.iter_mut().for_each(|response| localize_response(&time, response));
+
+    Ok(export::Csv {
+        filename: format!("form-{}-responses.csv", form.id),
+        body: export::responses_csv(&fields, &responses, &tags),
+    })
+}
+
+/// Every consent given on the form with the wording agreed to, for audits.
+/// Records outlive their responses, whose ids are then left blank.
+#[get("/form/<id>/consents.csv")]
+pub async fn export_consent_log(
+    db: &State<SqlitePool>,
+    reads: &State<ReadPool>,
+    user: AuthenticatedUser,
+    id: i64
+) -> Result<export::Csv, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?.ok_or(Status::NotFound)?;
+
+    let records = consent::log(&reads.0, form.id).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(export::Csv {
+        filename: format!("form-{}-consents.csv", form.id),
+        body: export::consent_log_csv(&records),
+    })
+}
+
+/// A codebook of the form's current fields, to read alongside the
+/// responses export.
+#[get("/form/<id>/dictionary.csv")]
+pub async fn export_dictionary(
+    db: &State<SqlitePool>,
+    reads: &State<ReadPool>,
+    time: TimePreferences,
+    user: AuthenticatedUser,
+    id: i64
+) -> Result<export::Csv, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?.ok_or(Status::NotFound)?;
+
+    let fields = schema::parse(&form.fields);
+    let history = revisions::history(&reads.0, form.id).await.map_err(|_| Status::InternalServerError)?;
+    let mut introductions = revisions::introductions(&history, &fields, &form.created_at);
+    introductions.values_mut().for_each(|introduction| time.localize(&mut introduction.at));
+
+    Ok(export::Csv {
+        filename: format!("form-{}-dictionary.csv", form.id),
+        body: export::dictionary_csv(&fields, &introductions),
+    })
+}
+
+async fn record_exported(db: &SqlitePool, form_id: i64, user_id: i64, exported: &mut Vec<i64>) {
+    if exported.is_empty() {
+        return;
+    }
+    let ids = serde_json::to_string(&exported).unwrap_or_default();
+    if record_response_events(db, form_id, user_id, &ids, ResponseEventKind::Exported, "ndjson").await.is_err() {
+        warn!("Failed to record the NDJSON export of {} responses to form {}", exported.len(), form_id);
+    }
+    exported.clear();
+}
+
+/// Streams straight from the database cursor, so memory stays flat however
+/// many responses the form has. Tags are left out to keep it that way. The
+/// filter is the one the response list and CSV export apply.
+#[get("/form/<id>/responses/export.ndjson?<filter..>")]
+pub async fn export_responses_ndjson(
+    db: &State<SqlitePool>,
+    reads: &State<ReadPool>,
+    user: AuthenticatedUser,
+    id: i64,
+    filter: Option<ResponseFilter>
+) -> Result<export::Ndjson<TextStream![String]>, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?.ok_or(Status::NotFound)?;
+
+    let db = db.inner().clone();
+    let reads = reads.0.clone();
+    let filter = filter.unwrap_or_default();
+    let form_id = form.id;
+    let user_id = user.0;
+
+    Ok(export::Ndjson {
+        filename: format!("form-{}-responses.ndjson", form_id),
+        body: TextStream! {
+            let query = FilteredResponses::new(form_id, user_id, &filter);
+            let mut rows = query.rows(&reads);
+
+            let mut exported = Vec::with_capacity(EXPORT_EVENT_CHUNK);
+            while let Some(row) = rows.next().await {
+                match row {
+                    Ok(mut response) => {
+                        exported.push(response.id);
+                        if exported.len() == EXPORT_EVENT_CHUNK {
+                            record_exported(&db, form_id, user_id, &mut exported).await;
+                        }
+                        response.answers = crypto::reveal(&response.answers);
+                        yield export::response_line(&response);
+                    }
+                    Err(e) => {
+                        // Headers are already sent, so a truncated file is all the client will see.
+                        error!("NDJSON export of form {} stopped early: {}", form_id, e);
+                        break;
+                    }
+                }
+            }
+            record_exported(&db, form_id, user_id, &mut exported).await;
+        },
+    })
+}
+
+#[post("/form/<id>/responses/import", data = "<upload>")]
+pub async fn import_responses(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    upload: Form<CsvImport>
+) -> Result<Template, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    let Some(form) = form else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let fields = schema::parse(&form.fields);
+    let csv = match import::responses_from_csv(&fields, &upload.file) {
+        Ok(csv) => csv,
+        Err(error) => {
+            return Ok(Template::render("responses_import", context! {
+                form: form,
+                error: error,
+                imported: 0,
+                row_errors: Vec::<import::RowError>::new(),
+                unmapped: Vec::<String>::new()
+            }));
+        }
+    };
+
+    let mut imported = 0;
+    let mut row_errors = Vec::new();
+    let mut tx = db.begin().await.map_err(|_| Status::InternalServerError)?;
+    for (row, answers) in csv.rows {
+        let errors = schema::validate(&fields, &answers);
+        if !errors.is_empty() {
+            row_errors.push(import::RowError { row, errors });
+            continue;
+        }
+
+        let stored = crypto::encrypt_answers(&fields, &answers).map_err(|e| {
+            error!("Failed to encrypt imported answers for form {}: {}", form.id, e);
+            Status::InternalServerError
+        })?;
+        let stored = serde_json::to_string(&stored).map_err(|_| Status::InternalServerError)?;
+        let answers_hash = answers_hash(&answers)?;
+
+        sqlx::query!(
+            "INSERT INTO responses (form_id, answers, answers_hash, updated_at, tenant_id) VALUES (?, ?, ?, CURRENT_TIMESTAMP, ?)",
+            form.id,
+            stored,
+            answers_hash,
+            form.tenant_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+        imported += 1;
+    }
+    tx.commit().await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("responses_import", context! {
+        form: form,
+        error: None::<String>,
+        imported: imported,
+        row_errors: row_errors,
+        unmapped: csv.unmapped
+    }))
+}
+
+#[get("/form/<id>/merge?<source>")]
+pub async fn merge_preview(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    source: Option<i64>
+) -> Result<Template, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    let Some(form) = form else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let forms = sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE author_id = ? AND id != ? ORDER BY title", user.0, form.id)
+        .fetch_all(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let source = forms.iter().find(|candidate| Some(candidate.id) == source);
+    let mapping = source.map(|source| import::field_mapping(&schema::parse(&source.fields), &schema::parse(&form.fields)));
+    let response_count = match source {
+        Some(source) => sqlx::query_scalar!("SELECT COUNT(*) FROM responses WHERE form_id = ?", source.id)
+            .fetch_one(db.inner())
+            .await
+            .map_err(|_| Status::InternalServerError)?,
+        None => 0,
+    };
+
+    Ok(Template::render("form_merge", context! {
+        form: &form,
+        forms: &forms,
+        source: source,
+        mapping: mapping,
+        response_count: response_count
+    }))
+}
+
+#[post("/form/<id>/merge", data = "<merge>")]
+pub async fn merge_responses(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    merge: Form<MergeRequest>
+) -> Result<Redirect, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?.ok_or(Status::NotFound)?;
+    let source = db.owned_form(merge.source, user.0)
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .filter(|source| source.id != form.id)
+        .ok_or(Status::NotFound)?;
+
+    let fields = schema::parse(&form.fields);
+    let mapping = import::field_mapping(&schema::parse(&source.fields), &fields);
+    let responses = sqlx::query_as!(FormResponse, "SELECT * FROM responses WHERE form_id = ? ORDER BY id", source.id)
+        .fetch_all(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let mut tx = db.begin().await.map_err(|_| Status::InternalServerError)?;
+    for response in responses {
+        let answers: HashMap<String, String> = crypto::decrypt_answers(&response.answers)
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let to = if mapping.is_empty() {
+                    Some(key)
+                } else {
+                    mapping.iter().find(|field| field.from == key)?.to.clone()
+                };
+                Some((to?, value))
+            })
+            .collect();
+
+        let stored = crypto::encrypt_answers(&fields, &answers).map_err(|e| {
+            error!("Failed to encrypt merged answers for form {}: {}", form.id, e);
+            Status::InternalServerError
+        })?;
+        let stored = serde_json::to_string(&stored).map_err(|_| Status::InternalServerError)?;
+        let answers_hash = answers_hash(&answers)?;
+
+        sqlx::query!(
+            "INSERT INTO responses (form_id, answers, is_test, created_at, device, status, respondent_email, answers_hash, updated_at, tenant_id)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, ?)",
+            form.id,
+            stored,
+            response.is_test,
+            response.created_at,
+            response.device,
+            response.status,
+            response.respondent_email,
+            answers_hash,
+            form.tenant_id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    }
+    tx.commit().await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_responses(form.id, _))))
+}
+
+#[post("/form/<id>/responses/delete", data = "<update>")]
+pub async fn delete_responses(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    update: Form<BulkSelection>
+) -> Result<Redirect, Status> {
+    db.delete_responses(id, user.0, &update.ids).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_responses(id, _))))
+}
+
+#[post("/form/<id>/responses/tags", data = "<update>")]
+pub async fn tag_responses(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    update: Form<BulkTagUpdate>
+) -> Result<Redirect, Status> {
+    let ids = serde_json::to_string(&update.ids).map_err(|_| Status::InternalServerError)?;
+    let tag = update.tag.trim();
+
+    sqlx::query!(
+        "INSERT OR IGNORE INTO response_tags (response_id, tag)
+         SELECT id, ? FROM responses
+         WHERE id IN (SELECT value FROM json_each(?))
+         AND form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+        tag,
+        ids,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_responses(id, _))))
+}
+
+#[post("/form/<id>/responses/status", data = "<update>")]
+pub async fn update_response_status(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    update: Form<StatusUpdate>
+) -> Result<Redirect, Status> {
+    let ids = serde_json::to_string(&update.ids).map_err(|_| Status::InternalServerError)?;
+    let status = update.status.as_str();
+
+    db.set_status(id, user.0, &update.ids, update.status).await.map_err(|_| Status::InternalServerError)?;
+    record_response_events(db, id, user.0, &ids, ResponseEventKind::StatusChanged, status).await?;
+
+    Ok(Redirect::to(uri!(form_responses(id, _))))
+}
+
+/// Moves waitlisted responses to accepted, past the form's cap if need be,
+/// and emails the respondents whose address the form verified.
+#[post("/form/<id>/responses/promote", data = "<selection>")]
+pub async fn promote_responses(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    selection: Form<BulkSelection>
+) -> Result<Redirect, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?.ok_or(Status::NotFound)?;
+
+    let ids = serde_json::to_string(&selection.ids).map_err(|_| Status::InternalServerError)?;
+    let promoted = sqlx::query!(
+        r#"UPDATE responses SET waitlisted = false
+           WHERE id IN (SELECT value FROM json_each(?)) AND form_id = ? AND waitlisted = true
+           RETURNING id AS "id!: i64", respondent_email"#,
+        ids,
+        form.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let promoted_ids: Vec<i64> = promoted.iter().map(|response| response.id).collect();
+    let promoted_ids = serde_json::to_string(&promoted_ids).map_err(|_| Status::InternalServerError)?;
+    record_response_events(db, form.id, user.0, &promoted_ids, ResponseEventKind::Promoted, "").await?;
+
+    let subject = format!("You're off the waitlist for \"{}\"", form.title);
+    let body = format!("Good news: your response to \"{}\" has been accepted.", form.title);
+    for email in promoted.into_iter().filter_map(|response| response.respondent_email) {
+        sqlx::query!("INSERT INTO email_queue (recipient, subject, body) VALUES (?, ?, ?)", email, subject, body)
+            .execute(db.inner())
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+    }
+
+    Ok(Redirect::to(uri!(form_responses(id, _))))
+}
+
+/// Releases responses the spam check caught. They are then announced as if
+/// they had just been submitted, unless still waiting on payment.
+#[post("/form/<id>/responses/not-spam", data = "<selection>")]
+pub async fn mark_not_spam(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    events: &State<EventBus>,
+    writes: &State<WriteBuffer>,
+    flags: &State<FlagCache>,
+    client: &State<reqwest::Client>,
+    pipeline: &State<Pipeline>,
+    user: AuthenticatedUser,
+    id: i64,
+    selection: Form<BulkSelection>
+) -> Result<Redirect, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?.ok_or(Status::NotFound)?;
+
+    let ids = serde_json::to_string(&selection.ids).map_err(|_| Status::InternalServerError)?;
+    let delivers = pipeline.delivers(db, config, form.id).await?;
+    let mut tx = db.begin().await.map_err(|_| Status::InternalServerError)?;
+    let released = sqlx::query_as!(FormResponse,
+        "UPDATE responses SET spam = false, updated_at = CURRENT_TIMESTAMP
+         WHERE id IN (SELECT value FROM json_each(?)) AND form_id = ? AND spam = true
+         RETURNING *",
+        ids,
+        form.id
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+    // Responses still waiting on payment are announced once paid.
+    let complete = |response: &FormResponse| response.payment_status.as_deref().map_or(true, |status| status == "paid");
+    for response in released.iter().filter(|response| complete(response) && !response.is_test && delivers) {
+        let event = OutboxEvent::ResponseSubmitted { form_id: form.id, response_id: response.id };
+        outbox::record(&mut *tx, event).await.map_err(|_| Status::InternalServerError)?;
+    }
+    tx.commit().await.map_err(|_| Status::InternalServerError)?;
+
+    let released_ids: Vec<i64> = released.iter().map(|response| response.id).collect();
+    let released_ids = serde_json::to_string(&released_ids).map_err(|_| Status::InternalServerError)?;
+    record_response_events(db, form.id, user.0, &released_ids, ResponseEventKind::NotSpam, "").await?;
+
+    let services = Services { db, config, client, events, writes, flags };
+    for mut response in released {
+        if !complete(&response) {
+            continue;
+        }
+        let answers = crypto::decrypt_answers(&response.answers);
+        response.answers = serde_json::to_string(&answers).map_err(|_| Status::InternalServerError)?;
+        pipeline.announce(&services, &form, &response, &answers).await?;
+    }
+
+    Ok(Redirect::to(uri!(form_responses(id, _))))
+}
+
+/// The assignee is notified of each response newly assigned to them, by
+/// email too if their preferences ask for it.
+#[post("/form/<id>/responses/assign", data = "<update>")]
+pub async fn assign_responses(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    update: Form<AssignmentUpdate>
+) -> Result<Redirect, Status> {
+    let assignee = if update.assignee.is_empty() {
+        None
+    } else {
+        let assignee = sqlx::query_scalar!(
+            "SELECT id FROM users WHERE username = ? AND tenant_id = (SELECT tenant_id FROM users WHERE id = ?)",
+            update.assignee,
+            user.0
+        )
+            .fetch_optional(db.inner())
+            .await
+            .map_err(|_| Status::InternalServerError)?
+            .ok_or(Status::NotFound)?;
+        Some(assignee)
+    };
+    let ids = serde_json::to_string(&update.ids).map_err(|_| Status::InternalServerError)?;
+
+    let assigned = sqlx::query_scalar!(
+        "UPDATE responses SET assigned_to = ?1
+         WHERE id IN (SELECT value FROM json_each(?2)) AND assigned_to IS NOT ?1
+         AND form_id IN (SELECT id FROM forms WHERE id = ?3 AND author_id = ?4)
+         RETURNING id",
+        assignee,
+        ids,
+        id,
+        user.0
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+    record_response_events(db, id, user.0, &ids, ResponseEventKind::Assigned, &update.assignee).await?;
+
+    if let Some(assignee) = assignee.filter(|assignee| *assignee != user.0) {
+        for response_id in assigned {
+            let link = uri!(response_detail(id, response_id)).to_string();
+            notify(db, assignee, "assignment", "A response was assigned to you", &link, Some(id)).await?;
+        }
+    }
+
+    Ok(Redirect::to(uri!(form_responses(id, _))))
+}
+
+async fn manual_entry_template(
+    db: &SqlitePool,
+    form: WebForm,
+    answers: HashMap<String, String>,
+    errors: Vec<schema::FieldError>,
+    message: Option<&str>,
+    entered: Option<i64>
+) -> Result<Template, Status> {
+    let fields = schema::parse(&form.fields);
+    let rules = schema::client_rules(&fields);
+    let slots = slots::available(db, form.id).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("response_new", context! {
+        form: form,
+        fields: schema::render(fields),
+        rules: rules,
+        answers: answers,
+        errors: errors,
+        message: message,
+        entered: entered,
+        slots: slots,
+        idempotency_field: IDEMPOTENCY_KEY_FIELD,
+        idempotency_key: Uuid::new_v4().to_string()
+    }))
+}
+
+/// Where the author keys in responses taken by phone or on paper, one after
+/// another; `entered` is the response just saved.
+#[get("/form/<id>/responses/new?<entered>")]
+pub async fn new_response(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64, entered: Option<i64>) -> Result<Template, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    let Some(form) = form else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    manual_entry_template(db, form, HashMap::new(), Vec::new(), None, entered).await
+}
+
+/// Goes through the same checks as a response submitted online, except the
+/// CAPTCHA and spam screening, and the form's opening dates. It counts as a
+/// real response rather than the author's test, marked as entered by them.
+#[post("/form/<id>/responses/new", data = "<answers>")]
+pub async fn create_response(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    events: &State<EventBus>,
+    writes: &State<WriteBuffer>,
+    flags: &State<FlagCache>,
+    client: &State<reqwest::Client>,
+    pipeline: &State<Pipeline>,
+    user: AuthenticatedUser,
+    id: i64,
+    answers: Form<HashMap<String, String>>
+) -> Result<Template, Status> {
+    let form = db.owned_form(id, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    let Some(form) = form else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let mut answers = answers.into_inner();
+    let idempotency_key = answers.remove(IDEMPOTENCY_KEY_FIELD).and_then(|key| IdempotencyKey::parse(&key));
+
+    let services = Services { db, config, client, events, writes, flags };
+    let mut submission = Submission::new(&form, Source::Manual(user.0), answers)
+        .idempotency_key(idempotency_key.as_deref());
+    let message = match pipeline.submit(&services, &mut submission).await {
+        // A fresh, empty form for the next one; resubmitting this page
+        // replays the same response rather than entering it twice.
+        Ok(response) => return manual_entry_template(db, form, HashMap::new(), Vec::new(), None, Some(response.id)).await,
+        Err(Rejection::Invalid(errors)) => {
+            let answers = submission.answers;
+            return manual_entry_template(db, form, answers, errors, None, None).await;
+        }
+        Err(Rejection::Refused(message)) => {
+            let answers = submission.answers;
+            return manual_entry_template(db, form, answers, Vec::new(), Some(&message), None).await;
+        }
+        Err(Rejection::Closed(ClosedReason::Full)) => "The form is not taking any more responses",
+        Err(Rejection::Closed(_)) => "The form is closed",
+        Err(Rejection::Duplicate) => "This response duplicates one already entered",
+        Err(Rejection::SlotTaken) => "The chosen slot is already full",
+        Err(Rejection::Unavailable) => "Responses that take a payment cannot be entered by hand",
+        Err(Rejection::Bot | Rejection::Challenge) => "The response was turned away",
+        Err(Rejection::Error(status)) => return Err(status),
+    };
+
+    let answers = submission.answers;
+    manual_entry_template(db, form, answers, Vec::new(), Some(message), None).await
+}
+
+#[get("/form/<id>/responses/<response_id>", rank = 2)]
+pub async fn response_detail(
+    db: &State<SqlitePool>,
+    time: TimePreferences,
+    user: AuthenticatedUser,
+    id: i64,
+    response_id: i64
+) -> Result<Template, Status> {
+    let response = db.owned_response(response_id, id, user.0).await.map_err(|_| Status::InternalServerError)?;
+    let Some(mut response) = response else {
+        return Ok(Template::render("404", context! {}));
+    };
+    response.answers = crypto::reveal(&response.answers);
+    localize_response(&time, &mut response);
+    let archived = revisions::archived_answers(db, response.id).await.map_err(|_| Status::InternalServerError)?;
+
+    let mut comments = sqlx::query_as!(ResponseComment,
+        "SELECT c.id, c.response_id, c.parent_id, c.author_id, u.username AS author, c.body, c.created_at
+         FROM response_comments c JOIN users u ON u.id = c.author_id
+         WHERE c.response_id = ? ORDER BY c.id",
+        response.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let tags = sqlx::query_scalar!("SELECT tag FROM response_tags WHERE response_id = ? ORDER BY tag", response.id)
+        .fetch_all(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let mut timeline = sqlx::query_as!(ResponseEvent,
+        "SELECT e.id, e.kind, e.detail, u.username AS actor, e.created_at
+         FROM response_events e LEFT JOIN users u ON u.id = e.actor_id
+         WHERE e.response_id = ? ORDER BY e.created_at, e.id",
+        response.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let entered_by = match response.entered_by {
+        Some(user_id) => sqlx::query_scalar!("SELECT username FROM users WHERE id = ?", user_id)
+            .fetch_optional(db.inner())
+            .await
+            .map_err(|_| Status::InternalServerError)?,
+        None => None,
+    };
+
+    comments.iter_mut().for_each(|comment| time.localize(&mut comment.created_at));
+    timeline.iter_mut().for_each(|event| time.localize(&mut event.created_at));
+
+    Ok(Template::render("response_detail", context! {
+        response: response,
+        entered_by: entered_by,
+        archived: archived,
+        comments: comments,
+        tags: tags,
+        timeline: timeline
+    }))
+}
+
+#[post("/form/<id>/responses/<response_id>/comments", data = "<comment>")]
+pub async fn add_response_comment(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    response_id: i64,
+    comment: Form<NewComment>
+) -> Result<Redirect, Status> {
+    let added = sqlx::query!(
+        "INSERT INTO response_comments (response_id, parent_id, author_id, body)
+         SELECT r.id, ?1, ?2, ?3 FROM responses r JOIN forms f ON f.id = r.form_id
+         WHERE r.id = ?4 AND f.id = ?5 AND f.author_id = ?2
+         AND (?1 IS NULL OR EXISTS (SELECT 1 FROM response_comments WHERE id = ?1 AND response_id = r.id))",
+        comment.parent_id,
+        user.0,
+        comment.body,
+        response_id,
+        id
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .rows_affected() > 0;
+
+    if added {
+        record_response_event(db, response_id, Some(user.0), ResponseEventKind::Commented, "").await?;
+    }
+
+    let mentions = mentioned_usernames(&comment.body);
+    if added && !mentions.is_empty() {
+        let mentions = serde_json::to_string(&mentions).map_err(|_| Status::InternalServerError)?;
+        let mentioned = sqlx::query_scalar!(
+            "SELECT id FROM users WHERE username IN (SELECT value FROM json_each(?1)) AND id != ?2
+             AND tenant_id = (SELECT tenant_id FROM users WHERE id = ?2)",
+            mentions,
+            user.0
+        )
+        .fetch_all(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+        let link = uri!(response_detail(id, response_id)).to_string();
+        for user_id in mentioned {
+            notify(db, user_id, "mention", "You were mentioned in a comment", &link, Some(id)).await?;
+        }
+    }
+
+    Ok(Redirect::to(uri!(response_detail(id, response_id))))
+}
+
+#[post("/form/<id>/responses/<response_id>/tags", data = "<update>")]
+pub async fn add_response_tag(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    response_id: i64,
+    update: Form<TagUpdate>
+) -> Result<Redirect, Status> {
+    let tag = update.tag.trim();
+    sqlx::query!(
+        "INSERT OR IGNORE INTO response_tags (response_id, tag)
+         SELECT r.id, ? FROM responses r JOIN forms f ON f.id = r.form_id
+         WHERE r.id = ? AND f.id = ? AND f.author_id = ?",
+        tag,
+        response_id,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(response_detail(id, response_id))))
+}
+
+#[post("/form/<id>/responses/<response_id>/tags/remove", data = "<update>")]
+pub async fn remove_response_tag(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    response_id: i64,
+    update: Form<TagUpdate>
+) -> Result<Redirect, Status> {
+    sqlx::query!(
+        "DELETE FROM response_tags WHERE tag = ? AND response_id IN (
+             SELECT r.id FROM responses r JOIN forms f ON f.id = r.form_id
+             WHERE r.id = ? AND f.id = ? AND f.author_id = ?
+         )",
+        update.tag,
+        response_id,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(response_detail(id, response_id))))
+}
+
+#[post("/form/<id>/responses/filters", data = "<filter>")]
+pub async fn save_response_filter(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    filter: Form<NewSavedFilter>
+) -> Result<Redirect, Status> {
+    let status = filter.status.map(ResponseStatus::as_str);
+    let tag = filter.tag.as_deref().map(str::trim).filter(|tag| !tag.is_empty());
+    let since = filter.since.as_deref().filter(|since| !since.is_empty());
+    let until = filter.until.as_deref().filter(|until| !until.is_empty());
+
+    sqlx::query!(
+        "INSERT INTO saved_filters (user_id, form_id, name, status, tag, since, until)
+         SELECT ?, id, ?, ?, ?, ?, ? FROM forms WHERE id = ? AND author_id = ?
+         ON CONFLICT (user_id, form_id, name) DO UPDATE
+         SET status = excluded.status, tag = excluded.tag, since = excluded.since, until = excluded.until",
+        user.0,
+        filter.name,
+        status,
+        tag,
+        since,
+        until,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_responses(id, _))))
+}
+
+#[get("/form/<id>/responses/filters/<filter_id>")]
+pub async fn apply_saved_filter(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    filter_id: i64
+) -> Result<Redirect, Status> {
+    let saved = sqlx::query_as!(SavedFilter,
+        "SELECT * FROM saved_filters WHERE id = ? AND user_id = ? AND form_id = ?",
+        filter_id,
+        user.0,
+        id
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .ok_or(Status::NotFound)?;
+
+    let query: Vec<String> = [("status", saved.status), ("tag", saved.tag), ("since", saved.since), ("until", saved.until)]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| format!("{}={}", key, RawStr::new(&value).percent_encode())))
+        .collect();
+
+    Ok(Redirect::to(format!("{}?{}", uri!(form_responses(id, _)), query.join("&"))))
+}
+
+#[post("/form/<id>/responses/filters/<filter_id>/delete")]
+pub async fn delete_saved_filter(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    filter_id: i64
+) -> Result<Redirect, Status> {
+    sqlx::query!("DELETE FROM saved_filters WHERE id = ? AND user_id = ? AND form_id = ?", filter_id, user.0, id)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_responses(id, _))))
+}
+
+#[get("/form/<id>/responses/stream")]
+pub async fn response_stream(
+    db: &State<SqlitePool>,
+    events: &State<EventBus>,
+    user: AuthenticatedUser,
+    mut end: Shutdown,
+    id: i64
+) -> Result<EventStream![], Status> {
+    sqlx::query_scalar!("SELECT id FROM forms WHERE id = ? AND author_id = ?", id, user.0)
+        .fetch_optional(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .ok_or(Status::NotFound)?;
+
+    let mut rx = events.subscribe();
+    Ok(EventStream! {
+        loop {
+            let event = select! {
+                event = rx.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut end => break,
+            };
+
+            if let DomainEvent::ResponseSubmitted(response) = event {
+                if response.form_id == id {
+                    yield Event::json(&response);
+                }
+            }
+        }
+    })
+}
+
+#[post("/form/<id>/responses/purge-test")]
+pub async fn purge_test_responses(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64) -> Result<Redirect, Status> {
+    db.purge_test_responses(id, user.0).await.map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_responses(id, _))))
+}
@@ -0,0 +1,407 @@
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::response::Redirect;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::{api, integrations};
+use crate::db::EMAILED_BY_DEFAULT;
+use crate::guards::{AuthenticatedUser, NotImpersonating};
+use crate::localtime::TimePreferences;
+use crate::integrations::{FormIntegration, IntegrationDelivery};
+use crate::models::{DigestFrequency, NewApiToken, NewIntegration, NewServiceAccount, NewSheetSync, Notification, NotificationPreference, NotificationSettings, SheetSync, WebForm};
+use crate::tenant::Tenant;
+
+const NOTIFICATION_KINDS: [&str; 7] = [
+    "submission", "mention", "assignment", "approval_request", "webhook_failure", "withdrawal", "upload_infected"
+];
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        notifications, mark_notifications_read, notification_settings, update_notification_settings,
+        mute_form, unmute_form, api_tokens, create_service_account, delete_service_account, create_api_token,
+        delete_api_token, form_integrations, create_sheet_sync, delete_sheet_sync, create_integration,
+        delete_integration
+    ]
+}
+
+#[get("/notifications")]
+pub async fn notifications(db: &State<SqlitePool>, time: TimePreferences, user: AuthenticatedUser) -> Result<Template, Status> {
+    let mut notifications = sqlx::query_as!(Notification,
+        "SELECT * FROM notifications WHERE user_id = ? ORDER BY id DESC LIMIT 100",
+        user.0
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    notifications.iter_mut().for_each(|notification| time.localize(&mut notification.created_at));
+    let unread = notifications.iter().filter(|notification| !notification.is_read).count();
+
+    Ok(Template::render("notifications", context! { notifications: notifications, unread: unread }))
+}
+
+#[post("/notifications/read-all")]
+pub async fn mark_notifications_read(db: &State<SqlitePool>, user: AuthenticatedUser) -> Result<Redirect, Status> {
+    sqlx::query!("UPDATE notifications SET is_read = true WHERE user_id = ? AND is_read = false", user.0)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(notifications)))
+}
+
+#[get("/settings/notifications")]
+pub async fn notification_settings(db: &State<SqlitePool>, user: AuthenticatedUser) -> Result<Template, Status> {
+    let account = sqlx::query!("SELECT email, digest FROM users WHERE id = ?", user.0)
+        .fetch_one(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let stored = sqlx::query_as!(NotificationPreference,
+        "SELECT kind, in_app, email FROM notification_preferences WHERE user_id = ?",
+        user.0
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let preferences: Vec<NotificationPreference> = NOTIFICATION_KINDS.iter()
+        .map(|&kind| stored.iter()
+            .find(|preference| preference.kind == kind)
+            .map(|preference| NotificationPreference { kind: kind.to_string(), ..*preference })
+            .unwrap_or(NotificationPreference { kind: kind.to_string(), in_app: true, email: EMAILED_BY_DEFAULT.contains(&kind) }))
+        .collect();
+
+    let muted_forms = sqlx::query_as!(WebForm,
+        "SELECT f.* FROM forms f JOIN muted_forms m ON m.form_id = f.id WHERE m.user_id = ?",
+        user.0
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("notification_settings", context! {
+        email: account.email,
+        digest: account.digest,
+        preferences: preferences,
+        muted_forms: muted_forms
+    }))
+}
+
+#[post("/settings/notifications", data = "<settings>")]
+pub async fn update_notification_settings(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    _own: NotImpersonating,
+    settings: Form<NotificationSettings>
+) -> Result<Redirect, Status> {
+    let email = Some(settings.email.trim()).filter(|email| !email.is_empty());
+    let digest = settings.digest.map(DigestFrequency::as_str);
+    sqlx::query!("UPDATE users SET email = ?, digest = ? WHERE id = ?", email, digest, user.0)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    for (kind, choice) in &settings.preferences {
+        if !NOTIFICATION_KINDS.contains(&kind.as_str()) {
+            continue;
+        }
+
+        sqlx::query!(
+            "INSERT INTO notification_preferences (user_id, kind, in_app, email) VALUES (?, ?, ?, ?)
+             ON CONFLICT (user_id, kind) DO UPDATE SET in_app = excluded.in_app, email = excluded.email",
+            user.0,
+            kind,
+            choice.in_app,
+            choice.email
+        )
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    }
+
+    Ok(Redirect::to(uri!(notification_settings)))
+}
+
+#[post("/form/<id>/mute")]
+pub async fn mute_form(db: &State<SqlitePool>, user: AuthenticatedUser, tenant: Tenant, id: i64) -> Result<Redirect, Status> {
+    sqlx::query!(
+        "INSERT OR IGNORE INTO muted_forms (user_id, form_id) SELECT ?, id FROM forms WHERE id = ? AND tenant_id = ?",
+        user.0,
+        id,
+        tenant.id
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(notification_settings)))
+}
+
+#[post("/form/<id>/unmute")]
+pub async fn unmute_form(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64) -> Result<Redirect, Status> {
+    sqlx::query!("DELETE FROM muted_forms WHERE user_id = ? AND form_id = ?", user.0, id)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(notification_settings)))
+}
+
+#[get("/settings/tokens")]
+pub async fn api_tokens(db: &State<SqlitePool>, user: AuthenticatedUser) -> Result<Template, Status> {
+    let tokens = sqlx::query_as!(api::ApiToken, "SELECT * FROM api_tokens WHERE user_id = ? ORDER BY id", user.0)
+        .fetch_all(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let service_accounts = service_accounts(db, user.0).await?;
+
+    Ok(Template::render("api_tokens", context! {
+        tokens: tokens,
+        service_accounts: service_accounts,
+        new_token: None::<String>
+    }))
+}
+
+fn parse_form_ids(input: &str) -> Result<Option<String>, Status> {
+    let form_ids = input.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|form_id| !form_id.is_empty())
+        .map(|form_id| form_id.parse::<i64>().map_err(|_| Status::UnprocessableEntity))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if form_ids.is_empty() {
+        return Ok(None);
+    }
+    serde_json::to_string(&form_ids).map(Some).map_err(|_| Status::InternalServerError)
+}
+
+async fn service_accounts(db: &SqlitePool, user_id: i64) -> Result<Vec<api::ServiceAccount>, Status> {
+    sqlx::query_as!(api::ServiceAccount, "SELECT * FROM service_accounts WHERE user_id = ? ORDER BY id", user_id)
+        .fetch_all(db)
+        .await
+        .map_err(|_| Status::InternalServerError)
+}
+
+#[post("/settings/service-accounts", data = "<account>")]
+pub async fn create_service_account(
+    db: &State<SqlitePool>,
+    tenant: Tenant,
+    user: AuthenticatedUser,
+    _own: NotImpersonating,
+    account: Form<NewServiceAccount>
+) -> Result<Redirect, Status> {
+    let scopes = serde_json::to_string(&account.scopes).map_err(|_| Status::InternalServerError)?;
+    let form_ids = parse_form_ids(&account.form_ids)?;
+    let subject = account.subject.trim();
+
+    sqlx::query!(
+        "INSERT INTO service_accounts (user_id, tenant_id, name, subject, scopes, form_ids) VALUES (?, ?, ?, ?, ?, ?)",
+        user.0,
+        tenant.id,
+        account.name,
+        subject,
+        scopes,
+        form_ids
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::Conflict)?;
+
+    Ok(Redirect::to(uri!(api_tokens)))
+}
+
+#[post("/settings/service-accounts/<id>/delete")]
+pub async fn delete_service_account(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64) -> Result<Redirect, Status> {
+    sqlx::query!("DELETE FROM service_accounts WHERE id = ? AND user_id = ?", id, user.0)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(api_tokens)))
+}
+
+#[post("/settings/tokens", data = "<token>")]
+pub async fn create_api_token(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    _own: NotImpersonating,
+    token: Form<NewApiToken>
+) -> Result<Template, Status> {
+    let secret = format!("fs_{}{}", Uuid::new_v4().to_simple(), Uuid::new_v4().to_simple());
+    let token_hash = api::hash_token(&secret);
+    let scopes = serde_json::to_string(&token.scopes).map_err(|_| Status::InternalServerError)?;
+    let form_ids = parse_form_ids(&token.form_ids)?;
+
+    sqlx::query!(
+        "INSERT INTO api_tokens (user_id, name, token_hash, scopes, form_ids) VALUES (?, ?, ?, ?, ?)",
+        user.0,
+        token.name,
+        token_hash,
+        scopes,
+        form_ids
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let tokens = sqlx::query_as!(api::ApiToken, "SELECT * FROM api_tokens WHERE user_id = ? ORDER BY id", user.0)
+        .fetch_all(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let service_accounts = service_accounts(db, user.0).await?;
+
+    Ok(Template::render("api_tokens", context! {
+        tokens: tokens,
+        service_accounts: service_accounts,
+        new_token: Some(secret)
+    }))
+}
+
+#[post("/settings/tokens/<id>/delete")]
+pub async fn delete_api_token(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64) -> Result<Redirect, Status> {
+    sqlx::query!("DELETE FROM api_tokens WHERE id = ? AND user_id = ?", id, user.0)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(api_tokens)))
+}
+
+#[get("/form/<id>/integrations")]
+pub async fn form_integrations(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64) -> Result<Template, Status> {
+    let form = sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE id = ? AND author_id = ?", id, user.0)
+        .fetch_optional(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let Some(form) = form else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let integrations = sqlx::query_as!(FormIntegration, "SELECT * FROM form_integrations WHERE form_id = ? ORDER BY id", form.id)
+        .fetch_all(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    let deliveries = sqlx::query_as!(IntegrationDelivery,
+        "SELECT d.* FROM integration_deliveries d JOIN form_integrations i ON i.id = d.integration_id
+         WHERE i.form_id = ? ORDER BY d.id DESC LIMIT 50",
+        form.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let sheet_syncs = sqlx::query_as!(SheetSync, "SELECT * FROM sheet_syncs WHERE form_id = ? ORDER BY id", form.id)
+        .fetch_all(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Template::render("integrations", context! {
+        form: form,
+        integrations: integrations,
+        deliveries: deliveries,
+        sheet_syncs: sheet_syncs
+    }))
+}
+
+#[post("/form/<id>/integrations/sheets", data = "<sync>")]
+pub async fn create_sheet_sync(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    sync: Form<NewSheetSync>
+) -> Result<Redirect, Status> {
+    let columns: Vec<&str> = sync.columns.split(',')
+        .map(str::trim)
+        .filter(|column| !column.is_empty())
+        .collect();
+    let columns = serde_json::to_string(&columns).map_err(|_| Status::InternalServerError)?;
+
+    sqlx::query!(
+        "INSERT INTO sheet_syncs (form_id, spreadsheet_id, sheet_name, columns)
+         SELECT id, ?, ?, ? FROM forms WHERE id = ? AND author_id = ?",
+        sync.spreadsheet_id,
+        sync.sheet_name,
+        columns,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_integrations(id))))
+}
+
+#[post("/form/<id>/integrations/sheets/<sync_id>/delete")]
+pub async fn delete_sheet_sync(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    sync_id: i64
+) -> Result<Redirect, Status> {
+    sqlx::query!(
+        "DELETE FROM sheet_syncs WHERE id = ? AND form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+        sync_id,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_integrations(id))))
+}
+
+#[post("/form/<id>/integrations", data = "<integration>")]
+pub async fn create_integration(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    integration: Form<NewIntegration>
+) -> Result<Redirect, Status> {
+    let config = integrations::config(integration.kind, &integration.target, &integration.fields)
+        .map_err(|_| Status::UnprocessableEntity)?;
+    let kind = integration.kind.as_str();
+    let cadence = integration.cadence.as_str();
+
+    sqlx::query!(
+        "INSERT INTO form_integrations (form_id, kind, config, cadence)
+         SELECT id, ?, ?, ? FROM forms WHERE id = ? AND author_id = ?",
+        kind,
+        config,
+        cadence,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_integrations(id))))
+}
+
+#[post("/form/<id>/integrations/<integration_id>/delete")]
+pub async fn delete_integration(
+    db: &State<SqlitePool>,
+    user: AuthenticatedUser,
+    id: i64,
+    integration_id: i64
+) -> Result<Redirect, Status> {
+    sqlx::query!(
+        "DELETE FROM form_integrations WHERE id = ? AND form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+        integration_id,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(form_integrations(id))))
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::response::Redirect;
+use rocket::{Route, State};
+use rocket_dyn_templates::{Template, context};
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::{crypto, schema, slots};
+use crate::guards::AuthenticatedUser;
+use crate::models::{NewSlot, WebForm};
+use crate::schema::FieldKind;
+use crate::slots::Slot;
+
+pub fn routes() -> Vec<Route> {
+    routes![slot_schedule, create_slot, delete_slot]
+}
+
+#[derive(Debug, Serialize)]
+struct Booking {
+    response_id: i64,
+    answers: HashMap<String, String>,
+    created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ScheduledSlot {
+    #[serde(flatten)]
+    slot: Slot,
+    bookings: Vec<Booking>,
+}
+
+async fn owned_form(db: &SqlitePool, id: i64, author_id: i64) -> Result<Option<WebForm>, Status> {
+    sqlx::query_as!(WebForm, "SELECT * FROM forms WHERE id = ? AND author_id = ?", id, author_id)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| Status::InternalServerError)
+}
+
+/// Every slot of the form in time order with the responses booked into it.
+#[get("/form/<id>/slots")]
+pub async fn slot_schedule(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64) -> Result<Template, Status> {
+    let Some(form) = owned_form(db, id, user.0).await? else {
+        return Ok(Template::render("404", context! {}));
+    };
+
+    let slot_fields: Vec<String> = schema::parse(&form.fields).into_iter()
+        .filter(|field| field.kind == FieldKind::Slot)
+        .map(|field| field.key)
+        .collect();
+    let all = slots::all(db, form.id).await.map_err(|_| Status::InternalServerError)?;
+
+    let booked = sqlx::query!(
+        r#"SELECT s.id AS "slot_id!: i64", r.id AS "response_id!: i64", r.answers, r.created_at
+           FROM form_slots s JOIN response_slots rs ON rs.slot_id = s.id JOIN responses r ON r.id = rs.response_id
+           WHERE s.form_id = ? ORDER BY r.id"#,
+        form.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    let mut bookings: HashMap<i64, Vec<Booking>> = HashMap::new();
+    for row in booked {
+        bookings.entry(row.slot_id).or_default().push(Booking {
+            response_id: row.response_id,
+            answers: serde_json::from_str(&crypto::reveal(&row.answers)).unwrap_or_default(),
+            created_at: row.created_at,
+        });
+    }
+    let schedule: Vec<ScheduledSlot> = all.into_iter()
+        .map(|slot| ScheduledSlot { bookings: bookings.remove(&slot.id).unwrap_or_default(), slot })
+        .collect();
+
+    Ok(Template::render("form_slots", context! {
+        form: form,
+        slot_fields: slot_fields,
+        schedule: schedule
+    }))
+}
+
+#[post("/form/<id>/slots", data = "<slot>")]
+pub async fn create_slot(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64, slot: Form<NewSlot>) -> Result<Redirect, Status> {
+    let form = owned_form(db, id, user.0).await?.ok_or(Status::NotFound)?;
+    let is_slot_field = schema::parse(&form.fields).iter().any(|field| field.key == slot.field_key && field.kind == FieldKind::Slot);
+    if !is_slot_field || slot.starts_at.trim().is_empty() {
+        return Err(Status::UnprocessableEntity);
+    }
+
+    sqlx::query!(
+        "INSERT INTO form_slots (form_id, field_key, starts_at, ends_at, capacity) VALUES (?, ?, datetime(?), datetime(?), ?)",
+        form.id,
+        slot.field_key,
+        slot.starts_at,
+        slot.ends_at,
+        slot.capacity
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(slot_schedule(id))))
+}
+
+/// Slots with bookings are kept, so no respondent loses a confirmed time.
+#[post("/form/<id>/slots/<slot_id>/delete")]
+pub async fn delete_slot(db: &State<SqlitePool>, user: AuthenticatedUser, id: i64, slot_id: i64) -> Result<Redirect, Status> {
+    let deleted = sqlx::query!(
+        "DELETE FROM form_slots WHERE id = ? AND booked = 0 AND form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+        slot_id,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .rows_affected();
+
+    if deleted == 0 {
+        return Err(Status::Conflict);
+    }
+    Ok(Redirect::to(uri!(slot_schedule(id))))
+}
@@ -0,0 +1,127 @@
+use rocket::time::OffsetDateTime;
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+
+use crate::models::{FormResponse, WebForm};
+
+const ICS_PRODUCT: &str = "-//forms_system//RSVP//EN";
+
+const ICS_CONTENT_TYPE: &str = "text/calendar; method=PUBLISH; charset=UTF-8";
+
+/// The event an RSVP form collects responses for. A form with one of these
+/// is in RSVP mode.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RsvpEvent {
+    pub form_id: i64,
+    pub title: String,
+    /// UTC, as SQLite `datetime()` writes it.
+    pub starts_at: String,
+    pub ends_at: Option<String>,
+    pub location: Option<String>,
+    /// The choice field whose answers the headcount is grouped by.
+    pub choice_field: Option<String>,
+}
+
+pub async fn event(db: &SqlitePool, form_id: i64) -> Result<Option<RsvpEvent>, sqlx::Error> {
+    sqlx::query_as!(RsvpEvent, "SELECT * FROM form_rsvp WHERE form_id = ?", form_id)
+        .fetch_optional(db)
+        .await
+}
+
+/// `2024-06-10 18:30:00` to `20240610T183000Z`.
+fn ics_time(datetime: &str) -> String {
+    let mut time: String = datetime.chars().filter(|c| c.is_ascii_digit() || *c == ' ').collect();
+    time = time.replacen(' ', "T", 1);
+    time.push('Z');
+    time
+}
+
+fn ics_now() -> String {
+    let now = OffsetDateTime::now_utc();
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+fn ics_text(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+/// A single-event iCalendar file for the respondent's calendar.
+pub fn ics(event: &RsvpEvent, response_id: i64) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", ICS_PRODUCT),
+        "METHOD:PUBLISH".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:rsvp-{}-{}@forms_system", event.form_id, response_id),
+        format!("DTSTAMP:{}", ics_now()),
+        format!("DTSTART:{}", ics_time(&event.starts_at)),
+    ];
+    if let Some(ends_at) = &event.ends_at {
+        lines.push(format!("DTEND:{}", ics_time(ends_at)));
+    }
+    lines.push(format!("SUMMARY:{}", ics_text(&event.title)));
+    if let Some(location) = &event.location {
+        lines.push(format!("LOCATION:{}", ics_text(location)));
+    }
+    lines.extend(["END:VEVENT".to_string(), "END:VCALENDAR".to_string()]);
+
+    let mut ics = lines.join("\r\n");
+    ics.push_str("\r\n");
+    ics
+}
+
+/// Queues the confirmation email with its calendar attachment. Forms not in
+/// RSVP mode, waitlisted responses and respondents without a verified
+/// address get nothing: an address typed into an email field could be
+/// anyone's.
+pub async fn confirm(
+    db: &SqlitePool,
+    form: &WebForm,
+    response: &FormResponse,
+    respondent_email: Option<&str>
+) -> Result<(), sqlx::Error> {
+    if response.waitlisted {
+        return Ok(());
+    }
+    let Some(event) = event(db, form.id).await? else {
+        return Ok(());
+    };
+    let Some(email) = respondent_email else {
+        return Ok(());
+    };
+
+    let subject = format!("You're registered: {}", event.title);
+    let mut body = format!("Thanks for your RSVP to {}.\n\nWhen: {} UTC\n", event.title, event.starts_at);
+    if let Some(location) = &event.location {
+        body.push_str(&format!("Where: {}\n", location));
+    }
+    body.push_str("\nThe attached invitation adds it to your calendar.\n");
+    let attachment = ics(&event, response.id).into_bytes();
+
+    sqlx::query!(
+        "INSERT INTO email_queue (recipient, subject, body, attachment_name, attachment, attachment_type) VALUES (?, ?, ?, ?, ?, ?)",
+        email,
+        subject,
+        body,
+        "invite.ics",
+        attachment,
+        ICS_CONTENT_TYPE
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,511 @@
+use std::collections::{HashMap, HashSet};
+
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
+
+use crate::field_types::{self, FieldType, PrintLayout};
+
+/// A field's `type`. The built-in types are named so the rest of the app
+/// can match on them; any other name is looked up in the field type
+/// registry, and treated as text if nothing is registered for it.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum FieldKind {
+    #[default]
+    Text,
+    Textarea,
+    Email,
+    Number,
+    Choice,
+    Checkbox,
+    Date,
+    Consent,
+    Slot,
+    Payment,
+    Custom(String),
+}
+
+impl FieldKind {
+    pub fn name(&self) -> &str {
+        match self {
+            FieldKind::Text => "text",
+            FieldKind::Textarea => "textarea",
+            FieldKind::Email => "email",
+            FieldKind::Number => "number",
+            FieldKind::Choice => "choice",
+            FieldKind::Checkbox => "checkbox",
+            FieldKind::Date => "date",
+            FieldKind::Consent => "consent",
+            FieldKind::Slot => "slot",
+            FieldKind::Payment => "payment",
+            FieldKind::Custom(name) => name,
+        }
+    }
+}
+
+impl From<String> for FieldKind {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "text" => FieldKind::Text,
+            "textarea" => FieldKind::Textarea,
+            "email" => FieldKind::Email,
+            "number" => FieldKind::Number,
+            "choice" => FieldKind::Choice,
+            "checkbox" => FieldKind::Checkbox,
+            "date" => FieldKind::Date,
+            "consent" => FieldKind::Consent,
+            "slot" => FieldKind::Slot,
+            "payment" => FieldKind::Payment,
+            _ => FieldKind::Custom(name),
+        }
+    }
+}
+
+impl From<FieldKind> for String {
+    fn from(kind: FieldKind) -> Self {
+        match kind {
+            FieldKind::Custom(name) => name,
+            kind => kind.name().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FieldDef {
+    /// Generated when the field is first saved and never derived from the
+    /// label, so rewording a question keeps its answers, export column and
+    /// webhook payload key.
+    #[serde(default)]
+    pub key: String,
+    #[serde(default)]
+    pub label: String,
+    pub description: Option<String>,
+    #[serde(rename = "type", default)]
+    #[schemars(with = "String")]
+    pub kind: FieldKind,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub encrypted: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub pattern: Option<String>,
+    pub show_if: Option<Condition>,
+    pub legal_text: Option<String>,
+    /// For payment fields, in the currency's minor unit.
+    pub amount: Option<i64>,
+    /// A number field the amount is multiplied by, such as a ticket count.
+    pub amount_field: Option<String>,
+    /// Bounds on a respondent-chosen amount, in the currency's minor unit.
+    pub min_amount: Option<i64>,
+    pub max_amount: Option<i64>,
+    /// Suggested respondent-chosen amounts, in the currency's minor unit.
+    #[serde(default)]
+    pub presets: Vec<i64>,
+    /// Anything a custom field type needs that the fields above do not
+    /// cover.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub settings: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Condition {
+    pub field: String,
+    pub equals: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenderedField {
+    #[serde(flatten)]
+    pub field: FieldDef,
+    pub input_type: &'static str,
+    pub attributes: String,
+    pub description_html: Option<String>,
+    /// Extra values the field's type gives its template.
+    pub context: Value,
+}
+
+/// A field's key beside its label, for the editor to show.
+#[derive(Debug, Serialize)]
+pub struct FieldKey {
+    pub key: String,
+    pub label: String,
+}
+
+/// A field on the printed blank form.
+#[derive(Debug, Serialize)]
+pub struct PrintedField {
+    #[serde(flatten)]
+    pub field: FieldDef,
+    #[serde(flatten)]
+    pub layout: PrintLayout,
+    pub description_html: Option<String>,
+    /// When the field only applies to some respondents, which ones, since
+    /// paper cannot hide it from the rest.
+    pub condition: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldDef {
+    pub fn visible(&self, answers: &HashMap<String, String>) -> bool {
+        self.show_if.as_ref().map_or(true, |condition| {
+            answers.get(&condition.field).is_some_and(|value| value.trim() == condition.equals)
+        })
+    }
+
+    pub fn field_type(&self) -> &'static dyn FieldType {
+        field_types::get(&self.kind)
+    }
+
+    fn html_attributes(&self) -> String {
+        let field_type = self.field_type();
+        let mut attributes = vec![
+            format!("name=\"{}\"", escape_attribute(&self.key)),
+            format!("id=\"field-{}\"", escape_attribute(&self.key)),
+        ];
+        let input_type = field_type.input_type(self);
+        if !matches!(input_type, "textarea" | "select" | "payment") {
+            attributes.push(format!("type=\"{}\"", input_type));
+        }
+        if self.required && self.show_if.is_none() {
+            attributes.push("required".to_string());
+        }
+
+        attributes.extend(field_type.attributes(self));
+
+        if let Some(condition) = &self.show_if {
+            attributes.push(format!("data-show-if-field=\"{}\"", escape_attribute(&condition.field)));
+            attributes.push(format!("data-show-if-equals=\"{}\"", escape_attribute(&condition.equals)));
+            if self.required {
+                attributes.push("data-required".to_string());
+            }
+        }
+
+        attributes.join(" ")
+    }
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError { field: field.to_string(), message: message.into() }
+    }
+}
+
+pub fn parse(fields: &str) -> Vec<FieldDef> {
+    let mut fields: Vec<FieldDef> = serde_json::from_str(fields).unwrap_or_default();
+    for field in &mut fields {
+        field.description = field.description.as_deref().map(rich_text);
+    }
+    fields
+}
+
+pub fn keys(fields: &[FieldDef]) -> Vec<FieldKey> {
+    fields.iter()
+        .map(|field| FieldKey { key: field.key.clone(), label: field.label.clone() })
+        .collect()
+}
+
+/// A key for a new field that none of `taken` already has.
+pub fn new_field_key(taken: &HashSet<String>) -> String {
+    loop {
+        let key = format!("f_{}", &Uuid::new_v4().to_simple().to_string()[..8]);
+        if !taken.contains(&key) {
+            return key;
+        }
+    }
+}
+
+/// Gives every field saved without a key a new one. The schema is otherwise
+/// left exactly as written, and returned unchanged if it is not a list of
+/// fields.
+pub fn assign_keys(fields: &str) -> String {
+    let Ok(Value::Array(mut defs)) = serde_json::from_str::<Value>(fields) else {
+        return fields.to_string();
+    };
+
+    let mut taken: HashSet<String> = defs.iter()
+        .filter_map(|def| def.get("key")?.as_str())
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+        .collect();
+    let mut assigned = false;
+    for def in defs.iter_mut().filter_map(Value::as_object_mut) {
+        if def.get("key").and_then(Value::as_str).is_some_and(|key| !key.is_empty()) {
+            continue;
+        }
+        let key = new_field_key(&taken);
+        taken.insert(key.clone());
+        def.insert("key".to_string(), Value::String(key));
+        assigned = true;
+    }
+
+    if !assigned {
+        return fields.to_string();
+    }
+    serde_json::to_string(&defs).unwrap_or_else(|_| fields.to_string())
+}
+
+pub fn render(fields: Vec<FieldDef>) -> Vec<RenderedField> {
+    fields.into_iter()
+        .map(|field| RenderedField {
+            input_type: field.field_type().input_type(&field),
+            attributes: field.html_attributes(),
+            description_html: field.description.clone(),
+            context: field.field_type().render_context(&field),
+            field,
+        })
+        .collect()
+}
+
+/// Roughly how many lines of fields fit on one printed page.
+const PRINT_PAGE_LINES: usize = 30;
+
+/// The fields laid out for paper, a page at a time. Every conditional
+/// field is included, with the condition written out beside it.
+pub fn printable(fields: Vec<FieldDef>) -> Vec<Vec<PrintedField>> {
+    let labels: HashMap<String, String> = fields.iter()
+        .map(|field| (field.key.clone(), if field.label.is_empty() { field.key.clone() } else { field.label.clone() }))
+        .collect();
+
+    let mut pages: Vec<Vec<PrintedField>> = Vec::new();
+    let mut used = PRINT_PAGE_LINES;
+    for field in fields {
+        let Some(layout) = field.field_type().print(&field) else {
+            continue;
+        };
+        let height = layout.height() + 1;
+        if used + height > PRINT_PAGE_LINES {
+            pages.push(Vec::new());
+            used = 0;
+        }
+        used += height;
+
+        let condition = field.show_if.as_ref().map(|condition| {
+            let label = labels.get(&condition.field).map_or(condition.field.as_str(), String::as_str);
+            format!("Only answer if \"{}\" is \"{}\"", label, condition.equals)
+        });
+        if let Some(page) = pages.last_mut() {
+            page.push(PrintedField {
+                description_html: field.description.clone(),
+                layout,
+                condition,
+                field,
+            });
+        }
+    }
+    pages
+}
+
+pub fn client_rules(fields: &[FieldDef]) -> String {
+    serde_json::to_string(fields).unwrap_or_else(|_| "[]".to_string()).replace("</", "<\\/")
+}
+
+pub fn rich_text(html: &str) -> String {
+    ammonia::Builder::default()
+        .tags(HashSet::from(["a", "b", "br", "em", "i", "li", "ol", "p", "strong", "ul"]))
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(html)
+        .to_string()
+}
+
+pub fn escape_attribute(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn json_schema(title: &str, fields: &[FieldDef]) -> Value {
+    if fields.is_empty() {
+        return json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": title,
+            "type": "object",
+            "additionalProperties": { "type": ["string", "number", "boolean"] },
+        });
+    }
+
+    let properties: Map<String, Value> = fields.iter()
+        .map(|field| (field.key.clone(), field_schema(field)))
+        .collect();
+    let required: Vec<&str> = fields.iter()
+        .filter(|field| field.required && field.show_if.is_none())
+        .map(|field| field.key.as_str())
+        .collect();
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": title,
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+fn field_schema(field: &FieldDef) -> Value {
+    let mut schema = field.field_type().json_schema(field);
+    if !field.label.is_empty() {
+        schema["title"] = json!(field.label);
+    }
+    schema
+}
+
+pub fn answers_from_json(payload: HashMap<String, Value>) -> Result<HashMap<String, String>, Vec<FieldError>> {
+    let mut answers = HashMap::new();
+    let mut errors = Vec::new();
+
+    for (key, value) in payload {
+        match value {
+            Value::Null => {}
+            Value::String(value) => {
+                answers.insert(key, value);
+            }
+            Value::Bool(value) => {
+                answers.insert(key, value.to_string());
+            }
+            Value::Number(value) => {
+                answers.insert(key, value.to_string());
+            }
+            Value::Array(_) | Value::Object(_) => errors.push(FieldError::new(&key, "must be a string, number or boolean")),
+        }
+    }
+
+    if errors.is_empty() { Ok(answers) } else { Err(errors) }
+}
+
+/// A respondent-chosen amount such as `25` or `25.50` in the currency's
+/// minor unit. Only two-decimal currencies are supported.
+pub fn parse_amount(value: &str) -> Option<i64> {
+    let (whole, fraction) = value.trim().split_once('.').unwrap_or((value.trim(), ""));
+    if whole.is_empty() || fraction.len() > 2 || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let cents: i64 = format!("{:0<2}", fraction).parse().ok()?;
+    whole.parse::<i64>().ok()?.checked_mul(100)?.checked_add(cents)
+}
+
+pub fn format_amount(amount: i64) -> String {
+    format!("{}.{:02}", amount / 100, amount % 100)
+}
+
+/// Whether a checkbox answer is ticked.
+pub fn checked(value: &str) -> bool {
+    matches!(value.trim(), "true" | "on")
+}
+
+pub fn validate(fields: &[FieldDef], answers: &HashMap<String, String>) -> Vec<FieldError> {
+    if fields.is_empty() {
+        return Vec::new();
+    }
+
+    let mut errors: Vec<FieldError> = answers.keys()
+        .filter(|key| !fields.iter().any(|field| &field.key == *key))
+        .map(|key| FieldError::new(key, "is not a field on this form"))
+        .collect();
+
+    for field in fields.iter().filter(|field| field.visible(answers)) {
+        let field_type = field.field_type();
+        let value = answers.get(&field.key).map(|value| value.trim()).unwrap_or_default();
+        if value.is_empty() || !field_type.answered(field, value) {
+            if field.required {
+                errors.push(FieldError::new(&field.key, "is required"));
+            }
+            continue;
+        }
+
+        if let Err(message) = field_type.validate(field, value) {
+            errors.push(FieldError::new(&field.key, message));
+        }
+    }
+
+    errors.sort_by(|a, b| a.field.cmp(&b.field));
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(json: Value) -> Vec<FieldDef> {
+        parse(&json.to_string())
+    }
+
+    #[test]
+    fn rich_text_drops_scripts() {
+        let cleaned = rich_text("<p>Welcome</p><script>alert(document.cookie)</script>");
+        assert_eq!(cleaned, "<p>Welcome</p>");
+    }
+
+    #[test]
+    fn rich_text_drops_images_and_their_handlers() {
+        let cleaned = rich_text(r#"<p>Logo <img src="x" onerror="alert(1)"></p>"#);
+        assert!(!cleaned.contains("<img"));
+        assert!(!cleaned.contains("onerror"));
+        assert!(cleaned.starts_with("<p>Logo"));
+    }
+
+    #[test]
+    fn rich_text_strips_javascript_links() {
+        let cleaned = rich_text(r#"<a href="javascript:alert(1)">terms</a>"#);
+        assert!(!cleaned.contains("javascript:"));
+        assert!(cleaned.contains(">terms</a>"));
+
+        let cleaned = rich_text(r#"<a href="https://example.com/terms">terms</a>"#);
+        assert!(cleaned.contains(r#"href="https://example.com/terms""#));
+        assert!(cleaned.contains(r#"rel="noopener noreferrer nofollow""#));
+    }
+
+    #[test]
+    fn parse_sanitizes_descriptions() {
+        let parsed = fields(json!([{
+            "key": "name",
+            "type": "text",
+            "description": "<b>Full</b> name<script>alert(1)</script><img src=x onerror=alert(1)>"
+        }]));
+        assert_eq!(parsed[0].description.as_deref(), Some("<b>Full</b> name"));
+    }
+
+    /// Labels and options are plain text: they are kept as written and
+    /// escaped where they are output, never interpreted as markup.
+    #[test]
+    fn labels_and_options_are_escaped_where_output() {
+        let parsed = fields(json!([{
+            "key": "colour",
+            "type": "choice",
+            "label": "<img src=x onerror=alert(1)>Colour",
+            "options": ["Red", "</script><script>alert(1)</script>"]
+        }, {
+            "key": "shade",
+            "type": "text",
+            "label": "Shade",
+            "show_if": { "field": "colour", "equals": "\"><script>alert(1)</script>" }
+        }]));
+        assert_eq!(parsed[0].label, "<img src=x onerror=alert(1)>Colour");
+        assert_eq!(parsed[0].options[1], "</script><script>alert(1)</script>");
+
+        let rules = client_rules(&parsed);
+        assert!(!rules.contains("</script>"));
+        assert!(rules.contains(r"<\/script>"));
+
+        let attributes = parsed[1].html_attributes();
+        assert!(!attributes.contains("<script>"));
+        assert!(attributes.contains(r#"data-show-if-equals="&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;""#));
+    }
+
+    #[test]
+    fn escape_attribute_escapes_markup_and_quotes() {
+        assert_eq!(escape_attribute(r#"" onmouseover="alert(1)"#), "&quot; onmouseover=&quot;alert(1)");
+        assert_eq!(escape_attribute("<b>&</b>"), "&lt;b&gt;&amp;&lt;/b&gt;");
+    }
+}
@@ -0,0 +1,324 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::{Json, Value, json};
+use rocket::State;
+use serde::Deserialize;
+use serde_json::Map;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::{AppConfig, api};
+use crate::events::{DomainEvent, EventBus};
+use crate::guards::SessionStore;
+use crate::tenant::Tenant;
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+
+const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
+pub struct ScimClient;
+
+struct ScimUser {
+    id: i64,
+    username: String,
+    email: Option<String>,
+    active: bool,
+    external_id: Option<String>,
+    created_at: String,
+    updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScimEmail {
+    value: String,
+    #[serde(default)]
+    primary: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserResource {
+    user_name: String,
+    #[serde(default = "active_default")]
+    active: bool,
+    external_id: Option<String>,
+    #[serde(default)]
+    emails: Vec<ScimEmail>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchOperation {
+    op: String,
+    path: Option<String>,
+    value: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PatchRequest {
+    operations: Vec<PatchOperation>,
+}
+
+fn active_default() -> bool {
+    true
+}
+
+impl UserResource {
+    fn email(&self) -> Option<&str> {
+        self.emails.iter().find(|email| email.primary).or(self.emails.first()).map(|email| email.value.as_str())
+    }
+}
+
+/// SCIM wants RFC 3339; the database keeps UTC as `YYYY-MM-DD HH:MM:SS`.
+fn scim_time(stored: &str) -> String {
+    format!("{}Z", stored.replacen(' ', "T", 1))
+}
+
+impl ScimUser {
+    fn resource(&self) -> Value {
+        json!({
+            "schemas": [USER_SCHEMA],
+            "id": self.id.to_string(),
+            "externalId": self.external_id,
+            "userName": self.username,
+            "active": self.active,
+            "emails": self.email.iter().map(|email| json!({ "value": email, "primary": true })).collect::<Vec<_>>(),
+            "meta": {
+                "resourceType": "User",
+                "location": format!("/scim/v2/Users/{}", self.id),
+                "created": scim_time(&self.created_at),
+                "lastModified": scim_time(&self.updated_at),
+            },
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ScimClient {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(expected) = request.rocket().state::<AppConfig>().and_then(|config| config.scim_token.as_deref()) else {
+            return Outcome::Error((Status::NotFound, ()));
+        };
+
+        let presented = request.headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .map(|token| api::hash_token(token.trim()));
+
+        if presented == Some(api::hash_token(expected)) {
+            Outcome::Success(ScimClient)
+        } else {
+            Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+#[catch(default)]
+pub fn scim_error(status: Status, _request: &Request<'_>) -> Json<Value> {
+    Json(json!({ "schemas": [ERROR_SCHEMA], "status": status.code.to_string(), "detail": status.reason_lossy() }))
+}
+
+async fn find_user(db: &SqlitePool, tenant: &Tenant, id: i64) -> Result<ScimUser, Status> {
+    sqlx::query_as!(ScimUser,
+        "SELECT id, username, email, active, external_id, created_at, updated_at FROM users WHERE id = ? AND tenant_id = ?",
+        id,
+        tenant.id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .ok_or(Status::NotFound)
+}
+
+#[get("/Users?<filter>")]
+pub async fn list_users(
+    db: &State<SqlitePool>,
+    _client: ScimClient,
+    tenant: Tenant,
+    filter: Option<&str>
+) -> Result<Json<Value>, Status> {
+    let username = match filter {
+        Some(filter) => {
+            let value = filter.strip_prefix("userName eq ")
+                .map(|value| value.trim().trim_matches('"'))
+                .ok_or(Status::BadRequest)?;
+            Some(value.to_string())
+        }
+        None => None,
+    };
+
+    let users = sqlx::query_as!(ScimUser,
+        "SELECT id, username, email, active, external_id, created_at, updated_at FROM users
+         WHERE tenant_id = ?2 AND (?1 IS NULL OR username = ?1) ORDER BY id",
+        username,
+        tenant.id
+    )
+    .fetch_all(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Json(json!({
+        "schemas": [LIST_SCHEMA],
+        "totalResults": users.len(),
+        "startIndex": 1,
+        "itemsPerPage": users.len(),
+        "Resources": users.iter().map(ScimUser::resource).collect::<Vec<_>>(),
+    })))
+}
+
+#[get("/Users/<id>")]
+pub async fn get_user(db: &State<SqlitePool>, _client: ScimClient, tenant: Tenant, id: i64) -> Result<Json<Value>, Status> {
+    Ok(Json(find_user(db, &tenant, id).await?.resource()))
+}
+
+#[post("/Users", data = "<user>")]
+pub async fn create_user(
+    db: &State<SqlitePool>,
+    bus: &State<EventBus>,
+    _client: ScimClient,
+    tenant: Tenant,
+    user: Json<UserResource>
+) -> Result<(Status, Json<Value>), Status> {
+    let password_hash = bcrypt::hash(Uuid::new_v4().to_string(), bcrypt::DEFAULT_COST).map_err(|_| Status::InternalServerError)?;
+    let email = user.email();
+
+    let user = sqlx::query_as!(ScimUser,
+        "INSERT INTO users (username, password_hash, email, active, external_id, tenant_id, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+         ON CONFLICT (tenant_id, username) DO NOTHING
+         RETURNING id, username, email, active, external_id, created_at, updated_at",
+        user.user_name,
+        password_hash,
+        email,
+        user.active,
+        user.external_id,
+        tenant.id
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?
+    .ok_or(Status::Conflict)?;
+    bus.publish(DomainEvent::UserRegistered { user_id: user.id, via: "scim" });
+
+    Ok((Status::Created, Json(user.resource())))
+}
+
+#[put("/Users/<id>", data = "<user>")]
+pub async fn replace_user(
+    db: &State<SqlitePool>,
+    sessions: &State<SessionStore>,
+    _client: ScimClient,
+    tenant: Tenant,
+    id: i64,
+    user: Json<UserResource>
+) -> Result<Json<Value>, Status> {
+    let email = user.email();
+    let user = sqlx::query_as!(ScimUser,
+        "UPDATE users SET username = ?, email = ?, active = ?, external_id = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND tenant_id = ?
+         RETURNING id, username, email, active, external_id, created_at, updated_at",
+        user.user_name,
+        email,
+        user.active,
+        user.external_id,
+        id,
+        tenant.id
+    )
+    .fetch_optional(db.inner())
+    .await
+    .map_err(|_| Status::Conflict)?
+    .ok_or(Status::NotFound)?;
+
+    if !user.active {
+        sessions.end_user(user.id);
+    }
+
+    Ok(Json(user.resource()))
+}
+
+#[patch("/Users/<id>", data = "<patch>")]
+pub async fn patch_user(
+    db: &State<SqlitePool>,
+    sessions: &State<SessionStore>,
+    _client: ScimClient,
+    tenant: Tenant,
+    id: i64,
+    patch: Json<PatchRequest>
+) -> Result<Json<Value>, Status> {
+    let mut user = find_user(db, &tenant, id).await?;
+
+    for operation in &patch.operations {
+        if !operation.op.eq_ignore_ascii_case("replace") && !operation.op.eq_ignore_ascii_case("add") {
+            return Err(Status::BadRequest);
+        }
+
+        let changes = match &operation.path {
+            Some(path) => Value::Object(Map::from_iter([(path.clone(), operation.value.clone())])),
+            None => operation.value.clone(),
+        };
+        let Some(changes) = changes.as_object() else {
+            return Err(Status::BadRequest);
+        };
+
+        for (path, value) in changes {
+            match path.as_str() {
+                "active" => {
+                    user.active = value.as_bool()
+                        .or_else(|| value.as_str().map(|value| value.eq_ignore_ascii_case("true")))
+                        .ok_or(Status::BadRequest)?;
+                }
+                "userName" => user.username = value.as_str().ok_or(Status::BadRequest)?.to_string(),
+                "externalId" => user.external_id = value.as_str().map(str::to_string),
+                "emails" => {
+                    let emails: Vec<ScimEmail> = serde_json::from_value(value.clone()).map_err(|_| Status::BadRequest)?;
+                    user.email = emails.iter().find(|email| email.primary).or(emails.first()).map(|email| email.value.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    user.updated_at = sqlx::query_scalar!(
+        "UPDATE users SET username = ?, email = ?, active = ?, external_id = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?
+         RETURNING updated_at",
+        user.username,
+        user.email,
+        user.active,
+        user.external_id,
+        user.id
+    )
+    .fetch_one(db.inner())
+    .await
+    .map_err(|_| Status::Conflict)?;
+
+    if !user.active {
+        sessions.end_user(user.id);
+    }
+
+    Ok(Json(user.resource()))
+}
+
+#[delete("/Users/<id>")]
+pub async fn deactivate_user(
+    db: &State<SqlitePool>,
+    sessions: &State<SessionStore>,
+    _client: ScimClient,
+    tenant: Tenant,
+    id: i64
+) -> Result<Status, Status> {
+    let updated = sqlx::query!("UPDATE users SET active = false WHERE id = ? AND tenant_id = ?", id, tenant.id)
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .rows_affected();
+
+    if updated == 0 {
+        return Err(Status::NotFound);
+    }
+
+    sessions.end_user(id);
+    Ok(Status::NoContent)
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope};
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::response::Redirect;
+use rocket::State;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::AppConfig;
+use crate::flags::Flag;
+use crate::guards::AuthenticatedUser;
+use crate::pipeline::{Check, Rejection, Services, Source, Submission};
+use crate::schema::FieldError;
+
+/// The `[scripting]` configuration table: how much work one run of a form's
+/// script may do before it is stopped.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScriptConfig {
+    pub max_operations: u64,
+    pub timeout_ms: u64,
+    pub max_string_size: usize,
+    pub max_collection_size: usize,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        ScriptConfig {
+            max_operations: 100_000,
+            timeout_ms: 250,
+            max_string_size: 64 * 1024,
+            max_collection_size: 1_000,
+        }
+    }
+}
+
+/// What a script made of a submission.
+#[derive(Debug)]
+pub enum Outcome {
+    /// The answers as the script left them.
+    Accept(HashMap<String, String>),
+    /// Set through `errors.<field> = "message"`.
+    Invalid(Vec<FieldError>),
+    /// Raised with `throw "message"`; shown to the respondent as is.
+    Refuse(String),
+}
+
+/// An engine with no module loading or output, and limits on operations,
+/// nesting and value sizes. The timeout starts counting here.
+fn engine(config: &ScriptConfig) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_modules(0);
+    engine.set_max_operations(config.max_operations);
+    engine.set_max_call_levels(16);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(config.max_string_size);
+    engine.set_max_array_size(config.max_collection_size);
+    engine.set_max_map_size(config.max_collection_size);
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+
+    let started = Instant::now();
+    let timeout = Duration::from_millis(config.timeout_ms);
+    engine.on_progress(move |_| (started.elapsed() > timeout).then(|| Dynamic::from("timed out")));
+    engine
+}
+
+/// Checks a script parses, before it is saved.
+pub fn compile(config: &ScriptConfig, source: &str) -> Result<(), String> {
+    engine(config).compile(source).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn text(value: Dynamic) -> String {
+    if value.is_string() {
+        value.into_string().unwrap_or_default()
+    } else {
+        value.to_string()
+    }
+}
+
+/// The message of a `throw`, looking through the function calls it was
+/// thrown from.
+fn thrown(error: &EvalAltResult) -> Option<String> {
+    match error {
+        EvalAltResult::ErrorRuntime(value, _) => Some(text(value.clone())),
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => thrown(inner),
+        _ => None,
+    }
+}
+
+/// Runs `source` with `answers`, `errors`, `source` and `is_test` in scope.
+/// Blocks, so callers off the blocking pool should use `spawn_blocking`.
+/// Errors are the script's own failures: bad syntax, a runtime error or a
+/// limit.
+pub fn run(config: &ScriptConfig, source: &str, answers: HashMap<String, String>, channel: &str, is_test: bool) -> Result<Outcome, String> {
+    let engine = engine(config);
+    let ast = engine.compile(source).map_err(|e| e.to_string())?;
+
+    let answers: Map = answers.into_iter().map(|(key, value)| (key.into(), Dynamic::from(value))).collect();
+    let mut scope = Scope::new();
+    scope.push("answers", answers);
+    scope.push("errors", Map::new());
+    scope.push_constant("source", channel.to_string());
+    scope.push_constant("is_test", is_test);
+
+    if let Err(e) = engine.run_ast_with_scope(&mut scope, &ast) {
+        return match thrown(&e) {
+            Some(message) => Ok(Outcome::Refuse(message)),
+            None => Err(e.to_string()),
+        };
+    }
+
+    let errors: Vec<FieldError> = scope.get_value::<Map>("errors").unwrap_or_default()
+        .into_iter()
+        .map(|(field, message)| FieldError::new(&field, text(message)))
+        .collect();
+    if !errors.is_empty() {
+        return Ok(Outcome::Invalid(errors));
+    }
+
+    // Setting an answer to `()` clears it.
+    let answers = scope.get_value::<Map>("answers").ok_or("the script removed `answers`")?
+        .into_iter()
+        .filter(|(_, value)| !value.is_unit())
+        .map(|(key, value)| (key.to_string(), text(value)))
+        .collect();
+    Ok(Outcome::Accept(answers))
+}
+
+/// The form's script, if it has one. Runs after the built-in validation, so
+/// answers it rewrites are not checked again. A script that fails keeps the
+/// form from taking responses until the author fixes it. Where the
+/// `scripting` flag is off, scripts are kept but not run.
+pub struct Script;
+
+#[rocket::async_trait]
+impl Check for Script {
+    fn name(&self) -> &'static str {
+        "script"
+    }
+
+    async fn run(&self, services: &Services<'_>, submission: &mut Submission<'_>) -> Result<(), Rejection> {
+        let form = submission.form;
+        let source = sqlx::query_scalar!("SELECT source FROM form_scripts WHERE form_id = ?", form.id)
+            .fetch_optional(services.db)
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        let Some(source) = source else {
+            return Ok(());
+        };
+        let enabled = services.flags.enabled_for_form(services.db, &services.config.flags, form.id, Flag::Scripting)
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+        if !enabled {
+            return Ok(());
+        }
+
+        let config = services.config.scripting.clone();
+        let answers = submission.answers.clone();
+        let channel = match submission.source {
+            Source::Web => "web",
+            Source::Kiosk(_) => "kiosk",
+            Source::Api => "api",
+            Source::Manual(_) => "manual",
+        };
+        let is_test = submission.is_test;
+        let outcome = rocket::tokio::task::spawn_blocking(move || run(&config, &source, answers, channel, is_test))
+            .await
+            .map_err(|_| Status::InternalServerError)?;
+
+        match outcome {
+            Ok(Outcome::Accept(mut answers)) => {
+                // Scripts may rewrite answers but not add fields the form
+                // does not have.
+                answers.retain(|key, _| {
+                    submission.answers.contains_key(key) || submission.fields.iter().any(|field| &field.key == key)
+                });
+                submission.answers = answers;
+                Ok(())
+            }
+            Ok(Outcome::Invalid(errors)) => Err(Rejection::Invalid(errors)),
+            Ok(Outcome::Refuse(message)) => Err(Rejection::Refused(message)),
+            Err(e) => {
+                warn!("Script for form {} failed: {}", form.id, e);
+                Err(Rejection::Unavailable)
+            }
+        }
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct ScriptUpdate {
+    pub source: String,
+}
+
+/// Saving an empty script removes it. Scripts that do not parse are refused.
+#[post("/form/<id>/script", data = "<update>")]
+pub async fn update_form_script(
+    db: &State<SqlitePool>,
+    config: &State<AppConfig>,
+    user: AuthenticatedUser,
+    id: i64,
+    update: Form<ScriptUpdate>
+) -> Result<Redirect, Status> {
+    let source = update.source.trim();
+    if source.is_empty() {
+        sqlx::query!(
+            "DELETE FROM form_scripts WHERE form_id IN (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+            id,
+            user.0
+        )
+        .execute(db.inner())
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+
+        return Ok(Redirect::to(uri!(crate::routes::forms::edit_form(id))));
+    }
+
+    if let Err(e) = compile(&config.scripting, source) {
+        info!("Refused a script for form {}: {}", id, e);
+        return Err(Status::UnprocessableEntity);
+    }
+
+    sqlx::query!(
+        "INSERT INTO form_scripts (form_id, source)
+         SELECT id, ? FROM forms WHERE id = ? AND author_id = ?
+         ON CONFLICT (form_id) DO UPDATE SET source = excluded.source, updated_at = CURRENT_TIMESTAMP",
+        source,
+        id,
+        user.0
+    )
+    .execute(db.inner())
+    .await
+    .map_err(|_| Status::InternalServerError)?;
+
+    Ok(Redirect::to(uri!(crate::routes::forms::edit_form(id))))
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use bcrypt::{hash, DEFAULT_COST};
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::schema::{self, FieldDef, FieldKind};
+use crate::tenant::DEFAULT_TENANT;
+
+pub const DEMO_PASSWORD: &str = "demo";
+
+const DEMO_USERS: [(&str, bool); 3] = [("demo-admin", true), ("demo-author", false), ("demo-reviewer", true)];
+
+const RESPONSES_PER_FORM: usize = 40;
+
+const STATUSES: [&str; 4] = ["new", "in-progress", "resolved", "rejected"];
+
+const NAMES: [&str; 8] = ["Ada", "Grace", "Linus", "Margaret", "Alan", "Barbara", "Ken", "Frances"];
+
+const COMMENTS: [&str; 5] = [
+    "Looking forward to it.",
+    "Could the session start a little later?",
+    "Great work, thanks!",
+    "The signup link in the newsletter was broken.",
+    "",
+];
+
+struct Rng(u128);
+
+impl Rng {
+    fn new() -> Self {
+        Rng(Uuid::new_v4().as_u128() | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        // xorshift over the low bits; good enough for demo data.
+        let mut x = self.0 as u64;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x as u128;
+        x
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+fn demo_forms() -> Vec<(&'static str, Value)> {
+    vec![
+        ("Community meetup registration", json!([
+            {"key": "name", "label": "Your name", "type": "text", "required": true, "max": 80},
+            {"key": "email", "label": "Email", "type": "email", "required": true},
+            {"key": "track", "label": "Preferred track", "type": "choice", "required": true,
+             "options": ["Web", "Systems", "Data", "Design"]},
+            {"key": "date", "label": "Which day will you attend?", "type": "date"},
+            {"key": "guests", "label": "Number of guests", "type": "number", "min": 0, "max": 5},
+            {"key": "newsletter", "label": "Subscribe to the newsletter", "type": "checkbox"},
+            {"key": "dietary", "label": "Dietary requirements", "type": "text",
+             "show_if": {"field": "newsletter", "equals": "on"}},
+            {"key": "comments", "label": "Anything else?", "type": "textarea",
+             "description": "Questions for the organisers are **welcome**."}
+        ])),
+        ("Product feedback", json!([
+            {"key": "rating", "label": "How would you rate us?", "type": "number", "required": true, "min": 1, "max": 10},
+            {"key": "plan", "label": "Plan", "type": "choice", "options": ["Free", "Team", "Enterprise"]},
+            {"key": "comments", "label": "What should we improve?", "type": "textarea"}
+        ])),
+    ]
+}
+
+fn random_answers(rng: &mut Rng, fields: &[FieldDef]) -> HashMap<String, String> {
+    let mut answers = HashMap::new();
+    for field in fields {
+        if !field.visible(&answers) {
+            continue;
+        }
+        let value = match field.kind {
+            FieldKind::Text => rng.pick(&NAMES).to_string(),
+            FieldKind::Textarea => rng.pick(&COMMENTS).to_string(),
+            FieldKind::Email => format!("{}{}@example.com", rng.pick(&NAMES).to_lowercase(), rng.below(100)),
+            FieldKind::Number => {
+                let min = field.min.unwrap_or(0.0) as usize;
+                let max = field.max.unwrap_or(100.0) as usize;
+                (min + rng.below(max.saturating_sub(min) + 1)).to_string()
+            }
+            FieldKind::Choice => {
+                let options: Vec<&str> = field.options.iter().map(String::as_str).collect();
+                rng.pick(&options).to_string()
+            }
+            FieldKind::Checkbox if rng.below(2) == 0 => "on".to_string(),
+            FieldKind::Checkbox => continue,
+            FieldKind::Consent => "on".to_string(),
+            FieldKind::Slot | FieldKind::Payment | FieldKind::Custom(_) => continue,
+            FieldKind::Date => format!("2024-06-{:02}", 10 + rng.below(3)),
+        };
+        answers.insert(field.key.clone(), value);
+    }
+    answers
+}
+
+/// Creates demo users, forms covering every field type and randomized
+/// responses. Users that already exist are left as they are.
+pub async fn run(db: &SqlitePool) -> Result<String, String> {
+    let password_hash = hash(DEMO_PASSWORD, DEFAULT_COST).map_err(|e| e.to_string())?;
+    let mut user_ids = Vec::new();
+    for (username, is_approver) in DEMO_USERS {
+        sqlx::query!(
+            "INSERT INTO users (username, password_hash, is_approver) VALUES (?, ?, ?) ON CONFLICT (tenant_id, username) DO NOTHING",
+            username,
+            password_hash,
+            is_approver
+        )
+        .execute(db)
+        .await
+        .map_err(|e| e.to_string())?;
+        let id = sqlx::query_scalar!("SELECT id FROM users WHERE username = ? AND tenant_id = ?", username, DEFAULT_TENANT)
+            .fetch_one(db)
+            .await
+            .map_err(|e| e.to_string())?;
+        user_ids.push(id);
+    }
+
+    let mut rng = Rng::new();
+    let mut responses = 0;
+    let forms = demo_forms();
+    for (title, fields) in &forms {
+        let fields = fields.to_string();
+        let author_id = user_ids[1];
+        let form_id = sqlx::query_scalar!(
+            "INSERT INTO forms (title, fields, published, author_id, live_results, updated_at)
+             VALUES (?, ?, true, ?, true, CURRENT_TIMESTAMP) RETURNING id",
+            title,
+            fields,
+            author_id
+        )
+        .fetch_one(db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let fields = schema::parse(&fields);
+        for _ in 0..RESPONSES_PER_FORM {
+            let answers = random_answers(&mut rng, &fields);
+            let stored = serde_json::to_string(&answers).map_err(|e| e.to_string())?;
+            let answers_hash = crate::db::answers_hash(&answers).map_err(|status| status.to_string())?;
+            let age = format!("-{} minutes", rng.below(30 * 24 * 60));
+            let status = rng.pick(&STATUSES);
+            sqlx::query!(
+                "INSERT INTO responses (form_id, answers, created_at, status, answers_hash, updated_at)
+                 VALUES (?, ?, datetime('now', ?), ?, ?, CURRENT_TIMESTAMP)",
+                form_id,
+                stored,
+                age,
+                status,
+                answers_hash
+            )
+            .execute(db)
+            .await
+            .map_err(|e| e.to_string())?;
+            responses += 1;
+        }
+    }
+
+    Ok(format!(
+        "Seeded {} users, {} forms and {} responses. Log in as {} with the password \"{}\".",
+        DEMO_USERS.len(),
+        forms.len(),
+        responses,
+        DEMO_USERS[1].0,
+        DEMO_PASSWORD
+    ))
+}
@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use rocket::tokio::sync::RwLock;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+const JWKS_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// The JWKS is fetched at most this often, however many tokens with
+/// unknown kids come in.
+const JWKS_REFETCH_COOLDOWN: Duration = Duration::from_secs(60);
+
+const JWKS_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServiceJwtConfig {
+    pub issuer: Option<String>,
+    pub jwks_url: Option<String>,
+    pub audience: Option<String>,
+    pub subject_claim: String,
+}
+
+impl Default for ServiceJwtConfig {
+    fn default() -> Self {
+        ServiceJwtConfig {
+            issuer: None,
+            jwks_url: None,
+            audience: None,
+            subject_claim: "sub".to_string(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Jwks(RwLock<CachedKeys>);
+
+#[derive(Default)]
+struct CachedKeys {
+    keys: Option<(Instant, JwkSet)>,
+    /// When the JWKS was last asked for, whether or not that worked.
+    requested_at: Option<Instant>,
+}
+
+impl CachedKeys {
+    fn key(&self, kid: &str) -> Option<Result<DecodingKey, String>> {
+        let (fetched_at, keys) = self.keys.as_ref()?;
+        let jwk = keys.find(kid).filter(|_| fetched_at.elapsed() < JWKS_MAX_AGE)?;
+        Some(DecodingKey::from_jwk(jwk).map_err(|e| e.to_string()))
+    }
+}
+
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+async fn fetch_keys(client: &reqwest::Client, url: &str) -> Result<JwkSet, String> {
+    client.get(url)
+        .timeout(JWKS_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn decoding_key(config: &ServiceJwtConfig, jwks: &Jwks, client: &reqwest::Client, kid: &str) -> Result<DecodingKey, String> {
+    let url = config.jwks_url.as_deref().ok_or("no jwks_url is configured")?;
+
+    if let Some(key) = jwks.0.read().await.key(kid) {
+        return key;
+    }
+
+    // Requests that get here together wait for one fetch, and the cooldown
+    // stops tokens with made-up kids from turning into a fetch each.
+    let mut cache = jwks.0.write().await;
+    if let Some(key) = cache.key(kid) {
+        return key;
+    }
+    if cache.requested_at.is_some_and(|requested_at| requested_at.elapsed() < JWKS_REFETCH_COOLDOWN) {
+        return Err(format!("no key with kid \"{}\" in the JWKS", kid));
+    }
+
+    cache.requested_at = Some(Instant::now());
+    let keys = fetch_keys(client, url).await?;
+    let key = keys.find(kid).ok_or_else(|| format!("no key with kid \"{}\" in the JWKS", kid)).map(DecodingKey::from_jwk);
+    cache.keys = Some((Instant::now(), keys));
+    key?.map_err(|e| e.to_string())
+}
+
+pub async fn subject(config: &ServiceJwtConfig, jwks: &Jwks, client: &reqwest::Client, token: &str) -> Result<String, String> {
+    let issuer = config.issuer.as_deref().ok_or("no issuer is configured")?;
+    let header = decode_header(token).map_err(|e| e.to_string())?;
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err("symmetric algorithms are not accepted".to_string());
+    }
+    let kid = header.kid.ok_or("token has no kid")?;
+    let key = decoding_key(config, jwks, client, &kid).await?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[issuer]);
+    match &config.audience {
+        Some(audience) => validation.set_audience(&[audience]),
+        None => validation.validate_aud = false,
+    }
+
+    let claims = decode::<Map<String, Value>>(token, &key, &validation).map_err(|e| e.to_string())?.claims;
+    claims.get(&config.subject_claim)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| format!("token has no \"{}\" claim", config.subject_claim))
+}
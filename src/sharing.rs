@@ -0,0 +1,57 @@
+use sqlx::SqlitePool;
+
+use crate::crypto;
+
+/// 50 bits, written in groups of five.
+const SHARE_CODE_LENGTH: usize = 10;
+
+/// The code other authors can redeem for their own copy of the form, if
+/// its author has shared it.
+pub async fn code(db: &SqlitePool, form_id: i64) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT code FROM form_share_codes WHERE form_id = ?", form_id)
+        .fetch_optional(db)
+        .await
+}
+
+/// Issues a new share code for the form, replacing any earlier one so a
+/// code that got passed too far around can be retired. Returns `None` when
+/// the form is not the author's.
+pub async fn share(db: &SqlitePool, form_id: i64, author_id: i64) -> Result<Option<String>, sqlx::Error> {
+    let code = crypto::readable_code(SHARE_CODE_LENGTH);
+    sqlx::query_scalar!(
+        "INSERT INTO form_share_codes (form_id, code) SELECT id, ? FROM forms WHERE id = ? AND author_id = ?
+         ON CONFLICT (form_id) DO UPDATE SET code = excluded.code, created_at = CURRENT_TIMESTAMP
+         RETURNING code",
+        code,
+        form_id,
+        author_id
+    )
+    .fetch_optional(db)
+    .await
+}
+
+pub async fn unshare(db: &SqlitePool, form_id: i64, author_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM form_share_codes WHERE form_id = (SELECT id FROM forms WHERE id = ? AND author_id = ?)",
+        form_id,
+        author_id
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// The form a share code was issued for, as long as it belongs to someone
+/// in the same tenant.
+pub async fn shared_form(db: &SqlitePool, tenant_id: i64, code: &str) -> Result<Option<i64>, sqlx::Error> {
+    let code = crypto::group_code(&crypto::normalize_code(code));
+    sqlx::query_scalar!(
+        "SELECT s.form_id FROM form_share_codes s JOIN forms f ON f.id = s.form_id
+         WHERE s.code = ? AND f.tenant_id = ?",
+        code,
+        tenant_id
+    )
+    .fetch_optional(db)
+    .await
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::schema::{FieldDef, FieldKind};
+
+/// A bookable time for a `slot` field. Answers to the field hold the slot's
+/// id.
+#[derive(Debug, Serialize)]
+pub struct Slot {
+    pub id: i64,
+    pub form_id: i64,
+    pub field_key: String,
+    /// UTC, as SQLite `datetime()` writes it.
+    pub starts_at: String,
+    pub ends_at: Option<String>,
+    pub capacity: i64,
+    pub booked: i64,
+}
+
+/// Every slot of a form, soonest first, including full ones.
+pub async fn all(db: &SqlitePool, form_id: i64) -> Result<Vec<Slot>, sqlx::Error> {
+    sqlx::query_as!(Slot,
+        "SELECT id, form_id, field_key, starts_at, ends_at, capacity, booked FROM form_slots WHERE form_id = ? ORDER BY starts_at, id",
+        form_id
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// The slots respondents can still book, keyed by field.
+pub async fn available(db: &SqlitePool, form_id: i64) -> Result<HashMap<String, Vec<Slot>>, sqlx::Error> {
+    let mut available: HashMap<String, Vec<Slot>> = HashMap::new();
+    for slot in all(db, form_id).await?.into_iter().filter(|slot| slot.booked < slot.capacity) {
+        available.entry(slot.field_key.clone()).or_default().push(slot);
+    }
+    Ok(available)
+}
+
+/// Books the slot chosen for each slot field in `answers`. Each booking
+/// claims a place in one statement, so two respondents can never both take
+/// the last one. When any chosen slot is full, the bookings already made are
+/// released and the full field's key is returned.
+pub async fn book(
+    db: &SqlitePool,
+    form_id: i64,
+    fields: &[FieldDef],
+    answers: &HashMap<String, String>
+) -> Result<Result<Vec<i64>, String>, sqlx::Error> {
+    let mut booked = Vec::new();
+    for field in fields.iter().filter(|field| field.kind == FieldKind::Slot) {
+        let Some(slot_id) = answers.get(&field.key).and_then(|value| value.trim().parse::<i64>().ok()) else {
+            continue;
+        };
+
+        let claimed = sqlx::query!(
+            "UPDATE form_slots SET booked = booked + 1 WHERE id = ? AND form_id = ? AND field_key = ? AND booked < capacity",
+            slot_id,
+            form_id,
+            field.key
+        )
+        .execute(db)
+        .await?
+        .rows_affected();
+
+        if claimed == 0 {
+            release(db, &booked).await;
+            return Ok(Err(field.key.clone()));
+        }
+        booked.push(slot_id);
+    }
+    Ok(Ok(booked))
+}
+
+/// Gives back places booked for a response that was then not stored.
+pub async fn release(db: &SqlitePool, slot_ids: &[i64]) {
+    for slot_id in slot_ids {
+        if let Err(e) = sqlx::query!("UPDATE form_slots SET booked = booked - 1 WHERE id = ? AND booked > 0", slot_id)
+            .execute(db)
+            .await
+        {
+            error!("Failed to release a place in slot {}: {}", slot_id, e);
+        }
+    }
+}
+
+/// Ties the places booked for a response to it, in its transaction, so
+/// deleting it frees them.
+pub async fn record(conn: &mut SqliteConnection, response_id: i64, slot_ids: &[i64]) -> Result<(), sqlx::Error> {
+    for slot_id in slot_ids {
+        sqlx::query!("INSERT INTO response_slots (response_id, slot_id) VALUES (?, ?)", response_id, slot_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Frees the place the response booked for `field_key`, as deleting it
+/// would.
+pub async fn release_answer(conn: &mut SqliteConnection, response_id: i64, form_id: i64, field_key: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM response_slots WHERE response_id = ? AND slot_id IN (SELECT id FROM form_slots WHERE form_id = ? AND field_key = ?)",
+        response_id,
+        form_id,
+        field_key
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
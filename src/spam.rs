@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use serde::Deserialize;
+
+use crate::models::WebForm;
+use crate::schema::{FieldDef, FieldKind};
+
+const DEFAULT_PHRASES: [&str; 10] = [
+    "viagra", "cialis", "casino", "crypto investment", "seo services", "backlinks",
+    "work from home", "loan offer", "click here", "buy followers",
+];
+
+/// A word this common in a long enough answer is filler, not writing.
+const REPEATED_WORD_SHARE: f64 = 0.5;
+
+const REPEATED_WORD_MIN_WORDS: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpamMode {
+    #[default]
+    Off,
+    Local,
+    Akismet,
+}
+
+/// The `[spam]` configuration table. With Akismet, the local checks are the
+/// fallback when the API cannot be reached.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SpamConfig {
+    pub mode: SpamMode,
+    pub akismet_key: Option<String>,
+    /// The site Akismet keys are registered to, e.g. `https://forms.example.com`.
+    pub akismet_site: String,
+    pub max_links: usize,
+    /// Matched case-insensitively. Replaces the built-in list when set.
+    pub phrases: Option<Vec<String>>,
+}
+
+impl Default for SpamConfig {
+    fn default() -> Self {
+        SpamConfig {
+            mode: SpamMode::Off,
+            akismet_key: None,
+            akismet_site: "http://localhost:8000".to_string(),
+            max_links: 3,
+            phrases: None,
+        }
+    }
+}
+
+/// Free text the respondent wrote, in field order. Encrypted fields are left
+/// out so they are never sent to Akismet.
+fn written_answers<'a>(fields: &[FieldDef], answers: &'a HashMap<String, String>) -> Vec<&'a str> {
+    if fields.is_empty() {
+        return answers.values().map(String::as_str).collect();
+    }
+    fields.iter()
+        .filter(|field| matches!(field.kind, FieldKind::Text | FieldKind::Textarea) && !field.encrypted)
+        .filter_map(|field| answers.get(&field.key))
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+fn count_links(text: &str) -> usize {
+    let text = text.to_lowercase();
+    ["http://", "https://", "www."].iter().map(|marker| text.matches(marker).count()).sum::<usize>()
+        - text.matches("://www.").count()
+}
+
+fn repeated_word(text: &str) -> bool {
+    let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+    if words.len() < REPEATED_WORD_MIN_WORDS {
+        return false;
+    }
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for word in &words {
+        *counts.entry(word.as_str()).or_default() += 1;
+    }
+    counts.values().any(|count| *count as f64 / words.len() as f64 > REPEATED_WORD_SHARE)
+}
+
+impl SpamConfig {
+    /// Links, filler and known phrases. Returns why the answers look like spam.
+    pub fn local_check(&self, fields: &[FieldDef], answers: &HashMap<String, String>) -> Option<String> {
+        let written = written_answers(fields, answers);
+
+        let links: usize = written.iter().map(|text| count_links(text)).sum();
+        if links > self.max_links {
+            return Some(format!("{} links", links));
+        }
+
+        if written.iter().any(|text| repeated_word(text)) {
+            return Some("repeated text".to_string());
+        }
+        let mut distinct = written.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        if written.len() >= 3 && distinct.len() == 1 {
+            return Some("the same text in every field".to_string());
+        }
+
+        let text = written.join("\n").to_lowercase();
+        let phrase = match &self.phrases {
+            Some(phrases) => phrases.iter().map(String::as_str).find(|phrase| text.contains(&phrase.to_lowercase())),
+            None => DEFAULT_PHRASES.into_iter().find(|phrase| text.contains(phrase)),
+        };
+        phrase.map(|phrase| format!("contains \"{}\"", phrase))
+    }
+
+    async fn akismet_check(
+        &self,
+        client: &reqwest::Client,
+        key: &str,
+        fields: &[FieldDef],
+        answers: &HashMap<String, String>,
+        ip: Option<IpAddr>,
+        email: Option<&str>
+    ) -> Result<bool, String> {
+        let content = written_answers(fields, answers).join("\n\n");
+        let ip = ip.map(|ip| ip.to_string()).unwrap_or_default();
+        let verdict = client.post(format!("https://{}.rest.akismet.com/1.1/comment-check", key))
+            .form(&[
+                ("blog", self.akismet_site.as_str()),
+                ("user_ip", ip.as_str()),
+                ("comment_type", "contact-form"),
+                ("comment_content", content.as_str()),
+                ("comment_author_email", email.unwrap_or_default()),
+            ])
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        match verdict.trim() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            other => Err(format!("unexpected Akismet answer: {}", other)),
+        }
+    }
+
+    /// Why the answers look like spam, if they do.
+    pub async fn check(
+        &self,
+        client: &reqwest::Client,
+        form: &WebForm,
+        answers: &HashMap<String, String>,
+        ip: Option<IpAddr>,
+        email: Option<&str>
+    ) -> Option<String> {
+        let fields = crate::schema::parse(&form.fields);
+        match (self.mode, &self.akismet_key) {
+            (SpamMode::Off, _) => None,
+            (SpamMode::Akismet, Some(key)) => match self.akismet_check(client, key, &fields, answers, ip, email).await {
+                Ok(true) => Some("flagged by Akismet".to_string()),
+                Ok(false) => None,
+                Err(e) => {
+                    warn!("Falling back to local spam checks for form {}: {}", form.id, e);
+                    self.local_check(&fields, answers)
+                }
+            },
+            (SpamMode::Local | SpamMode::Akismet, _) => self.local_check(&fields, answers),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rocket::fs::{NamedFile, TempFile};
+use rocket::http::ContentType;
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::TcpStream;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::db::notify;
+
+/// The `[uploads]` configuration table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Created on startup if it does not exist.
+    pub directory: String,
+    /// Larger uploads are refused. Rocket's own `file` data limit also
+    /// applies.
+    pub max_size: u64,
+    /// A clamd TCP address such as `127.0.0.1:3310`. When set, uploads wait
+    /// in quarantine until a background scan clears them.
+    pub clamd: Option<String>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig { directory: "uploads".to_string(), max_size: 2 * 1024 * 1024, clamd: None }
+    }
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    TooLarge,
+    UnsupportedType,
+    Io(io::Error),
+}
+
+impl From<io::Error> for UploadError {
+    fn from(e: io::Error) -> Self {
+        UploadError::Io(e)
+    }
+}
+
+/// Uploaded files, kept on local disk under random names. Callers store the
+/// returned key and hand it back to open or remove the file.
+pub struct Storage {
+    directory: PathBuf,
+    quarantine: PathBuf,
+    max_size: u64,
+    clamd: Option<String>,
+}
+
+/// Where uploads wait for their scan, beside the uploads but never served.
+/// Infected ones stay there.
+const QUARANTINE_DIRECTORY: &str = "quarantine";
+
+const SCAN_BATCH_SIZE: i64 = 20;
+
+/// clamd's INSTREAM command takes the file in chunks of at most this size.
+const CLAMD_CHUNK: usize = 64 * 1024;
+
+impl Storage {
+    pub async fn open(config: &StorageConfig) -> io::Result<Self> {
+        let directory = PathBuf::from(&config.directory);
+        let quarantine = directory.join(QUARANTINE_DIRECTORY);
+        rocket::tokio::fs::create_dir_all(&directory).await?;
+        if config.clamd.is_some() {
+            rocket::tokio::fs::create_dir_all(&quarantine).await?;
+        }
+        Ok(Storage { directory, quarantine, max_size: config.max_size, clamd: config.clamd.clone() })
+    }
+
+    /// Whether uploads are scanned, and so start out in quarantine.
+    pub fn scans(&self) -> bool {
+        self.clamd.is_some()
+    }
+
+    /// Keys are only ever ones `save` made, so anything else is not found.
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        valid_key(key).then(|| self.directory.join(key))
+    }
+
+    fn quarantined_path(&self, key: &str) -> Option<PathBuf> {
+        valid_key(key).then(|| self.quarantine.join(key))
+    }
+
+    /// Saves an image upload, returning its key. With clamd configured the
+    /// file goes to quarantine, and is only served once `scan_pending` has
+    /// cleared it.
+    pub async fn save_image(&self, file: &mut TempFile<'_>) -> Result<String, UploadError> {
+        if file.len() > self.max_size {
+            return Err(UploadError::TooLarge);
+        }
+        let extension = match file.content_type() {
+            Some(content_type) if *content_type == ContentType::PNG => "png",
+            Some(content_type) if *content_type == ContentType::JPEG => "jpg",
+            Some(content_type) if *content_type == ContentType::GIF => "gif",
+            Some(content_type) if *content_type == ContentType::WEBP => "webp",
+            _ => return Err(UploadError::UnsupportedType),
+        };
+
+        let key = format!("{}.{}", Uuid::new_v4(), extension);
+        let directory = if self.scans() { &self.quarantine } else { &self.directory };
+        file.copy_to(directory.join(&key)).await?;
+        Ok(key)
+    }
+
+    /// Scans a quarantined upload, moving it into place if it is clean.
+    /// Returns the signature clamd matched, if any; an infected file stays
+    /// where it is.
+    async fn release(&self, key: &str) -> io::Result<Option<String>> {
+        let (Some(address), Some(path)) = (&self.clamd, self.quarantined_path(key)) else {
+            return Ok(None);
+        };
+        let signature = scan(address, &path).await?;
+        if signature.is_none() {
+            rocket::tokio::fs::rename(&path, self.directory.join(key)).await?;
+        }
+        Ok(signature)
+    }
+
+    pub async fn get(&self, key: &str) -> Option<NamedFile> {
+        NamedFile::open(self.path(key)?).await.ok()
+    }
+
+    /// Removes the file, wherever it is. Removing a file that is already
+    /// gone is not an error.
+    pub async fn remove(&self, key: &str) -> io::Result<()> {
+        for path in [self.path(key), self.quarantined_path(key)].into_iter().flatten() {
+            match rocket::tokio::fs::remove_file(path).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') && !key.starts_with('.')
+}
+
+/// Records an upload `user_id` made, as waiting for its scan when uploads
+/// are scanned.
+pub async fn record(db: &SqlitePool, storage: &Storage, user_id: i64, key: &str, size: u64) -> Result<(), sqlx::Error> {
+    let status = if storage.scans() { "pending" } else { "clean" };
+    let size = size as i64;
+    sqlx::query!(
+        "INSERT INTO uploads (key, user_id, size, status) VALUES (?, ?, ?, ?)",
+        key,
+        user_id,
+        size,
+        status
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes an upload's file and its record.
+pub async fn discard(db: &SqlitePool, storage: &Storage, key: &str) -> Result<(), String> {
+    storage.remove(key).await.map_err(|e| e.to_string())?;
+    sqlx::query!("DELETE FROM uploads WHERE key = ?", key)
+        .execute(db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Scans uploads waiting in quarantine. Clean ones are moved into place.
+/// Infected ones stay quarantined, are taken off the profile using them,
+/// and their owner is told. Uploads clamd could not be asked about are
+/// left for the next run.
+pub async fn scan_pending(db: &SqlitePool, storage: &Storage) -> Result<(), sqlx::Error> {
+    if !storage.scans() {
+        return Ok(());
+    }
+
+    let pending = sqlx::query!(
+        "SELECT key, user_id FROM uploads WHERE status = 'pending' ORDER BY id LIMIT ?",
+        SCAN_BATCH_SIZE
+    )
+    .fetch_all(db)
+    .await?;
+
+    for upload in pending {
+        match storage.release(&upload.key).await {
+            Ok(None) => {
+                sqlx::query!("UPDATE uploads SET status = 'clean', scanned_at = CURRENT_TIMESTAMP WHERE key = ?", upload.key)
+                    .execute(db)
+                    .await?;
+            }
+            Ok(Some(signature)) => {
+                warn!("Upload {} by user {} is infected: {}", upload.key, upload.user_id, signature);
+                let mut tx = db.begin().await?;
+                sqlx::query!(
+                    "UPDATE uploads SET status = 'infected', signature = ?, scanned_at = CURRENT_TIMESTAMP WHERE key = ?",
+                    signature,
+                    upload.key
+                )
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query!("UPDATE user_profiles SET avatar = NULL WHERE avatar = ?", upload.key)
+                    .execute(&mut *tx)
+                    .await?;
+                tx.commit().await?;
+
+                let message = "A picture you uploaded failed the virus scan and was not kept";
+                let link = uri!(crate::routes::account::account_page(wrong_password = false, upload_error = Some("infected"))).to_string();
+                if notify(db, upload.user_id, "upload_infected", message, &link, None).await.is_err() {
+                    error!("Failed to tell user {} about infected upload {}", upload.user_id, upload.key);
+                }
+            }
+            Err(e) => warn!("Failed to scan upload {}: {}", upload.key, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Streams the file to clamd, returning the signature it matched, if any.
+async fn scan(address: &str, path: &Path) -> io::Result<Option<String>> {
+    let mut file = File::open(path).await?;
+    let mut clamd = TcpStream::connect(address).await?;
+    clamd.write_all(b"zINSTREAM\0").await?;
+
+    let mut chunk = vec![0; CLAMD_CHUNK];
+    loop {
+        let read = file.read(&mut chunk).await?;
+        clamd.write_all(&(read as u32).to_be_bytes()).await?;
+        if read == 0 {
+            break;
+        }
+        clamd.write_all(&chunk[..read]).await?;
+    }
+
+    let mut reply = Vec::new();
+    clamd.read_to_end(&mut reply).await?;
+    let reply = String::from_utf8_lossy(&reply);
+    let reply = reply.trim_end_matches('\0').trim();
+    match reply.strip_prefix("stream: ") {
+        Some("OK") => Ok(None),
+        Some(found) if found.ends_with(" FOUND") => Ok(Some(found.trim_end_matches(" FOUND").to_string())),
+        _ => Err(io::Error::other(format!("unexpected reply from clamd: {}", reply))),
+    }
+}
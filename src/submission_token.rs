@@ -0,0 +1,87 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+const ISSUER: &str = "forms_system/submission";
+
+/// The hidden field the public form template renders the token into.
+pub const SUBMISSION_TOKEN_FIELD: &str = "_submission_token";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SubmissionTokenConfig {
+    /// Shared by every instance behind a load balancer. Without one, a
+    /// random secret is made at startup and open forms stop submitting
+    /// after a restart.
+    pub secret: Option<String>,
+    pub ttl_hours: u64,
+}
+
+impl Default for SubmissionTokenConfig {
+    fn default() -> Self {
+        SubmissionTokenConfig {
+            secret: None,
+            ttl_hours: 24,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    iss: String,
+    form: i64,
+    tenant: i64,
+    exp: u64,
+}
+
+/// Signs the token embedded in each rendered public form. Submissions must
+/// bring one back for the same form and tenant before it expires, which
+/// turns away direct POSTs that never loaded the form and stale pages
+/// replayed long after.
+pub struct SubmissionTokens {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    ttl_secs: u64,
+}
+
+impl SubmissionTokens {
+    pub fn new(config: &SubmissionTokenConfig) -> Self {
+        let secret = match &config.secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                warn!("No submission_tokens.secret is configured; open forms will need a reload after a restart");
+                [Uuid::new_v4().as_bytes().as_slice(), Uuid::new_v4().as_bytes()].concat()
+            }
+        };
+
+        SubmissionTokens {
+            encoding: EncodingKey::from_secret(&secret),
+            decoding: DecodingKey::from_secret(&secret),
+            ttl_secs: config.ttl_hours * 3600,
+        }
+    }
+
+    pub fn issue(&self, form_id: i64, tenant_id: i64) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or_default();
+        let claims = Claims {
+            iss: ISSUER.to_string(),
+            form: form_id,
+            tenant: tenant_id,
+            exp: now + self.ttl_secs,
+        };
+        encode(&Header::new(Algorithm::HS256), &claims, &self.encoding).unwrap_or_default()
+    }
+
+    pub fn verify(&self, token: &str, form_id: i64, tenant_id: i64) -> Result<(), String> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[ISSUER]);
+
+        let claims = decode::<Claims>(token, &self.decoding, &validation).map_err(|e| e.to_string())?.claims;
+        if claims.form != form_id || claims.tenant != tenant_id {
+            return Err(format!("token was issued for form {} of tenant {}", claims.form, claims.tenant));
+        }
+        Ok(())
+    }
+}
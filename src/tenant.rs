@@ -0,0 +1,106 @@
+use std::fmt::Display;
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use serde::{Serialize, Deserialize};
+use sqlx::SqlitePool;
+
+use crate::AppConfig;
+
+pub const DEFAULT_TENANT: i64 = 1;
+
+/// Where links in emails point when no `base_url` is configured.
+const DEFAULT_BASE_URL: &str = "http://localhost:8000";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tenant {
+    pub id: i64,
+    pub slug: String,
+    pub name: String,
+    pub logo_url: Option<String>,
+    pub accent_color: Option<String>,
+    pub created_at: String,
+}
+
+impl Tenant {
+    /// `path` as a link that works from an email: on the configured
+    /// `base_url`, at the tenant's subdomain of `tenant_domain` unless it is
+    /// the default tenant.
+    pub fn url(&self, config: &AppConfig, path: impl Display) -> String {
+        let base = config.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL).trim_end_matches('/');
+        let host = config.tenant_domain.as_ref()
+            .filter(|_| self.id != DEFAULT_TENANT)
+            .map(|domain| format!("{}.{}", self.slug, domain));
+        let Some(host) = host else {
+            return format!("{}{}", base, path);
+        };
+        let Ok(mut url) = reqwest::Url::parse(base) else {
+            return format!("{}{}", base, path);
+        };
+        if url.set_host(Some(&host)).is_err() {
+            return format!("{}{}", base, path);
+        }
+        format!("{}{}", url.as_str().trim_end_matches('/'), path)
+    }
+}
+
+/// As [`Tenant::url`], for the tenant with that id.
+pub async fn url(db: &SqlitePool, config: &AppConfig, tenant_id: i64, path: impl Display) -> Result<String, sqlx::Error> {
+    let tenant = sqlx::query_as!(Tenant, "SELECT * FROM tenants WHERE id = ?", tenant_id)
+        .fetch_one(db)
+        .await?;
+    Ok(tenant.url(config, path))
+}
+
+fn subdomain<'a>(host: &'a str, domain: &str) -> Option<&'a str> {
+    let host = host.split(':').next().unwrap_or(host);
+    host.strip_suffix(domain)?
+        .strip_suffix('.')
+        .filter(|slug| !slug.is_empty() && !slug.contains('.'))
+}
+
+/// Picks the tenant from the request's subdomain of `tenant_domain`, or the
+/// default tenant when there is none. Tenants aren't selected by a `/t/<slug>`
+/// path prefix: the links and redirects `uri!` generates are root-relative,
+/// so the prefix would be lost on the first click and the visitor silently
+/// moved to the default tenant. A subdomain survives every URL unchanged.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Tenant {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let tenant = request.local_cache_async(async {
+            let db = request.rocket().state::<SqlitePool>().unwrap();
+            let domain = request.rocket().state::<AppConfig>().and_then(|config| config.tenant_domain.as_deref());
+            let slug = match (domain, request.host()) {
+                (Some(domain), Some(host)) => subdomain(host.domain().as_str(), domain),
+                _ => None,
+            };
+
+            match slug {
+                Some(slug) => sqlx::query_as!(Tenant, "SELECT * FROM tenants WHERE slug = ?", slug).fetch_optional(db).await,
+                None => sqlx::query_as!(Tenant, "SELECT * FROM tenants WHERE id = ?", DEFAULT_TENANT).fetch_optional(db).await,
+            }
+            .map_err(|_| Status::InternalServerError)
+        })
+        .await;
+
+        match tenant {
+            Ok(Some(tenant)) => Outcome::Success(tenant.clone()),
+            Ok(None) => Outcome::Error((Status::NotFound, ())),
+            Err(status) => Outcome::Error((*status, ())),
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for Tenant {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
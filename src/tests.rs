@@ -0,0 +1,463 @@
+//! End-to-end tests against a local client. Each test gets its own
+//! in-memory database with every migration applied, and the builders below
+//! seed it with just the users, forms and responses the test needs.
+
+use bcrypt::hash;
+use rocket::figment::Figment;
+use rocket::http::{ContentType, Header, RawStr, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+use rocket::tokio::net::TcpListener;
+use serde_json::{json, Map, Value};
+use sqlx::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use uuid::Uuid;
+
+use crate::{api, storage};
+use crate::build_rocket;
+use crate::storage::{Storage, StorageConfig};
+use crate::tenant::DEFAULT_TENANT;
+
+/// Kept low so building users doesn't dominate the test run.
+const TEST_HASH_COST: u32 = 4;
+
+/// A fresh in-memory database with the full schema. A single connection
+/// that is never recycled, since every connection to `sqlite::memory:`
+/// opens a database of its own.
+async fn database() -> SqlitePool {
+    let options = SqliteConnectOptions::new()
+        .in_memory(true)
+        .foreign_keys(true);
+    let db = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(options)
+        .await
+        .expect("in-memory database opens");
+    sqlx::migrate!().run(&db).await.expect("migrations apply");
+    db
+}
+
+fn figment() -> Figment {
+    rocket::Config::figment()
+        .merge(("log_level", "off"))
+        .merge(("secret_key", [7u8; 64].as_slice()))
+}
+
+/// A client for the app built around `db`, keeping cookies between
+/// requests like a browser.
+async fn client(db: &SqlitePool) -> Client {
+    Client::tracked(build_rocket(figment(), db.clone())).await.expect("app ignites")
+}
+
+/// Like `client`, with uploads kept and scanned as `uploads` says.
+async fn client_with_uploads(db: &SqlitePool, uploads: &StorageConfig) -> Client {
+    let uploads = json!({ "directory": uploads.directory, "max_size": uploads.max_size, "clamd": uploads.clamd });
+    Client::tracked(build_rocket(figment().merge(("uploads", uploads)), db.clone())).await.expect("app ignites")
+}
+
+/// Upload settings for a fresh directory, scanned by a stand-in for clamd
+/// that answers every scan with `verdict`.
+async fn scanned_uploads(verdict: &'static str) -> StorageConfig {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    rocket::tokio::spawn(async move {
+        while let Ok((mut clamd, _)) = listener.accept().await {
+            let mut command = [0; 10];
+            clamd.read_exact(&mut command).await.unwrap();
+            loop {
+                let mut length = [0; 4];
+                clamd.read_exact(&mut length).await.unwrap();
+                let mut chunk = vec![0; u32::from_be_bytes(length) as usize];
+                if chunk.is_empty() {
+                    break;
+                }
+                clamd.read_exact(&mut chunk).await.unwrap();
+            }
+            clamd.write_all(format!("stream: {}\0", verdict).as_bytes()).await.unwrap();
+        }
+    });
+
+    let directory = std::env::temp_dir().join(format!("forms-uploads-{}", Uuid::new_v4()));
+    StorageConfig { directory: directory.to_string_lossy().into_owned(), max_size: 1024, clamd: Some(address) }
+}
+
+struct TestUser {
+    id: i64,
+    username: String,
+    password: String,
+}
+
+struct UserBuilder {
+    username: String,
+    password: String,
+    is_approver: bool,
+}
+
+impl UserBuilder {
+    fn new(username: &str) -> Self {
+        UserBuilder { username: username.to_string(), password: "password".to_string(), is_approver: false }
+    }
+
+    fn approver(mut self) -> Self {
+        self.is_approver = true;
+        self
+    }
+
+    async fn create(self, db: &SqlitePool) -> TestUser {
+        let password_hash = hash(&self.password, TEST_HASH_COST).unwrap();
+        let id = sqlx::query_scalar!(
+            "INSERT INTO users (username, password_hash, is_approver, tenant_id) VALUES (?, ?, ?, ?) RETURNING id",
+            self.username,
+            password_hash,
+            self.is_approver,
+            DEFAULT_TENANT
+        )
+        .fetch_one(db)
+        .await
+        .unwrap();
+
+        TestUser { id, username: self.username, password: self.password }
+    }
+}
+
+struct FormBuilder {
+    title: String,
+    fields: Vec<Value>,
+    published: bool,
+    response_cap: Option<i64>,
+}
+
+impl FormBuilder {
+    fn new(title: &str) -> Self {
+        FormBuilder { title: title.to_string(), fields: Vec::new(), published: true, response_cap: None }
+    }
+
+    /// Adds a field of `kind` whose answers are stored under `key`.
+    fn field(mut self, key: &str, kind: &str, label: &str) -> Self {
+        self.fields.push(json!({ "key": key, "type": kind, "label": label }));
+        self
+    }
+
+    fn draft(mut self) -> Self {
+        self.published = false;
+        self
+    }
+
+    fn cap(mut self, responses: i64) -> Self {
+        self.response_cap = Some(responses);
+        self
+    }
+
+    fn fields_json(&self) -> String {
+        Value::Array(self.fields.clone()).to_string()
+    }
+
+    /// The body the form editor posts for this form.
+    fn web_form(&self, author: &TestUser) -> String {
+        let mut body = form_body(&[
+            ("id", "0"),
+            ("title", &self.title),
+            ("fields", &self.fields_json()),
+            ("author_id", &author.id.to_string()),
+            ("created_at", ""),
+            ("updated_at", ""),
+            ("version", "1"),
+            ("duplicate_policy", "off"),
+            ("duplicate_window_hours", "0"),
+            ("captcha_accept_score", "0.5"),
+            ("captcha_reject_score", "0.1"),
+        ]);
+        if self.published {
+            body.push_str("&published=true");
+        }
+        body
+    }
+
+    async fn create(self, db: &SqlitePool, author: &TestUser) -> i64 {
+        let fields = self.fields_json();
+        sqlx::query_scalar!(
+            "INSERT INTO forms (title, fields, published, response_cap, author_id) VALUES (?, ?, ?, ?, ?) RETURNING id",
+            self.title,
+            fields,
+            self.published,
+            self.response_cap,
+            author.id
+        )
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+}
+
+struct ResponseBuilder {
+    form_id: i64,
+    answers: Map<String, Value>,
+    spam: bool,
+}
+
+impl ResponseBuilder {
+    fn new(form_id: i64) -> Self {
+        ResponseBuilder { form_id, answers: Map::new(), spam: false }
+    }
+
+    fn answer(mut self, key: &str, value: &str) -> Self {
+        self.answers.insert(key.to_string(), Value::String(value.to_string()));
+        self
+    }
+
+    fn spam(mut self) -> Self {
+        self.spam = true;
+        self
+    }
+
+    async fn create(self, db: &SqlitePool) -> i64 {
+        let answers = Value::Object(self.answers).to_string();
+        sqlx::query_scalar!(
+            "INSERT INTO responses (form_id, answers, spam) VALUES (?, ?, ?) RETURNING id",
+            self.form_id,
+            answers,
+            self.spam
+        )
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+}
+
+fn form_body(pairs: &[(&str, &str)]) -> String {
+    pairs.iter()
+        .map(|(name, value)| format!("{}={}", name, RawStr::new(value).percent_encode()))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Signs `user` in, so the client's later requests carry their session.
+async fn sign_in(client: &Client, user: &TestUser) {
+    let response = client.post("/login")
+        .header(ContentType::Form)
+        .body(form_body(&[("username", &user.username), ("password_hash", &user.password)]))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    assert!(client.cookies().get_private("session_id").is_some(), "{} could not sign in", user.username);
+}
+
+/// A bearer token for the API, with the default scopes.
+async fn api_token(db: &SqlitePool, user: &TestUser) -> Header<'static> {
+    let token = Uuid::new_v4().to_string();
+    let token_hash = api::hash_token(&token);
+    sqlx::query!("INSERT INTO api_tokens (user_id, name, token_hash) VALUES (?, 'tests', ?)", user.id, token_hash)
+        .execute(db)
+        .await
+        .unwrap();
+    Header::new("Authorization", format!("Bearer {}", token))
+}
+
+/// Sets a picture as the signed-in user's avatar.
+/// Uploads a seven-byte avatar and returns where the app redirected to.
+async fn upload_avatar(client: &Client) -> String {
+    let boundary = "avatar-boundary";
+    let mut body = String::new();
+    for name in ["display_name", "bio", "timezone", "locale"] {
+        body.push_str(&format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n\r\n", boundary, name));
+    }
+    body.push_str(&format!(
+        "--{0}\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"avatar.png\"\r\nContent-Type: image/png\r\n\r\npicture\r\n--{0}--\r\n",
+        boundary
+    ));
+
+    let response = client.post("/account/profile")
+        .header(ContentType::new("multipart", "form-data").with_params(("boundary", boundary)))
+        .body(body)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    response.headers().get_one("Location").unwrap_or_default().to_string()
+}
+
+async fn json_body(response: LocalResponse<'_>) -> Value {
+    response.into_json().await.expect("response is JSON")
+}
+
+#[rocket::async_test]
+async fn created_form_takes_submissions_listed_in_its_responses() {
+    let db = database().await;
+    let author = UserBuilder::new("author").create(&db).await;
+    let client = client(&db).await;
+    sign_in(&client, &author).await;
+
+    let form = FormBuilder::new("Feedback").field("name", "text", "Your name");
+    let response = client.post("/form")
+        .header(ContentType::Form)
+        .body(form.web_form(&author))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::SeeOther);
+    let form_id = sqlx::query_scalar!("SELECT id FROM forms WHERE author_id = ? AND title = 'Feedback'", author.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+    let response = client.post(format!("/api/v1/f/{}/submit", form_id))
+        .header(ContentType::JSON)
+        .body(json!({ "name": "Ada" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let receipt = json_body(response).await;
+    assert_eq!(receipt["form_id"], form_id);
+
+    let response = client.get(format!("/api/v1/forms/{}/responses", form_id))
+        .header(api_token(&db, &author).await)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let page = json_body(response).await;
+    let responses = page["data"].as_array().unwrap();
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0]["id"], receipt["id"]);
+    assert_eq!(responses[0]["answers"]["name"], "Ada");
+}
+
+#[rocket::async_test]
+async fn drafts_take_no_submissions() {
+    let db = database().await;
+    let author = UserBuilder::new("author").create(&db).await;
+    let form_id = FormBuilder::new("Unfinished").field("name", "text", "Your name").draft().create(&db, &author).await;
+    let client = client(&db).await;
+
+    let response = client.post(format!("/api/v1/f/{}/submit", form_id))
+        .header(ContentType::JSON)
+        .body(json!({ "name": "Ada" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn spam_takes_no_place_under_the_response_cap() {
+    let db = database().await;
+    let author = UserBuilder::new("author").create(&db).await;
+    let form_id = FormBuilder::new("Workshop").field("name", "text", "Your name").cap(1).create(&db, &author).await;
+    ResponseBuilder::new(form_id).answer("name", "Buy now").spam().create(&db).await;
+    let client = client(&db).await;
+
+    let submit = || client.post(format!("/api/v1/f/{}/submit", form_id))
+        .header(ContentType::JSON)
+        .body(json!({ "name": "Ada" }).to_string())
+        .dispatch();
+    assert_eq!(submit().await.status(), Status::Created);
+    assert_eq!(submit().await.status(), Status::TooManyRequests);
+}
+
+#[rocket::async_test]
+async fn responses_are_listed_to_their_form_author_only() {
+    let db = database().await;
+    let author = UserBuilder::new("author").create(&db).await;
+    let other = UserBuilder::new("other").approver().create(&db).await;
+    let form_id = FormBuilder::new("Feedback").field("name", "text", "Your name").create(&db, &author).await;
+    ResponseBuilder::new(form_id).answer("name", "Ada").create(&db).await;
+    ResponseBuilder::new(form_id).answer("name", "Grace").create(&db).await;
+    let client = client(&db).await;
+
+    let response = client.get(format!("/api/v1/forms/{}/responses", form_id))
+        .header(api_token(&db, &author).await)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let page = json_body(response).await;
+    let names: Vec<&str> = page["data"].as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|response| response["answers"]["name"].as_str())
+        .collect();
+    assert_eq!(names, ["Ada", "Grace"]);
+
+    let response = client.get(format!("/api/v1/forms/{}/responses", form_id))
+        .header(api_token(&db, &other).await)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn avatars_are_served_once_their_scan_clears_them() {
+    let db = database().await;
+    let user = UserBuilder::new("author").create(&db).await;
+    let uploads = scanned_uploads("OK").await;
+    let client = client_with_uploads(&db, &uploads).await;
+    sign_in(&client, &user).await;
+
+    upload_avatar(&client).await;
+    let avatar = format!("/users/{}/avatar", user.id);
+    assert_eq!(client.get(avatar.clone()).dispatch().await.status(), Status::NotFound);
+
+    storage::scan_pending(&db, &Storage::open(&uploads).await.unwrap()).await.unwrap();
+    let status = sqlx::query_scalar!("SELECT status FROM uploads WHERE user_id = ?", user.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(status, "clean");
+    assert_eq!(client.get(avatar).dispatch().await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn infected_avatars_stay_in_quarantine_and_their_owner_is_told() {
+    let db = database().await;
+    let user = UserBuilder::new("author").create(&db).await;
+    let uploads = scanned_uploads("Eicar-Signature FOUND").await;
+    let client = client_with_uploads(&db, &uploads).await;
+    sign_in(&client, &user).await;
+
+    upload_avatar(&client).await;
+    storage::scan_pending(&db, &Storage::open(&uploads).await.unwrap()).await.unwrap();
+
+    let upload = sqlx::query!("SELECT status, signature FROM uploads WHERE user_id = ?", user.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(upload.status, "infected");
+    assert_eq!(upload.signature.as_deref(), Some("Eicar-Signature"));
+    let avatar = sqlx::query_scalar!("SELECT avatar FROM user_profiles WHERE user_id = ?", user.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(avatar, None);
+    assert_eq!(client.get(format!("/users/{}/avatar", user.id)).dispatch().await.status(), Status::NotFound);
+
+    let told = sqlx::query_scalar!("SELECT COUNT(*) FROM notifications WHERE user_id = ? AND kind = 'upload_infected'", user.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(told, 1);
+}
+
+#[rocket::async_test]
+async fn avatars_past_the_plan_upload_quota_are_refused() {
+    let db = database().await;
+    let user = UserBuilder::new("author").create(&db).await;
+    let uploads = scanned_uploads("OK").await;
+    let uploads = json!({ "directory": uploads.directory, "max_size": uploads.max_size, "clamd": uploads.clamd });
+    let figment = figment()
+        .merge(("uploads", uploads))
+        .merge(("plans", json!({ "default": { "max_upload_bytes": 10 } })));
+    let client = Client::tracked(build_rocket(figment, db.clone())).await.expect("app ignites");
+    sign_in(&client, &user).await;
+
+    // Replacing the avatar frees its bytes first.
+    assert!(!upload_avatar(&client).await.contains("upload_error"));
+    assert!(!upload_avatar(&client).await.contains("upload_error"));
+
+    sqlx::query!("INSERT INTO uploads (key, user_id, size, status) VALUES ('other', ?, 5, 'clean')", user.id)
+        .execute(&db)
+        .await
+        .unwrap();
+    assert!(upload_avatar(&client).await.contains("upload_error=over_quota"));
+    let stored = sqlx::query_scalar!("SELECT COUNT(*) FROM uploads WHERE user_id = ?", user.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(stored, 2);
+}
@@ -0,0 +1,214 @@
+use std::time::Duration;
+
+use rocket::tokio::sync::{mpsc, oneshot};
+use rocket::tokio::time::{Instant, timeout_at};
+use serde::Deserialize;
+use sqlx::{Connection, SqliteConnection, SqlitePool};
+
+use crate::consent::{self, GivenConsent};
+use crate::models::{Attribution, FormResponse, ResponseEventKind};
+use crate::outbox::{self, OutboxEvent};
+use crate::slots;
+
+/// The `[write_buffer]` configuration table. Off by default; every
+/// submission then does its own insert, as before.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WriteBufferConfig {
+    pub enabled: bool,
+    /// Most inserts committed in one transaction.
+    pub max_batch: usize,
+    /// How long the writer waits for a batch to fill after its first insert.
+    pub max_delay_ms: u64,
+    /// Queued inserts beyond this make submitters wait for room.
+    pub capacity: usize,
+}
+
+impl Default for WriteBufferConfig {
+    fn default() -> Self {
+        WriteBufferConfig {
+            enabled: false,
+            max_batch: 64,
+            max_delay_ms: 5,
+            capacity: 4096,
+        }
+    }
+}
+
+/// A response ready to be written, after quota, encryption and duplicate
+/// checks have passed.
+pub struct NewResponse {
+    pub form_id: i64,
+    pub answers: String,
+    pub is_test: bool,
+    pub device: Option<String>,
+    pub respondent_email: Option<String>,
+    pub answers_hash: String,
+    pub duplicate_of: Option<i64>,
+    pub idempotency_key: Option<String>,
+    pub attribution: Attribution,
+    pub waitlisted: bool,
+    pub payment_status: Option<String>,
+    pub payment_amount: Option<i64>,
+    pub captcha_score: Option<f64>,
+    pub spam_reason: Option<String>,
+    pub entered_by: Option<i64>,
+    /// Logged with the response, in its transaction.
+    pub consents: Vec<GivenConsent>,
+    /// The slots booked for it, recorded in its transaction.
+    pub booked: Vec<i64>,
+    /// The response is complete as stored, so its `response.submitted`
+    /// event goes in the outbox with it.
+    pub announce: bool,
+}
+
+struct PendingInsert {
+    response: NewResponse,
+    reply: oneshot::Sender<Result<FormResponse, sqlx::Error>>,
+}
+
+/// Funnels response inserts through one writer task that commits them in
+/// batches, since SQLite serialises writers anyway and a commit per
+/// submission is what limits throughput under a spike.
+///
+/// Durability is unchanged: `insert` only returns once the transaction
+/// holding the row has committed, so a submitter never sees success for a
+/// response that could still be lost. Batching only adds up to
+/// `max_delay_ms` of latency. When the buffer is disabled or the writer has
+/// gone away, inserts are written synchronously on the caller's task.
+#[derive(Default)]
+pub struct WriteBuffer {
+    queue: Option<mpsc::Sender<PendingInsert>>,
+}
+
+impl WriteBuffer {
+    /// Spawns the writer task, so this has to run inside the runtime.
+    pub fn start(db: SqlitePool, config: &WriteBufferConfig) -> Self {
+        if !config.enabled {
+            return WriteBuffer::default();
+        }
+
+        let (queue, pending) = mpsc::channel(config.capacity.max(1));
+        let max_delay = Duration::from_millis(config.max_delay_ms);
+        rocket::tokio::spawn(run_writer(db, pending, config.max_batch.max(1), max_delay));
+        WriteBuffer { queue: Some(queue) }
+    }
+
+    pub async fn insert(&self, db: &SqlitePool, response: NewResponse) -> Result<FormResponse, sqlx::Error> {
+        let Some(queue) = &self.queue else {
+            return insert_now(db, &response).await;
+        };
+
+        let (reply, result) = oneshot::channel();
+        match queue.send(PendingInsert { response, reply }).await {
+            // A dropped reply means the writer died mid-batch, so whether the
+            // row was committed is unknown; report failure rather than retry.
+            Ok(()) => result.await.unwrap_or(Err(sqlx::Error::WorkerCrashed)),
+            Err(mpsc::error::SendError(pending)) => insert_now(db, &pending.response).await,
+        }
+    }
+}
+
+/// In its own transaction, or a savepoint inside a batch's, so the row, its
+/// `submitted` history event, its consent records and its outbox event are
+/// written together.
+async fn insert(conn: &mut SqliteConnection, response: &NewResponse) -> Result<FormResponse, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+    let spam = response.spam_reason.is_some();
+    let stored = sqlx::query_as!(FormResponse,
+        "INSERT INTO responses (form_id, answers, is_test, device, respondent_email, answers_hash, duplicate_of, idempotency_key,
+         utm_source, utm_medium, utm_campaign, referrer, waitlisted, payment_status, payment_amount, captcha_score, spam, spam_reason, entered_by,
+         updated_at, tenant_id)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP, (SELECT tenant_id FROM forms WHERE id = ?))
+         RETURNING *",
+        response.form_id,
+        response.answers,
+        response.is_test,
+        response.device,
+        response.respondent_email,
+        response.answers_hash,
+        response.duplicate_of,
+        response.idempotency_key,
+        response.attribution.utm_source,
+        response.attribution.utm_medium,
+        response.attribution.utm_campaign,
+        response.attribution.referrer,
+        response.waitlisted,
+        response.payment_status,
+        response.payment_amount,
+        response.captcha_score,
+        spam,
+        response.spam_reason,
+        response.entered_by,
+        response.form_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let submitted = ResponseEventKind::Submitted.as_str();
+    let device = response.device.as_deref().unwrap_or_default();
+    sqlx::query!(
+        "INSERT INTO response_events (response_id, actor_id, kind, detail) VALUES (?, ?, ?, ?)",
+        stored.id,
+        response.entered_by,
+        submitted,
+        device
+    )
+    .execute(&mut *tx)
+    .await?;
+    consent::record(&mut *tx, stored.form_id, stored.id, &response.consents).await?;
+    slots::record(&mut *tx, stored.id, &response.booked).await?;
+
+    if response.announce {
+        outbox::record(&mut *tx, OutboxEvent::ResponseSubmitted { form_id: stored.form_id, response_id: stored.id }).await?;
+    }
+    tx.commit().await?;
+    Ok(stored)
+}
+
+async fn insert_now(db: &SqlitePool, response: &NewResponse) -> Result<FormResponse, sqlx::Error> {
+    let mut conn = db.acquire().await?;
+    insert(&mut *conn, response).await
+}
+
+/// A failing row is rolled back on its own and the rest of the batch still
+/// commits; the outer error means nothing was committed.
+async fn insert_batch(db: &SqlitePool, batch: &[PendingInsert]) -> Result<Vec<Result<FormResponse, sqlx::Error>>, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let mut results = Vec::with_capacity(batch.len());
+    for pending in batch {
+        results.push(insert(&mut *tx, &pending.response).await);
+    }
+    tx.commit().await?;
+    Ok(results)
+}
+
+async fn run_writer(db: SqlitePool, mut pending: mpsc::Receiver<PendingInsert>, max_batch: usize, max_delay: Duration) {
+    let mut batch = Vec::with_capacity(max_batch);
+    while let Some(first) = pending.recv().await {
+        batch.push(first);
+        let deadline = Instant::now() + max_delay;
+        while batch.len() < max_batch {
+            match timeout_at(deadline, pending.recv()).await {
+                Ok(Some(next)) => batch.push(next),
+                _ => break,
+            }
+        }
+
+        let results = match insert_batch(&db, &batch).await {
+            Ok(results) => results,
+            Err(e) => {
+                warn!("Batched insert of {} responses failed, writing them one at a time: {}", batch.len(), e);
+                let mut results = Vec::with_capacity(batch.len());
+                for pending in &batch {
+                    results.push(insert_now(&db, &pending.response).await);
+                }
+                results
+            }
+        };
+
+        for (pending, result) in batch.drain(..).zip(results) {
+            let _ = pending.reply.send(result);
+        }
+    }
+}